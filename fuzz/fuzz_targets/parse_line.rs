@@ -0,0 +1,13 @@
+#![no_main]
+
+use codex_mcp_rs::parser::{self, Line};
+use libfuzzer_sys::fuzz_target;
+
+// Decoding and field extraction must accept any line without panicking
+fuzz_target!(|data: &[u8]| {
+    if let Line::Event(event) = parser::parse_line(data) {
+        let fields = parser::extract(&event);
+        assert!(fields.error_message.is_none() || fields.failed);
+        assert!(fields.thread_id.map_or(true, |id| !id.is_empty()));
+    }
+});
@@ -0,0 +1,36 @@
+#![no_main]
+
+use codex_mcp_rs::parser::{self, read_line_with_limit};
+use libfuzzer_sys::fuzz_target;
+use tokio::io::BufReader;
+
+// Any byte stream, in any chunking, is consumed to EOF in bounded lines
+fuzz_target!(|data: &[u8]| {
+    let Some((&control, stream)) = data.split_first() else {
+        return;
+    };
+    let capacity = usize::from(control % 16) + 1;
+    let max_len = usize::from(control / 16) * 8 + 1;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let mut reader = BufReader::with_capacity(capacity, stream);
+        let mut buf = Vec::new();
+        let mut consumed = 0;
+        loop {
+            buf.clear();
+            let read = read_line_with_limit(&mut reader, &mut buf, max_len, None)
+                .await
+                .unwrap();
+            if read.bytes_read == 0 {
+                break;
+            }
+            assert!(buf.len() <= max_len);
+            consumed += read.bytes_read;
+            let _ = parser::parse_line(&buf);
+        }
+        assert_eq!(consumed, stream.len());
+    });
+});
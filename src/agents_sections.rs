@@ -0,0 +1,245 @@
+/// AGENTS.md cut down to a byte limit at section boundaries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fitted {
+    pub content: String,
+    /// Headings of the sections left out, in document order
+    pub dropped: Vec<String>,
+}
+
+/// One Markdown section: a heading and everything up to the next heading of
+/// the same or a higher level
+#[derive(Debug)]
+struct Section<'a> {
+    heading: String,
+    text: &'a str,
+}
+
+/// Fit `content` into `max_bytes` by leaving out whole sections instead of
+/// cutting mid-sentence. Text before the first heading (including front
+/// matter) is always kept. Sections named in `priority` (matched against
+/// their heading, case-insensitively) are kept first, in that order, then
+/// the others in document order; kept sections stay in document order and
+/// a note names the ones left out. `None` when the file has no sections or
+/// not even its leading text fits, so the caller can fall back to a plain
+/// cut.
+pub fn fit(content: &str, max_bytes: usize, priority: &[String]) -> Option<Fitted> {
+    if content.len() <= max_bytes {
+        return Some(Fitted {
+            content: content.to_string(),
+            dropped: Vec::new(),
+        });
+    }
+    let (preamble, sections) = split(content);
+    if sections.is_empty() || preamble.len() > max_bytes {
+        return None;
+    }
+
+    let rank = |section: &Section| {
+        priority
+            .iter()
+            .position(|name| name.trim().eq_ignore_ascii_case(&section.heading))
+            .unwrap_or(priority.len())
+    };
+    let mut order: Vec<usize> = (0..sections.len()).collect();
+    // Stable, so sections of equal rank keep document order
+    order.sort_by_key(|&index| rank(&sections[index]));
+
+    // Keep what fits, leaving room for the note naming what did not; a
+    // longer note can push out more sections, so repeat until it settles
+    let mut reserve = 0;
+    for _ in 0..=sections.len() {
+        let mut kept = vec![false; sections.len()];
+        let mut used = preamble.len();
+        for &index in &order {
+            if used + sections[index].text.len() + reserve <= max_bytes {
+                kept[index] = true;
+                used += sections[index].text.len();
+            }
+        }
+        let fitted = assemble(preamble, &sections, &kept);
+        if fitted.content.len() <= max_bytes {
+            return Some(fitted);
+        }
+        reserve = fitted.content.len() - used;
+    }
+    None
+}
+
+fn assemble(preamble: &str, sections: &[Section], kept: &[bool]) -> Fitted {
+    let mut content = preamble.to_string();
+    let mut dropped = Vec::new();
+    for (section, &kept) in sections.iter().zip(kept) {
+        if kept {
+            content.push_str(section.text);
+        } else {
+            dropped.push(section.heading.clone());
+        }
+    }
+    if !dropped.is_empty() {
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&format!(
+            "\n[... AGENTS.md sections left out to fit the limit: {}]\n",
+            dropped.join(", ")
+        ));
+    }
+    Fitted { content, dropped }
+}
+
+/// Text before the first section, and the sections. Sections start at the
+/// shallowest heading level used more than once (typically `##` under a
+/// single `#` title), so subsections stay with their parent. Headings inside
+/// code fences or front matter do not count.
+fn split(content: &str) -> (&str, Vec<Section<'_>>) {
+    let mut headings = Vec::new();
+    let mut offset = 0;
+    let mut in_fence = false;
+    let mut in_front_matter = content.starts_with("---\n") || content.starts_with("---\r\n");
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_end();
+        if in_front_matter {
+            if index > 0 && trimmed == "---" {
+                in_front_matter = false;
+            }
+        } else if trimmed.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        } else if !in_fence {
+            if let Some((level, title)) = heading(trimmed) {
+                headings.push((offset, level, title));
+            }
+        }
+        offset += line.len();
+    }
+
+    let levels: Vec<usize> = headings.iter().map(|(_, level, _)| *level).collect();
+    let Some(split_level) = (1..=6)
+        .find(|level| levels.iter().filter(|l| *l == level).count() > 1)
+        .or_else(|| levels.iter().copied().min())
+    else {
+        return (content, Vec::new());
+    };
+
+    let starts: Vec<(usize, String)> = headings
+        .into_iter()
+        .filter(|(_, level, _)| *level <= split_level)
+        .map(|(offset, _, title)| (offset, title))
+        .collect();
+    let preamble = &content[..starts[0].0];
+    let sections = starts
+        .iter()
+        .enumerate()
+        .map(|(index, (start, title))| {
+            let end = starts.get(index + 1).map_or(content.len(), |(end, _)| *end);
+            Section {
+                heading: title.clone(),
+                text: &content[*start..end],
+            }
+        })
+        .collect();
+    (preamble, sections)
+}
+
+/// Level and text of an ATX heading line such as `## Testing`
+fn heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let title = rest.trim().trim_end_matches('#').trim();
+    Some((level, title.to_string()))
+}
+
+/// Headings listed under `priority:` in a leading `---` front matter block,
+/// either inline (`priority: [Security, Testing]`) or as a `- ` list on the
+/// following lines
+pub fn front_matter_priority(content: &str) -> Vec<String> {
+    let Some(rest) = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    else {
+        return Vec::new();
+    };
+    let Some(end) = rest.find("\n---") else {
+        return Vec::new();
+    };
+    let mut lines = rest[..end].lines();
+    let Some(inline) = lines.find_map(|line| line.trim().strip_prefix("priority:")) else {
+        return Vec::new();
+    };
+    let unquote = |name: &str| name.trim().trim_matches(['"', '\'']).to_string();
+    let inline = inline.trim();
+    if !inline.is_empty() {
+        return inline
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(unquote)
+            .filter(|name| !name.is_empty())
+            .collect();
+    }
+    lines
+        .map_while(|line| line.trim().strip_prefix("- "))
+        .map(unquote)
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AGENTS: &str = "---\n\
+priority:\n  - Security\n  - \"Testing\"\n\
+---\n\
+# Project rules\n\
+Intro.\n\
+## Style\n\
+Indent with four spaces, wrap at 100 columns, and keep imports sorted; # is not a heading here.\n\
+## Testing\n\
+Run `cargo test`.\n\
+### Fixtures\n\
+Keep them small.\n\
+```sh\n\
+# Security\n\
+```\n\
+## Security\n\
+Never commit secrets.\n";
+
+    #[test]
+    fn test_fit_drops_lowest_priority_sections() {
+        let priority = front_matter_priority(AGENTS);
+        assert_eq!(priority, ["Security", "Testing"]);
+        assert_eq!(
+            front_matter_priority("---\npriority: [A, 'B']\n---\nbody"),
+            ["A", "B"]
+        );
+
+        let full = fit(AGENTS, AGENTS.len(), &priority).unwrap();
+        assert!(full.dropped.is_empty());
+
+        let fitted = fit(AGENTS, AGENTS.len() - 1, &priority).unwrap();
+        assert_eq!(fitted.dropped, ["Style"]);
+        assert!(fitted.content.len() < AGENTS.len());
+        assert!(fitted.content.contains("### Fixtures\nKeep them small."));
+        assert!(fitted
+            .content
+            .contains("## Security\nNever commit secrets.\n"));
+        assert!(fitted
+            .content
+            .ends_with("left out to fit the limit: Style]\n"));
+
+        // Without priorities earlier sections win; a later one still fills
+        // the room left
+        let fitted = fit(AGENTS, AGENTS.len() - 1, &[]).unwrap();
+        assert_eq!(fitted.dropped, ["Testing"]);
+        assert!(fitted.content.contains("## Security"));
+
+        assert_eq!(fit("no headings at all", 5, &[]), None);
+        assert_eq!(fit(AGENTS, 10, &priority), None);
+    }
+}
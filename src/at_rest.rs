@@ -0,0 +1,294 @@
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// Environment variable holding an age secret key (`AGE-SECRET-KEY-1...`);
+/// takes precedence over `encryption.identity_file`
+pub const KEY_ENV: &str = "CODEX_MCP_AGE_KEY";
+
+/// Extension appended to files stored encrypted
+const EXTENSION: &str = "age";
+
+/// `encryption` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EncryptionConfig {
+    /// age identity file, as written by `age-keygen`
+    #[serde(default)]
+    pub identity_file: Option<PathBuf>,
+    /// `age` binary; `age-keygen` is looked up next to it
+    #[serde(default)]
+    pub age_bin: Option<PathBuf>,
+}
+
+/// Key for encrypting stored run logs, diffs, and spilled output with the
+/// `age` CLI
+pub struct AgeKey {
+    age_bin: PathBuf,
+    identity: String,
+    recipient: String,
+}
+
+impl std::fmt::Debug for AgeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgeKey")
+            .field("age_bin", &self.age_bin)
+            .field("recipient", &self.recipient)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AgeKey {
+    /// Key from `CODEX_MCP_AGE_KEY` or `identity_file`; `None` when neither
+    /// is set, so files are stored in plain text
+    pub fn load(config: &EncryptionConfig) -> Result<Option<Self>, String> {
+        let identity = match std::env::var(KEY_ENV) {
+            Ok(key) if !key.trim().is_empty() => key,
+            _ => match &config.identity_file {
+                Some(path) => std::fs::read_to_string(path)
+                    .map_err(|e| format!("cannot read {}: {}", path.display(), e))?,
+                None => return Ok(None),
+            },
+        };
+        let age_bin = config
+            .age_bin
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("age"));
+        let keygen_bin = match age_bin.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.join("age-keygen"),
+            _ => PathBuf::from("age-keygen"),
+        };
+        let recipient = recipient_of(&keygen_bin, &identity)?;
+        Ok(Some(Self {
+            age_bin,
+            identity,
+            recipient,
+        }))
+    }
+
+    /// Start encrypting to `path`; write the plain text to the returned sink
+    pub fn encryptor(&self, path: &Path) -> std::io::Result<Encryptor> {
+        let mut child = Command::new(&self.age_bin)
+            .arg("-r")
+            .arg(&self.recipient)
+            .arg("-o")
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        Ok(Encryptor { child, stdin })
+    }
+
+    /// Write `bytes` encrypted to `path`
+    pub fn write(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+        let mut encryptor = self.encryptor(path)?;
+        encryptor.write_all(bytes)?;
+        encryptor.finish()
+    }
+
+    /// Stream the decrypted content of `path`
+    pub fn decryptor(&self, path: &Path) -> std::io::Result<Decryptor> {
+        let mut child = Command::new(&self.age_bin)
+            .args(["-d", "-i", "-"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // The identity is small enough to fit the pipe buffer, and age reads
+        // it before any output is produced
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(self.identity.as_bytes())?;
+        }
+        let stdout = child.stdout.take();
+        Ok(Decryptor {
+            child: Some(child),
+            stdout,
+        })
+    }
+
+    /// Decrypt `path` into memory
+    pub fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        self.decryptor(path)?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+}
+
+/// Read `path`, decrypting `.age` files with `key`
+pub fn read(path: &Path, key: Option<&AgeKey>) -> std::io::Result<Vec<u8>> {
+    if !is_encrypted(path) {
+        return std::fs::read(path);
+    }
+    match key {
+        Some(key) => key.read(path),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "file is encrypted and no key is configured",
+        )),
+    }
+}
+
+/// `path` with the `.age` extension appended
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    if is_encrypted(path) {
+        return path.to_path_buf();
+    }
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Whether `path` names an encrypted file
+pub fn is_encrypted(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == EXTENSION)
+}
+
+/// `path` without its `.age` extension
+pub fn plain_path(path: &Path) -> PathBuf {
+    if is_encrypted(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+fn recipient_of(keygen_bin: &Path, identity: &str) -> Result<String, String> {
+    let mut child = Command::new(keygen_bin)
+        .arg("-y")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", keygen_bin.display(), e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(identity.as_bytes())
+            .map_err(|e| format!("failed to pass the age identity: {}", e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run {}: {}", keygen_bin.display(), e))?;
+    let recipient = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("age1"))
+        .map(str::to_string);
+    match recipient {
+        Some(recipient) if output.status.success() => Ok(recipient),
+        _ => Err(format!(
+            "not a valid age identity: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+fn age_error(child: &mut Child, status: std::process::ExitStatus) -> std::io::Error {
+    let mut stderr = String::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        let _ = pipe.read_to_string(&mut stderr);
+    }
+    std::io::Error::other(format!("age exited with {}: {}", status, stderr.trim()))
+}
+
+/// Plain-text sink feeding an `age` process. Output is written in 64 KiB
+/// chunks, so a file whose encryptor was never finished loses its tail.
+#[derive(Debug)]
+pub struct Encryptor {
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Encryptor {
+    /// Close the input and wait for `age` to write the last chunk
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        let Some(stdin) = self.stdin.take() else {
+            return Ok(());
+        };
+        drop(stdin);
+        let status = self.child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(age_error(&mut self.child, status))
+        }
+    }
+}
+
+impl Write for Encryptor {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.stdin.as_mut() {
+            Some(stdin) => stdin.write(buf),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "encryptor already finished",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stdin.as_mut().map_or(Ok(()), |stdin| stdin.flush())
+    }
+}
+
+impl Drop for Encryptor {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Plain text read from an `age -d` process; a failed decryption surfaces as
+/// an error at the end of the stream
+#[derive(Debug)]
+pub struct Decryptor {
+    child: Option<Child>,
+    stdout: Option<ChildStdout>,
+}
+
+impl Read for Decryptor {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = match self.stdout.as_mut() {
+            Some(stdout) => stdout.read(buf)?,
+            None => 0,
+        };
+        if read == 0 && !buf.is_empty() {
+            self.stdout = None;
+            if let Some(mut child) = self.child.take() {
+                let status = child.wait()?;
+                if !status.success() {
+                    return Err(age_error(&mut child, status));
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl Drop for Decryptor {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_paths() {
+        let log = Path::new("/runs/abc.jsonl.gz");
+        let encrypted = encrypted_path(log);
+        assert_eq!(encrypted, PathBuf::from("/runs/abc.jsonl.gz.age"));
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_encrypted(log));
+        assert_eq!(encrypted_path(&encrypted), encrypted);
+        assert_eq!(plain_path(&encrypted), log);
+        assert_eq!(plain_path(log), log);
+    }
+}
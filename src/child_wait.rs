@@ -0,0 +1,168 @@
+use crate::rusage::{self, ResourceUsage};
+use std::ops::{Deref, DerefMut};
+use tokio::process::Child;
+use tokio::task::JoinHandle;
+
+/// A spawned Codex process that is killed and waited for if it is dropped
+/// before it was reaped, e.g. when its run times out or is cancelled. The
+/// wait reaps it on the runtime right away; a plain `kill_on_drop` child
+/// joins tokio's orphan queue instead, which is only drained on the next
+/// SIGCHLD, so a process that exited just as its run was dropped could stay
+/// a zombie until another one exits.
+#[derive(Debug)]
+pub struct OwnedChild(Option<Child>);
+
+impl OwnedChild {
+    pub fn new(child: Child) -> Self {
+        Self(Some(child))
+    }
+}
+
+impl Deref for OwnedChild {
+    type Target = Child;
+
+    fn deref(&self) -> &Child {
+        self.0.as_ref().expect("child is only taken when dropped")
+    }
+}
+
+impl DerefMut for OwnedChild {
+    fn deref_mut(&mut self) -> &mut Child {
+        self.0.as_mut().expect("child is only taken when dropped")
+    }
+}
+
+impl Drop for OwnedChild {
+    fn drop(&mut self) {
+        let Some(mut child) = self.0.take() else {
+            return;
+        };
+        // Reaped already, or exited and reaped just now
+        if !matches!(child.try_wait(), Ok(None)) {
+            return;
+        }
+        // Without a runtime, `kill_on_drop` still kills it
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                let _ = child.kill().await;
+            });
+        }
+    }
+}
+
+/// Resolves with the resource usage of process `pid` once it exits, without
+/// reaping it, so the caller's own `wait()` still gets its exit status. On
+/// Linux 5.3 and later this waits on a pidfd, which always refers to this
+/// process, even after it was reaped; elsewhere a blocking thread waits on
+/// the pid.
+pub fn watch_exit(pid: u32) -> JoinHandle<Option<ResourceUsage>> {
+    #[cfg(target_os = "linux")]
+    if let Ok(pidfd) = linux::PidFd::open(pid) {
+        return tokio::spawn(async move { pidfd.exited().await });
+    }
+    tokio::task::spawn_blocking(move || rusage::wait_exited(pid))
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use crate::rusage::ResourceUsage;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use tokio::io::unix::AsyncFd;
+    use tokio::io::Interest;
+
+    /// `waitid` id type selecting a pidfd (linux/wait.h)
+    const P_PIDFD: libc::idtype_t = 3;
+
+    /// A pidfd, readable once its process has exited
+    pub struct PidFd(AsyncFd<OwnedFd>);
+
+    impl PidFd {
+        pub fn open(pid: u32) -> std::io::Result<Self> {
+            let pid = libc::pid_t::try_from(pid)
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+            let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) };
+            Ok(Self(AsyncFd::with_interest(fd, Interest::READABLE)?))
+        }
+
+        /// Wait for the process to exit and return its usage, leaving it
+        /// unreaped; `None` if the usage cannot be read
+        pub async fn exited(&self) -> Option<ResourceUsage> {
+            loop {
+                let mut ready = self.0.readable().await.ok()?;
+                match self.usage_if_exited() {
+                    Ok(Some(usage)) => return Some(usage),
+                    Ok(None) => ready.clear_ready(),
+                    Err(_) => return None,
+                }
+            }
+        }
+
+        fn usage_if_exited(&self) -> std::io::Result<Option<ResourceUsage>> {
+            // As in `rusage::wait_exited`: the syscall, unlike glibc's
+            // waitid(), reports the usage
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+            loop {
+                let rc = unsafe {
+                    libc::syscall(
+                        libc::SYS_waitid,
+                        P_PIDFD,
+                        self.0.get_ref().as_raw_fd(),
+                        &mut info as *mut libc::siginfo_t,
+                        libc::WEXITED | libc::WNOWAIT | libc::WNOHANG,
+                        &mut usage as *mut libc::rusage,
+                    )
+                };
+                if rc == 0 {
+                    break;
+                }
+                let error = std::io::Error::last_os_error();
+                if error.raw_os_error() != Some(libc::EINTR) {
+                    return Err(error);
+                }
+            }
+            // With WNOHANG, a process still running leaves `info` zeroed
+            if unsafe { info.si_pid() } == 0 {
+                return Ok(None);
+            }
+            Ok(Some(ResourceUsage::from_rusage(&usage)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[cfg(target_os = "linux")]
+    async fn test_exit_is_seen_and_dropped_children_are_reaped() {
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        assert!(watch_exit(child.id().unwrap()).await.unwrap().is_some());
+        // Still reapable with its real exit status
+        assert_eq!(child.wait().await.unwrap().code(), Some(3));
+
+        let child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let pid = child.id().unwrap();
+        drop(OwnedChild::new(child));
+        let stat = format!("/proc/{}/stat", pid);
+        for _ in 0..100 {
+            if !std::path::Path::new(&stat).exists() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("process {} was not reaped", pid);
+    }
+}
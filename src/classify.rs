@@ -0,0 +1,180 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// What a run was mainly spent on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TaskKind {
+    /// Answered a question without changing files
+    Explain,
+    /// Changed existing code
+    Edit,
+    /// Ran the test suite and fixed what failed
+    TestFix,
+    /// Restructured code without changing behaviour
+    Refactor,
+    /// Mostly created new files
+    Scaffold,
+}
+
+/// Test runners across common ecosystems, matched as command substrings
+const TEST_COMMANDS: &[&str] = &[
+    "cargo test",
+    "cargo nextest",
+    "pytest",
+    "python -m unittest",
+    "go test",
+    "npm test",
+    "npm run test",
+    "yarn test",
+    "pnpm test",
+    "jest",
+    "vitest",
+    "mocha",
+    "mvn test",
+    "gradle test",
+    "./gradlew test",
+    "dotnet test",
+    "rspec",
+    "rake test",
+    "phpunit",
+    "ctest",
+    "make test",
+    "swift test",
+];
+
+const TEST_FIX_WORDS: &[&str] = &["fail", "fix", "broken", "bug", "flaky", "passing"];
+
+const REFACTOR_WORDS: &[&str] = &[
+    "refactor",
+    "rename",
+    "extract",
+    "restructure",
+    "reorganize",
+    "clean up",
+    "cleanup",
+    "simplify",
+    "deduplicate",
+    "split",
+];
+
+const SCAFFOLD_WORDS: &[&str] = &[
+    "scaffold",
+    "bootstrap",
+    "boilerplate",
+    "skeleton",
+    "new project",
+    "set up",
+    "setup",
+    "initialize",
+    "create a new",
+    "generate",
+];
+
+/// What the Codex event stream shows a run did
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Activity {
+    added_files: usize,
+    changed_files: usize,
+    ran_tests: bool,
+}
+
+fn activity(events: &[HashMap<String, Value>]) -> Activity {
+    let mut activity = Activity::default();
+    for event in events {
+        let Some(item) = event.get("item") else {
+            continue;
+        };
+        match item.get("type").and_then(Value::as_str) {
+            Some("command_execution") => {
+                let command = item.get("command").and_then(Value::as_str).unwrap_or("");
+                activity.ran_tests |= is_test_command(command);
+            }
+            Some("file_change") => {
+                let changes = item.get("changes").and_then(Value::as_array);
+                for change in changes.into_iter().flatten() {
+                    match change.get("kind").and_then(Value::as_str) {
+                        Some("add") => activity.added_files += 1,
+                        _ => activity.changed_files += 1,
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    activity
+}
+
+/// Whether `command` runs a test suite
+pub(crate) fn is_test_command(command: &str) -> bool {
+    TEST_COMMANDS.iter().any(|test| command.contains(test))
+}
+
+fn mentions(prompt: &str, words: &[&str]) -> bool {
+    words.iter().any(|word| prompt.contains(word))
+}
+
+/// Classify a run from its prompt and the events Codex emitted. Runs that
+/// changed no files are explanations; otherwise test runs, the prompt's
+/// wording, and the share of new files decide.
+pub fn classify(prompt: &str, events: &[HashMap<String, Value>]) -> TaskKind {
+    let prompt = prompt.to_lowercase();
+    let activity = activity(events);
+    let touched = activity.added_files + activity.changed_files;
+
+    if touched == 0 {
+        return TaskKind::Explain;
+    }
+    if activity.ran_tests && mentions(&prompt, TEST_FIX_WORDS) {
+        return TaskKind::TestFix;
+    }
+    if activity.added_files > activity.changed_files || mentions(&prompt, SCAFFOLD_WORDS) {
+        return TaskKind::Scaffold;
+    }
+    if mentions(&prompt, REFACTOR_WORDS) {
+        return TaskKind::Refactor;
+    }
+    TaskKind::Edit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(item: Value) -> HashMap<String, Value> {
+        HashMap::from([
+            ("type".to_string(), json!("item.completed")),
+            ("item".to_string(), item),
+        ])
+    }
+
+    #[test]
+    fn test_classify() {
+        let edit = event(json!({
+            "type": "file_change",
+            "changes": [{"path": "src/lib.rs", "kind": "update"}]
+        }));
+        let add = event(json!({
+            "type": "file_change",
+            "changes": [{"path": "src/new.rs", "kind": "add"}, {"path": "src/mod.rs", "kind": "add"}]
+        }));
+        let tests = event(json!({"type": "command_execution", "command": "bash -lc 'cargo test'"}));
+
+        assert_eq!(
+            classify("Why does this fail?", &[tests.clone()]),
+            TaskKind::Explain
+        );
+        assert_eq!(
+            classify("Fix the failing tests", &[tests.clone(), edit.clone()]),
+            TaskKind::TestFix
+        );
+        assert_eq!(classify("Add a CLI", &[add]), TaskKind::Scaffold);
+        assert_eq!(
+            classify("Rename Foo to Bar", &[edit.clone()]),
+            TaskKind::Refactor
+        );
+        assert_eq!(classify("Add a --verbose flag", &[edit]), TaskKind::Edit);
+    }
+}
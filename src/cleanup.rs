@@ -0,0 +1,267 @@
+use crate::codex::{self, MAX_TIMEOUT_SECS};
+use crate::codex_home;
+use crate::final_schema;
+use crate::image_preprocess;
+use crate::pdf_pages;
+use crate::run_log::{self, RetentionConfig};
+use crate::workspace_lock;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+/// Per-run temp directories older than this belong to runs that can no
+/// longer be in progress (the longest timeout, retried once)
+const ORPHAN_AGE: Duration = Duration::from_secs(2 * MAX_TIMEOUT_SECS);
+
+/// Spilled parse-error lines are kept this long unless
+/// `runs_retention.max_age_days` is set
+const DEFAULT_SPILL_MAX_AGE_DAYS: u64 = 7;
+
+/// What one kind of artifact gave back
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CleanupItem {
+    pub category: &'static str,
+    pub removed: usize,
+    pub freed_bytes: u64,
+}
+
+/// Result of one cleanup pass
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CleanupReport {
+    pub freed_bytes: u64,
+    pub items: Vec<CleanupItem>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl CleanupReport {
+    fn add(&mut self, item: CleanupItem) {
+        self.freed_bytes += item.freed_bytes;
+        self.items.push(item);
+    }
+}
+
+/// Remove artifacts this server left behind: stale workspace locks, per-run
+/// temp files of runs that are long over (isolated homes, processed images,
+/// rendered PDF pages, FINAL_SCHEMA files), old spilled parse-error lines, and run logs
+/// past `runs_retention`. Blocking; run it off the async runtime.
+pub fn run() -> CleanupReport {
+    let mut report = CleanupReport::default();
+    let now = SystemTime::now();
+
+    match remove_stale_locks(&workspace_lock::lock_dir(), now) {
+        Ok(item) => report.add(item),
+        Err(e) => push_error(&mut report, "locks", e),
+    }
+    for (category, dir) in [
+        ("isolated_homes", codex_home::homes_dir()),
+        ("images", image_preprocess::images_dir()),
+        ("pdf_pages", pdf_pages::pages_dir()),
+        ("schemas", final_schema::schemas_dir()),
+    ] {
+        match remove_orphaned(category, &dir, now) {
+            Ok(item) => report.add(item),
+            Err(e) => push_error(&mut report, category, e),
+        }
+    }
+
+    let runs_dir = codex::runs_dir();
+    let retention = codex::runs_retention();
+    let spill_age_days = retention.max_age_days.unwrap_or(DEFAULT_SPILL_MAX_AGE_DAYS);
+    match remove_spill_files(&runs_dir, days(spill_age_days), now) {
+        Ok(item) => report.add(item),
+        Err(e) => push_error(&mut report, "spill_files", e),
+    }
+    if *retention != RetentionConfig::default() {
+        match run_log::sweep(&runs_dir, retention) {
+            Ok(swept) => report.add(CleanupItem {
+                category: "run_logs",
+                removed: swept.removed_files,
+                freed_bytes: swept.freed_bytes,
+            }),
+            Err(e) => push_error(&mut report, "run_logs", e),
+        }
+    }
+    report
+}
+
+fn push_error(report: &mut CleanupReport, category: &str, e: std::io::Error) {
+    // A directory that was never created has nothing to clean
+    if e.kind() != std::io::ErrorKind::NotFound {
+        report.errors.push(format!("{}: {}", category, e));
+    }
+}
+
+fn days(days: u64) -> Duration {
+    Duration::from_secs(days.saturating_mul(86_400))
+}
+
+fn older_than(path: &Path, age: Duration, now: SystemTime) -> bool {
+    std::fs::symlink_metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| {
+            now.duration_since(modified)
+                .is_ok_and(|elapsed| elapsed > age)
+        })
+}
+
+/// Lock files whose holder exited or timed out, and leftover temp and
+/// set-aside files from interrupted acquisitions
+fn remove_stale_locks(dir: &Path, now: SystemTime) -> std::io::Result<CleanupItem> {
+    let mut item = CleanupItem {
+        category: "locks",
+        ..Default::default()
+    };
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("lock") => {
+                let size = disk_size(&path);
+                if workspace_lock::is_stale_lock_file(&path)
+                    && matches!(workspace_lock::remove_if_stale(&path), Ok(None))
+                {
+                    item.removed += 1;
+                    item.freed_bytes += size;
+                }
+            }
+            Some("tmp" | "stale") if older_than(&path, ORPHAN_AGE, now) => remove(&path, &mut item),
+            _ => {}
+        }
+    }
+    Ok(item)
+}
+
+/// Entries of `dir` (one directory or file per run) untouched for `ORPHAN_AGE`
+fn remove_orphaned(
+    category: &'static str,
+    dir: &Path,
+    now: SystemTime,
+) -> std::io::Result<CleanupItem> {
+    let mut item = CleanupItem {
+        category,
+        ..Default::default()
+    };
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if older_than(&path, ORPHAN_AGE, now) {
+            remove(&path, &mut item);
+        }
+    }
+    Ok(item)
+}
+
+/// `parse-error-*.txt` files in `runs_dir` older than `max_age`
+fn remove_spill_files(
+    runs_dir: &Path,
+    max_age: Duration,
+    now: SystemTime,
+) -> std::io::Result<CleanupItem> {
+    let mut item = CleanupItem {
+        category: "spill_files",
+        ..Default::default()
+    };
+    for entry in std::fs::read_dir(runs_dir)? {
+        let path = entry?.path();
+        let spilled = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| {
+                let name = name.strip_suffix(".age").unwrap_or(name);
+                name.starts_with("parse-error-") && name.ends_with(".txt")
+            });
+        if spilled && path.is_file() && older_than(&path, max_age, now) {
+            remove(&path, &mut item);
+        }
+    }
+    Ok(item)
+}
+
+fn remove(path: &Path, item: &mut CleanupItem) {
+    let size = disk_size(path);
+    let removed = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    if removed.is_ok() {
+        item.removed += 1;
+        item.freed_bytes += size;
+    }
+}
+
+/// Bytes of a file, or of everything below a directory
+fn disk_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    let mut total = 0;
+    let mut pending: Vec<PathBuf> = vec![path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => pending.push(entry.path()),
+                Ok(metadata) => total += metadata.len(),
+                Err(_) => {}
+            }
+        }
+    }
+    total
+}
+
+/// Run the cleanup every `interval`, if `cleanup_interval_secs` is configured
+pub fn start(interval: Option<Duration>) -> Option<JoinHandle<()>> {
+    let interval = interval.filter(|interval| !interval.is_zero())?;
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Ok(report) = tokio::task::spawn_blocking(run).await {
+                if report.freed_bytes > 0 {
+                    eprintln!("Cleanup: freed {} bytes", report.freed_bytes);
+                }
+                for error in &report.errors {
+                    eprintln!("Warning: cleanup failed for {}", error);
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_removes_only_old_artifacts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pages = temp_dir.path().join("pages");
+        let runs = temp_dir.path().join("runs");
+        std::fs::create_dir_all(pages.join("run-1/nested")).unwrap();
+        std::fs::create_dir_all(&runs).unwrap();
+        std::fs::write(pages.join("run-1/nested/page.png"), vec![0u8; 300]).unwrap();
+        std::fs::write(runs.join("parse-error-1.txt"), vec![b'x'; 40]).unwrap();
+        std::fs::write(runs.join("run-1.jsonl"), vec![b'x'; 40]).unwrap();
+
+        let now = SystemTime::now();
+        let fresh = remove_orphaned("pdf_pages", &pages, now).unwrap();
+        assert_eq!(fresh.removed, 0);
+
+        let later = now + ORPHAN_AGE + Duration::from_secs(60);
+        let orphaned = remove_orphaned("pdf_pages", &pages, later).unwrap();
+        assert_eq!(orphaned.removed, 1);
+        assert_eq!(orphaned.freed_bytes, 300);
+        assert!(!pages.join("run-1").exists());
+
+        let spilled = remove_spill_files(&runs, days(1), later + days(1)).unwrap();
+        assert_eq!(spilled.removed, 1);
+        assert_eq!(spilled.freed_bytes, 40);
+        assert!(runs.join("run-1.jsonl").exists());
+    }
+}
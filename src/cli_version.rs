@@ -0,0 +1,194 @@
+use crate::codex;
+use crate::raw_command;
+use crate::warm_up;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio::sync::OnceCell;
+
+/// `cli_version` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CliVersionConfig {
+    /// Oldest Codex CLI runs are allowed with, e.g. `0.44.0`
+    #[serde(default)]
+    pub min: Option<String>,
+    /// Versions whose JSONL output this server cannot parse
+    #[serde(default)]
+    pub incompatible: Vec<IncompatibleRange>,
+}
+
+impl CliVersionConfig {
+    fn enabled(&self) -> bool {
+        self.min.is_some() || !self.incompatible.is_empty()
+    }
+}
+
+/// Versions from `from` up to, but not including, `before`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IncompatibleRange {
+    pub from: String,
+    /// Open-ended when unset
+    #[serde(default)]
+    pub before: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// `major.minor.patch`; pre-release and build suffixes are ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(u64, u64, u64);
+
+impl Version {
+    /// The first version number in `text`, e.g. `0.44.0` in `codex-cli 0.44.0`
+    pub fn find(text: &str) -> Option<Self> {
+        text.split_whitespace().find_map(Self::parse)
+    }
+
+    fn parse(token: &str) -> Option<Self> {
+        let token = token.strip_prefix('v').unwrap_or(token);
+        let core = token.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+        let major = parts.next()??;
+        let minor = parts.next().unwrap_or(Some(0))?;
+        let patch = parts.next().unwrap_or(Some(0))?;
+        Some(Self(major, minor, patch))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Error returned instead of starting a run with an unsupported Codex CLI
+#[derive(Debug, Clone)]
+pub struct UnsupportedCliVersion {
+    pub found: String,
+    /// Configured minimum, when the CLI is older than it
+    pub minimum: Option<String>,
+    pub reason: String,
+}
+
+impl fmt::Display for UnsupportedCliVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "UnsupportedCliVersion: Codex CLI {} {}; install a supported version",
+            self.found, self.reason
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedCliVersion {}
+
+/// Check `version` against `config`. Malformed configured versions are
+/// ignored rather than refusing every run.
+pub fn check_version(
+    config: &CliVersionConfig,
+    version: Version,
+) -> Result<(), UnsupportedCliVersion> {
+    if let Some(min) = config.min.as_deref() {
+        if Version::find(min).is_some_and(|min| version < min) {
+            return Err(UnsupportedCliVersion {
+                found: version.to_string(),
+                minimum: Some(min.to_string()),
+                reason: format!("is older than the minimum supported version {}", min),
+            });
+        }
+    }
+    for range in &config.incompatible {
+        let Some(from) = Version::find(&range.from) else {
+            continue;
+        };
+        let before = range.before.as_deref().and_then(Version::find);
+        if version >= from && before.is_none_or(|before| version < before) {
+            return Err(UnsupportedCliVersion {
+                found: version.to_string(),
+                minimum: None,
+                reason: format!(
+                    "is known to be incompatible{}",
+                    range
+                        .reason
+                        .as_deref()
+                        .map(|reason| format!(": {}", reason))
+                        .unwrap_or_default()
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Version of the installed CLI, probed once per server. The warm-up's
+/// result is reused when it already ran.
+async fn installed() -> Option<Version> {
+    static INSTALLED: OnceCell<Option<Version>> = OnceCell::const_new();
+    *INSTALLED
+        .get_or_init(|| async {
+            if let Some(version) = warm_up::cli_version() {
+                return Version::find(&version);
+            }
+            let output = raw_command::run(&codex::codex_bin(), &["--version"])
+                .await
+                .ok()?;
+            if output.exit_code != Some(0) {
+                return None;
+            }
+            Version::find(&output.stdout)
+        })
+        .await
+}
+
+/// Refuse runs with an unsupported CLI when `cli_version` is configured. A
+/// CLI whose version cannot be read is let through; the run reports why it
+/// failed to start.
+pub async fn check(config: &CliVersionConfig) -> Result<(), UnsupportedCliVersion> {
+    if !config.enabled() {
+        return Ok(());
+    }
+    match installed().await {
+        Some(version) => check_version(config, version),
+        None => Ok(()),
+    }
+}
+
+/// Probe the CLI at startup when `cli_version` is configured, so an
+/// unsupported CLI is reported in the server log before the first run
+pub fn start(config: &'static CliVersionConfig) -> Option<tokio::task::JoinHandle<()>> {
+    if !config.enabled() {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        if let Err(e) = check(config).await {
+            eprintln!("Warning: {}", e);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checks_minimum_and_incompatible_ranges() {
+        assert_eq!(Version::find("codex-cli 0.44.1"), Some(Version(0, 44, 1)));
+        assert_eq!(Version::find("v1.2-beta"), Some(Version(1, 2, 0)));
+        assert_eq!(Version::find("codex-cli dev"), None);
+
+        let config = CliVersionConfig {
+            min: Some("0.40.0".to_string()),
+            incompatible: vec![IncompatibleRange {
+                from: "0.45.0".to_string(),
+                before: Some("0.45.3".to_string()),
+                reason: Some("item events lack ids".to_string()),
+            }],
+        };
+        let too_old = check_version(&config, Version(0, 39, 9)).unwrap_err();
+        assert_eq!(too_old.minimum.as_deref(), Some("0.40.0"));
+        assert!(too_old.to_string().contains("older than"));
+        let broken = check_version(&config, Version(0, 45, 2)).unwrap_err();
+        assert!(broken.to_string().contains("item events lack ids"));
+        assert!(check_version(&config, Version(0, 40, 0)).is_ok());
+        assert!(check_version(&config, Version(0, 45, 3)).is_ok());
+    }
+}
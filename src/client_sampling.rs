@@ -0,0 +1,389 @@
+use crate::codex::CodexResult;
+use crate::model_fallback;
+use crate::run_summary;
+use rmcp::model::CreateMessageRequestParam;
+use rmcp::schemars;
+use rmcp::service::Peer;
+use rmcp::RoleServer;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Characters of the prompt sent to the client's model
+const MAX_PROMPT_CHARS: usize = 2_000;
+
+/// Characters of the agent's answer sent to the client's model, split
+/// between its start and end
+const MAX_MESSAGE_CHARS: usize = 12_000;
+
+/// Characters of the run's error sent to the client's model
+const MAX_ERROR_CHARS: usize = 2_000;
+
+/// Characters of a title, locally derived or generated
+const MAX_TITLE_CHARS: usize = 60;
+
+/// Characters of a generated summary
+const MAX_SUMMARY_CHARS: usize = 300;
+
+const SYSTEM_PROMPT: &str = "You describe finished runs of the Codex coding agent for a chat \
+client. Reply with a single JSON object and nothing else: {\"title\": a title of at most 8 \
+words for the task, \"summary\": one sentence on the outcome, \"failure_kind\": for failed \
+runs one of \"timeout\", \"cancelled\", \"auth\", \"rate_limit\", \"model\", \"network\", \
+\"sandbox\", \"invalid_arguments\", \"crash\", \"task\", \"other\"; null for successful \
+runs}.";
+
+/// `client_sampling` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClientSamplingConfig {
+    /// Ask the client's model (MCP `sampling/createMessage`) for run titles,
+    /// summaries, and failure kinds when the client supports sampling
+    #[serde(default)]
+    pub enabled: bool,
+    /// Shorter answers of successful runs are described locally, which is
+    /// good enough for them
+    #[serde(default = "default_min_message_chars")]
+    pub min_message_chars: usize,
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: u32,
+    /// How long the client gets to answer before the local description is
+    /// used
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_min_message_chars() -> usize {
+    4_000
+}
+
+fn default_max_tokens() -> u32 {
+    400
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+impl Default for ClientSamplingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_message_chars: default_min_message_chars(),
+            max_tokens: default_max_tokens(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+/// Why a run failed, coarsely, so clients can decide whether to retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    Timeout,
+    Cancelled,
+    /// Not logged in, or the credentials were rejected
+    Auth,
+    RateLimit,
+    /// The requested model was unknown or unavailable
+    Model,
+    Network,
+    /// The sandbox blocked what the run needed
+    Sandbox,
+    /// The CLI rejected a flag or its value
+    InvalidArguments,
+    /// The Codex process died from a signal
+    Crash,
+    /// Codex ran, but did not accomplish the task
+    Task,
+    Other,
+}
+
+/// Title, summary, and failure kind of a finished run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Insights {
+    pub title: String,
+    pub summary: String,
+    pub failure_kind: Option<FailureKind>,
+    /// Model that wrote them, when the client did
+    pub generated_by: Option<String>,
+}
+
+/// Describe the run with local heuristics
+pub fn local(result: &CodexResult, prompt: &str) -> Insights {
+    Insights {
+        title: local_title(prompt),
+        summary: run_summary::summarize(result),
+        failure_kind: (!result.success).then(|| classify_failure(result)),
+        generated_by: None,
+    }
+}
+
+/// Describe the run with the client's own model when `client_sampling` is
+/// enabled, the client supports sampling, and the run is worth it (failed, or
+/// a long answer); otherwise, or when the client's answer is unusable, with
+/// local heuristics. Fields the client leaves out keep their local value.
+pub async fn insights(
+    peer: &Peer<RoleServer>,
+    config: &ClientSamplingConfig,
+    result: &CodexResult,
+    prompt: &str,
+) -> Insights {
+    let mut insights = local(result, prompt);
+    let worth_asking = !result.success || result.agent_messages.len() >= config.min_message_chars;
+    if !config.enabled || !worth_asking || !supported(peer) {
+        return insights;
+    }
+    let Some((model, reply)) = ask(peer, config, &describe(result, prompt, &insights)).await else {
+        return insights;
+    };
+    let Some(reply) = parse_reply(&reply) else {
+        return insights;
+    };
+    if let Some(title) = reply.title.filter(|title| !title.trim().is_empty()) {
+        insights.title = shorten(title.trim(), MAX_TITLE_CHARS);
+    }
+    if let Some(summary) = reply.summary.filter(|summary| !summary.trim().is_empty()) {
+        insights.summary = shorten(summary.trim(), MAX_SUMMARY_CHARS);
+    }
+    if !result.success {
+        insights.failure_kind = reply.failure_kind.or(insights.failure_kind);
+    }
+    insights.generated_by = Some(model);
+    insights
+}
+
+/// Whether the client advertised the `sampling` capability
+fn supported(peer: &Peer<RoleServer>) -> bool {
+    peer.peer_info()
+        .and_then(|info| serde_json::to_value(&info.capabilities).ok())
+        .is_some_and(|capabilities| !capabilities["sampling"].is_null())
+}
+
+/// Send `content` to the client's model; its name and text reply
+async fn ask(
+    peer: &Peer<RoleServer>,
+    config: &ClientSamplingConfig,
+    content: &str,
+) -> Option<(String, String)> {
+    // Built from JSON so the request follows the protocol's wire format
+    let request: CreateMessageRequestParam = serde_json::from_value(json!({
+        "messages": [{"role": "user", "content": {"type": "text", "text": content}}],
+        "systemPrompt": SYSTEM_PROMPT,
+        "includeContext": "none",
+        "maxTokens": config.max_tokens,
+        "modelPreferences": {"speedPriority": 0.8, "costPriority": 0.8, "intelligencePriority": 0.2},
+    }))
+    .ok()?;
+    let timeout = std::time::Duration::from_secs(config.timeout_secs);
+    let response = match tokio::time::timeout(timeout, peer.create_message(request)).await {
+        Ok(Ok(response)) => serde_json::to_value(&response).unwrap_or_default(),
+        Ok(Err(e)) => {
+            eprintln!("Warning: client sampling failed: {}", e);
+            return None;
+        }
+        Err(_) => {
+            eprintln!(
+                "Warning: client sampling timed out after {} seconds",
+                config.timeout_secs
+            );
+            return None;
+        }
+    };
+    let text = response["content"]["text"]
+        .as_str()
+        .or_else(|| response["message"]["content"]["text"].as_str())?;
+    let model = response["model"].as_str().unwrap_or("client").to_string();
+    Some((model, text.to_string()))
+}
+
+/// What the client's model is told about the run
+fn describe(result: &CodexResult, prompt: &str, local: &Insights) -> String {
+    let mut text = format!(
+        "Task prompt:\n{}\n\nOutcome: {}\nLocal summary: {}\n",
+        shorten(prompt, MAX_PROMPT_CHARS),
+        if result.success {
+            "succeeded"
+        } else {
+            "failed"
+        },
+        local.summary
+    );
+    if let Some(error) = &result.error {
+        text.push_str(&format!("\nError:\n{}\n", shorten(error, MAX_ERROR_CHARS)));
+    }
+    if !result.agent_messages.is_empty() {
+        text.push_str(&format!(
+            "\nAgent's answer:\n{}\n",
+            head_and_tail(&result.agent_messages, MAX_MESSAGE_CHARS)
+        ));
+    }
+    text
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Reply {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    summary: Option<String>,
+    /// Unknown kinds are ignored rather than failing the whole reply
+    #[serde(default, deserialize_with = "lenient_failure_kind")]
+    failure_kind: Option<FailureKind>,
+}
+
+fn lenient_failure_kind<'de, D>(deserializer: D) -> Result<Option<FailureKind>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).ok())
+}
+
+/// The JSON object in the model's reply, which may be wrapped in prose or a
+/// code fence
+fn parse_reply(text: &str) -> Option<Reply> {
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    serde_json::from_str(text.get(start..=end)?).ok()
+}
+
+/// First line of the prompt with content, cut at a word boundary
+fn local_title(prompt: &str) -> String {
+    let line = prompt
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('<'))
+        .unwrap_or("");
+    if line.chars().count() <= MAX_TITLE_CHARS {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(MAX_TITLE_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) if space > MAX_TITLE_CHARS / 2 => &cut[..space],
+        _ => &cut,
+    };
+    format!("{}…", cut.trim_end_matches([',', '.', ':', ';', ' ']))
+}
+
+/// Markers of each failure kind in the run's error, checked in order
+const FAILURE_MARKERS: &[(FailureKind, &[&str])] = &[
+    (FailureKind::Timeout, &["timed out"]),
+    (FailureKind::Cancelled, &["was cancelled"]),
+    (
+        FailureKind::Auth,
+        &[
+            "401 unauthorized",
+            "not logged in",
+            "codex login",
+            "invalid api key",
+        ],
+    ),
+    (
+        FailureKind::RateLimit,
+        &["429", "rate limit", "too many requests", "quota"],
+    ),
+    (
+        FailureKind::Network,
+        &[
+            "stream disconnected",
+            "connection",
+            "network",
+            "dns",
+            "error sending request",
+        ],
+    ),
+    (
+        FailureKind::Sandbox,
+        &["sandbox", "permission denied", "operation not permitted"],
+    ),
+    (
+        FailureKind::InvalidArguments,
+        &["unexpected argument", "unknown option", "invalid value for"],
+    ),
+];
+
+/// Failure kind from the run's error and termination
+pub fn classify_failure(result: &CodexResult) -> FailureKind {
+    if let Some(kind) = result.fatal_error {
+        return kind;
+    }
+    let error = result.error.as_deref().unwrap_or("");
+    if model_fallback::rejection(error).is_some() {
+        return FailureKind::Model;
+    }
+    let lowered = error.to_lowercase();
+    if let Some((kind, _)) = FAILURE_MARKERS
+        .iter()
+        .find(|(_, markers)| markers.iter().any(|marker| lowered.contains(marker)))
+    {
+        return *kind;
+    }
+    if result.termination.is_some() {
+        FailureKind::Crash
+    } else if !result.agent_messages.is_empty() {
+        FailureKind::Task
+    } else {
+        FailureKind::Other
+    }
+}
+
+fn shorten(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// `text` cut to about `max_chars`, keeping its start and its end, where
+/// answers usually conclude
+fn head_and_tail(text: &str, max_chars: usize) -> String {
+    let chars = text.chars().count();
+    if chars <= max_chars {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(max_chars / 2).collect();
+    let tail: String = text.chars().skip(chars - max_chars / 2).collect();
+    format!(
+        "{}\n[… {} characters left out …]\n{}",
+        head,
+        chars - max_chars,
+        tail
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_insights_and_reply_parsing() {
+        let result = CodexResult {
+            success: false,
+            error: Some("codex error: stream disconnected before completion".to_string()),
+            ..Default::default()
+        };
+        let insights = local(
+            &result,
+            "\n<primer_message index=\"1\">\n\nFix the flaky login test in the auth service, which times out on CI",
+        );
+        assert_eq!(
+            insights.title,
+            "Fix the flaky login test in the auth service, which times…"
+        );
+        assert_eq!(insights.failure_kind, Some(FailureKind::Network));
+        assert!(insights.summary.starts_with("Failed: "));
+
+        let result = CodexResult {
+            success: false,
+            error: Some("Codex execution timed out after 600 seconds".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(classify_failure(&result), FailureKind::Timeout);
+
+        let reply = parse_reply(
+            "```json\n{\"title\": \"Fix login test\", \"summary\": \"Tests pass.\", \"failure_kind\": \"bogus\"}\n```",
+        )
+        .unwrap();
+        assert_eq!(reply.title.as_deref(), Some("Fix login test"));
+        assert_eq!(reply.failure_kind, None);
+        assert!(parse_reply("no json here").is_none());
+    }
+}
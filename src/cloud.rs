@@ -0,0 +1,130 @@
+use crate::raw_command::{self, RawOutput};
+use serde::Serialize;
+
+/// Codex Cloud runs at most this many attempts per task
+pub const MAX_CLOUD_ATTEMPTS: u8 = 4;
+
+/// A task submitted with `codex cloud exec`
+#[derive(Debug, Serialize)]
+pub struct CloudTask {
+    pub environment: String,
+    /// Task id, taken from the task URL the CLI prints
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// CLI output, for when no task URL was recognized
+    pub output: RawOutput,
+}
+
+/// Submit `prompt` to the Codex Cloud environment `environment`
+pub async fn submit(
+    codex_bin: &str,
+    environment: &str,
+    attempts: Option<u8>,
+    prompt: &str,
+) -> Result<CloudTask, String> {
+    let attempts = attempts.unwrap_or(1);
+    if !(1..=MAX_CLOUD_ATTEMPTS).contains(&attempts) {
+        return Err(format!(
+            "CLOUD_ATTEMPTS must be between 1 and {}",
+            MAX_CLOUD_ATTEMPTS
+        ));
+    }
+    let environment = check_identifier("CLOUD_ENV", environment)?;
+    let attempts = attempts.to_string();
+    let args = [
+        "cloud",
+        "exec",
+        "--env",
+        environment,
+        "--attempts",
+        &attempts,
+        "--",
+        prompt,
+    ];
+    let mut output = supported(raw_command::run(codex_bin, &args).await?)?;
+    // Leave the prompt out of the echoed command
+    output.command = args[..6].join(" ");
+    let url = task_url(&output.stdout).map(str::to_string);
+    Ok(CloudTask {
+        environment: environment.to_string(),
+        task_id: url
+            .as_deref()
+            .and_then(|url| url.rsplit('/').next())
+            .map(str::to_string),
+        url,
+        output,
+    })
+}
+
+/// `codex cloud status <task_id>`
+pub async fn status(codex_bin: &str, task_id: &str) -> Result<RawOutput, String> {
+    let task_id = check_identifier("task_id", task_id)?;
+    supported(raw_command::run(codex_bin, &["cloud", "status", task_id]).await?)
+}
+
+/// Environment names and task ids are passed as CLI arguments, so they must
+/// not look like flags
+fn check_identifier<'a>(name: &str, value: &'a str) -> Result<&'a str, String> {
+    let value = value.trim();
+    let valid = !value.is_empty()
+        && !value.starts_with('-')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | ':'));
+    if valid {
+        Ok(value)
+    } else {
+        Err(format!("invalid {}: {:?}", name, value))
+    }
+}
+
+/// Refuse output from CLIs that do not have the `cloud` subcommand
+fn supported(output: RawOutput) -> Result<RawOutput, String> {
+    let unsupported = output.exit_code != Some(0)
+        && (output.stderr.contains("unrecognized subcommand")
+            || output.stderr.contains("unexpected argument"));
+    if unsupported {
+        return Err(format!(
+            "this Codex CLI does not support `codex {}`; upgrade the CLI to use Codex Cloud ({})",
+            output
+                .command
+                .split(" --")
+                .next()
+                .unwrap_or(&output.command),
+            output.stderr.lines().next().unwrap_or("").trim()
+        ));
+    }
+    Ok(output)
+}
+
+/// First `https://` URL in the CLI output
+fn task_url(stdout: &str) -> Option<&str> {
+    stdout
+        .split_whitespace()
+        .find(|word| word.starts_with("https://"))
+        .map(|url| url.trim_end_matches(['.', ',', ')']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_url() {
+        assert_eq!(
+            task_url("Submitted task: https://chatgpt.com/codex/tasks/task_e_123abc.\n"),
+            Some("https://chatgpt.com/codex/tasks/task_e_123abc")
+        );
+        assert_eq!(task_url("no link here"), None);
+    }
+
+    #[test]
+    fn test_check_identifier() {
+        assert_eq!(check_identifier("CLOUD_ENV", " env_1 ").unwrap(), "env_1");
+        assert!(check_identifier("CLOUD_ENV", "--yolo").is_err());
+        assert!(check_identifier("task_id", "task 1").is_err());
+        assert!(check_identifier("task_id", "").is_err());
+    }
+}
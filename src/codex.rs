@@ -1,18 +1,78 @@
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
 use std::path::PathBuf;
 use std::process::Stdio;
-use std::sync::OnceLock;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::sync::{Mutex, OnceLock};
+use tokio::io::BufReader;
 use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
-#[derive(Debug, Clone)]
+use crate::agents_sections;
+use crate::at_rest::{self, AgeKey, EncryptionConfig};
+use crate::child_wait::{self, OwnedChild};
+use crate::classify::{self, TaskKind};
+use crate::cli_version::{self, CliVersionConfig};
+use crate::client_sampling::{ClientSamplingConfig, FailureKind};
+use crate::codex_home;
+use crate::consensus::ConsensusProfile;
+use crate::context_budget::{self, ContextTracker};
+use crate::disk_guard::{self, DiskGuardConfig};
+use crate::explore::ExploreFirstConfig;
+use crate::failure_screenshot::FailureScreenshotConfig;
+use crate::fatal_error;
+use crate::files_read::{self, FilesRead};
+use crate::final_schema::{self, SchemaFile};
+use crate::fs_watch::{self, FsChanges};
+use crate::git_info;
+use crate::history::{self, Invocation, RunRecord};
+use crate::idempotency;
+use crate::identities::Identity;
+use crate::image_preprocess::{self, ImagePreprocessConfig};
+use crate::language;
+use crate::lossy_utf8::{self, LossyUtf8};
+use crate::model_fallback::{self, ModelFallback};
+use crate::next_steps::{self, NextStepsExtraction};
+use crate::output_budget::{OutputBudget, OutputBudgetConfig};
+use crate::parser::{self, read_line_with_limit, Line};
+use crate::path_denylist::DEFAULT_SENSITIVE_PATHS;
+use crate::path_rewrite::PathRewriter;
+use crate::pdf_pages::{self, PdfPages};
+use crate::plan::{self, PlanStep, PlanTracker, PLAN_TOOL_FLAG};
+use crate::progress::{ProgressConfig, ProgressEstimator, ProgressSender, ProgressUpdate};
+use crate::prompt_budget::{self, BudgetTrim};
+use crate::prompt_paths;
+use crate::prompt_secrets::PromptSecrets;
+use crate::provenance::{self, Provenance};
+use crate::redact::{RedactionConfig, Redactor};
+use crate::review_export::GithubReviewConfig;
+use crate::run_diff::{self, RunDiff, DEFAULT_MAX_INLINE_DIFF_BYTES};
+use crate::run_log::{self, Compression, LogTarget, RetentionConfig, RunLog};
+use crate::run_tail::{self, Tail};
+use crate::rusage::ResourceUsage;
+use crate::sanitize;
+use crate::schedules::{ScheduleConfig, UtcTime};
+use crate::session_expiry::{self, SessionExpiryConfig};
+use crate::session_import;
+use crate::tenants::ApiKeyConfig;
+use crate::termination::Termination;
+use crate::tool_aliases::ToolAlias;
+use crate::trust::TrustConfig;
+use crate::workspace_lock::{self, LockPolicy};
+use crate::write_scope::{self, ScopePolicy};
+
+#[derive(Debug, Clone, Default)]
 pub struct Options {
     pub prompt: String,
     pub working_dir: PathBuf,
     pub session_id: Option<String>,
+    /// Resume the most recent Codex session (`codex exec resume --last`) instead
+    /// of `session_id`.
+    pub resume_last: bool,
     /// Extra CLI flags to pass through to the Codex CLI.
     pub additional_args: Vec<String>,
     /// Image files to attach to the Codex prompt.
@@ -20,17 +80,524 @@ pub struct Options {
     /// Timeout in seconds for the codex execution. If None, defaults to 600 seconds (10 minutes).
     /// Set to a specific value to override. The library enforces a timeout to prevent unbounded execution.
     pub timeout_secs: Option<u64>,
+    /// `CODEX_HOME` for the child process. If None, the child inherits the server's
+    /// environment. With `isolated_codex_home` configured, the child gets a throwaway
+    /// home copied from this directory (or from the configured template when None).
+    pub codex_home: Option<PathBuf>,
+    /// Tee the raw JSONL stream from Codex to this file as it arrives.
+    pub log_to_file: Option<LogTarget>,
+    /// Globs (relative to `working_dir`) the run may change; empty means no restriction.
+    pub allowed_paths: Vec<String>,
+    /// What started the run, as shown in run history. Defaults to `codex`.
+    pub source: Option<String>,
+    /// Receives a message for each event matched by the `progress` rules.
+    pub progress: Option<ProgressSender>,
+    /// Return the diff of the files the run changed. Requires a git repository.
+    pub return_diff: bool,
+    /// Groups related runs in run history; see `history::task_summary`.
+    pub task_id: Option<String>,
+    /// Stops the run when cancelled: the Codex process is killed and the run
+    /// fails with a cancellation error.
+    pub cancel: Option<CancellationToken>,
+    /// JSON Schema the final agent message must match. Passed to the CLI as
+    /// `--output-schema` and checked again once the run ends; see
+    /// `CodexResult::final_value`.
+    pub final_schema: Option<Value>,
+    /// The tool call's MCP `_meta` (e.g. trace or user ids), kept in run
+    /// history and at the top of the run log to correlate the run with the
+    /// request that caused it
+    pub meta: Option<Value>,
+    /// Run this one repeats, recorded in run history (see `codex_rerun`)
+    pub rerun_of: Option<String>,
+    /// Role or context messages placed before the prompt of a new session,
+    /// each in its own delimited block
+    pub primer_messages: Vec<String>,
+    /// Language the final answer must be in (an ISO 639-1 code or English
+    /// name); overrides `response_language` from the config
+    pub response_language: Option<String>,
+    /// Pass `--include-plan-tool`, so Codex keeps a plan that is streamed as
+    /// progress and returned as `plan`. Turned on for every run when
+    /// `include_plan_tool` is configured and the CLI offers the flag.
+    pub include_plan_tool: bool,
+    /// Redact the run log even when `redaction.enabled` is off. Turned on
+    /// when the prompt contains a secret.
+    pub redact_log: bool,
 }
 
 const DEFAULT_TIMEOUT_SECS: u64 = 600;
-const MAX_TIMEOUT_SECS: u64 = 3600;
+
+/// The stdout loop yields to the runtime at least this often, so a flood of
+/// output cannot starve other tasks on the same worker
+const PARSE_TIME_SLICE: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// How long stdout and stderr may stay silent after the process exits before
+/// they are abandoned; anything the process started can keep them open
+const EXIT_DRAIN_GRACE: std::time::Duration = std::time::Duration::from_millis(200);
+pub(crate) const MAX_TIMEOUT_SECS: u64 = 3600;
+
+/// `sampling_config_keys` section of `codex-mcp.config.json`. Sampling controls
+/// depend on the model provider, so the target keys are not guessed: a
+/// parameter whose key is unset is refused.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SamplingConfigKeys {
+    #[serde(default)]
+    pub temperature: Option<String>,
+    #[serde(default)]
+    pub seed: Option<String>,
+}
+
+/// `run_environment` section of `codex-mcp.config.json`: locale and time
+/// context for runs, instead of whatever the server process inherited
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RunEnvironment {
+    /// `LANG` for the Codex process, e.g. `en_US.UTF-8`
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// `TZ` for the Codex process, e.g. `Europe/Warsaw`
+    #[serde(default)]
+    pub tz: Option<String>,
+    /// Start each prompt with the current date and time
+    #[serde(default)]
+    pub timestamp_in_prompt: bool,
+}
+
+impl RunEnvironment {
+    fn apply(&self, cmd: &mut Command) {
+        if let Some(lang) = &self.lang {
+            cmd.env("LANG", lang);
+        }
+        if let Some(tz) = &self.tz {
+            cmd.env("TZ", tz);
+        }
+    }
+
+    /// Line prepended to prompts when `timestamp_in_prompt` is set
+    fn preamble(&self, unix_secs: u64) -> Option<String> {
+        if !self.timestamp_in_prompt {
+            return None;
+        }
+        let now = UtcTime::from_unix(unix_secs).human();
+        Some(match &self.tz {
+            Some(tz) => format!("Current date and time: {} (user time zone: {})", now, tz),
+            None => format!("Current date and time: {}", now),
+        })
+    }
+
+    fn prepend_preamble(&self, prompt: &mut String) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        if let Some(preamble) = self.preamble(now) {
+            *prompt = format!("{}\n\n{}", preamble, prompt);
+        }
+    }
+}
+
+/// Which warnings reach the client, configured via `client_warning_level` in
+/// `codex-mcp.config.json`; the rest are only written to the server's stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WarningLevel {
+    /// Caller and operator warnings
+    #[default]
+    All,
+    /// Caller warnings only
+    Client,
+    /// No warnings
+    None,
+}
+
+/// What to do when a run creates a session but returns no agent messages, a
+/// known Codex CLI flake; configured via `retry_on_empty_response` in
+/// `codex-mcp.config.json`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyResponseRetry {
+    /// Return the empty result with a warning
+    #[default]
+    Off,
+    /// Run the same prompt once more in a new session
+    Resubmit,
+    /// Resume the empty session once, asking it to answer
+    Resume,
+}
+
+/// How AGENTS.md reaches Codex, configured via `agents_md_mode` in
+/// `codex-mcp.config.json`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentsMdMode {
+    /// Inline the file in the prompt, truncated to 1 MiB
+    #[default]
+    Inline,
+    /// Tell Codex to read the file itself
+    Reference,
+    /// Inline files that fit, reference larger ones
+    Auto,
+}
+
+/// Sandbox policy in effect for a Codex run, inferred from CLI flags. Ordered
+/// from least to most permissive.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Deserialize,
+    Serialize,
+    rmcp::schemars::JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum SandboxMode {
+    #[default]
+    ReadOnly,
+    WorkspaceWrite,
+    DangerFullAccess,
+}
+
+impl SandboxMode {
+    /// Infer the effective sandbox from Codex CLI flags. `codex exec` runs
+    /// read-only unless told otherwise; when flags conflict, the last one wins.
+    pub fn from_args(args: &[String]) -> Self {
+        let mut mode = SandboxMode::ReadOnly;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let value = match arg.as_str() {
+                "--yolo" | "--dangerously-bypass-approvals-and-sandbox" => {
+                    mode = SandboxMode::DangerFullAccess;
+                    continue;
+                }
+                "--full-auto" => {
+                    mode = SandboxMode::WorkspaceWrite;
+                    continue;
+                }
+                "--sandbox" | "-s" => iter.next().map(String::as_str),
+                "-c" | "--config" => iter.next().and_then(|value| sandbox_override(value)),
+                other => other.strip_prefix("--sandbox="),
+            };
+            if let Some(parsed) = value.and_then(Self::parse) {
+                mode = parsed;
+            }
+        }
+        mode
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "read-only" => Some(SandboxMode::ReadOnly),
+            "workspace-write" => Some(SandboxMode::WorkspaceWrite),
+            "danger-full-access" => Some(SandboxMode::DangerFullAccess),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SandboxMode::ReadOnly => "read-only",
+            SandboxMode::WorkspaceWrite => "workspace-write",
+            SandboxMode::DangerFullAccess => "danger-full-access",
+        }
+    }
+
+    /// Whether runs in this mode may modify the workspace
+    pub fn is_write(&self) -> bool {
+        *self != SandboxMode::ReadOnly
+    }
+}
+
+/// Replace every sandbox-selecting flag in `args` with `--sandbox <mode>`
+pub fn with_sandbox(args: &[String], mode: SandboxMode) -> Vec<String> {
+    let mut result = Vec::with_capacity(args.len() + 2);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--yolo" | "--dangerously-bypass-approvals-and-sandbox" | "--full-auto" => {}
+            "--sandbox" | "-s" => {
+                iter.next();
+            }
+            other if other.starts_with("--sandbox=") => {}
+            "-c" | "--config" => match iter.next() {
+                Some(value) if sandbox_override(value).is_some() => {}
+                value => {
+                    result.push(arg.clone());
+                    result.extend(value.cloned());
+                }
+            },
+            _ => result.push(arg.clone()),
+        }
+    }
+    result.push("--sandbox".to_string());
+    result.push(mode.as_str().to_string());
+    result
+}
+
+/// Mode set by a `-c sandbox_mode=...` config override
+fn sandbox_override(value: &str) -> Option<&str> {
+    let mode = value.strip_prefix("sandbox_mode=")?;
+    Some(mode.trim_matches('"'))
+}
+
+/// A sandbox request lowered to `sandbox_ceiling`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, rmcp::schemars::JsonSchema)]
+pub struct SandboxDowngrade {
+    pub requested: SandboxMode,
+    pub applied: SandboxMode,
+}
+
+/// Lower the sandbox `args` select to `ceiling`, if it is above it
+pub fn cap_sandbox(args: &mut Vec<String>, ceiling: SandboxMode) -> Option<SandboxDowngrade> {
+    let requested = SandboxMode::from_args(args);
+    if requested <= ceiling {
+        return None;
+    }
+    *args = with_sandbox(args, ceiling);
+    Some(SandboxDowngrade {
+        requested,
+        applied: ceiling,
+    })
+}
+
+/// Default bytes of agent messages kept from the start of an oversized run
+const DEFAULT_HEAD_BYTES: usize = 8 * 1024 * 1024;
+/// Default length of the output line quoted in a JSON parse error
+const DEFAULT_PARSE_ERROR_EXCERPT_BYTES: usize = 512;
+/// Default bytes of agent messages kept from the end of an oversized run
+const DEFAULT_TAIL_BYTES: usize = 2 * 1024 * 1024;
+
+/// `agent_messages_truncation` section of `codex-mcp.config.json`. Once agent
+/// messages exceed `head_bytes + tail_bytes`, the middle is elided so the
+/// conclusion (summary, next steps) survives.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TruncationConfig {
+    #[serde(default = "default_head_bytes")]
+    pub head_bytes: usize,
+    #[serde(default = "default_tail_bytes")]
+    pub tail_bytes: usize,
+}
+
+fn default_head_bytes() -> usize {
+    DEFAULT_HEAD_BYTES
+}
+
+fn default_tail_bytes() -> usize {
+    DEFAULT_TAIL_BYTES
+}
+
+impl Default for TruncationConfig {
+    fn default() -> Self {
+        Self {
+            head_bytes: DEFAULT_HEAD_BYTES,
+            tail_bytes: DEFAULT_TAIL_BYTES,
+        }
+    }
+}
 
 /// Configuration loaded from `codex-mcp.config.json` (or `CODEX_MCP_CONFIG_PATH`).
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 struct ServerConfig {
     #[serde(default)]
     additional_args: Vec<String>,
+    /// Most permissive sandbox any run may use; requests above it, from
+    /// `additional_args` or per call, are lowered to it
+    sandbox_ceiling: Option<SandboxMode>,
     timeout_secs: Option<u64>,
+    /// How concurrent write-mode runs on the same workspace are handled
+    #[serde(default)]
+    workspace_lock_policy: LockPolicy,
+    /// Optional downscaling/re-encoding of attached images (requires the
+    /// `image-preprocess` feature)
+    #[serde(default)]
+    image_preprocessing: Option<ImagePreprocessConfig>,
+    /// Run each Codex invocation with a private, throwaway `CODEX_HOME`
+    #[serde(default)]
+    isolated_codex_home: bool,
+    /// Directory copied into each isolated `CODEX_HOME` (defaults to
+    /// `$CODEX_HOME` or `~/.codex`)
+    codex_home_template: Option<PathBuf>,
+    /// Render the output tail of failed runs as an image (requires the
+    /// `failure-screenshot` feature)
+    #[serde(default)]
+    failure_screenshot: Option<FailureScreenshotConfig>,
+    /// How much of oversized agent output is kept
+    #[serde(default)]
+    agent_messages_truncation: TruncationConfig,
+    /// How `next_steps` are derived from agent messages
+    #[serde(default)]
+    next_steps_extraction: NextStepsExtraction,
+    /// Whether AGENTS.md is inlined in the prompt or read by Codex itself
+    #[serde(default)]
+    agents_md_mode: AgentsMdMode,
+    /// Directory for auto-named `LOG_TO_FILE` run logs (defaults to
+    /// `<temp>/codex-mcp-runs`)
+    runs_dir: Option<PathBuf>,
+    /// Compression for auto-named run logs
+    #[serde(default)]
+    run_log_compression: Compression,
+    /// Age and size limits for auto-named run logs in `runs_dir`
+    #[serde(default)]
+    runs_retention: RetentionConfig,
+    /// age encryption for run logs, stored diffs, and spilled output lines
+    #[serde(default)]
+    encryption: EncryptionConfig,
+    /// Run `codex_cleanup` this often in the background (never when unset)
+    cleanup_interval_secs: Option<u64>,
+    /// Posting review findings to GitHub pull requests
+    #[serde(default)]
+    github_review: GithubReviewConfig,
+    /// Confirmation required before working in a directory for the first time
+    #[serde(default)]
+    workspace_trust: TrustConfig,
+    /// When resuming a session is refused as expired
+    #[serde(default)]
+    session_expiry: SessionExpiryConfig,
+    /// Codex CLI versions runs are refused with
+    #[serde(default)]
+    cli_version: CliVersionConfig,
+    /// Run titles, summaries, and failure kinds written by the client's model
+    #[serde(default)]
+    client_sampling: ClientSamplingConfig,
+    /// What happens to changes outside a run's `ALLOWED_PATHS`
+    #[serde(default)]
+    out_of_scope_changes: ScopePolicy,
+    /// Models/profiles queried by `codex_consensus`
+    #[serde(default)]
+    consensus_profiles: Vec<ConsensusProfile>,
+    /// Codex config keys callers may set via `CONFIG_OVERRIDES` (defaults to
+    /// `DEFAULT_CONFIG_OVERRIDE_KEYS`). An entry ending in `.*` allows every key
+    /// under that table.
+    config_override_keys: Option<Vec<String>>,
+    /// Drop repeated `agent_message` items (default: true)
+    dedup_agent_messages: Option<bool>,
+    /// Strip ANSI escapes and spinner frames from agent messages (default:
+    /// true); turn off for clients that render ANSI
+    sanitize_agent_messages: Option<bool>,
+    /// Watch the working directory during write runs and report files that
+    /// changed on disk without a reported patch (default: false, Linux only)
+    watch_workspace: Option<bool>,
+    /// Show paths under the working directory as workspace-relative, and
+    /// other paths under the home directory under `~`, in run output
+    /// (default: true)
+    workspace_relative_paths: Option<bool>,
+    /// Language answers must be in, e.g. `de`; checked after each run, which
+    /// is resumed once to ask again when the answer drifted to another one
+    response_language: Option<String>,
+    /// Give runs Codex's plan tool when the CLI offers it (default: true)
+    include_plan_tool: Option<bool>,
+    /// Retry a run whose requested model the CLI rejects (default: true)
+    model_fallback: Option<bool>,
+    /// Model of those retries; without it, Codex picks its configured default
+    fallback_model: Option<String>,
+    /// Runs the server starts on its own, on a cron schedule
+    #[serde(default)]
+    schedules: Vec<ScheduleConfig>,
+    /// Extra tools that run `codex` with preset parameters
+    #[serde(default)]
+    tool_aliases: Vec<ToolAlias>,
+    /// Tools hidden from clients, e.g. `codex_raw`; re-read when the config
+    /// file changes
+    #[serde(default)]
+    disabled_tools: Vec<String>,
+    /// Codex accounts a run can select with `IDENTITY`
+    #[serde(default)]
+    identities: Vec<Identity>,
+    /// Free-space and growth limits for write-mode runs
+    #[serde(default)]
+    disk_guard: DiskGuardConfig,
+    /// Longest excerpt of an unparsable output line quoted in `error`
+    parse_error_excerpt_bytes: Option<usize>,
+    /// Bytes of Codex output kept per run, split between stdout events and
+    /// stderr
+    #[serde(default)]
+    output_budget: OutputBudgetConfig,
+    /// Time box and approval of the read-only phase of `EXPLORE_FIRST` runs
+    #[serde(default)]
+    explore_first: ExploreFirstConfig,
+    /// Path patterns refused as attachments (defaults to
+    /// `DEFAULT_SENSITIVE_PATHS`); set to `[]` to disable the denylist
+    sensitive_paths: Option<Vec<String>>,
+    /// Which warnings are returned to clients
+    #[serde(default)]
+    client_warning_level: WarningLevel,
+    /// Warn about repository-relative paths in the prompt that do not exist
+    #[serde(default)]
+    check_prompt_paths: bool,
+    /// Codex config keys that `TEMPERATURE` and `SEED` are written to
+    #[serde(default)]
+    sampling_config_keys: SamplingConfigKeys,
+    /// Secret redaction for run logs and run history
+    #[serde(default)]
+    redaction: RedactionConfig,
+    /// Locale, time zone, and timestamp context for runs
+    #[serde(default)]
+    run_environment: RunEnvironment,
+    /// Byte budget for AGENTS.md, the prompt, and attached images combined;
+    /// attachments and then the end of AGENTS.md are trimmed to fit
+    prompt_budget_bytes: Option<usize>,
+    /// Refuse runs whose prompt, AGENTS.md, attachments, and server-added
+    /// text exceed this many bytes after preprocessing and `prompt_budget_bytes`
+    max_prompt_bytes: Option<usize>,
+    /// Return `resource_usage` (CPU time, peak memory) in `codex` results
+    #[serde(default)]
+    include_resource_usage: bool,
+    /// Reject `codex` calls with unrecognized arguments instead of ignoring them
+    #[serde(default)]
+    strict_arguments: bool,
+    /// Let `codex_pipeline` steps run `verify` shell commands on the host
+    #[serde(default)]
+    allow_verify_commands: bool,
+    /// Expose the `codex_raw` troubleshooting tool to clients
+    #[serde(default)]
+    enable_codex_raw: bool,
+    /// Clients allowed over HTTP, each with its own limits
+    #[serde(default)]
+    api_keys: Vec<ApiKeyConfig>,
+    /// Which Codex events become progress notifications, and how
+    #[serde(default)]
+    progress: ProgressConfig,
+    /// `RETURN_DIFF` diffs larger than this are stored in `runs_dir` instead
+    /// of returned (defaults to `DEFAULT_MAX_INLINE_DIFF_BYTES`)
+    max_inline_diff_bytes: Option<usize>,
+    /// Check the Codex binary and login in the background at startup
+    #[serde(default)]
+    warm_up: bool,
+    /// Largest `codex` response, in bytes; bigger responses are downgraded
+    /// until they fit
+    max_response_bytes: Option<usize>,
+    /// Return `codex` results as readable text (summary and agent message)
+    /// with the full output as structured content, for clients that only
+    /// render text
+    #[serde(default)]
+    dual_output: bool,
+    /// Retry runs that return no agent messages
+    #[serde(default)]
+    retry_on_empty_response: EmptyResponseRetry,
+    /// Replacement characters (for invalid UTF-8 in Codex's output) a run
+    /// may contain before it gets a warning
+    lossy_utf8_warning_threshold: Option<usize>,
+    /// Pages of attached PDFs rendered as images per run
+    pdf_max_pages: Option<usize>,
+    /// Seconds a successful run's result is replayed for a repeated
+    /// `IDEMPOTENCY_KEY`
+    idempotency_ttl_secs: Option<u64>,
+    /// Context window of the model, in tokens, for CLI versions whose stream
+    /// does not report it
+    context_window_tokens: Option<u64>,
+    /// Share of the context window, in percent, at which a resumed session
+    /// gets a warning to start over
+    context_warning_percent: Option<u8>,
+    /// Config file path that was searched (from env or the current directory)
+    #[serde(skip)]
+    config_path: Option<PathBuf>,
+    /// Whether `config_path` was found and parsed successfully
+    #[serde(skip)]
+    config_loaded: bool,
+    /// Read or parse error for `config_path`, if any
+    #[serde(skip)]
+    config_error: Option<String>,
 }
 
 fn resolve_config_path() -> Option<PathBuf> {
@@ -48,20 +615,18 @@ fn resolve_config_path() -> Option<PathBuf> {
 }
 
 fn load_server_config() -> ServerConfig {
-    let mut cfg = ServerConfig {
-        additional_args: Vec::new(),
-        timeout_secs: None,
-    };
+    let mut cfg = ServerConfig::default();
 
     let Some(config_path) = resolve_config_path() else {
         return cfg;
     };
+    cfg.config_path = Some(config_path.clone());
 
     if !config_path.is_file() {
         return cfg;
     }
 
-    match std::fs::read_to_string(&config_path) {
+    let error = match std::fs::read_to_string(&config_path) {
         Ok(raw) => match serde_json::from_str::<ServerConfig>(&raw) {
             Ok(parsed) => {
                 let mut cleaned = parsed;
@@ -71,25 +636,17 @@ fn load_server_config() -> ServerConfig {
                     .map(|s| s.trim().to_string())
                     .filter(|s| !s.is_empty())
                     .collect();
-                cfg = cleaned;
-            }
-            Err(err) => {
-                eprintln!(
-                    "codex-mcp-rs: failed to parse config {}: {}",
-                    config_path.display(),
-                    err
-                );
+                cleaned.config_path = Some(config_path);
+                cleaned.config_loaded = true;
+                return cleaned;
             }
+            Err(err) => format!("failed to parse config {}: {}", config_path.display(), err),
         },
-        Err(err) => {
-            eprintln!(
-                "codex-mcp-rs: failed to read config {}: {}",
-                config_path.display(),
-                err
-            );
-        }
-    }
+        Err(err) => format!("failed to read config {}: {}", config_path.display(), err),
+    };
 
+    eprintln!("codex-mcp-rs: {}", error);
+    cfg.config_error = Some(error);
     cfg
 }
 
@@ -105,6 +662,19 @@ pub fn default_additional_args() -> Vec<String> {
     server_config().additional_args.clone()
 }
 
+pub fn sandbox_ceiling() -> Option<SandboxMode> {
+    server_config().sandbox_ceiling
+}
+
+/// Whether runs with a rejected model are retried with `fallback_model`
+pub fn model_fallback_enabled() -> bool {
+    server_config().model_fallback.unwrap_or(true)
+}
+
+pub fn fallback_model() -> Option<&'static str> {
+    server_config().fallback_model.as_deref()
+}
+
 /// Default timeout (in seconds) for Codex runs, configurable via
 /// `timeout_secs` in `codex-mcp.config.json`. Values <= 0 or missing
 /// fall back to 600; values above MAX_TIMEOUT_SECS are clamped.
@@ -120,217 +690,1526 @@ pub fn default_timeout_secs() -> u64 {
     })
 }
 
-#[derive(Debug)]
-pub struct CodexResult {
-    pub success: bool,
-    pub session_id: String,
-    pub agent_messages: String,
-    pub agent_messages_truncated: bool,
-    pub all_messages: Vec<HashMap<String, Value>>,
-    pub all_messages_truncated: bool,
-    pub error: Option<String>,
-    pub warnings: Option<String>,
+/// Failure screenshot settings, if enabled via `failure_screenshot` in
+/// `codex-mcp.config.json`.
+pub fn failure_screenshot_config() -> Option<&'static FailureScreenshotConfig> {
+    server_config().failure_screenshot.as_ref()
 }
 
-/// Result of reading a line with length limit
-#[derive(Debug)]
-struct ReadLineResult {
-    bytes_read: usize,
-    truncated: bool,
+/// Policy for concurrent write-mode runs, configured via `workspace_lock_policy`
+/// in `codex-mcp.config.json`.
+pub fn workspace_lock_policy() -> LockPolicy {
+    server_config().workspace_lock_policy
 }
 
-/// Validation mode for enforce_required_fields
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ValidationMode {
-    /// Perform full validation (check session_id and agent_messages)
-    Full,
-    /// Skip validation (for cases with well-defined errors like timeout or truncation)
-    Skip,
+pub fn client_warning_level() -> WarningLevel {
+    server_config().client_warning_level
 }
 
-/// Read a line from an async buffered reader with a maximum length limit to prevent memory spikes
-/// Returns the number of bytes read (0 on EOF) and whether the line was truncated
-/// Reads in chunks and enforces max_len during reading to prevent OOM from extremely long lines
-///
-/// After hitting max_len, continues reading until newline to properly consume the full line.
-/// This ensures the next read starts at the correct position. For subprocess stdout (our use case),
-/// this is appropriate because:
-/// 1. The Codex CLI always outputs newline-terminated JSON
-/// 2. Process-level timeout prevents indefinite blocking
-/// 3. We stop allocating memory once max_len is hit, preventing OOM
-async fn read_line_with_limit<R: AsyncBufReadExt + Unpin>(
-    reader: &mut R,
-    buf: &mut Vec<u8>,
-    max_len: usize,
-) -> std::io::Result<ReadLineResult> {
-    let mut total_read = 0;
-    let mut truncated = false;
-
-    loop {
-        // Fill the internal buffer if needed
-        let available = reader.fill_buf().await?;
-        if available.is_empty() {
-            break; // EOF
-        }
-
-        // Process available bytes
-        for (i, &byte) in available.iter().enumerate() {
-            if !truncated && buf.len() < max_len {
-                buf.push(byte);
-                total_read += 1;
-            } else if !truncated {
-                truncated = true;
-            }
-
-            if byte == b'\n' {
-                reader.consume(i + 1);
-                return Ok(ReadLineResult {
-                    bytes_read: total_read,
-                    truncated,
-                });
-            }
+/// Redactor built from `redaction.patterns`, whether or not redaction is enabled
+pub fn configured_redactor() -> &'static Redactor {
+    static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+    REDACTOR.get_or_init(|| {
+        let (redactor, errors) = Redactor::new(&server_config().redaction.patterns);
+        for error in errors {
+            eprintln!("codex-mcp-rs: {}", error);
         }
-
-        let consumed = available.len();
-        reader.consume(consumed);
-    }
-
-    Ok(ReadLineResult {
-        bytes_read: total_read,
-        truncated,
+        redactor
     })
 }
 
-/// Maximum allowed size for AGENTS.md content (1MB)
-const MAX_AGENTS_SIZE: usize = 1024 * 1024;
+/// Redactor to apply before run logs and history are stored, if enabled
+pub fn storage_redactor() -> Option<&'static Redactor> {
+    server_config().redaction.enabled.then(configured_redactor)
+}
 
-/// Read AGENTS.md from working directory if it exists
-/// Returns (content, warning) where warning is set if there are issues
-async fn read_agents_md(working_dir: &std::path::Path) -> (Option<String>, Option<String>) {
-    let agents_path = working_dir.join("AGENTS.md");
+pub fn include_resource_usage() -> bool {
+    server_config().include_resource_usage
+}
 
-    if !agents_path.exists() {
-        return (None, None);
-    }
+pub fn strict_arguments() -> bool {
+    server_config().strict_arguments
+}
 
-    // Check file size first to avoid allocating huge strings
-    let metadata = match tokio::fs::metadata(&agents_path).await {
-        Ok(m) => m,
-        Err(e) => {
-            let warning = format!("Failed to read AGENTS.md metadata: {}", e);
-            return (None, Some(warning));
-        }
-    };
+pub fn allow_verify_commands() -> bool {
+    server_config().allow_verify_commands
+}
 
-    let file_size = metadata.len(); // Keep as u64 to avoid overflow
+/// Whether operators enabled the `codex_raw` tool via `enable_codex_raw`
+pub fn codex_raw_enabled() -> bool {
+    server_config().enable_codex_raw
+}
 
-    // If file is extremely large, warn and skip to avoid OOM
-    const ABSOLUTE_MAX_SIZE: u64 = 10 * 1024 * 1024; // 10MB hard limit
-    if file_size > ABSOLUTE_MAX_SIZE {
-        let warning = format!(
-            "AGENTS.md is {} bytes, exceeding the absolute maximum of {} bytes and will be skipped.",
-            file_size,
-            ABSOLUTE_MAX_SIZE
-        );
-        return (None, Some(warning));
+/// Patterns of files that may not be attached to a run
+pub fn sensitive_paths() -> Vec<String> {
+    match &server_config().sensitive_paths {
+        Some(patterns) => patterns.clone(),
+        None => DEFAULT_SENSITIVE_PATHS
+            .iter()
+            .map(|p| p.to_string())
+            .collect(),
     }
+}
 
-    // Read only up to MAX_AGENTS_SIZE + a small buffer (safe to cast now since we checked against ABSOLUTE_MAX_SIZE)
-    let bytes_to_read = (file_size as usize).min(MAX_AGENTS_SIZE + 4); // +4 for potential multibyte char
-    let file = match tokio::fs::File::open(&agents_path).await {
-        Ok(f) => f,
-        Err(e) => {
-            let warning = format!("Failed to open AGENTS.md: {}", e);
-            return (None, Some(warning));
-        }
-    };
+/// Codex config keys accepted in `CONFIG_OVERRIDES` unless
+/// `config_override_keys` is configured
+const DEFAULT_CONFIG_OVERRIDE_KEYS: &[&str] = &[
+    "model",
+    "model_reasoning_effort",
+    "model_reasoning_summary",
+    "model_verbosity",
+    "hide_agent_reasoning",
+    "show_raw_agent_reasoning",
+];
 
-    let mut content = Vec::with_capacity(bytes_to_read);
-    use tokio::io::AsyncReadExt;
-    if let Err(e) = file
-        .take(bytes_to_read as u64)
-        .read_to_end(&mut content)
-        .await
-    {
-        let warning = format!("Failed to read AGENTS.md: {}", e);
-        return (None, Some(warning));
+/// Keys callers may override via `CONFIG_OVERRIDES`
+pub fn config_override_keys() -> Vec<String> {
+    match &server_config().config_override_keys {
+        Some(keys) => keys.clone(),
+        None => DEFAULT_CONFIG_OVERRIDE_KEYS
+            .iter()
+            .map(|k| k.to_string())
+            .collect(),
     }
+}
 
-    // Check if file is empty or whitespace-only
-    if content.is_empty() {
-        return (None, None);
-    }
+fn is_override_key_allowed(key: &str, allowed: &[String]) -> bool {
+    allowed.iter().any(|entry| match entry.strip_suffix(".*") {
+        Some(table) => key
+            .strip_prefix(table)
+            .is_some_and(|rest| rest.starts_with('.') && rest.len() > 1),
+        None => entry == key,
+    })
+}
 
-    // Check for whitespace-only content, but only for small files
-    // For large files, we can't be sure what comes after our read window
-    if file_size <= bytes_to_read as u64 {
-        if let Ok(s) = std::str::from_utf8(&content) {
-            if s.trim().is_empty() {
-                return (None, None); // Whitespace-only
-            }
+/// Translate `CONFIG_OVERRIDES` into repeated `-c key=value` flags. Values are
+/// written as TOML literals (strings quoted), so `"true"` stays a string.
+pub fn config_override_args(
+    overrides: &BTreeMap<String, Value>,
+    allowed: &[String],
+) -> std::result::Result<Vec<String>, String> {
+    let mut args = Vec::with_capacity(overrides.len() * 2);
+    for (key, value) in overrides {
+        let valid_key = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid_key {
+            return Err(format!("invalid config key: {:?}", key));
         }
+        if !is_override_key_allowed(key, allowed) {
+            return Err(format!(
+                "config key {} is not allowed; allowed keys: {}",
+                key,
+                allowed.join(", ")
+            ));
+        }
+        let literal = toml_literal(value)
+            .ok_or_else(|| format!("unsupported value for config key {}: {}", key, value))?;
+        args.push("-c".to_string());
+        args.push(format!("{}={}", key, literal));
     }
+    Ok(args)
+}
 
-    // Truncate to MAX_AGENTS_SIZE on a UTF-8 character boundary
-    let (final_content, warning) = if content.len() > MAX_AGENTS_SIZE {
-        // Use std::str::from_utf8 to find the longest valid UTF-8 prefix
-        let mut end = MAX_AGENTS_SIZE;
+/// `-c` flags applying the `TEMPERATURE` and `SEED` parameters
+pub fn sampling_args(
+    temperature: Option<f64>,
+    seed: Option<u64>,
+) -> std::result::Result<Vec<String>, String> {
+    sampling_override_args(&server_config().sampling_config_keys, temperature, seed)
+}
 
-        // Try to find the largest valid UTF-8 slice <= MAX_AGENTS_SIZE
-        while end > 0 {
-            if let Ok(valid_str) = std::str::from_utf8(&content[..end]) {
-                let warning = format!(
-                    "AGENTS.md is {} bytes, exceeding the {} byte limit and was truncated to {} bytes.",
-                    file_size,
-                    MAX_AGENTS_SIZE,
-                    end
-                );
-                return (Some(valid_str.to_string()), Some(warning));
-            }
-            end -= 1;
+fn sampling_override_args(
+    keys: &SamplingConfigKeys,
+    temperature: Option<f64>,
+    seed: Option<u64>,
+) -> std::result::Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    if let Some(temperature) = temperature {
+        if !(0.0..=2.0).contains(&temperature) {
+            return Err(format!(
+                "TEMPERATURE must be between 0 and 2, got {}",
+                temperature
+            ));
         }
+        let key = keys.temperature.as_deref().ok_or(
+            "TEMPERATURE is not supported: set sampling_config_keys.temperature in codex-mcp.config.json",
+        )?;
+        args.push("-c".to_string());
+        args.push(format!("{}={}", key, Value::from(temperature)));
+    }
+    if let Some(seed) = seed {
+        let key = keys.seed.as_deref().ok_or(
+            "SEED is not supported: set sampling_config_keys.seed in codex-mcp.config.json",
+        )?;
+        args.push("-c".to_string());
+        args.push(format!("{}={}", key, seed));
+    }
+    Ok(args)
+}
 
-        // If we can't find any valid UTF-8, skip the file
-        let warning = "AGENTS.md contains invalid UTF-8 and was skipped.".to_string();
-        return (None, Some(warning));
-    } else {
-        match String::from_utf8(content) {
-            Ok(s) => (s, None),
-            Err(_) => {
-                let warning = "AGENTS.md contains invalid UTF-8 and was skipped.".to_string();
-                return (None, Some(warning));
-            }
+/// TOML literal for a JSON scalar or array of scalars. JSON string escaping is
+/// valid for TOML basic strings.
+fn toml_literal(value: &Value) -> Option<String> {
+    match value {
+        Value::String(_) | Value::Bool(_) | Value::Number(_) => Some(value.to_string()),
+        Value::Array(items) => {
+            let items: Option<Vec<String>> = items
+                .iter()
+                .map(|item| match item {
+                    Value::Array(_) => None,
+                    other => toml_literal(other),
+                })
+                .collect();
+            Some(format!("[{}]", items?.join(", ")))
         }
-    };
+        Value::Null | Value::Object(_) => None,
+    }
+}
 
-    (Some(final_content), warning)
+/// Profiles queried by `codex_consensus`, configured via `consensus_profiles` in
+/// `codex-mcp.config.json`.
+pub fn consensus_profiles() -> &'static [ConsensusProfile] {
+    &server_config().consensus_profiles
 }
 
-/// Execute Codex CLI with the given options and return the result
-/// Requires timeout to be set to prevent unbounded execution
-pub async fn run(mut opts: Options) -> Result<CodexResult> {
-    // Read AGENTS.md if it exists and prepend to prompt
-    let (agents_content, agents_warning) = read_agents_md(&opts.working_dir).await;
-    if let Some(content) = agents_content {
-        opts.prompt = format!(
-            "<system_prompt>\n{}\n</system_prompt>\n\n{}",
-            content, opts.prompt
-        );
-    }
+pub fn lossy_utf8_warning_threshold() -> usize {
+    server_config()
+        .lossy_utf8_warning_threshold
+        .unwrap_or(lossy_utf8::DEFAULT_WARNING_THRESHOLD)
+}
 
-    // Ensure timeout is always set
-    if opts.timeout_secs.is_none() {
-        opts.timeout_secs = Some(default_timeout_secs());
-    }
+pub fn context_window_tokens() -> Option<u64> {
+    server_config().context_window_tokens
+}
 
-    let timeout_secs = opts.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS);
-    let duration = std::time::Duration::from_secs(timeout_secs);
+pub fn context_warning_percent() -> u8 {
+    server_config()
+        .context_warning_percent
+        .unwrap_or(context_budget::DEFAULT_WARNING_PERCENT)
+}
 
-    match tokio::time::timeout(duration, run_internal(opts, agents_warning.clone())).await {
-        Ok(result) => result,
-        Err(_) => {
-            // Timeout occurred - the child process will be killed automatically via kill_on_drop
-            let result = CodexResult {
+pub fn pdf_max_pages() -> usize {
+    server_config()
+        .pdf_max_pages
+        .unwrap_or(pdf_pages::DEFAULT_MAX_PAGES)
+}
+
+pub fn idempotency_ttl() -> std::time::Duration {
+    std::time::Duration::from_secs(
+        server_config()
+            .idempotency_ttl_secs
+            .unwrap_or(idempotency::DEFAULT_TTL_SECS),
+    )
+}
+
+pub fn parse_error_excerpt_bytes() -> usize {
+    server_config()
+        .parse_error_excerpt_bytes
+        .unwrap_or(DEFAULT_PARSE_ERROR_EXCERPT_BYTES)
+}
+
+pub fn max_response_bytes() -> Option<usize> {
+    server_config().max_response_bytes
+}
+
+pub fn dual_output() -> bool {
+    server_config().dual_output
+}
+
+pub fn warm_up_enabled() -> bool {
+    server_config().warm_up
+}
+
+pub fn max_inline_diff_bytes() -> usize {
+    server_config()
+        .max_inline_diff_bytes
+        .unwrap_or(DEFAULT_MAX_INLINE_DIFF_BYTES)
+}
+
+/// HTTP clients, configured via `api_keys` in `codex-mcp.config.json`
+pub fn api_keys() -> &'static [ApiKeyConfig] {
+    &server_config().api_keys
+}
+
+/// Scheduled runs, configured via `schedules` in `codex-mcp.config.json`.
+pub fn schedules() -> &'static [ScheduleConfig] {
+    &server_config().schedules
+}
+
+/// Extra tools registered at startup, configured via `tool_aliases` in
+/// `codex-mcp.config.json`
+pub fn tool_aliases() -> &'static [ToolAlias] {
+    &server_config().tool_aliases
+}
+
+/// Tools turned off at startup, configured via `disabled_tools` in
+/// `codex-mcp.config.json`; `tool_toggle` follows later edits
+pub fn disabled_tools() -> &'static [String] {
+    &server_config().disabled_tools
+}
+
+/// Config file the server looked for, watched for `disabled_tools` changes
+pub fn config_path() -> Option<&'static std::path::Path> {
+    server_config().config_path.as_deref()
+}
+
+/// Codex accounts runs can execute as, configured via `identities` in
+/// `codex-mcp.config.json`
+pub fn identities() -> &'static [Identity] {
+    &server_config().identities
+}
+
+/// Directory for auto-named run logs, configurable via `runs_dir` in
+/// `codex-mcp.config.json`.
+pub fn runs_dir() -> PathBuf {
+    server_config()
+        .runs_dir
+        .clone()
+        .unwrap_or_else(run_log::default_runs_dir)
+}
+
+/// Retention limits for auto-named run logs, configured via `runs_retention` in
+/// `codex-mcp.config.json`.
+pub fn runs_retention() -> &'static RetentionConfig {
+    &server_config().runs_retention
+}
+
+/// How often to clean up left-behind artifacts, configured via
+/// `cleanup_interval_secs` in `codex-mcp.config.json`
+pub fn cleanup_interval() -> Option<std::time::Duration> {
+    server_config()
+        .cleanup_interval_secs
+        .map(std::time::Duration::from_secs)
+}
+
+/// Key for encrypting files the server stores, if `encryption` or
+/// `CODEX_MCP_AGE_KEY` is set. A configured but unusable key is an error, so
+/// nothing silently falls back to plain text.
+pub fn at_rest_key() -> Result<Option<&'static AgeKey>, String> {
+    static KEY: OnceLock<Result<Option<AgeKey>, String>> = OnceLock::new();
+    match KEY.get_or_init(|| AgeKey::load(&server_config().encryption)) {
+        Ok(key) => Ok(key.as_ref()),
+        Err(e) => Err(format!("encryption key is unusable: {}", e)),
+    }
+}
+
+/// `github_review` section of the config, used by `codex_review_export`
+pub fn github_review() -> &'static GithubReviewConfig {
+    &server_config().github_review
+}
+
+/// `workspace_trust` section of the config
+pub fn workspace_trust() -> &'static TrustConfig {
+    &server_config().workspace_trust
+}
+
+/// `session_expiry` section of the config
+pub fn session_expiry() -> &'static SessionExpiryConfig {
+    &server_config().session_expiry
+}
+
+/// `cli_version` section of the config
+pub fn cli_version_config() -> &'static CliVersionConfig {
+    &server_config().cli_version
+}
+
+/// `client_sampling` section of the config
+pub fn client_sampling_config() -> &'static ClientSamplingConfig {
+    &server_config().client_sampling
+}
+
+pub fn explore_first_config() -> &'static ExploreFirstConfig {
+    &server_config().explore_first
+}
+
+/// Argument fragments whose values are masked in `effective_config()`
+const SECRET_ARG_MARKERS: &[&str] = &["key", "token", "secret", "password", "credential"];
+
+/// Fully resolved server configuration, as reported by the `codex_config` tool
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectiveConfig {
+    /// Config file the server looked for (`CODEX_MCP_CONFIG_PATH` or `./codex-mcp.config.json`)
+    pub config_path: Option<PathBuf>,
+    /// Whether `config_path` was found and parsed successfully
+    pub config_loaded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_error: Option<String>,
+    /// Codex binary used for runs (`CODEX_BIN` or `codex`)
+    pub codex_bin: String,
+    /// Extra CLI flags applied to every run, with secret values masked
+    pub additional_args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sandbox_ceiling: Option<SandboxMode>,
+    pub timeout_secs: u64,
+    pub workspace_lock_policy: LockPolicy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_preprocessing: Option<ImagePreprocessConfig>,
+    pub isolated_codex_home: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codex_home_template: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_screenshot: Option<FailureScreenshotConfig>,
+    pub agent_messages_truncation: TruncationConfig,
+    pub next_steps_extraction: NextStepsExtraction,
+    pub agents_md_mode: AgentsMdMode,
+    pub runs_dir: PathBuf,
+    pub run_log_compression: Compression,
+    pub runs_retention: RetentionConfig,
+    pub encrypt_at_rest: bool,
+    pub github_review: GithubReviewConfig,
+    pub workspace_trust: TrustConfig,
+    pub session_expiry: SessionExpiryConfig,
+    pub cli_version: CliVersionConfig,
+    pub client_sampling: ClientSamplingConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cleanup_interval_secs: Option<u64>,
+    pub out_of_scope_changes: ScopePolicy,
+    pub consensus_profiles: Vec<ConsensusProfile>,
+    pub config_override_keys: Vec<String>,
+    pub dedup_agent_messages: bool,
+    pub sanitize_agent_messages: bool,
+    pub watch_workspace: bool,
+    pub workspace_relative_paths: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_language: Option<String>,
+    pub include_plan_tool: bool,
+    pub model_fallback: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_model: Option<String>,
+    pub schedules: Vec<ScheduleConfig>,
+    pub tool_aliases: Vec<ToolAlias>,
+    pub disabled_tools: Vec<String>,
+    pub identities: Vec<Identity>,
+    pub disk_guard: DiskGuardConfig,
+    pub parse_error_excerpt_bytes: usize,
+    pub output_budget: OutputBudgetConfig,
+    pub explore_first: ExploreFirstConfig,
+    pub sensitive_paths: Vec<String>,
+    pub client_warning_level: WarningLevel,
+    pub check_prompt_paths: bool,
+    pub sampling_config_keys: SamplingConfigKeys,
+    pub redaction: RedactionConfig,
+    pub run_environment: RunEnvironment,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_budget_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_prompt_bytes: Option<usize>,
+    pub include_resource_usage: bool,
+    pub strict_arguments: bool,
+    pub allow_verify_commands: bool,
+    pub enable_codex_raw: bool,
+    pub api_keys: Vec<ApiKeyConfig>,
+    pub progress: ProgressConfig,
+    pub max_inline_diff_bytes: usize,
+    pub warm_up: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_response_bytes: Option<usize>,
+    pub dual_output: bool,
+    pub retry_on_empty_response: EmptyResponseRetry,
+    pub lossy_utf8_warning_threshold: usize,
+    pub pdf_max_pages: usize,
+    pub idempotency_ttl_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window_tokens: Option<u64>,
+    pub context_warning_percent: u8,
+}
+
+/// Resolve the effective configuration after env, config file, and defaults
+pub fn effective_config() -> EffectiveConfig {
+    let cfg = server_config();
+    EffectiveConfig {
+        config_path: cfg.config_path.clone(),
+        config_loaded: cfg.config_loaded,
+        config_error: cfg.config_error.clone(),
+        codex_bin: codex_bin(),
+        additional_args: mask_secret_args(&cfg.additional_args),
+        sandbox_ceiling: cfg.sandbox_ceiling,
+        timeout_secs: default_timeout_secs(),
+        workspace_lock_policy: cfg.workspace_lock_policy,
+        image_preprocessing: cfg.image_preprocessing.clone(),
+        isolated_codex_home: cfg.isolated_codex_home,
+        codex_home_template: cfg.codex_home_template.clone(),
+        failure_screenshot: cfg.failure_screenshot.clone(),
+        agent_messages_truncation: cfg.agent_messages_truncation.clone(),
+        next_steps_extraction: cfg.next_steps_extraction,
+        agents_md_mode: cfg.agents_md_mode,
+        runs_dir: runs_dir(),
+        run_log_compression: cfg.run_log_compression,
+        runs_retention: cfg.runs_retention.clone(),
+        encrypt_at_rest: matches!(at_rest_key(), Ok(Some(_))),
+        github_review: cfg.github_review.clone(),
+        workspace_trust: cfg.workspace_trust.clone(),
+        session_expiry: cfg.session_expiry.clone(),
+        cli_version: cfg.cli_version.clone(),
+        client_sampling: cfg.client_sampling.clone(),
+        cleanup_interval_secs: cfg.cleanup_interval_secs,
+        out_of_scope_changes: cfg.out_of_scope_changes,
+        consensus_profiles: cfg
+            .consensus_profiles
+            .iter()
+            .map(|profile| ConsensusProfile {
+                name: profile.name.clone(),
+                args: mask_secret_args(&profile.args),
+            })
+            .collect(),
+        config_override_keys: config_override_keys(),
+        dedup_agent_messages: cfg.dedup_agent_messages.unwrap_or(true),
+        sanitize_agent_messages: cfg.sanitize_agent_messages.unwrap_or(true),
+        watch_workspace: cfg.watch_workspace.unwrap_or(false),
+        workspace_relative_paths: cfg.workspace_relative_paths.unwrap_or(true),
+        response_language: cfg.response_language.clone(),
+        include_plan_tool: cfg.include_plan_tool.unwrap_or(true),
+        model_fallback: cfg.model_fallback.unwrap_or(true),
+        fallback_model: cfg.fallback_model.clone(),
+        schedules: cfg.schedules.clone(),
+        tool_aliases: cfg.tool_aliases.clone(),
+        disabled_tools: cfg.disabled_tools.clone(),
+        identities: cfg.identities.clone(),
+        disk_guard: cfg.disk_guard.clone(),
+        parse_error_excerpt_bytes: parse_error_excerpt_bytes(),
+        output_budget: cfg.output_budget.clone(),
+        explore_first: cfg.explore_first.clone(),
+        sensitive_paths: sensitive_paths(),
+        client_warning_level: cfg.client_warning_level,
+        check_prompt_paths: cfg.check_prompt_paths,
+        sampling_config_keys: cfg.sampling_config_keys.clone(),
+        redaction: cfg.redaction.clone(),
+        run_environment: cfg.run_environment.clone(),
+        prompt_budget_bytes: cfg.prompt_budget_bytes,
+        max_prompt_bytes: cfg.max_prompt_bytes,
+        include_resource_usage: cfg.include_resource_usage,
+        strict_arguments: cfg.strict_arguments,
+        allow_verify_commands: cfg.allow_verify_commands,
+        enable_codex_raw: cfg.enable_codex_raw,
+        api_keys: cfg.api_keys.clone(),
+        progress: ProgressConfig {
+            rules: Some(cfg.progress.rules()),
+            excerpt_bytes: cfg.progress.excerpt_bytes,
+        },
+        max_inline_diff_bytes: max_inline_diff_bytes(),
+        warm_up: cfg.warm_up,
+        max_response_bytes: cfg.max_response_bytes,
+        dual_output: cfg.dual_output,
+        retry_on_empty_response: cfg.retry_on_empty_response,
+        lossy_utf8_warning_threshold: lossy_utf8_warning_threshold(),
+        pdf_max_pages: pdf_max_pages(),
+        idempotency_ttl_secs: idempotency_ttl().as_secs(),
+        context_window_tokens: context_window_tokens(),
+        context_warning_percent: context_warning_percent(),
+    }
+}
+
+fn is_secret_arg(arg: &str) -> bool {
+    let lower = arg.to_ascii_lowercase();
+    SECRET_ARG_MARKERS
+        .iter()
+        .any(|marker| lower.contains(marker))
+}
+
+/// Mask values of secret-looking flags (`--api-key X`, `-c auth.token=X`, `--token=X`)
+fn mask_secret_args(args: &[String]) -> Vec<String> {
+    let mut masked = Vec::with_capacity(args.len());
+    let mut mask_next = false;
+    for arg in args {
+        if mask_next {
+            masked.push("****".to_string());
+            mask_next = false;
+        } else if let Some((key, _)) = arg.split_once('=') {
+            if is_secret_arg(key) {
+                masked.push(format!("{}=****", key));
+            } else {
+                masked.push(arg.clone());
+            }
+        } else {
+            mask_next = arg.starts_with('-') && is_secret_arg(arg);
+            masked.push(arg.clone());
+        }
+    }
+    masked
+}
+
+/// Codex binary to spawn; overridable for tests or custom setups
+pub(crate) fn codex_bin() -> String {
+    std::env::var("CODEX_BIN").unwrap_or_else(|_| "codex".to_string())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CodexResult {
+    /// Server-side id of this run, usable to look it up in run history
+    pub run_id: String,
+    pub success: bool,
+    pub session_id: String,
+    pub agent_messages: String,
+    pub agent_messages_truncated: bool,
+    pub all_messages: Vec<HashMap<String, Value>>,
+    pub all_messages_truncated: bool,
+    pub error: Option<String>,
+    /// Warnings meant for the caller
+    pub warnings: Option<String>,
+    /// Diagnostics meant for operators (AGENTS.md problems, image
+    /// preprocessing, Codex stderr); see `client_warning_level`
+    pub operator_warnings: Option<String>,
+    /// Paths mentioned in the prompt that do not exist, when `check_prompt_paths`
+    /// is enabled
+    pub unresolved_paths: Vec<String>,
+    /// Set when the requested sandbox was above `sandbox_ceiling`
+    pub sandbox_downgrade: Option<SandboxDowngrade>,
+    /// Set when the requested model was rejected and the run was retried
+    /// with another
+    pub model_fallback: Option<ModelFallback>,
+    /// Set when the Codex process died from a signal
+    pub termination: Option<Termination>,
+    /// Kind of the fatal error event the run was stopped on, without waiting
+    /// for Codex to exit
+    pub fatal_error: Option<FailureKind>,
+    /// CPU time and peak memory of the Codex process (Linux only)
+    pub resource_usage: Option<ResourceUsage>,
+    /// Tokens reported by `turn.completed` events, summed over turns
+    pub token_usage: Option<TokenUsage>,
+    /// Share of the model's context window the session fills after this run,
+    /// when the stream reports enough to tell
+    pub context_used_percent: Option<u8>,
+    /// What was left out to stay within `prompt_budget_bytes`
+    pub budget_trims: Vec<BudgetTrim>,
+    /// Changes the run made, when `return_diff` was requested
+    pub diff: Option<RunDiff>,
+    /// Files that changed on disk during a write run, compared with the
+    /// reported patches, when `watch_workspace` is enabled
+    pub fs_changes: Option<FsChanges>,
+    /// The agent's last plan, when Codex kept one
+    pub plan: Vec<PlanStep>,
+    /// Files Codex read with shell commands (`cat`, `sed -n`, `head`, ...),
+    /// in the order first read, at most `files_read::MAX_FILES_READ`
+    pub files_read: Vec<PathBuf>,
+    /// True if more files were read than `files_read` holds
+    pub files_read_truncated: bool,
+    /// Items of the last "Next steps" list in the agent's answer
+    pub next_steps: Vec<String>,
+    /// Invalid UTF-8 in Codex's output, once over `lossy_utf8_warning_threshold`
+    pub lossy_utf8: Option<LossyUtf8>,
+    /// Attached PDFs and how many of their pages were included
+    pub pdf_pages: Vec<PdfPages>,
+    /// The final agent message parsed as JSON, when `final_schema` was given
+    /// and the message matched it
+    pub final_value: Option<Value>,
+    /// File holding the full raw JSONL stream, when `log_to_file` was requested
+    pub log_file: Option<PathBuf>,
+}
+
+/// Token counts from the `usage` of `turn.completed` events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub cached_input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Add the counts of one `usage` object
+    fn add_event(&mut self, usage: &Value) {
+        let count = |key: &str| usage.get(key).and_then(Value::as_u64).unwrap_or(0);
+        self.input_tokens += count("input_tokens");
+        self.cached_input_tokens += count("cached_input_tokens");
+        self.output_tokens += count("output_tokens");
+    }
+
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.input_tokens += other.input_tokens;
+        self.cached_input_tokens += other.cached_input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// What to do with an incoming `agent_message` item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DedupVerdict {
+    /// A new message
+    Keep,
+    /// A revised text for an item id already collected; it replaces the earlier entry
+    Replace,
+    /// An exact repeat of a message already collected
+    Drop,
+}
+
+/// Filters repeated `agent_message` items. Some CLI versions emit the same
+/// message twice (streamed delta, then final), either under the same item id or
+/// as a repeat of the previous message. When an item id repeats with different
+/// text, the later text wins.
+#[derive(Debug, Default)]
+struct AgentMessageDedup {
+    texts_by_id: HashMap<String, String>,
+    last_text: String,
+}
+
+impl AgentMessageDedup {
+    /// Decides what to do with a message and records it unless it is dropped
+    fn admit(&mut self, id: Option<&str>, text: &str) -> DedupVerdict {
+        let trimmed = text.trim();
+        let verdict = match id.and_then(|id| self.texts_by_id.get_mut(id)) {
+            Some(earlier) if earlier.trim() == trimmed => return DedupVerdict::Drop,
+            Some(earlier) => {
+                *earlier = text.to_string();
+                DedupVerdict::Replace
+            }
+            None => {
+                if !trimmed.is_empty() && self.last_text.trim() == trimmed {
+                    return DedupVerdict::Drop;
+                }
+                if let Some(id) = id {
+                    self.texts_by_id.insert(id.to_string(), text.to_string());
+                }
+                DedupVerdict::Keep
+            }
+        };
+        self.last_text = text.to_string();
+        verdict
+    }
+}
+
+/// Accumulates agent messages within a byte budget. Everything is kept until
+/// `head_bytes + tail_bytes` is exceeded; after that only the first `head_bytes`
+/// and the last `tail_bytes` survive, joined by an elision marker.
+#[derive(Debug)]
+struct AgentMessageCollector {
+    head_bytes: usize,
+    tail_bytes: usize,
+    head: String,
+    tail: String,
+    total_bytes: usize,
+    truncated: bool,
+    /// Byte range of each message with an item id, as offsets into the full stream
+    entries: HashMap<String, (usize, usize)>,
+}
+
+impl AgentMessageCollector {
+    fn new(config: &TruncationConfig) -> Self {
+        Self {
+            head_bytes: config.head_bytes,
+            tail_bytes: config.tail_bytes,
+            head: String::new(),
+            tail: String::new(),
+            total_bytes: 0,
+            truncated: false,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, id: Option<&str>, text: &str) {
+        // Add a newline separator between multiple agent messages for better parsing
+        let separator = if self.total_bytes > 0 && !text.is_empty() {
+            "\n"
+        } else {
+            ""
+        };
+        self.total_bytes += separator.len() + text.len();
+        if let Some(id) = id {
+            self.entries
+                .insert(id.to_string(), (self.total_bytes - text.len(), text.len()));
+        }
+
+        if !self.truncated {
+            self.head.push_str(separator);
+            self.head.push_str(text);
+            self.split_if_over_budget();
+            if !self.truncated {
+                return;
+            }
+        } else {
+            self.tail.push_str(separator);
+            self.tail.push_str(text);
+        }
+
+        // Trim lazily so long runs of small messages don't copy the tail every time
+        if self.tail.len() > self.tail_bytes.saturating_mul(2).max(64 * 1024) {
+            self.trim_tail();
+        }
+    }
+
+    /// Swaps the text of the message recorded under `id` for `text`. A message
+    /// that is already partly elided only changes the elided byte count.
+    fn replace(&mut self, id: &str, text: &str) {
+        let Some(&(start, len)) = self.entries.get(id) else {
+            self.push(Some(id), text);
+            return;
+        };
+        let end = start + len;
+        let tail_start = self.total_bytes - self.tail.len();
+        if end <= self.head.len() {
+            self.head.replace_range(start..end, text);
+        } else if self.truncated && start >= tail_start {
+            self.tail
+                .replace_range(start - tail_start..end - tail_start, text);
+        }
+
+        self.total_bytes = self.total_bytes - len + text.len();
+        for (entry_start, entry_len) in self.entries.values_mut() {
+            if *entry_start > start {
+                *entry_start = *entry_start - len + text.len();
+            } else if *entry_start == start {
+                *entry_len = text.len();
+            }
+        }
+
+        if !self.truncated {
+            self.split_if_over_budget();
+        } else if self.head.len() > self.head_bytes {
+            // A longer message in the head pushes its overflow into the elided part
+            let split = floor_char_boundary(&self.head, self.head_bytes);
+            self.head.truncate(split);
+        }
+    }
+
+    fn split_if_over_budget(&mut self) {
+        if self.head.len() <= self.head_bytes + self.tail_bytes {
+            return;
+        }
+        // Budget exceeded: everything past head_bytes becomes the start of the tail
+        self.truncated = true;
+        let split = floor_char_boundary(&self.head, self.head_bytes);
+        self.tail = self.head.split_off(split);
+    }
+
+    fn trim_tail(&mut self) {
+        if self.tail.len() > self.tail_bytes {
+            let start = ceil_char_boundary(&self.tail, self.tail.len() - self.tail_bytes);
+            self.tail.drain(..start);
+        }
+    }
+
+    /// Returns the collected text and whether anything was elided
+    fn finish(mut self) -> (String, bool) {
+        if !self.truncated {
+            return (self.head, false);
+        }
+        self.trim_tail();
+        let elided = self.total_bytes - self.head.len() - self.tail.len();
+        let mut text = self.head;
+        text.push_str(&format!(
+            "\n[... {} bytes of agent messages elided due to size limit ...]\n",
+            elided
+        ));
+        text.push_str(&self.tail);
+        (text, true)
+    }
+}
+
+/// Largest char boundary in `s` that is <= `index`
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// `line` shortened to about `max_bytes` by eliding its middle, with
+/// `note` added to the elision marker; `None` if it already fits
+fn elide_middle(line: &str, max_bytes: usize, note: &str) -> Option<String> {
+    if line.len() <= max_bytes {
+        return None;
+    }
+    let head = floor_char_boundary(line, max_bytes / 2);
+    let tail = ceil_char_boundary(line, line.len() - max_bytes / 2);
+    Some(format!(
+        "{} ... [{} bytes elided; {}] ... {}",
+        &line[..head],
+        tail - head,
+        note,
+        &line[tail..]
+    ))
+}
+
+/// Smallest char boundary in `s` that is >= `index`
+fn ceil_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// Validation mode for enforce_required_fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValidationMode {
+    /// Perform full validation (check session_id and agent_messages)
+    Full,
+    /// Skip validation (for cases with well-defined errors like timeout or truncation)
+    Skip,
+}
+
+/// Maximum allowed size for AGENTS.md content (1MB)
+const MAX_AGENTS_SIZE: usize = 1024 * 1024;
+
+/// AGENTS.md variant that replaces AGENTS.md for runs in `sandbox`
+fn agents_variant(sandbox: SandboxMode) -> &'static str {
+    if sandbox.is_write() {
+        "AGENTS.write.md"
+    } else {
+        "AGENTS.read-only.md"
+    }
+}
+
+/// Read AGENTS.md from working directory if it exists, preferring
+/// `AGENTS.write.md` or `AGENTS.read-only.md` for runs in `sandbox`. A
+/// `modes:` list in the file's front matter limits it to those sandbox modes.
+/// Returns (content, warning) where warning is set if there are issues
+pub(crate) async fn read_agents_for(
+    working_dir: &std::path::Path,
+    sandbox: SandboxMode,
+) -> (Option<String>, Option<String>) {
+    let (content, warning, _) = read_agents_with_priority(working_dir, sandbox).await;
+    (content, warning)
+}
+
+/// `read_agents_for`, plus the section headings its front matter lists under
+/// `priority:`, which are kept first when the content has to be cut
+async fn read_agents_with_priority(
+    working_dir: &std::path::Path,
+    sandbox: SandboxMode,
+) -> (Option<String>, Option<String>, Vec<String>) {
+    let variant = agents_variant(sandbox);
+    let name = if working_dir.join(variant).is_file() {
+        variant
+    } else {
+        "AGENTS.md"
+    };
+    let by_reference = match server_config().agents_md_mode {
+        AgentsMdMode::Inline => false,
+        AgentsMdMode::Reference => true,
+        AgentsMdMode::Auto => tokio::fs::metadata(working_dir.join(name))
+            .await
+            .is_ok_and(|metadata| metadata.len() > MAX_AGENTS_SIZE as u64),
+    };
+    if by_reference {
+        return (
+            agents_reference(working_dir, name, sandbox).await,
+            None,
+            Vec::new(),
+        );
+    }
+    let (content, warning) = read_agents_file(working_dir, name).await;
+    let priority = content
+        .as_deref()
+        .map(agents_sections::front_matter_priority)
+        .unwrap_or_default();
+    (
+        content.and_then(|content| apply_front_matter(&content, sandbox)),
+        warning,
+        priority,
+    )
+}
+
+/// Bytes read from the start of a referenced AGENTS.md, enough for its front
+/// matter
+const AGENTS_REFERENCE_HEAD_BYTES: u64 = 64 * 1024;
+
+/// Instruction pointing Codex at `name` instead of inlining it, or `None`
+/// when the file is missing, blank, or excluded for `sandbox` by its front
+/// matter. Codex reads the whole file, so nothing is truncated.
+async fn agents_reference(
+    working_dir: &std::path::Path,
+    name: &str,
+    sandbox: SandboxMode,
+) -> Option<String> {
+    use tokio::io::AsyncReadExt;
+    let file = tokio::fs::File::open(working_dir.join(name)).await.ok()?;
+    let mut head = Vec::new();
+    file.take(AGENTS_REFERENCE_HEAD_BYTES)
+        .read_to_end(&mut head)
+        .await
+        .ok()?;
+    let head = String::from_utf8_lossy(&head);
+    if head.trim().is_empty() {
+        return None;
+    }
+    let has_front_matter = head.starts_with("---\n") || head.starts_with("---\r\n");
+    if has_front_matter && apply_front_matter(&head, sandbox).is_none() {
+        return None;
+    }
+    Some(format!(
+        "This project's instructions are in `{}` in the working directory. Read the whole file before doing anything else and follow it as if its content were here{}.",
+        name,
+        if has_front_matter {
+            ", skipping its leading `---` front matter block"
+        } else {
+            ""
+        }
+    ))
+}
+
+/// Strip a leading `---` front matter block. Returns `None` if its `modes:`
+/// list (e.g. `modes: [read-only]`) excludes `sandbox`; `write` matches both
+/// write modes.
+fn apply_front_matter(content: &str, sandbox: SandboxMode) -> Option<String> {
+    let Some(rest) = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    else {
+        return Some(content.to_string());
+    };
+    let Some(end) = rest.find("\n---") else {
+        return Some(content.to_string());
+    };
+    let front_matter = &rest[..end];
+    let body = rest[end + 4..].trim_start_matches(['\r', '\n']);
+
+    let modes = front_matter
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("modes:"));
+    if let Some(modes) = modes {
+        let included = modes
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(|mode| mode.trim().trim_matches(['"', '\'']))
+            .any(|mode| mode == sandbox.as_str() || (mode == "write" && sandbox.is_write()));
+        if !included {
+            return None;
+        }
+    }
+    (!body.trim().is_empty()).then(|| body.to_string())
+}
+
+/// Read `name` from the working directory, capped at `MAX_AGENTS_SIZE`. A
+/// larger file loses whole sections (see `agents_sections::fit`), or, when
+/// it has none, its end.
+async fn read_agents_file(
+    working_dir: &std::path::Path,
+    name: &str,
+) -> (Option<String>, Option<String>) {
+    let agents_path = working_dir.join(name);
+
+    if !agents_path.exists() {
+        return (None, None);
+    }
+
+    // Check file size first to avoid allocating huge strings
+    let metadata = match tokio::fs::metadata(&agents_path).await {
+        Ok(m) => m,
+        Err(e) => {
+            let warning = format!("Failed to read {} metadata: {}", name, e);
+            return (None, Some(warning));
+        }
+    };
+
+    let file_size = metadata.len(); // Keep as u64 to avoid overflow
+
+    // If file is extremely large, warn and skip to avoid OOM
+    const ABSOLUTE_MAX_SIZE: u64 = 10 * 1024 * 1024; // 10MB hard limit
+    if file_size > ABSOLUTE_MAX_SIZE {
+        let warning = format!(
+            "{} is {} bytes, exceeding the absolute maximum of {} bytes and will be skipped.",
+            name, file_size, ABSOLUTE_MAX_SIZE
+        );
+        return (None, Some(warning));
+    }
+
+    // Read the whole file, so it can be cut at section boundaries (safe to cast now since we checked against ABSOLUTE_MAX_SIZE)
+    let bytes_to_read = file_size as usize;
+    let file = match tokio::fs::File::open(&agents_path).await {
+        Ok(f) => f,
+        Err(e) => {
+            let warning = format!("Failed to open {}: {}", name, e);
+            return (None, Some(warning));
+        }
+    };
+
+    let mut content = Vec::with_capacity(bytes_to_read);
+    use tokio::io::AsyncReadExt;
+    if let Err(e) = file
+        .take(bytes_to_read as u64)
+        .read_to_end(&mut content)
+        .await
+    {
+        let warning = format!("Failed to read {}: {}", name, e);
+        return (None, Some(warning));
+    }
+
+    // Check if file is empty or whitespace-only
+    if content.is_empty() {
+        return (None, None);
+    }
+
+    // Check for whitespace-only content
+    if let Ok(s) = std::str::from_utf8(&content) {
+        if s.trim().is_empty() {
+            return (None, None);
+        }
+    }
+
+    // Leave out the lowest-priority sections to fit MAX_AGENTS_SIZE
+    if content.len() > MAX_AGENTS_SIZE {
+        let fitted = std::str::from_utf8(&content).ok().and_then(|text| {
+            agents_sections::fit(
+                text,
+                MAX_AGENTS_SIZE,
+                &agents_sections::front_matter_priority(text),
+            )
+        });
+        if let Some(fitted) = fitted {
+            let warning = format!(
+                "{} is {} bytes, exceeding the {} byte limit; left out sections: {}.",
+                name,
+                file_size,
+                MAX_AGENTS_SIZE,
+                fitted.dropped.join(", ")
+            );
+            return (Some(fitted.content), Some(warning));
+        }
+    }
+
+    // Otherwise truncate to MAX_AGENTS_SIZE on a UTF-8 character boundary
+    let (final_content, warning) = if content.len() > MAX_AGENTS_SIZE {
+        // Use std::str::from_utf8 to find the longest valid UTF-8 prefix
+        let mut end = MAX_AGENTS_SIZE;
+
+        // Try to find the largest valid UTF-8 slice <= MAX_AGENTS_SIZE
+        while end > 0 {
+            if let Ok(valid_str) = std::str::from_utf8(&content[..end]) {
+                let warning = format!(
+                    "{} is {} bytes, exceeding the {} byte limit and was truncated to {} bytes.",
+                    name, file_size, MAX_AGENTS_SIZE, end
+                );
+                return (Some(valid_str.to_string()), Some(warning));
+            }
+            end -= 1;
+        }
+
+        // If we can't find any valid UTF-8, skip the file
+        let warning = format!("{} contains invalid UTF-8 and was skipped.", name);
+        return (None, Some(warning));
+    } else {
+        match String::from_utf8(content) {
+            Ok(s) => (s, None),
+            Err(_) => {
+                let warning = format!("{} contains invalid UTF-8 and was skipped.", name);
+                return (None, Some(warning));
+            }
+        }
+    };
+
+    (Some(final_content), warning)
+}
+
+/// Move `primer_messages` into the prompt, each in its own block before the
+/// caller's prompt
+fn prepend_primer(opts: &mut Options) {
+    if opts.primer_messages.is_empty() {
+        return;
+    }
+    let mut prompt = String::new();
+    for (index, message) in opts.primer_messages.drain(..).enumerate() {
+        prompt.push_str(&format!(
+            "<primer_message index=\"{}\">\n{}\n</primer_message>\n\n",
+            index + 1,
+            message.trim()
+        ));
+    }
+    prompt.push_str(&opts.prompt);
+    opts.prompt = prompt;
+}
+
+/// Execute Codex CLI with the given options and return the result
+/// Requires timeout to be set to prevent unbounded execution
+pub async fn run(opts: Options) -> Result<CodexResult> {
+    run_with_id(opts, Uuid::new_v4().to_string()).await
+}
+
+/// `run` with a run id chosen by the caller, e.g. to hand it out before the
+/// run finishes
+pub(crate) async fn run_with_id(mut opts: Options, run_id: String) -> Result<CodexResult> {
+    let mut invocation = Invocation {
+        prompt: opts.prompt.clone(),
+        primer_messages: opts.primer_messages.clone(),
+        additional_args: opts.additional_args.clone(),
+        image_paths: opts.image_paths.clone(),
+        timeout_secs: opts.timeout_secs,
+        allowed_paths: opts.allowed_paths.clone(),
+        return_diff: opts.return_diff,
+        final_schema: opts.final_schema.clone(),
+        codex_home: opts.codex_home.clone(),
+        response_language: opts.response_language.clone(),
+    };
+    // Secrets pasted into the prompt still reach Codex, but not history,
+    // the run log, or errors
+    let prompt_secrets =
+        PromptSecrets::detect(configured_redactor(), &opts.prompt, &opts.primer_messages);
+    if let Some(secrets) = &prompt_secrets {
+        secrets.scrub_invocation(&mut invocation);
+        opts.redact_log = true;
+    }
+    let primer_messages = opts.primer_messages.len();
+    prepend_primer(&mut opts);
+    // The caller's prompt, before preambles and AGENTS.md, for classification
+    // and max_prompt_bytes
+    let user_prompt = opts.prompt.clone();
+    let response_language = response_language_for(&mut opts);
+
+    // Lower the sandbox first, so AGENTS.md variants, locking, and diffs
+    // follow the one the run actually gets
+    let sandbox_downgrade =
+        sandbox_ceiling().and_then(|ceiling| cap_sandbox(&mut opts.additional_args, ceiling));
+
+    // Refuse a CLI whose output would fail to parse before doing any work
+    cli_version::check(cli_version_config()).await?;
+    if server_config().include_plan_tool.unwrap_or(true) && !opts.include_plan_tool {
+        opts.include_plan_tool = plan::tool_available().await;
+    }
+
+    // Refuse sessions the CLI can no longer resume before doing any work
+    if let Some(session_id) = opts.session_id.as_deref() {
+        let sessions_dir = opts
+            .codex_home
+            .as_ref()
+            .map(|home| home.join("sessions"))
+            .or_else(session_import::sessions_dir);
+        session_expiry::check(session_expiry(), session_id, sessions_dir.as_deref())?;
+    }
+
+    // Check the caller's own prompt, before AGENTS.md is prepended
+    let unresolved_paths = if server_config().check_prompt_paths {
+        prompt_paths::unresolved(&opts.working_dir, &opts.prompt)
+    } else {
+        Vec::new()
+    };
+
+    // Read AGENTS.md (or its variant for this sandbox) if it exists; it is
+    // prepended once the prompt budget is applied
+    let sandbox = SandboxMode::from_args(&opts.additional_args);
+    let (mut agents_content, agents_warning, agents_priority) =
+        read_agents_with_priority(&opts.working_dir, sandbox).await;
+
+    // Ensure timeout is always set
+    if opts.timeout_secs.is_none() {
+        opts.timeout_secs = Some(default_timeout_secs());
+        invocation.timeout_secs = opts.timeout_secs;
+    }
+
+    let timeout_secs = opts.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS);
+    let duration = std::time::Duration::from_secs(timeout_secs);
+
+    // Serialize write-mode runs per workspace; the lock is released when this guard drops
+    let write_mode = sandbox.is_write();
+    let _workspace_lock = if write_mode {
+        workspace_lock::acquire(
+            &opts.working_dir,
+            &run_id,
+            server_config().workspace_lock_policy,
+            duration,
+        )
+        .await?
+    } else {
+        None
+    };
+
+    // Refuse write-mode runs on a nearly full disk, and measure the workspace
+    // so growth can be reported afterwards
+    let guard = &server_config().disk_guard;
+    let usage_before = if write_mode {
+        if let Some(min_free_bytes) = guard.min_free_bytes {
+            disk_guard::check_free_space(&opts.working_dir, min_free_bytes)?;
+        }
+        match guard.max_growth_bytes {
+            Some(_) => {
+                let dir = opts.working_dir.clone();
+                tokio::task::spawn_blocking(move || disk_guard::dir_usage(&dir))
+                    .await
+                    .ok()
+            }
+            None => None,
+        }
+    } else {
+        None
+    };
+
+    // Give the child a private CODEX_HOME if configured; removed when this guard drops
+    let _isolated_home = if server_config().isolated_codex_home {
+        if opts.session_id.is_some() || opts.resume_last {
+            anyhow::bail!(
+                "SESSION_ID resume is unavailable with isolated_codex_home: each run starts from a fresh CODEX_HOME"
+            );
+        }
+        let template = opts
+            .codex_home
+            .take()
+            .or_else(|| server_config().codex_home_template.clone())
+            .or_else(codex_home::default_template)
+            .context("isolated_codex_home is enabled but no CODEX_HOME template was found")?;
+        let home = codex_home::create(&template, &run_id).with_context(|| {
+            format!(
+                "Failed to create isolated CODEX_HOME from {}",
+                template.display()
+            )
+        })?;
+        opts.codex_home = Some(home.path().to_path_buf());
+        Some(home)
+    } else {
+        None
+    };
+
+    // Replace PDF attachments with images of their pages; the rendered pages
+    // are removed when this guard drops
+    let expanded_pdfs = pdf_pages::expand(&opts.image_paths, &run_id, pdf_max_pages())
+        .await
+        .map_err(anyhow::Error::msg)?;
+    opts.image_paths = expanded_pdfs.paths.clone();
+
+    // Downscale/re-encode attachments if configured; processed copies are removed
+    // when this guard drops
+    let prepared_images = image_preprocess::prepare(
+        &opts.image_paths,
+        &run_id,
+        server_config().image_preprocessing.as_ref(),
+    )
+    .await;
+    opts.image_paths = prepared_images.paths.clone();
+
+    let mut operator_warnings = agents_warning;
+    for warning in &prepared_images.warnings {
+        operator_warnings = push_warning(operator_warnings, warning);
+    }
+    let mut pre_run_warnings = None;
+    if let Some(downgrade) = sandbox_downgrade {
+        pre_run_warnings = push_warning(
+            pre_run_warnings,
+            &format!(
+                "Requested sandbox {} exceeds sandbox_ceiling; the run used {}",
+                downgrade.requested.as_str(),
+                downgrade.applied.as_str()
+            ),
+        );
+    }
+    for warning in expanded_pdfs.pdfs.iter().filter_map(partial_pdf_warning) {
+        pre_run_warnings = push_warning(pre_run_warnings, &warning);
+    }
+    if !unresolved_paths.is_empty() {
+        pre_run_warnings = push_warning(
+            pre_run_warnings,
+            &format!(
+                "Paths mentioned in PROMPT do not exist in the working directory: {}",
+                unresolved_paths.join(", ")
+            ),
+        );
+    }
+
+    server_config()
+        .run_environment
+        .prepend_preamble(&mut opts.prompt);
+
+    // Trim attachments, then AGENTS.md, to fit prompt_budget_bytes
+    let mut budget_trims = Vec::new();
+    if let Some(budget) = server_config().prompt_budget_bytes {
+        let mut images: Vec<(PathBuf, u64)> = opts
+            .image_paths
+            .iter()
+            .map(|path| (path.clone(), std::fs::metadata(path).map_or(0, |m| m.len())))
+            .collect();
+        budget_trims = prompt_budget::fit(
+            budget,
+            &opts.prompt,
+            &mut agents_content,
+            &agents_priority,
+            &mut images,
+        );
+        opts.image_paths = images.into_iter().map(|(path, _)| path).collect();
+        if !budget_trims.is_empty() {
+            let trimmed: Vec<String> = budget_trims.iter().map(BudgetTrim::describe).collect();
+            pre_run_warnings = push_warning(
+                pre_run_warnings,
+                &format!(
+                    "Prompt exceeded prompt_budget_bytes ({}): {}",
+                    budget,
+                    trimmed.join("; ")
+                ),
+            );
+        }
+    }
+    if let Some(max_bytes) = server_config().max_prompt_bytes {
+        prompt_budget::check_max(
+            max_bytes,
+            &user_prompt,
+            opts.prompt.len().saturating_sub(user_prompt.len()),
+            agents_content.as_deref(),
+            &opts.image_paths,
+        )?;
+    }
+    if let Some(content) = agents_content {
+        opts.prompt = format!(
+            "<system_prompt>\n{}\n</system_prompt>\n\n{}",
+            content, opts.prompt
+        );
+    }
+
+    // Record the working tree so changes outside ALLOWED_PATHS, and the
+    // run's diff, can be detected
+    let allowed_paths = opts.allowed_paths.clone();
+    let return_diff = opts.return_diff;
+    let scope_snapshot = if allowed_paths.is_empty() && !return_diff {
+        None
+    } else {
+        match write_scope::snapshot(&opts.working_dir).await {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                let disabled = match (allowed_paths.is_empty(), return_diff) {
+                    (false, true) => "ALLOWED_PATHS is not enforced and RETURN_DIFF is unavailable",
+                    (false, false) => "ALLOWED_PATHS is not enforced",
+                    _ => "RETURN_DIFF is unavailable",
+                };
+                pre_run_warnings = push_warning(pre_run_warnings, &format!("{}: {}", disabled, e));
+                None
+            }
+        }
+    };
+
+    // Watch the tree for changes Codex makes without reporting a patch,
+    // e.g. through shell commands
+    let fs_watcher = if write_mode && server_config().watch_workspace.unwrap_or(false) {
+        match fs_watch::start(&opts.working_dir) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                pre_run_warnings = push_warning(pre_run_warnings, &e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let working_dir = opts.working_dir.clone();
+    let resume_last = opts.resume_last;
+    let model = provenance::model_from_args(&opts.additional_args);
+    let source = opts.source.clone().unwrap_or_else(|| "codex".to_string());
+    let task_id = opts.task_id.clone();
+    let meta = opts.meta.clone();
+    let rerun_of = opts.rerun_of.clone();
+    let started_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let started = std::time::Instant::now();
+    let mut timed_out = false;
+    let log_path = opts.log_to_file.as_ref().map(|target| {
+        target.resolve(
+            &runs_dir(),
+            &working_dir,
+            &run_id,
+            server_config().run_log_compression,
+        )
+    });
+    let log_path = match (log_path, at_rest_key()) {
+        (Some(path), Ok(Some(_))) => Some(at_rest::encrypted_path(&path)),
+        (Some(path), Err(e)) => {
+            pre_run_warnings = push_warning(
+                pre_run_warnings,
+                &format!("Run log {} was not written: {}", path.display(), e),
+            );
+            None
+        }
+        (path, _) => path,
+    };
+
+    let retry_policy = server_config().retry_on_empty_response;
+    let mut retry_opts = (retry_policy != EmptyResponseRetry::Off).then(|| opts.clone());
+    let fallback_opts = model_fallback_enabled()
+        .then(|| model_fallback::replace_model(&opts.additional_args, fallback_model()))
+        .flatten()
+        .map(|additional_args| Options {
+            additional_args,
+            ..opts.clone()
+        });
+    let mut language_opts = response_language.as_ref().map(|_| opts.clone());
+    let mut empty_response_retries = 0;
+    // Buffers events for `codex_tail`; the run shows as finished once this drops
+    let tail = run_tail::start(&run_id, &working_dir);
+    let mut result = match tokio::time::timeout(duration, async {
+        let mut first = run_internal(
+            opts,
+            pre_run_warnings.clone(),
+            log_path.clone(),
+            Some(&tail),
+        )
+        .await?;
+        if let Some(fallback_opts) = fallback_opts.filter(|_| is_model_rejected(&first)) {
+            for retry_opts in [retry_opts.as_mut(), language_opts.as_mut()]
+                .into_iter()
+                .flatten()
+            {
+                retry_opts.additional_args = fallback_opts.additional_args.clone();
+            }
+            first = retry_with_fallback_model(
+                first,
+                model.clone().unwrap_or_default(),
+                fallback_opts,
+                pre_run_warnings.clone(),
+                &tail,
+            )
+            .await?;
+        }
+        if let Some(retry_opts) = retry_opts.filter(|_| is_empty_response(&first)) {
+            empty_response_retries += 1;
+            first = retry_empty_response(
+                first,
+                retry_opts,
+                retry_policy,
+                pre_run_warnings.clone(),
+                &tail,
+            )
+            .await?;
+        }
+        match (language_opts, response_language.as_deref()) {
+            (Some(language_opts), Some(language)) if is_wrong_language(&first, language) => {
+                retry_wrong_language(
+                    first,
+                    language_opts,
+                    language,
+                    pre_run_warnings.clone(),
+                    &tail,
+                )
+                .await
+            }
+            _ => Ok(first),
+        }
+    })
+    .await
+    {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => {
+            history::record(RunRecord {
+                run_id,
+                source,
+                working_dir,
+                started_at,
+                duration_ms: started.elapsed().as_millis() as u64,
+                success: false,
+                timed_out: false,
+                session_id: String::new(),
+                error: Some(match &prompt_secrets {
+                    Some(secrets) => secrets.scrub(&e.to_string()),
+                    None => e.to_string(),
+                }),
+                failure_fingerprint: None,
+                resource_usage: None,
+                task_id,
+                meta,
+                token_usage: None,
+                changed_files: Vec::new(),
+                diff_file: None,
+                empty_response_retries,
+                task_kind: None,
+                rerun_of,
+                primer_messages,
+                agent_messages: String::new(),
+                invocation: Some(invocation),
+            });
+            return Err(e);
+        }
+        Err(_) => {
+            // Timeout occurred - the child process is killed and reaped as its run is dropped
+            timed_out = true;
+            let result = CodexResult {
+                run_id: String::new(),
                 success: false,
                 session_id: String::new(),
                 agent_messages: String::new(),
@@ -341,46 +2220,704 @@ pub async fn run(mut opts: Options) -> Result<CodexResult> {
                     "Codex execution timed out after {} seconds",
                     timeout_secs
                 )),
-                warnings: agents_warning,
+                warnings: pre_run_warnings,
+                operator_warnings: None,
+                unresolved_paths: Vec::new(),
+                sandbox_downgrade: None,
+                model_fallback: None,
+                termination: None,
+                fatal_error: None,
+                resource_usage: None,
+                token_usage: None,
+                context_used_percent: None,
+                budget_trims: Vec::new(),
+                diff: None,
+                fs_changes: None,
+                plan: Vec::new(),
+                files_read: Vec::new(),
+                files_read_truncated: false,
+                next_steps: Vec::new(),
+                lossy_utf8: None,
+                pdf_pages: Vec::new(),
+                final_value: None,
+                log_file: log_path,
             };
             // Skip validation since timeout error is already well-defined
-            Ok(enforce_required_fields(result, ValidationMode::Skip))
+            enforce_required_fields(result, ValidationMode::Skip)
+        }
+    };
+
+    if let Some(before) = scope_snapshot
+        .as_ref()
+        .filter(|_| !allowed_paths.is_empty())
+    {
+        let policy = server_config().out_of_scope_changes;
+        match write_scope::enforce(&working_dir, before, &allowed_paths, policy).await {
+            Ok(report) if report.is_clean() => {}
+            Ok(report) if policy == ScopePolicy::Fail => {
+                result.success = false;
+                result.error = Some(match result.error.take() {
+                    Some(existing) => format!("{}\n{}", existing, report.describe()),
+                    None => report.describe(),
+                });
+            }
+            Ok(report) => {
+                result.warnings = push_warning(result.warnings.take(), &report.describe());
+            }
+            Err(e) => {
+                result.warnings = push_warning(
+                    result.warnings.take(),
+                    &format!("Failed to verify ALLOWED_PATHS: {}", e),
+                );
+            }
+        }
+    }
+
+    if let Some(watcher) = fs_watcher {
+        let observed = watcher.stop().await;
+        let changes = fs_watch::reconcile(&working_dir, observed, &result.all_messages).await;
+        for warning in changes.warnings() {
+            result.warnings = push_warning(result.warnings.take(), &warning);
         }
+        result.fs_changes = Some(changes);
     }
+
+    if let Some(before) = scope_snapshot.as_ref().filter(|_| return_diff) {
+        let max_inline_bytes = max_inline_diff_bytes();
+        let provenance = Provenance::new(
+            &run_id,
+            &result.session_id,
+            // The model the changes were actually made with
+            match &result.model_fallback {
+                Some(fallback) => fallback.used.clone(),
+                None => model,
+            },
+            provenance::prompt_hash(&working_dir, &user_prompt).await,
+        );
+        match run_diff::collect(
+            &working_dir,
+            before,
+            max_inline_bytes,
+            &runs_dir(),
+            provenance,
+        )
+        .await
+        {
+            Ok(diff) => {
+                if diff.diff_file.is_some() {
+                    result.warnings = push_warning(
+                        result.warnings.take(),
+                        &format!(
+                            "Diff ({} bytes) exceeds max_inline_diff_bytes ({}); only per-file stats are returned",
+                            diff.diff_bytes, max_inline_bytes
+                        ),
+                    );
+                }
+                result.diff = Some(diff);
+            }
+            Err(e) => {
+                result.warnings = push_warning(
+                    result.warnings.take(),
+                    &format!("Failed to collect RETURN_DIFF: {}", e),
+                );
+            }
+        }
+    }
+
+    if let (Some(before), Some(max_growth_bytes)) = (usage_before, guard.max_growth_bytes) {
+        let dir = working_dir.clone();
+        if let Ok(after) = tokio::task::spawn_blocking(move || disk_guard::dir_usage(&dir)).await {
+            if let Some(warning) = disk_guard::growth_warning(before, after, max_growth_bytes) {
+                result.warnings = push_warning(result.warnings.take(), &warning);
+            }
+        }
+    }
+
+    result.unresolved_paths = unresolved_paths;
+    // The run may have committed or switched branches
+    git_info::invalidate(&working_dir);
+    result.sandbox_downgrade = sandbox_downgrade;
+    if let Some(fallback) = &result.model_fallback {
+        result.warnings = push_warning(result.warnings.take(), &fallback.warning());
+    }
+    result.pdf_pages = expanded_pdfs.pdfs.clone();
+    result.budget_trims = budget_trims;
+    if let Some(operator_warnings) = operator_warnings {
+        result.operator_warnings = match result.operator_warnings.take() {
+            Some(existing) => Some(format!("{}\n{}", operator_warnings, existing)),
+            None => Some(operator_warnings),
+        };
+    }
+
+    if resume_last && !result.session_id.is_empty() {
+        if let Some(warning) = check_resumed_session(&working_dir, &result.session_id) {
+            result.warnings = push_warning(result.warnings.take(), &warning);
+        }
+    }
+    if !result.session_id.is_empty() {
+        record_session(&working_dir, &result.session_id);
+    }
+
+    if let Some(secrets) = &prompt_secrets {
+        secrets.apply(&mut result);
+        result.warnings = push_warning(result.warnings.take(), &secrets.warning());
+    }
+
+    if server_config().workspace_relative_paths.unwrap_or(true) {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        PathRewriter::new(&working_dir, home.as_deref()).apply(&mut result);
+    }
+
+    result.run_id = run_id.clone();
+    history::record(RunRecord {
+        run_id,
+        source,
+        working_dir,
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        success: result.success,
+        timed_out,
+        session_id: result.session_id.clone(),
+        error: result.error.clone(),
+        failure_fingerprint: None,
+        resource_usage: result.resource_usage,
+        task_id,
+        meta,
+        token_usage: result.token_usage,
+        changed_files: result
+            .diff
+            .as_ref()
+            .map(|diff| diff.files.clone())
+            .unwrap_or_default(),
+        diff_file: result.diff.as_ref().and_then(|diff| diff.diff_file.clone()),
+        empty_response_retries,
+        // A timed-out run has no events to go by
+        task_kind: (!timed_out).then(|| classify::classify(&user_prompt, &result.all_messages)),
+        rerun_of,
+        primer_messages,
+        agent_messages: result.agent_messages.clone(),
+        invocation: Some(invocation),
+    });
+
+    Ok(result)
 }
 
-/// Internal implementation of codex execution
-async fn run_internal(opts: Options, agents_warning: Option<String>) -> Result<CodexResult> {
-    // Allow overriding the codex binary for tests or custom setups
-    let codex_bin = std::env::var("CODEX_BIN").unwrap_or_else(|_| "codex".to_string());
+/// Prompt sent when resuming a session that returned no agent messages
+const EMPTY_RESPONSE_RETRY_PROMPT: &str =
+    "Your previous turn ended without a reply. Please answer the request.";
 
-    // Build the base command
-    let mut cmd = Command::new(codex_bin);
-    cmd.args(["exec", "--cd"]);
+/// A session was created but Codex said nothing: the CLI flake that
+/// `retry_on_empty_response` works around
+fn is_empty_response(result: &CodexResult) -> bool {
+    result.success && result.agent_messages.is_empty() && !result.session_id.is_empty()
+}
 
-    // Use OsStr for path handling to support non-UTF-8 paths
-    cmd.arg(opts.working_dir.as_os_str());
-    cmd.arg("--json");
+/// Retry a run whose `first` attempt returned no agent messages. The retry is
+/// not written to the run log, so the log keeps the empty attempt.
+async fn retry_empty_response(
+    first: CodexResult,
+    mut opts: Options,
+    policy: EmptyResponseRetry,
+    pre_run_warnings: Option<String>,
+    tail: &Tail,
+) -> Result<CodexResult> {
+    if policy == EmptyResponseRetry::Resume {
+        opts.prompt = EMPTY_RESPONSE_RETRY_PROMPT.to_string();
+        opts.session_id = Some(first.session_id.clone());
+        opts.resume_last = false;
+        opts.image_paths.clear();
+    }
+    let mut result = run_internal(opts, pre_run_warnings, None, Some(tail)).await?;
+    result.token_usage = match (first.token_usage, result.token_usage) {
+        (Some(mut total), Some(retry)) => {
+            total.add(&retry);
+            Some(total)
+        }
+        (usage, None) | (None, usage) => usage,
+    };
+    result.log_file = first.log_file;
+    result.model_fallback = first.model_fallback;
+    result.warnings = push_warning(
+        result.warnings.take(),
+        &format!(
+            "Session {} returned no agent messages; retried once ({})",
+            first.session_id,
+            match policy {
+                EmptyResponseRetry::Resume => "resumed the session",
+                _ => "resubmitted the prompt",
+            }
+        ),
+    );
+    Ok(result)
+}
+
+/// The run's response language, from the request or the config. Its
+/// instruction is appended to the prompt.
+fn response_language_for(opts: &mut Options) -> Option<String> {
+    let language = opts
+        .response_language
+        .clone()
+        .or_else(|| server_config().response_language.clone())
+        .filter(|language| !language.trim().is_empty())?;
+    opts.prompt = format!("{}\n\n{}", opts.prompt, language::instruction(&language));
+    Some(language)
+}
+
+/// A session answered, but not in the requested language
+fn is_wrong_language(result: &CodexResult, language: &str) -> bool {
+    result.success
+        && !result.session_id.is_empty()
+        && language::is_written_in(&result.agent_messages, language) == Some(false)
+}
+
+/// Resume a session whose `first` answer was not in `language` and ask for it
+/// again. The retry is not written to the run log.
+async fn retry_wrong_language(
+    first: CodexResult,
+    mut opts: Options,
+    language: &str,
+    pre_run_warnings: Option<String>,
+    tail: &Tail,
+) -> Result<CodexResult> {
+    let name = language::name(language);
+    opts.prompt = format!(
+        "Your previous answer was not in {name}. Repeat your final answer in {name}, without \
+         making further changes.\n\n{}",
+        language::instruction(language)
+    );
+    opts.session_id = Some(first.session_id.clone());
+    opts.resume_last = false;
+    opts.image_paths.clear();
+    let mut result = run_internal(opts, pre_run_warnings, None, Some(tail)).await?;
+    if !result.success {
+        // Keep the answer in the wrong language over none at all
+        let mut first = first;
+        first.warnings = push_warning(
+            first.warnings.take(),
+            &format!(
+                "Answer was not in {}; asking again failed: {}",
+                name,
+                result.error.as_deref().unwrap_or("unknown error")
+            ),
+        );
+        return Ok(first);
+    }
+    result.token_usage = match (first.token_usage, result.token_usage) {
+        (Some(mut total), Some(retry)) => {
+            total.add(&retry);
+            Some(total)
+        }
+        (usage, None) | (None, usage) => usage,
+    };
+    result.log_file = first.log_file;
+    result.model_fallback = first.model_fallback;
+    let still_wrong = language::is_written_in(&result.agent_messages, language) == Some(false);
+    result.warnings = push_warning(
+        result.warnings.take(),
+        &format!(
+            "Session {} answered in another language than {}; resumed it once to ask again{}",
+            first.session_id,
+            name,
+            if still_wrong {
+                ", but the answer is still not in that language"
+            } else {
+                ""
+            }
+        ),
+    );
+    Ok(result)
+}
+
+/// Characters of the CLI's error kept in `ModelFallback::reason`
+const MAX_FALLBACK_REASON_CHARS: usize = 300;
+
+/// The CLI refused the run's model
+fn is_model_rejected(result: &CodexResult) -> bool {
+    !result.success
+        && result
+            .error
+            .as_deref()
+            .and_then(model_fallback::rejection)
+            .is_some()
+}
+
+/// Retry a run whose `first` attempt was refused for its model, with `opts`
+/// selecting the fallback model. The retry is not written to the run log.
+async fn retry_with_fallback_model(
+    first: CodexResult,
+    requested: String,
+    opts: Options,
+    pre_run_warnings: Option<String>,
+    tail: &Tail,
+) -> Result<CodexResult> {
+    let reason: String = first
+        .error
+        .as_deref()
+        .and_then(model_fallback::rejection)
+        .unwrap_or_default()
+        .chars()
+        .take(MAX_FALLBACK_REASON_CHARS)
+        .collect();
+    let fallback = ModelFallback {
+        requested,
+        used: provenance::model_from_args(&opts.additional_args),
+        reason,
+    };
+    let mut result = run_internal(opts, pre_run_warnings, None, Some(tail)).await?;
+    result.log_file = first.log_file;
+    result.model_fallback = Some(fallback);
+    Ok(result)
+}
 
-    // Append any extra CLI flags requested by the caller, before the prompt delimiter.
-    for arg in &opts.additional_args {
-        cmd.arg(arg);
+/// Warning for a PDF of which only the first `pdf_max_pages` pages were
+/// attached
+fn partial_pdf_warning(pdf: &PdfPages) -> Option<String> {
+    let total = pdf
+        .total_pages
+        .filter(|total| *total > pdf.pages_included)?;
+    Some(format!(
+        "Only {} of {} pages of {} were attached (pdf_max_pages is {})",
+        pdf.pages_included,
+        total,
+        pdf.file.display(),
+        pdf_max_pages()
+    ))
+}
+
+/// Answer a question with `codex exec` in plain-text mode (no `--json`) and
+/// return its stdout as `agent_messages`. No events are parsed, so there is no
+/// SESSION_ID; runs are always read-only. `session_id`, `resume_last`,
+/// `log_to_file`, and `allowed_paths` are ignored.
+pub async fn run_plain(mut opts: Options) -> Result<CodexResult> {
+    cli_version::check(cli_version_config()).await?;
+    let prompt_secrets =
+        PromptSecrets::detect(configured_redactor(), &opts.prompt, &opts.primer_messages);
+    let primer_messages = opts.primer_messages.len();
+    prepend_primer(&mut opts);
+    let user_prompt = opts.prompt.clone();
+    // Plain runs have no session to resume, so the answer is not checked
+    response_language_for(&mut opts);
+    server_config()
+        .run_environment
+        .prepend_preamble(&mut opts.prompt);
+    let (agents_content, agents_warning) =
+        read_agents_for(&opts.working_dir, SandboxMode::ReadOnly).await;
+    if let Some(max_bytes) = server_config().max_prompt_bytes {
+        prompt_budget::check_max(
+            max_bytes,
+            &user_prompt,
+            opts.prompt.len().saturating_sub(user_prompt.len()),
+            agents_content.as_deref(),
+            &opts.image_paths,
+        )?;
+    }
+    if let Some(content) = agents_content {
+        opts.prompt = format!(
+            "<system_prompt>\n{}\n</system_prompt>\n\n{}",
+            content, opts.prompt
+        );
     }
 
-    // Attach image files, if any, as repeated --image flags.
+    let timeout_secs = opts.timeout_secs.unwrap_or_else(default_timeout_secs);
+    let run_id = Uuid::new_v4().to_string();
+    let started_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let started = std::time::Instant::now();
+
+    // PDF attachments are sent as images of their pages, as in `run`; the
+    // rendered pages are removed when this guard drops
+    let expanded_pdfs = pdf_pages::expand(&opts.image_paths, &run_id, pdf_max_pages())
+        .await
+        .map_err(anyhow::Error::msg)?;
+    opts.image_paths = expanded_pdfs.paths.clone();
+
+    let mut cmd = Command::new(codex_bin());
+    cmd.args(["exec", "--cd"]);
+    cmd.arg(opts.working_dir.as_os_str());
+    cmd.args(with_sandbox(&opts.additional_args, SandboxMode::ReadOnly));
     for image_path in &opts.image_paths {
         cmd.arg("--image");
         cmd.arg(image_path);
     }
+    cmd.args(["--", &opts.prompt]);
+    if let Some(ref codex_home) = opts.codex_home {
+        cmd.env("CODEX_HOME", codex_home);
+    }
+    server_config().run_environment.apply(&mut cmd);
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    cmd.kill_on_drop(true);
+
+    let mut result = CodexResult {
+        run_id: run_id.clone(),
+        operator_warnings: agents_warning,
+        pdf_pages: expanded_pdfs.pdfs.clone(),
+        warnings: expanded_pdfs
+            .pdfs
+            .iter()
+            .filter_map(partial_pdf_warning)
+            .reduce(|all, warning| format!("{}\n{}", all, warning)),
+        ..Default::default()
+    };
+    let mut timed_out = false;
+    let cancel = opts.cancel.clone().unwrap_or_default();
+    // Dropping the read on timeout or cancellation kills the process
+    let output = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => None,
+        output = tokio::time::timeout(
+            std::time::Duration::from_secs(timeout_secs),
+            read_plain_output(&mut cmd),
+        ) => Some(output),
+    };
+    match output {
+        Some(Ok(Ok(output))) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if server_config().sanitize_agent_messages.unwrap_or(true) {
+                text = sanitize::agent_text(&text).into_owned();
+            }
+            result.agent_messages_truncated = output.truncated;
+            if text.len() > DEFAULT_HEAD_BYTES {
+                text.truncate(floor_char_boundary(&text, DEFAULT_HEAD_BYTES));
+                result.agent_messages_truncated = true;
+            }
+            let stderr = output.stderr.trim().to_string();
+            result.success = output.status.success();
+            result.agent_messages = text;
+            if !result.success {
+                result.termination = Termination::from_status(&output.status, false);
+                let error_msg = match &result.termination {
+                    Some(termination) => termination.message(None),
+                    None => format!(
+                        "codex command failed with exit code: {:?}",
+                        output.status.code()
+                    ),
+                };
+                result.error = Some(if stderr.is_empty() {
+                    error_msg
+                } else {
+                    format!("{}\nStderr: {}", error_msg, stderr)
+                });
+            } else if result.agent_messages.is_empty() {
+                result.success = false;
+                result.error = Some("Codex returned no output".to_string());
+            }
+        }
+        Some(Ok(Err(e))) => {
+            result.error = Some(format!("Failed to run codex command: {}", e));
+        }
+        Some(Err(_)) => {
+            timed_out = true;
+            result.error = Some(format!(
+                "Codex execution timed out after {} seconds",
+                timeout_secs
+            ));
+        }
+        None => {
+            result.error = Some("Codex run was cancelled".to_string());
+        }
+    }
 
-    // Add session resume or prompt
-    if let Some(ref session_id) = opts.session_id {
-        cmd.args(["resume", session_id]);
+    if let Some(secrets) = &prompt_secrets {
+        secrets.apply(&mut result);
+        result.warnings = push_warning(result.warnings.take(), &secrets.warning());
     }
 
-    // Add the prompt at the end - Command::arg() handles proper escaping across platforms
-    // Note: When resuming, the prompt serves as a continuation message in the existing session
-    cmd.args(["--", &opts.prompt]);
+    history::record(RunRecord {
+        run_id,
+        source: opts.source.unwrap_or_else(|| "plain".to_string()),
+        working_dir: opts.working_dir,
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        success: result.success,
+        timed_out,
+        session_id: String::new(),
+        error: result.error.clone(),
+        failure_fingerprint: None,
+        resource_usage: None,
+        task_id: opts.task_id,
+        meta: opts.meta,
+        token_usage: None,
+        changed_files: Vec::new(),
+        diff_file: None,
+        empty_response_retries: 0,
+        // Plain runs are read-only
+        task_kind: Some(TaskKind::Explain),
+        rerun_of: opts.rerun_of,
+        primer_messages,
+        agent_messages: result.agent_messages.clone(),
+        invocation: None,
+    });
+
+    Ok(result)
+}
+
+/// Output of a plain-text run
+struct PlainOutput {
+    status: std::process::ExitStatus,
+    stdout: Vec<u8>,
+    /// Lines were dropped or cut short to stay within the limits
+    truncated: bool,
+    stderr: String,
+}
+
+/// Spawn `cmd` and read its output with the line and output budget limits of
+/// `run_internal`. Reading stops keeping stdout past `DEFAULT_HEAD_BYTES` but
+/// drains the rest, so Codex never blocks on a full pipe.
+async fn read_plain_output(cmd: &mut Command) -> std::io::Result<PlainOutput> {
+    let mut child = OwnedChild::new(cmd.spawn()?);
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| std::io::Error::other("Failed to get stdout"))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| std::io::Error::other("Failed to get stderr"))?;
+
+    let budget = std::sync::Arc::new(OutputBudget::new(&server_config().output_budget));
+    let (stderr_handle, stderr_buf) = spawn_stderr_reader(stderr, budget.clone());
+
+    let mut reader = BufReader::new(stdout);
+    let mut output = Vec::new();
+    let mut truncated = false;
+    let mut line_buf = Vec::new();
+    loop {
+        line_buf.clear();
+        let read = read_line_with_limit(&mut reader, &mut line_buf, MAX_LINE_LENGTH, None).await?;
+        if read.bytes_read == 0 {
+            break; // EOF
+        }
+        // Once a line is dropped, later ones are only drained so the
+        // answer has no gaps
+        if truncated {
+            continue;
+        }
+        if output.len() > DEFAULT_HEAD_BYTES || !budget.take_stdout(line_buf.len()) {
+            truncated = true;
+            continue;
+        }
+        output.extend_from_slice(&line_buf);
+        truncated = read.truncated;
+    }
+
+    let (status, stderr) = tokio::join!(
+        child.wait(),
+        collect_stderr(stderr_handle, &stderr_buf, None)
+    );
+    Ok(PlainOutput {
+        status: status?,
+        stdout: output,
+        truncated,
+        stderr,
+    })
+}
+
+/// Last session started or resumed by this server, per working directory. Used to
+/// tell callers which session `resume --last` actually picked up.
+fn session_registry() -> &'static Mutex<HashMap<PathBuf, String>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<PathBuf, String>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub(crate) fn record_session(working_dir: &std::path::Path, session_id: &str) {
+    session_expiry::touch(session_id);
+    if let Ok(mut registry) = session_registry().lock() {
+        registry.insert(working_dir.to_path_buf(), session_id.to_string());
+    }
+}
+
+/// Warning text if the session resumed via `--last` is not the one this server
+/// last saw for `working_dir`
+fn check_resumed_session(working_dir: &std::path::Path, resumed: &str) -> Option<String> {
+    let registry = session_registry().lock().ok()?;
+    match registry.get(working_dir) {
+        Some(expected) if expected == resumed => None,
+        Some(expected) => Some(format!(
+            "Resumed last session {}, but the last session this server ran in {} was {}; the Codex CLI may have picked a session started elsewhere.",
+            resumed,
+            working_dir.display(),
+            expected
+        )),
+        None => Some(format!(
+            "Resumed last session {}, which was not started by this server.",
+            resumed
+        )),
+    }
+}
+
+/// Arguments of a `codex exec` run, after the binary name:
+///
+/// `exec --cd DIR [extra args] [--image FILE].. [--output-schema FILE] --json
+/// [resume (ID | --last)] -- PROMPT`
+///
+/// `--image` takes any number of values, so it must be followed by another
+/// flag; otherwise `resume` and the session id would be read as image files.
+/// `--json` is always present and goes last among the options for that
+/// reason. `resume` comes after every `exec` option since the CLI parses
+/// options after it as the subcommand's, and the prompt follows `--` so one
+/// starting with `-` is not taken for a flag.
+fn build_argv(opts: &Options, schema_path: Option<&std::path::Path>) -> Vec<OsString> {
+    let mut argv: Vec<OsString> = vec!["exec".into(), "--cd".into()];
+    // Use OsString for paths to support non-UTF-8 paths
+    argv.push(opts.working_dir.clone().into_os_string());
+
+    // Extra CLI flags requested by the caller, e.g. `--model` or `--profile`
+    argv.extend(opts.additional_args.iter().map(OsString::from));
+
+    for image_path in &opts.image_paths {
+        argv.push("--image".into());
+        argv.push(image_path.clone().into_os_string());
+    }
+    if let Some(path) = schema_path {
+        argv.push("--output-schema".into());
+        argv.push(path.as_os_str().to_os_string());
+    }
+    if opts.include_plan_tool {
+        argv.push(PLAN_TOOL_FLAG.into());
+    }
+    argv.push("--json".into());
+
+    // When resuming, the prompt serves as a continuation message in the
+    // existing session
+    if let Some(session_id) = &opts.session_id {
+        argv.extend(["resume".into(), session_id.into()]);
+    } else if opts.resume_last {
+        argv.extend(["resume".into(), "--last".into()]);
+    }
+
+    argv.extend(["--".into(), opts.prompt.as_str().into()]);
+    argv
+}
+
+/// Internal implementation of codex execution
+async fn run_internal(
+    opts: Options,
+    mut pre_run_warnings: Option<String>,
+    log_path: Option<PathBuf>,
+    tail: Option<&Tail>,
+) -> Result<CodexResult> {
+    // The CLI reads the schema from a file, kept until the run ends
+    let schema_file = match &opts.final_schema {
+        Some(schema) => Some(
+            SchemaFile::write(schema, &Uuid::new_v4().to_string())
+                .context("Failed to write FINAL_SCHEMA file")?,
+        ),
+        None => None,
+    };
+
+    let mut cmd = Command::new(codex_bin());
+    cmd.args(build_argv(
+        &opts,
+        schema_file.as_ref().map(|file| file.path()),
+    ));
+
+    if let Some(ref codex_home) = opts.codex_home {
+        cmd.env("CODEX_HOME", codex_home);
+    }
+    server_config().run_environment.apply(&mut cmd);
 
     // Configure process
     cmd.stdin(Stdio::null());
@@ -388,14 +2925,42 @@ async fn run_internal(opts: Options, agents_warning: Option<String>) -> Result<C
     cmd.stderr(Stdio::piped());
     cmd.kill_on_drop(true); // Ensure child is killed if this future is dropped (e.g., on timeout)
 
-    // Spawn the process
-    let mut child = cmd.spawn().context("Failed to spawn codex command")?;
+    // Open the run log before spawning so no output is missed
+    let mut run_log = match log_path {
+        Some(path) => match RunLog::create(&path, at_rest_key().ok().flatten()).await {
+            Ok(log) => {
+                let redactor =
+                    storage_redactor().or_else(|| opts.redact_log.then(configured_redactor));
+                let mut log = log.redacted_with(redactor);
+                if let Some(meta) = &opts.meta {
+                    log.write_meta(meta).await;
+                }
+                Some(log)
+            }
+            Err(e) => {
+                pre_run_warnings = push_warning(
+                    pre_run_warnings,
+                    &format!("Failed to create run log {}: {}", path.display(), e),
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    // Spawn the process; if this future is dropped, it is killed and reaped
+    let mut child = OwnedChild::new(cmd.spawn().context("Failed to spawn codex command")?);
 
     // Read stdout
     let stdout = child.stdout.take().context("Failed to get stdout")?;
     let stderr = child.stderr.take().context("Failed to get stderr")?;
 
+    // Resolves once the process exits, without reaping it, so a failure is
+    // noticed while something it spawned still holds the pipes open
+    let mut exit_watch = child.id().map(child_wait::watch_exit);
+
     let mut result = CodexResult {
+        run_id: String::new(),
         success: true,
         session_id: String::new(),
         agent_messages: String::new(),
@@ -404,72 +2969,108 @@ async fn run_internal(opts: Options, agents_warning: Option<String>) -> Result<C
         all_messages_truncated: false,
         error: None,
         warnings: None,
+        operator_warnings: None,
+        unresolved_paths: Vec::new(),
+        sandbox_downgrade: None,
+        model_fallback: None,
+        termination: None,
+        fatal_error: None,
+        resource_usage: None,
+        token_usage: None,
+        context_used_percent: None,
+        budget_trims: Vec::new(),
+        diff: None,
+        fs_changes: None,
+        plan: Vec::new(),
+        files_read: Vec::new(),
+        files_read_truncated: false,
+        next_steps: Vec::new(),
+        lossy_utf8: None,
+        pdf_pages: Vec::new(),
+        final_value: None,
+        log_file: run_log.as_ref().map(|log| log.path().to_path_buf()),
     };
 
-    // Spawn a task to drain stderr and capture diagnostics with better error handling
-    const MAX_STDERR_SIZE: usize = 1024 * 1024; // 1MB limit for stderr
-    const MAX_LINE_LENGTH: usize = 1024 * 1024; // 1MB per line to prevent memory spikes
-    const MAX_AGENT_MESSAGES_SIZE: usize = 10 * 1024 * 1024; // 10MB limit for agent messages
-    const MAX_ALL_MESSAGES_SIZE: usize = 50 * 1024 * 1024; // 50MB limit for all messages combined
-    let stderr_handle = tokio::spawn(async move {
-        let mut stderr_output = String::new();
-        let mut stderr_reader = BufReader::new(stderr);
-        let mut truncated = false;
-        let mut line_buf = Vec::new();
-
-        loop {
-            line_buf.clear();
-            match read_line_with_limit(&mut stderr_reader, &mut line_buf, MAX_LINE_LENGTH).await {
-                Ok(read_result) => {
-                    if read_result.bytes_read == 0 {
-                        break; // EOF
-                    }
-                    // Convert to string, handling invalid UTF-8
-                    let line = String::from_utf8_lossy(&line_buf);
-                    let line = line.trim_end_matches('\n').trim_end_matches('\r');
-
-                    // Check if adding this line would exceed the limit
-                    let new_size = stderr_output.len() + line.len() + 1; // +1 for newline
-                    if new_size > MAX_STDERR_SIZE {
-                        if !truncated {
-                            if !stderr_output.is_empty() {
-                                stderr_output.push('\n');
-                            }
-                            stderr_output.push_str("[... stderr truncated due to size limit ...]");
-                            truncated = true;
-                        }
-                        // Continue draining to prevent blocking the child process
-                    } else if !truncated {
-                        if !stderr_output.is_empty() {
-                            stderr_output.push('\n');
-                        }
-                        stderr_output.push_str(line.as_ref());
-                    }
-                }
-                Err(e) => {
-                    // Log the read error but continue - this preserves diagnostic info
-                    eprintln!("Warning: Failed to read from stderr: {}", e);
-                    break;
-                }
-            }
-        }
-
-        stderr_output
-    });
+    // stdout events and stderr each get a fixed share of one budget, so
+    // neither can crowd out the other
+    let budget = std::sync::Arc::new(OutputBudget::new(&server_config().output_budget));
+    let (stderr_handle, stderr_buf) = spawn_stderr_reader(stderr, budget.clone());
 
     // Read stdout line by line with length limit
     let mut reader = BufReader::new(stdout);
+    let mut agent_messages = AgentMessageCollector::new(&server_config().agent_messages_truncation);
+    let mut dedup = server_config()
+        .dedup_agent_messages
+        .unwrap_or(true)
+        .then(AgentMessageDedup::default);
+    let sanitize_messages = server_config().sanitize_agent_messages.unwrap_or(true);
+    let mut last_agent_message: Option<String> = None;
     let mut parse_error_seen = false;
+    // A SIGKILL codex-mcp sent itself is not an out-of-memory kill
+    let mut killed_by_server = false;
     let mut line_buf = Vec::new();
-    let mut all_messages_size: usize = 0;
+    let mut line_number = 0;
+    let mut lossy_utf8 = LossyUtf8::default();
+    let cancel = opts.cancel.clone().unwrap_or_default();
+    let mut slice_started = std::time::Instant::now();
+    let mut drain_deadline: Option<tokio::time::Instant> = None;
+    let mut context = ContextTracker::new(context_window_tokens());
+    let mut plan_tracker = PlanTracker::default();
+    let mut files_read = FilesRead::default();
+    let mut estimator = opts
+        .progress
+        .is_some()
+        .then(|| ProgressEstimator::new(history::expected_duration(&opts.working_dir)));
 
     loop {
+        if slice_started.elapsed() >= PARSE_TIME_SLICE {
+            tokio::task::yield_now().await;
+            slice_started = std::time::Instant::now();
+        }
+
         line_buf.clear();
-        match read_line_with_limit(&mut reader, &mut line_buf, MAX_LINE_LENGTH).await {
+        // Checked before every line, so a producer that never lets the read
+        // return Pending still cannot keep a cancelled run alive
+        let read = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => {
+                result.success = false;
+                result.error = Some("Codex run was cancelled".to_string());
+                killed_by_server = true;
+                let _ = child.start_kill();
+                break;
+            }
+            usage = watch_exit(&mut exit_watch), if exit_watch.is_some() => {
+                if usage.is_some() {
+                    result.resource_usage = usage;
+                    drain_deadline = Some(tokio::time::Instant::now() + EXIT_DRAIN_GRACE);
+                }
+                continue;
+            }
+            read = read_line_with_limit(
+                &mut reader,
+                &mut line_buf,
+                MAX_LINE_LENGTH,
+                run_log.as_mut(),
+            ) => read,
+            // Only reached while no output is pending, so buffered lines are
+            // still parsed after the process exits
+            _ = tokio::time::sleep_until(drain_deadline.unwrap_or_else(tokio::time::Instant::now)),
+                if drain_deadline.is_some() =>
+            {
+                result.warnings = push_warning(
+                    result.warnings.take(),
+                    "Codex exited but its output stayed open, likely held by a process it started; stopped reading",
+                );
+                break;
+            }
+        };
+        match read {
             Ok(read_result) => {
                 if read_result.bytes_read == 0 {
                     break; // EOF
                 }
+                line_number += 1;
 
                 // Check for line truncation - short-circuit to error instead of attempting parse
                 if read_result.truncated {
@@ -482,99 +3083,113 @@ async fn run_internal(opts: Options, agents_warning: Option<String>) -> Result<C
                     if !parse_error_seen {
                         parse_error_seen = true;
                         // Stop the child so it cannot block on a full pipe, then keep draining
+                        killed_by_server = true;
                         let _ = child.start_kill();
                     }
                     continue;
                 }
 
-                // Convert to string
-                let line = String::from_utf8_lossy(&line_buf);
-                let line = line.trim_end_matches('\n').trim_end_matches('\r');
-
-                if line.is_empty() {
-                    continue;
-                }
+                lossy_utf8.record(line_number, &line_buf);
 
                 // After a parse error, keep draining stdout to avoid blocking the child process
                 if parse_error_seen {
                     continue;
                 }
 
-                // Parse JSON line
-                let line_data: Value = match serde_json::from_str(line) {
-                    Ok(data) => data,
-                    Err(e) => {
-                        record_parse_error(&mut result, &e, line);
-                        if !parse_error_seen {
-                            parse_error_seen = true;
-                            // Stop the child so it cannot block on a full pipe, then keep draining
-                            let _ = child.start_kill();
-                        }
+                let line_data = match parser::parse_line(&line_buf) {
+                    Line::Blank => continue,
+                    Line::Event(event) => event,
+                    Line::Invalid { text, error } => {
+                        let log_file = run_log.as_ref().map(|log| log.path());
+                        record_parse_error(&mut result, &error, &text, log_file);
+                        parse_error_seen = true;
+                        // Stop the child so it cannot block on a full pipe, then keep draining
+                        killed_by_server = true;
+                        let _ = child.start_kill();
                         continue;
                     }
                 };
 
+                if let Some(tail) = tail {
+                    tail.push(&line_data);
+                }
+
                 // Collect all messages with bounds checking
                 if let Ok(map) = serde_json::from_value::<HashMap<String, Value>>(line_data.clone())
                 {
                     // Estimate size of this message (JSON serialized size)
                     let message_size = serde_json::to_string(&map).map(|s| s.len()).unwrap_or(0);
 
-                    // Check if adding this message would exceed byte limit
-                    if all_messages_size + message_size <= MAX_ALL_MESSAGES_SIZE {
-                        all_messages_size += message_size;
+                    // Check if adding this message would exceed the output budget
+                    if budget.take_stdout(message_size) {
                         result.all_messages.push(map);
                     } else if !result.all_messages_truncated {
                         result.all_messages_truncated = true;
                     }
                 }
 
-                // Extract thread_id
-                if let Some(thread_id) = line_data.get("thread_id").and_then(|v| v.as_str()) {
-                    if !thread_id.is_empty() {
-                        result.session_id = thread_id.to_string();
+                let plan_update = plan_tracker.observe(&line_data);
+                files_read.observe(&line_data);
+                if let (Some(progress), Some(estimator)) = (&opts.progress, estimator.as_mut()) {
+                    estimator.observe(&line_data);
+                    // Plan changes get their own notification, with the steps
+                    // that moved
+                    let message = match &plan_update {
+                        Some(update) => Some(plan::message(update)),
+                        None => server_config().progress.message(&line_data),
+                    };
+                    if let Some(message) = message {
+                        let _ = progress.send(ProgressUpdate {
+                            message,
+                            percent: estimator.percent(),
+                            plan: plan_update,
+                        });
                     }
                 }
 
-                // Extract agent messages with size limits
-                if let Some(item) = line_data.get("item").and_then(|v| v.as_object()) {
-                    if let Some(item_type) = item.get("type").and_then(|v| v.as_str()) {
-                        if item_type == "agent_message" {
-                            if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
-                                // Check if adding this text would exceed the limit
-                                let new_size = result.agent_messages.len() + text.len();
-                                if new_size > MAX_AGENT_MESSAGES_SIZE {
-                                    if !result.agent_messages_truncated {
-                                        result.agent_messages.push_str(
-                                    "\n[... Agent messages truncated due to size limit ...]",
-                                );
-                                        result.agent_messages_truncated = true;
-                                    }
-                                } else if !result.agent_messages_truncated {
-                                    // Add a newline separator between multiple agent messages for better parsing
-                                    if !result.agent_messages.is_empty() && !text.is_empty() {
-                                        result.agent_messages.push('\n');
-                                    }
-                                    result.agent_messages.push_str(text);
-                                }
-                            }
-                        }
+                context.observe(&line_data);
+                let fields = parser::extract(&line_data);
+                if let Some(usage) = fields.usage {
+                    result
+                        .token_usage
+                        .get_or_insert_with(TokenUsage::default)
+                        .add_event(usage);
+                }
+                if let Some(thread_id) = fields.thread_id {
+                    result.session_id = thread_id.to_string();
+                }
+                // Agent messages are kept within their size limits
+                if let Some(message) = fields.agent_message {
+                    let text = if sanitize_messages {
+                        sanitize::agent_text(message.text)
+                    } else {
+                        Cow::Borrowed(message.text)
+                    };
+                    last_agent_message = Some(text.to_string());
+                    let verdict = dedup
+                        .as_mut()
+                        .map_or(DedupVerdict::Keep, |d| d.admit(message.id, &text));
+                    match (verdict, message.id) {
+                        (DedupVerdict::Replace, Some(id)) => agent_messages.replace(id, &text),
+                        (DedupVerdict::Drop, _) => {}
+                        _ => agent_messages.push(message.id, &text),
                     }
                 }
-
-                // Check for errors
-                if let Some(line_type) = line_data.get("type").and_then(|v| v.as_str()) {
-                    if line_type.contains("fail") || line_type.contains("error") {
-                        // Always mark as failure when we encounter error/fail events
-                        result.success = false;
-                        if let Some(error_obj) = line_data.get("error").and_then(|v| v.as_object())
-                        {
-                            if let Some(msg) = error_obj.get("message").and_then(|v| v.as_str()) {
-                                result.error = Some(format!("codex error: {}", msg));
+                // Always mark as failure when we encounter error/fail events
+                if fields.failed {
+                    result.success = false;
+                    if let Some(message) = fields.error_message {
+                        result.error = Some(format!("codex error: {}", message));
+                        // Nothing useful follows an auth failure, exhausted
+                        // quota, rejected model, or invalid flag, so stop
+                        // Codex instead of waiting for it to wind down
+                        if result.fatal_error.is_none() {
+                            if let Some(kind) = fatal_error::classify(message) {
+                                result.fatal_error = Some(kind);
+                                killed_by_server = true;
+                                let _ = child.start_kill();
+                                break;
                             }
-                        } else if let Some(msg) = line_data.get("message").and_then(|v| v.as_str())
-                        {
-                            result.error = Some(format!("codex error: {}", msg));
                         }
                     }
                 }
@@ -582,32 +3197,81 @@ async fn run_internal(opts: Options, agents_warning: Option<String>) -> Result<C
             Err(e) => {
                 // Create a simple IO error for the parse error
                 let io_error = std::io::Error::from(e.kind());
-                record_parse_error(&mut result, &serde_json::Error::io(io_error), "");
+                record_parse_error(&mut result, &serde_json::Error::io(io_error), "", None);
                 break;
             }
         }
     }
 
-    // Wait for process to finish
-    let status = child
-        .wait()
-        .await
-        .context("Failed to wait for codex command")?;
+    if let Some(log) = run_log.as_mut() {
+        log.finish().await;
+        if let Some(error) = log.error() {
+            pre_run_warnings = push_warning(pre_run_warnings, error);
+        }
+    }
 
-    // Collect stderr output with better error handling
-    let stderr_output = match stderr_handle.await {
-        Ok(output) => output,
-        Err(e) => {
-            // Log the join error but continue processing
-            eprintln!("Warning: Failed to join stderr task: {}", e);
-            String::new()
+    if result.all_messages_truncated {
+        let warning = budget.stdout_truncated_warning();
+        result.warnings = push_warning(result.warnings.take(), &warning);
+    }
+
+    result.plan = plan_tracker.finish();
+    let (files, dropped) = files_read.finish();
+    result.files_read = files;
+    result.files_read_truncated = dropped > 0;
+    if let Some(warning) = files_read::sensitive_warning(&result.files_read, &sensitive_paths()) {
+        result.warnings = push_warning(result.warnings.take(), &warning);
+    }
+    let (messages, truncated) = agent_messages.finish();
+    result.agent_messages = messages;
+    result.agent_messages_truncated = truncated;
+    if let (Some(schema), true) = (&opts.final_schema, result.success) {
+        match final_schema::parse_final(schema, last_agent_message.as_deref()) {
+            Ok(value) => result.final_value = Some(value),
+            Err(e) => {
+                result.success = false;
+                result.error = Some(format!("Final message does not match FINAL_SCHEMA: {}", e));
+            }
         }
-    };
+    }
+    if let Some(warning) = lossy_utf8.warning(lossy_utf8_warning_threshold()) {
+        result.warnings = push_warning(result.warnings.take(), &warning);
+        result.lossy_utf8 = Some(lossy_utf8);
+    }
+    if server_config().next_steps_extraction == NextStepsExtraction::Heuristic {
+        result.next_steps = next_steps::extract(&result.agent_messages);
+    }
+    result.context_used_percent = context.used_percent();
+    if opts.session_id.is_some() {
+        if let Some(warning) = result
+            .context_used_percent
+            .and_then(|percent| context_budget::warning(percent, context_warning_percent()))
+        {
+            result.warnings = push_warning(result.warnings.take(), &warning);
+        }
+    }
+
+    // Read CPU and memory usage once the process exits, before it is reaped
+    if exit_watch.is_some() {
+        result.resource_usage = watch_exit(&mut exit_watch).await;
+    }
+    // Once the exit was observed, stderr only gets a short grace to reach EOF
+    let stderr_grace = result.resource_usage.map(|_| EXIT_DRAIN_GRACE);
+
+    // Reap the process while stderr finishes draining
+    let (status, stderr_output) = tokio::join!(
+        child.wait(),
+        collect_stderr(stderr_handle, &stderr_buf, stderr_grace)
+    );
+    let status = status.context("Failed to wait for codex command")?;
 
     if !status.success() {
         result.success = false;
+        result.termination = Termination::from_status(&status, killed_by_server);
         let error_msg = if let Some(ref err) = result.error {
             err.clone()
+        } else if let Some(termination) = &result.termination {
+            termination.message(result.resource_usage.as_ref())
         } else {
             format!("codex command failed with exit code: {:?}", status.code())
         };
@@ -619,22 +3283,43 @@ async fn run_internal(opts: Options, agents_warning: Option<String>) -> Result<C
             result.error = Some(error_msg);
         }
     } else if !stderr_output.is_empty() {
-        // On success, put stderr in warnings field instead of error
-        result.warnings = Some(stderr_output);
+        // On success, stderr is diagnostic noise for operators rather than an error
+        result.operator_warnings = Some(stderr_output);
     }
 
-    // Prepend AGENTS.md warning if present
-    if let Some(agents_warn) = agents_warning {
+    // Prepend pre-run warnings (e.g. ALLOWED_PATHS setup) if present
+    if let Some(pre_run_warn) = pre_run_warnings {
         result.warnings = match result.warnings.take() {
-            Some(existing) => Some(format!("{}\n{}", agents_warn, existing)),
-            None => Some(agents_warn),
+            Some(existing) => Some(format!("{}\n{}", pre_run_warn, existing)),
+            None => Some(pre_run_warn),
         };
     }
 
     Ok(enforce_required_fields(result, ValidationMode::Full))
 }
 
-fn record_parse_error(result: &mut CodexResult, error: &serde_json::Error, line: &str) {
+/// Mark `result` failed with a parse error quoting `line`. Long lines are
+/// shortened; the full line is in `log_file` when the run is logged, and is
+/// otherwise saved next to the run logs.
+fn record_parse_error(
+    result: &mut CodexResult,
+    error: &serde_json::Error,
+    line: &str,
+    log_file: Option<&std::path::Path>,
+) {
+    let max_bytes = parse_error_excerpt_bytes();
+    let line = if line.len() <= max_bytes {
+        Cow::Borrowed(line)
+    } else {
+        let note = match log_file {
+            Some(path) => format!("full line in {}", path.display()),
+            None => match spill_line(line) {
+                Ok(path) => format!("full line saved to {}", path.display()),
+                Err(e) => format!("full line not saved: {}", e),
+            },
+        };
+        Cow::Owned(elide_middle(line, max_bytes, &note).unwrap_or_default())
+    };
     let parse_msg = format!("JSON parse error: {}. Line: {}", error, line);
     result.success = false;
     result.error = match result.error.take() {
@@ -643,6 +3328,125 @@ fn record_parse_error(result: &mut CodexResult, error: &serde_json::Error, line:
     };
 }
 
+/// Save an unparsable output line to `runs_dir` for later inspection
+fn spill_line(line: &str) -> std::io::Result<PathBuf> {
+    let dir = runs_dir();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("parse-error-{}.txt", Uuid::new_v4()));
+    let line = match storage_redactor() {
+        Some(redactor) => redactor.redact(line),
+        None => line.to_string(),
+    };
+    match at_rest_key().map_err(std::io::Error::other)? {
+        Some(key) => {
+            let path = at_rest::encrypted_path(&path);
+            key.write(&path, line.as_bytes())?;
+            Ok(path)
+        }
+        None => {
+            std::fs::write(&path, line)?;
+            Ok(path)
+        }
+    }
+}
+
+/// Resolve the exit watch started after spawning: the process's resource
+/// usage, or `None` when it cannot be read. Pending once it has resolved.
+async fn watch_exit(
+    watch: &mut Option<tokio::task::JoinHandle<Option<ResourceUsage>>>,
+) -> Option<ResourceUsage> {
+    let Some(handle) = watch.as_mut() else {
+        return std::future::pending().await;
+    };
+    let usage = handle.await.ok().flatten();
+    *watch = None;
+    usage
+}
+
+/// Longest stdout or stderr line kept in memory; the rest of a longer line
+/// is read and dropped
+const MAX_LINE_LENGTH: usize = 1024 * 1024;
+
+/// Drain `stderr` in a task, keeping what fits in stderr's share of
+/// `budget`. The text is shared with the task, so what was read can be
+/// reported even when the task is abandoned on a pipe that never closes.
+fn spawn_stderr_reader(
+    stderr: tokio::process::ChildStderr,
+    budget: std::sync::Arc<OutputBudget>,
+) -> (tokio::task::JoinHandle<()>, std::sync::Arc<Mutex<String>>) {
+    let buf = std::sync::Arc::new(Mutex::new(String::new()));
+    let sink = buf.clone();
+    let handle = tokio::spawn(async move {
+        let mut stderr_reader = BufReader::new(stderr);
+        let mut truncated = false;
+        let mut line_buf = Vec::new();
+
+        loop {
+            line_buf.clear();
+            match read_line_with_limit(&mut stderr_reader, &mut line_buf, MAX_LINE_LENGTH, None)
+                .await
+            {
+                Ok(read_result) => {
+                    if read_result.bytes_read == 0 {
+                        break; // EOF
+                    }
+                    // Convert to string, handling invalid UTF-8
+                    let line = String::from_utf8_lossy(&line_buf);
+                    let line = line.trim_end_matches('\n').trim_end_matches('\r');
+                    let mut stderr_output = sink.lock().unwrap_or_else(|e| e.into_inner());
+
+                    // Check if adding this line would exceed stderr's share of the budget
+                    if truncated {
+                        // Continue draining to prevent blocking the child process
+                    } else if !budget.take_stderr(line.len() + 1) {
+                        // +1 for newline
+                        if !stderr_output.is_empty() {
+                            stderr_output.push('\n');
+                        }
+                        stderr_output.push_str("[... stderr truncated due to size limit ...]");
+                        truncated = true;
+                    } else {
+                        if !stderr_output.is_empty() {
+                            stderr_output.push('\n');
+                        }
+                        stderr_output.push_str(line.as_ref());
+                    }
+                }
+                Err(e) => {
+                    // Log the read error but continue - this preserves diagnostic info
+                    eprintln!("Warning: Failed to read from stderr: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+    (handle, buf)
+}
+
+/// Wait for the stderr task, for at most `grace` when given, and return what
+/// it read
+async fn collect_stderr(
+    mut handle: tokio::task::JoinHandle<()>,
+    buf: &Mutex<String>,
+    grace: Option<std::time::Duration>,
+) -> String {
+    let joined = match grace {
+        Some(grace) => match tokio::time::timeout(grace, &mut handle).await {
+            Ok(joined) => joined,
+            Err(_) => {
+                handle.abort();
+                Ok(())
+            }
+        },
+        None => (&mut handle).await,
+    };
+    if let Err(e) = joined {
+        // Log the join error but continue processing
+        eprintln!("Warning: Failed to join stderr task: {}", e);
+    }
+    std::mem::take(&mut *buf.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
 fn push_warning(existing: Option<String>, warning: &str) -> Option<String> {
     match existing {
         Some(mut current) => {
@@ -691,6 +3495,7 @@ mod tests {
             additional_args: Vec::new(),
             image_paths: Vec::new(),
             timeout_secs: None,
+            ..Default::default()
         };
 
         assert_eq!(opts.prompt, "test prompt");
@@ -706,6 +3511,7 @@ mod tests {
             additional_args: vec!["--json".to_string()],
             image_paths: vec![PathBuf::from("image.png")],
             timeout_secs: Some(600),
+            ..Default::default()
         };
 
         assert_eq!(opts.session_id, Some("test-session-123".to_string()));
@@ -713,6 +3519,413 @@ mod tests {
         assert_eq!(opts.image_paths.len(), 1);
     }
 
+    fn argv_strings(opts: &Options, schema_path: Option<&std::path::Path>) -> Vec<String> {
+        build_argv(opts, schema_path)
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_build_argv_golden() {
+        let base = Options {
+            prompt: "-fix the bug".to_string(),
+            working_dir: PathBuf::from("/repo"),
+            ..Default::default()
+        };
+        let images = vec![PathBuf::from("a.png"), PathBuf::from("b.png")];
+        let model = vec!["--model".to_string(), "gpt-5".to_string()];
+        let profile = vec!["--profile".to_string(), "fast".to_string()];
+        let schema = std::path::Path::new("/tmp/schema.json");
+
+        let cases: Vec<(Options, Option<&std::path::Path>, &str)> = vec![
+            (
+                base.clone(),
+                None,
+                "exec --cd /repo --json -- -fix the bug",
+            ),
+            (
+                Options {
+                    image_paths: images.clone(),
+                    ..base.clone()
+                },
+                None,
+                "exec --cd /repo --image a.png --image b.png --json -- -fix the bug",
+            ),
+            (
+                Options {
+                    additional_args: model.clone(),
+                    ..base.clone()
+                },
+                Some(schema),
+                "exec --cd /repo --model gpt-5 --output-schema /tmp/schema.json --json -- -fix the bug",
+            ),
+            (
+                Options {
+                    session_id: Some("sid-1".to_string()),
+                    ..base.clone()
+                },
+                None,
+                "exec --cd /repo --json resume sid-1 -- -fix the bug",
+            ),
+            (
+                Options {
+                    include_plan_tool: true,
+                    ..base.clone()
+                },
+                Some(schema),
+                "exec --cd /repo --output-schema /tmp/schema.json --include-plan-tool --json -- -fix the bug",
+            ),
+            (
+                Options {
+                    session_id: Some("sid-1".to_string()),
+                    image_paths: images.clone(),
+                    ..base.clone()
+                },
+                None,
+                "exec --cd /repo --image a.png --image b.png --json resume sid-1 -- -fix the bug",
+            ),
+            (
+                Options {
+                    session_id: Some("sid-1".to_string()),
+                    image_paths: images.clone(),
+                    additional_args: [model.clone(), profile.clone()].concat(),
+                    ..base.clone()
+                },
+                None,
+                "exec --cd /repo --model gpt-5 --profile fast --image a.png --image b.png --json resume sid-1 -- -fix the bug",
+            ),
+            (
+                Options {
+                    resume_last: true,
+                    additional_args: profile.clone(),
+                    ..base.clone()
+                },
+                None,
+                "exec --cd /repo --profile fast --json resume --last -- -fix the bug",
+            ),
+            (
+                Options {
+                    resume_last: true,
+                    image_paths: images.clone(),
+                    additional_args: model.clone(),
+                    ..base.clone()
+                },
+                Some(schema),
+                "exec --cd /repo --model gpt-5 --image a.png --image b.png --output-schema /tmp/schema.json --json resume --last -- -fix the bug",
+            ),
+        ];
+        for (opts, schema_path, expected) in cases {
+            assert_eq!(argv_strings(&opts, schema_path).join(" "), expected);
+        }
+    }
+
+    #[test]
+    fn test_build_argv_ordering_holds_for_every_combination() {
+        let sessions = [
+            (None, false),
+            (Some("sid-1".to_string()), false),
+            (None, true),
+        ];
+        let image_sets = [vec![], vec![PathBuf::from("a.png"), PathBuf::from("b.png")]];
+        let extra_sets = [
+            vec![],
+            vec!["--model".to_string(), "gpt-5".to_string()],
+            vec!["--profile".to_string(), "fast".to_string()],
+            vec![
+                "--model".to_string(),
+                "gpt-5".to_string(),
+                "-c".to_string(),
+                "model_reasoning_effort=high".to_string(),
+            ],
+        ];
+        let schema = std::path::Path::new("/tmp/schema.json");
+        for (session_id, resume_last) in &sessions {
+            for image_paths in &image_sets {
+                for additional_args in &extra_sets {
+                    for schema_path in [None, Some(schema)] {
+                        let opts = Options {
+                            prompt: "go".to_string(),
+                            working_dir: PathBuf::from("/repo"),
+                            session_id: session_id.clone(),
+                            resume_last: *resume_last,
+                            image_paths: image_paths.clone(),
+                            additional_args: additional_args.clone(),
+                            ..Default::default()
+                        };
+                        let argv = argv_strings(&opts, schema_path);
+                        let context = argv.join(" ");
+
+                        // The prompt is always last, right after the delimiter
+                        assert_eq!(argv[argv.len() - 2..], ["--", "go"], "{}", context);
+                        assert_eq!(argv.iter().filter(|a| *a == "--").count(), 1, "{}", context);
+
+                        // Every image value is followed by another flag
+                        for (i, arg) in argv.iter().enumerate() {
+                            if arg == "--image" {
+                                assert!(argv[i + 2].starts_with("--"), "{}", context);
+                            }
+                        }
+
+                        // All exec options precede `resume`, which directly
+                        // follows `--json`
+                        let json = argv.iter().position(|a| a == "--json").unwrap();
+                        match argv.iter().position(|a| a == "resume") {
+                            Some(resume) => {
+                                assert!(session_id.is_some() || *resume_last, "{}", context);
+                                assert_eq!(resume, json + 1, "{}", context);
+                                let selector = session_id.as_deref().unwrap_or("--last");
+                                assert_eq!(argv[resume + 1], selector, "{}", context);
+                                assert_eq!(resume + 3, argv.len() - 1, "{}", context);
+                            }
+                            None => {
+                                assert!(session_id.is_none() && !resume_last, "{}", context);
+                                assert_eq!(json + 1, argv.len() - 2, "{}", context);
+                            }
+                        }
+                        let extras = &argv[3..3 + additional_args.len()];
+                        assert_eq!(extras, additional_args.as_slice(), "{}", context);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sandbox_mode_from_args() {
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(SandboxMode::from_args(&[]), SandboxMode::ReadOnly);
+        assert_eq!(
+            SandboxMode::from_args(&args(&["--sandbox", "workspace-write"])),
+            SandboxMode::WorkspaceWrite
+        );
+        assert_eq!(
+            SandboxMode::from_args(&args(&["--sandbox=danger-full-access"])),
+            SandboxMode::DangerFullAccess
+        );
+        assert_eq!(
+            SandboxMode::from_args(&args(&["--profile", "gpt-5", "--yolo"])),
+            SandboxMode::DangerFullAccess
+        );
+        assert_eq!(
+            SandboxMode::from_args(&args(&["--full-auto", "-s", "read-only"])),
+            SandboxMode::ReadOnly
+        );
+        assert!(!SandboxMode::ReadOnly.is_write());
+        assert!(SandboxMode::WorkspaceWrite.is_write());
+    }
+
+    #[test]
+    fn test_config_override_args() {
+        let allowed = vec!["model_reasoning_effort".to_string(), "tools.*".to_string()];
+        let mut overrides = BTreeMap::new();
+        overrides.insert("model_reasoning_effort".to_string(), Value::from("high"));
+        overrides.insert("tools.web_search".to_string(), Value::from(true));
+        assert_eq!(
+            config_override_args(&overrides, &allowed).unwrap(),
+            vec![
+                "-c",
+                "model_reasoning_effort=\"high\"",
+                "-c",
+                "tools.web_search=true"
+            ]
+        );
+
+        let mut rejected = BTreeMap::new();
+        rejected.insert(
+            "sandbox_mode".to_string(),
+            Value::from("danger-full-access"),
+        );
+        assert!(config_override_args(&rejected, &allowed)
+            .unwrap_err()
+            .contains("not allowed"));
+
+        let mut bad_key = BTreeMap::new();
+        bad_key.insert("tools.x=1 -c y".to_string(), Value::from(1));
+        assert!(config_override_args(&bad_key, &allowed).is_err());
+        assert!(!is_override_key_allowed("tools", &allowed));
+    }
+
+    #[test]
+    fn test_run_environment_preamble() {
+        let mut env = RunEnvironment::default();
+        assert_eq!(env.preamble(1_710_495_000), None);
+
+        env.timestamp_in_prompt = true;
+        assert_eq!(
+            env.preamble(1_710_495_000).as_deref(),
+            Some("Current date and time: Friday, 2024-03-15 09:30 UTC")
+        );
+        env.tz = Some("Europe/Warsaw".to_string());
+        assert!(env
+            .preamble(1_710_495_000)
+            .unwrap()
+            .ends_with("(user time zone: Europe/Warsaw)"));
+    }
+
+    #[test]
+    fn test_sampling_override_args() {
+        let keys = SamplingConfigKeys {
+            temperature: Some("model_temperature".to_string()),
+            seed: None,
+        };
+        assert_eq!(
+            sampling_override_args(&keys, Some(0.0), None).unwrap(),
+            vec!["-c", "model_temperature=0.0"]
+        );
+        assert!(sampling_override_args(&keys, Some(2.5), None).is_err());
+        assert!(sampling_override_args(&keys, None, Some(7))
+            .unwrap_err()
+            .contains("sampling_config_keys.seed"));
+        assert!(sampling_override_args(&keys, None, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_mask_secret_args() {
+        let args: Vec<String> = [
+            "--profile",
+            "gpt-5",
+            "--api-key",
+            "sk-123",
+            "-c",
+            "model_providers.x.token=abc",
+            "--secret=hunter2",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let masked = mask_secret_args(&args);
+        assert_eq!(
+            masked,
+            vec![
+                "--profile",
+                "gpt-5",
+                "--api-key",
+                "****",
+                "-c",
+                "model_providers.x.token=****",
+                "--secret=****",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_agent_message_collector_keeps_everything_within_budget() {
+        let mut collector = AgentMessageCollector::new(&TruncationConfig {
+            head_bytes: 10,
+            tail_bytes: 10,
+        });
+        collector.push(None, "first");
+        collector.push(None, "second");
+
+        let (text, truncated) = collector.finish();
+        assert_eq!(text, "first\nsecond");
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_agent_message_collector_keeps_head_and_tail() {
+        let mut collector = AgentMessageCollector::new(&TruncationConfig {
+            head_bytes: 8,
+            tail_bytes: 12,
+        });
+        collector.push(None, "plan: step");
+        for i in 0..100 {
+            collector.push(None, &format!("noise {}", i));
+        }
+        collector.push(None, "summary: ok");
+
+        let (text, truncated) = collector.finish();
+        assert!(truncated);
+        assert!(text.starts_with("plan: st"));
+        assert!(text.ends_with("\nsummary: ok"));
+        assert!(text.contains("bytes of agent messages elided"));
+        assert!(!text.contains("noise 50"));
+    }
+
+    #[test]
+    fn test_agent_message_collector_respects_char_boundaries() {
+        let mut collector = AgentMessageCollector::new(&TruncationConfig {
+            head_bytes: 4,
+            tail_bytes: 4,
+        });
+        collector.push(None, "你好世界你好世界");
+
+        let (text, truncated) = collector.finish();
+        assert!(truncated);
+        assert!(text.starts_with("你"));
+        assert!(text.ends_with("界"));
+    }
+
+    #[test]
+    fn test_agent_message_dedup() {
+        let mut dedup = AgentMessageDedup::default();
+        assert_eq!(
+            dedup.admit(Some("item_0"), "First paragraph."),
+            DedupVerdict::Keep
+        );
+        assert_eq!(
+            dedup.admit(Some("item_0"), "First paragraph, revised."),
+            DedupVerdict::Replace
+        );
+        assert_eq!(
+            dedup.admit(Some("item_0"), "First paragraph, revised.\n"),
+            DedupVerdict::Drop
+        );
+        assert_eq!(
+            dedup.admit(Some("item_1"), "Plan:\n- a\n\nDone."),
+            DedupVerdict::Keep
+        );
+        // Final message repeating the streamed one
+        assert_eq!(
+            dedup.admit(None, "Plan:\n- a\n\nDone.\n"),
+            DedupVerdict::Drop
+        );
+        // A message that merely ends like the previous one is kept
+        assert_eq!(dedup.admit(Some("item_2"), "Done."), DedupVerdict::Keep);
+        assert_eq!(dedup.admit(None, "Something new"), DedupVerdict::Keep);
+    }
+
+    #[test]
+    fn test_agent_message_collector_replaces_entry_by_id() {
+        let mut collector = AgentMessageCollector::new(&TruncationConfig {
+            head_bytes: 100,
+            tail_bytes: 100,
+        });
+        collector.push(Some("item_0"), "First");
+        collector.push(Some("item_1"), "Second");
+        collector.replace("item_0", "First, revised");
+        collector.replace("item_1", "Second, revised");
+
+        let (text, truncated) = collector.finish();
+        assert_eq!(text, "First, revised\nSecond, revised");
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_agent_message_collector_replaces_entry_in_tail() {
+        let mut collector = AgentMessageCollector::new(&TruncationConfig {
+            head_bytes: 8,
+            tail_bytes: 20,
+        });
+        collector.push(None, "plan: step");
+        for i in 0..100 {
+            collector.push(None, &format!("noise {}", i));
+        }
+        collector.push(Some("item_9"), "summary: draft");
+        collector.replace("item_9", "summary: ok");
+
+        let (text, truncated) = collector.finish();
+        assert!(truncated);
+        assert!(text.starts_with("plan: st"));
+        assert!(text.ends_with("\nsummary: ok"));
+        assert!(!text.contains("draft"));
+    }
+
     #[test]
     fn test_record_parse_error_sets_failure_and_appends_message() {
         let mut result = CodexResult {
@@ -724,16 +3937,32 @@ mod tests {
             all_messages_truncated: false,
             error: Some("existing".to_string()),
             warnings: None,
+            ..Default::default()
         };
 
         let err = serde_json::from_str::<Value>("not-json").unwrap_err();
-        record_parse_error(&mut result, &err, "not-json");
+        record_parse_error(&mut result, &err, "not-json", None);
 
         assert!(!result.success);
         assert!(result.error.as_ref().unwrap().contains("JSON parse error"));
         assert!(result.error.as_ref().unwrap().contains("existing"));
     }
 
+    #[test]
+    fn test_elide_middle_keeps_head_and_tail() {
+        assert_eq!(elide_middle("short", 10, "note"), None);
+        let line = format!("{}{}{}", "h".repeat(10), "x".repeat(1000), "é".repeat(5));
+        let elided = elide_middle(&line, 20, "full line in run.jsonl").unwrap();
+        assert_eq!(
+            elided,
+            format!(
+                "{} ... [1000 bytes elided; full line in run.jsonl] ... {}",
+                "h".repeat(10),
+                "é".repeat(5)
+            )
+        );
+    }
+
     #[test]
     fn test_enforce_required_fields_warns_on_missing_agent_messages() {
         let result = CodexResult {
@@ -745,6 +3974,7 @@ mod tests {
             all_messages_truncated: false,
             error: None,
             warnings: None,
+            ..Default::default()
         };
 
         let updated = enforce_required_fields(result, ValidationMode::Full);
@@ -757,6 +3987,79 @@ mod tests {
             .contains("No agent_messages"));
     }
 
+    #[test]
+    fn test_with_sandbox_replaces_sandbox_flags() {
+        let args: Vec<String> = [
+            "--full-auto",
+            "-m",
+            "o3",
+            "--sandbox",
+            "workspace-write",
+            "--sandbox=danger-full-access",
+            "--yolo",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let forced = with_sandbox(&args, SandboxMode::ReadOnly);
+        assert_eq!(forced, vec!["-m", "o3", "--sandbox", "read-only"]);
+        assert_eq!(SandboxMode::from_args(&forced), SandboxMode::ReadOnly);
+    }
+
+    #[test]
+    fn test_cap_sandbox_lowers_requests_above_ceiling() {
+        let mut args: Vec<String> = ["-c", "sandbox_mode=\"danger-full-access\"", "-m", "o3"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(SandboxMode::from_args(&args), SandboxMode::DangerFullAccess);
+        assert_eq!(
+            cap_sandbox(&mut args, SandboxMode::WorkspaceWrite),
+            Some(SandboxDowngrade {
+                requested: SandboxMode::DangerFullAccess,
+                applied: SandboxMode::WorkspaceWrite,
+            })
+        );
+        assert_eq!(args, vec!["-m", "o3", "--sandbox", "workspace-write"]);
+
+        let mut read_only = vec!["-m".to_string(), "o3".to_string()];
+        assert_eq!(
+            cap_sandbox(&mut read_only, SandboxMode::WorkspaceWrite),
+            None
+        );
+        assert_eq!(read_only, vec!["-m", "o3"]);
+    }
+
+    #[test]
+    fn test_check_resumed_session_against_registry() {
+        let dir = PathBuf::from("/tmp/codex-mcp-registry-test");
+        assert!(check_resumed_session(&dir, "s1")
+            .unwrap()
+            .contains("not started by this server"));
+
+        record_session(&dir, "s1");
+        assert!(check_resumed_session(&dir, "s1").is_none());
+        assert!(check_resumed_session(&dir, "s2").unwrap().contains("s1"));
+    }
+
+    #[test]
+    fn test_is_empty_response() {
+        let mut result = CodexResult {
+            success: true,
+            session_id: "session".to_string(),
+            ..Default::default()
+        };
+        assert!(is_empty_response(&result));
+        result.agent_messages = "done".to_string();
+        assert!(!is_empty_response(&result));
+        result.agent_messages.clear();
+        result.success = false;
+        assert!(!is_empty_response(&result));
+        result.success = true;
+        result.session_id.clear();
+        assert!(!is_empty_response(&result));
+    }
+
     #[test]
     fn test_enforce_required_fields_requires_session_id() {
         let result = CodexResult {
@@ -768,6 +4071,7 @@ mod tests {
             all_messages_truncated: false,
             error: None,
             warnings: None,
+            ..Default::default()
         };
 
         let updated = enforce_required_fields(result, ValidationMode::Full);
@@ -800,6 +4104,7 @@ mod tests {
             all_messages_truncated: false,
             error: Some("Codex execution timed out after 10 seconds".to_string()),
             warnings: None,
+            ..Default::default()
         };
 
         let updated = enforce_required_fields(result, ValidationMode::Skip);
@@ -831,6 +4136,7 @@ mod tests {
                     .to_string(),
             ),
             warnings: None,
+            ..Default::default()
         };
 
         let updated = enforce_required_fields(result, ValidationMode::Full);
@@ -852,7 +4158,7 @@ mod tests {
     async fn test_read_agents_md_returns_none_when_file_not_exists() {
         let temp_dir = tempfile::tempdir().unwrap();
 
-        let (content, warning) = read_agents_md(temp_dir.path()).await;
+        let (content, warning) = read_agents_for(temp_dir.path(), SandboxMode::ReadOnly).await;
         assert!(content.is_none());
         assert!(warning.is_none());
     }
@@ -865,7 +4171,7 @@ mod tests {
         let test_content = "# System Prompt\nYou are a helpful assistant.";
         tokio::fs::write(&agents_path, test_content).await.unwrap();
 
-        let (content, warning) = read_agents_md(temp_dir.path()).await;
+        let (content, warning) = read_agents_for(temp_dir.path(), SandboxMode::ReadOnly).await;
         assert!(content.is_some());
         assert_eq!(content.unwrap(), test_content);
         assert!(warning.is_none());
@@ -878,7 +4184,7 @@ mod tests {
 
         tokio::fs::write(&agents_path, "   \n\t  \n").await.unwrap();
 
-        let (content, warning) = read_agents_md(temp_dir.path()).await;
+        let (content, warning) = read_agents_for(temp_dir.path(), SandboxMode::ReadOnly).await;
         assert!(content.is_none());
         assert!(warning.is_none());
     }
@@ -894,7 +4200,7 @@ mod tests {
             .await
             .unwrap();
 
-        let (content, warning) = read_agents_md(temp_dir.path()).await;
+        let (content, warning) = read_agents_for(temp_dir.path(), SandboxMode::ReadOnly).await;
         assert!(content.is_some());
         assert!(warning.is_some());
 
@@ -920,7 +4226,7 @@ mod tests {
             perms.set_mode(0o000); // No permissions
             std::fs::set_permissions(&agents_path, perms).unwrap();
 
-            let (content, warning) = read_agents_md(temp_dir.path()).await;
+            let (content, warning) = read_agents_for(temp_dir.path(), SandboxMode::ReadOnly).await;
             assert!(content.is_none());
             assert!(warning.is_some());
             let warn_msg = warning.unwrap();
@@ -935,11 +4241,83 @@ mod tests {
         #[cfg(not(unix))]
         {
             // On Windows, just verify the function doesn't panic
-            let (content, _warning) = read_agents_md(temp_dir.path()).await;
+            let (content, _warning) = read_agents_for(temp_dir.path(), SandboxMode::ReadOnly).await;
             assert!(content.is_some());
         }
     }
 
+    #[tokio::test]
+    async fn test_read_agents_md_picks_variant_and_applies_modes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        tokio::fs::write(
+            dir.join("AGENTS.md"),
+            "---\nmodes: [write]\n---\nRun the tests after editing.",
+        )
+        .await
+        .unwrap();
+
+        let (content, _) = read_agents_for(dir, SandboxMode::ReadOnly).await;
+        assert!(content.is_none());
+        let (content, _) = read_agents_for(dir, SandboxMode::WorkspaceWrite).await;
+        assert_eq!(content.as_deref(), Some("Run the tests after editing."));
+
+        tokio::fs::write(dir.join("AGENTS.read-only.md"), "Only analyze.")
+            .await
+            .unwrap();
+        let (content, _) = read_agents_for(dir, SandboxMode::ReadOnly).await;
+        assert_eq!(content.as_deref(), Some("Only analyze."));
+        let (content, _) = read_agents_for(dir, SandboxMode::DangerFullAccess).await;
+        assert_eq!(content.as_deref(), Some("Run the tests after editing."));
+    }
+
+    #[test]
+    fn test_prepend_primer_delimits_each_message() {
+        let mut opts = Options {
+            prompt: "Review src/lib.rs".to_string(),
+            primer_messages: vec!["You are a reviewer.\n".to_string(), "Be brief.".to_string()],
+            ..Default::default()
+        };
+        prepend_primer(&mut opts);
+        assert_eq!(
+            opts.prompt,
+            "<primer_message index=\"1\">\nYou are a reviewer.\n</primer_message>\n\n\
+             <primer_message index=\"2\">\nBe brief.\n</primer_message>\n\n\
+             Review src/lib.rs"
+        );
+        assert!(opts.primer_messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_agents_reference_points_at_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        assert_eq!(
+            agents_reference(dir, "AGENTS.md", SandboxMode::ReadOnly).await,
+            None
+        );
+
+        tokio::fs::write(
+            dir.join("AGENTS.md"),
+            format!(
+                "---\nmodes: [write]\n---\n{}",
+                "a".repeat(MAX_AGENTS_SIZE + 10)
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            agents_reference(dir, "AGENTS.md", SandboxMode::ReadOnly).await,
+            None
+        );
+        let pointer = agents_reference(dir, "AGENTS.md", SandboxMode::WorkspaceWrite)
+            .await
+            .unwrap();
+        assert!(pointer.contains("`AGENTS.md`"));
+        assert!(pointer.contains("front matter"));
+        assert!(pointer.len() < 512);
+    }
+
     #[tokio::test]
     async fn test_read_agents_md_handles_invalid_utf8() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -949,7 +4327,7 @@ mod tests {
         let invalid_utf8 = vec![0xFF, 0xFE, 0xFD];
         tokio::fs::write(&agents_path, &invalid_utf8).await.unwrap();
 
-        let (content, warning) = read_agents_md(temp_dir.path()).await;
+        let (content, warning) = read_agents_for(temp_dir.path(), SandboxMode::ReadOnly).await;
         assert!(content.is_none());
         assert!(warning.is_some());
         assert!(warning.unwrap().contains("invalid UTF-8"));
@@ -969,7 +4347,7 @@ mod tests {
             .await
             .unwrap();
 
-        let (content, warning) = read_agents_md(temp_dir.path()).await;
+        let (content, warning) = read_agents_for(temp_dir.path(), SandboxMode::ReadOnly).await;
         assert!(content.is_some());
         assert!(warning.is_some());
 
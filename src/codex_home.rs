@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+
+/// Template entries never copied into an isolated home because they carry
+/// per-user history rather than configuration
+const EXCLUDED_FILES: &[&str] = &["history.jsonl"];
+
+/// Private, throwaway `CODEX_HOME` for a single run; removed on drop
+#[derive(Debug)]
+pub struct IsolatedHome {
+    path: PathBuf,
+}
+
+impl IsolatedHome {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for IsolatedHome {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Root directory for isolated homes of all runs
+pub fn homes_dir() -> PathBuf {
+    std::env::temp_dir().join("codex-mcp-homes")
+}
+
+/// The Codex home the server itself would use: `$CODEX_HOME`, else `~/.codex`
+pub fn default_template() -> Option<PathBuf> {
+    if let Ok(codex_home) = std::env::var("CODEX_HOME") {
+        let trimmed = codex_home.trim();
+        if !trimmed.is_empty() {
+            return Some(PathBuf::from(trimmed));
+        }
+    }
+
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".codex"))
+}
+
+/// Create an isolated home for `run_id` by copying the top-level files of
+/// `template` (auth.json, config.toml, ...). Subdirectories such as `sessions/`
+/// and `log/` are not copied, so rollouts and history never leak between runs.
+pub fn create(template: &Path, run_id: &str) -> std::io::Result<IsolatedHome> {
+    let home = IsolatedHome {
+        path: homes_dir().join(run_id),
+    };
+    std::fs::create_dir_all(&home.path)?;
+
+    // The copy contains credentials; keep it private to the server user
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&home.path, std::fs::Permissions::from_mode(0o700))?;
+    }
+
+    for entry in std::fs::read_dir(template)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name
+            .to_str()
+            .is_some_and(|name| EXCLUDED_FILES.contains(&name))
+        {
+            continue;
+        }
+        if entry.file_type()?.is_file() {
+            std::fs::copy(entry.path(), home.path.join(&name))?;
+        }
+    }
+
+    Ok(home)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_copies_top_level_files_only() {
+        let template = tempfile::tempdir().unwrap();
+        std::fs::write(template.path().join("auth.json"), "{}").unwrap();
+        std::fs::write(template.path().join("config.toml"), "model = \"x\"").unwrap();
+        std::fs::write(template.path().join("history.jsonl"), "secret").unwrap();
+        std::fs::create_dir(template.path().join("sessions")).unwrap();
+        std::fs::write(template.path().join("sessions").join("rollout.jsonl"), "").unwrap();
+
+        let home = create(template.path(), "test-isolated-home").unwrap();
+        let path = home.path().to_path_buf();
+
+        assert!(path.join("auth.json").is_file());
+        assert!(path.join("config.toml").is_file());
+        assert!(!path.join("history.jsonl").exists());
+        assert!(!path.join("sessions").exists());
+
+        drop(home);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_create_fails_for_missing_template() {
+        let template = tempfile::tempdir().unwrap();
+        let missing = template.path().join("does-not-exist");
+        assert!(create(&missing, "test-missing-template").is_err());
+        assert!(!homes_dir().join("test-missing-template").exists());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::codex::{self, CodexResult, Options, SandboxMode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use tokio::task::JoinSet;
+
+/// One entry of `consensus_profiles` in `codex-mcp.config.json`: a named set of
+/// extra CLI flags, e.g. `{"name": "o3", "args": ["-m", "o3"]}`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ConsensusProfile {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Answer from a single profile
+#[derive(Debug, Serialize)]
+pub struct ConsensusAnswer {
+    pub profile: String,
+    pub success: bool,
+    #[serde(rename = "SESSION_ID", skip_serializing_if = "String::is_empty")]
+    pub session_id: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Answers from every profile plus agreement signals
+#[derive(Debug, Serialize)]
+pub struct ConsensusResult {
+    pub answers: Vec<ConsensusAnswer>,
+    /// Mean pairwise word overlap (0-1) between successful answers; absent
+    /// when fewer than two profiles answered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agreement: Option<f64>,
+    /// Profile whose answer overlaps most with the others
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub representative: Option<String>,
+    pub summary: String,
+}
+
+/// Run `prompt` read-only against each profile concurrently. The server's
+/// `additional_args` apply to every profile, followed by the profile's own
+/// flags; any sandbox flag is replaced with `--sandbox read-only`.
+pub async fn run(
+    prompt: &str,
+    working_dir: PathBuf,
+    profiles: &[ConsensusProfile],
+) -> ConsensusResult {
+    let mut tasks = JoinSet::new();
+    for (index, profile) in profiles.iter().enumerate() {
+        let mut args = codex::default_additional_args();
+        args.extend(profile.args.iter().cloned());
+        let opts = Options {
+            prompt: prompt.to_string(),
+            working_dir: working_dir.clone(),
+            additional_args: codex::with_sandbox(&args, SandboxMode::ReadOnly),
+            ..Default::default()
+        };
+        tasks.spawn(async move { (index, codex::run(opts).await) });
+    }
+
+    let mut slots: Vec<Option<ConsensusAnswer>> = profiles.iter().map(|_| None).collect();
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((index, outcome)) = joined else {
+            continue;
+        };
+        slots[index] = Some(answer(&profiles[index].name, outcome));
+    }
+
+    let answers: Vec<ConsensusAnswer> = slots
+        .into_iter()
+        .zip(profiles)
+        .map(|(slot, profile)| {
+            slot.unwrap_or_else(|| ConsensusAnswer {
+                profile: profile.name.clone(),
+                success: false,
+                session_id: String::new(),
+                message: String::new(),
+                error: Some("Consensus task panicked".to_string()),
+            })
+        })
+        .collect();
+
+    summarize(answers)
+}
+
+fn answer(profile: &str, outcome: anyhow::Result<CodexResult>) -> ConsensusAnswer {
+    match outcome {
+        Ok(result) => ConsensusAnswer {
+            profile: profile.to_string(),
+            success: result.success,
+            session_id: result.session_id,
+            message: result.agent_messages,
+            error: result.error,
+        },
+        Err(e) => ConsensusAnswer {
+            profile: profile.to_string(),
+            success: false,
+            session_id: String::new(),
+            message: String::new(),
+            error: Some(format!("Failed to execute codex: {}", e)),
+        },
+    }
+}
+
+fn summarize(answers: Vec<ConsensusAnswer>) -> ConsensusResult {
+    let answered: Vec<(usize, HashSet<String>)> = answers
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| a.success && !a.message.trim().is_empty())
+        .map(|(i, a)| (i, words(&a.message)))
+        .collect();
+
+    let mut summary = format!("{}/{} profiles answered", answered.len(), answers.len());
+    if answered.len() < 2 {
+        summary.push_str("; not enough answers to compare");
+        return ConsensusResult {
+            answers,
+            agreement: None,
+            representative: None,
+            summary,
+        };
+    }
+
+    // Mean similarity of each answer to all others; the highest is the most
+    // representative answer, and the overall mean is the agreement score
+    let mut totals = vec![0.0; answered.len()];
+    for i in 0..answered.len() {
+        for j in (i + 1)..answered.len() {
+            let similarity = jaccard(&answered[i].1, &answered[j].1);
+            totals[i] += similarity;
+            totals[j] += similarity;
+        }
+    }
+    let peers = (answered.len() - 1) as f64;
+    let means: Vec<f64> = totals.iter().map(|t| t / peers).collect();
+    let agreement = means.iter().sum::<f64>() / means.len() as f64;
+    let mut best = 0;
+    for (i, mean) in means.iter().enumerate() {
+        if *mean > means[best] {
+            best = i;
+        }
+    }
+    let representative = answers[answered[best].0].profile.clone();
+
+    summary.push_str(&format!(
+        "; agreement {:.0}% ({}); most representative answer: {}",
+        agreement * 100.0,
+        agreement_label(agreement),
+        representative
+    ));
+
+    ConsensusResult {
+        answers,
+        agreement: Some((agreement * 100.0).round() / 100.0),
+        representative: Some(representative),
+        summary,
+    }
+}
+
+fn agreement_label(agreement: f64) -> &'static str {
+    if agreement >= 0.6 {
+        "high"
+    } else if agreement >= 0.3 {
+        "moderate"
+    } else {
+        "low"
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(profile: &str, message: &str) -> ConsensusAnswer {
+        ConsensusAnswer {
+            profile: profile.to_string(),
+            success: true,
+            session_id: "s".to_string(),
+            message: message.to_string(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_summarize_picks_representative_answer() {
+        let result = summarize(vec![
+            ok("a", "The bug is in the parser module loop"),
+            ok("b", "The bug is in the parser module"),
+            ok("c", "Unrelated answer about networking timeouts"),
+        ]);
+        assert_eq!(result.representative.as_deref(), Some("a"));
+        let agreement = result.agreement.unwrap();
+        assert!(agreement > 0.0 && agreement < 1.0);
+        assert!(result.summary.starts_with("3/3 profiles answered"));
+    }
+
+    #[test]
+    fn test_summarize_needs_two_answers() {
+        let mut failed = ok("b", "");
+        failed.success = false;
+        let result = summarize(vec![ok("a", "answer"), failed]);
+        assert!(result.agreement.is_none());
+        assert!(result.summary.contains("not enough answers"));
+    }
+}
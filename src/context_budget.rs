@@ -0,0 +1,111 @@
+use serde_json::Value;
+
+/// `context_warning_percent` when not configured
+pub const DEFAULT_WARNING_PERCENT: u8 = 80;
+
+/// How full the session's context window is, from the last token count in
+/// the stream. The window size comes from `token_count` events, or from
+/// `context_window_tokens` for CLI versions that do not report it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContextTracker {
+    window: Option<u64>,
+    used: Option<u64>,
+}
+
+impl ContextTracker {
+    pub fn new(configured_window: Option<u64>) -> Self {
+        Self {
+            window: configured_window.filter(|window| *window > 0),
+            used: None,
+        }
+    }
+
+    /// Take the token counts of one event. Each turn re-sends the whole
+    /// conversation, so the latest turn's tokens are what the context holds.
+    pub fn observe(&mut self, event: &Value) {
+        let event = event.get("msg").unwrap_or(event);
+        match event.get("type").and_then(Value::as_str) {
+            Some("token_count") => {
+                let Some(info) = event.get("info") else {
+                    return;
+                };
+                if let Some(window) = info
+                    .get("model_context_window")
+                    .and_then(Value::as_u64)
+                    .filter(|window| *window > 0)
+                {
+                    self.window = Some(window);
+                }
+                if let Some(used) = info.get("last_token_usage").and_then(turn_tokens) {
+                    self.used = Some(used);
+                }
+            }
+            Some("turn.completed") => {
+                if let Some(used) = event.get("usage").and_then(turn_tokens) {
+                    self.used = Some(used);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Share of the context window in use, when both counts are known
+    pub fn used_percent(&self) -> Option<u8> {
+        let (used, window) = (self.used?, self.window?);
+        Some(used.saturating_mul(100).div_ceil(window).min(100) as u8)
+    }
+}
+
+fn turn_tokens(usage: &Value) -> Option<u64> {
+    if let Some(total) = usage.get("total_tokens").and_then(Value::as_u64) {
+        return Some(total);
+    }
+    let count = |key: &str| usage.get(key).and_then(Value::as_u64);
+    match (count("input_tokens"), count("output_tokens")) {
+        (None, None) => None,
+        (input, output) => Some(input.unwrap_or(0) + output.unwrap_or(0)),
+    }
+}
+
+/// Warning for a resumed session at or above `threshold` percent of its window
+pub fn warning(used_percent: u8, threshold: u8) -> Option<String> {
+    (used_percent >= threshold).then(|| {
+        format!(
+            "This session has used {}% of its context window; start a new session (omit SESSION_ID) before answers degrade",
+            used_percent
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tracks_latest_turn_against_window() {
+        let mut tracker = ContextTracker::new(None);
+        tracker.observe(&json!({"type": "turn.completed", "usage": {"input_tokens": 9000, "output_tokens": 1000}}));
+        assert_eq!(tracker.used_percent(), None);
+
+        tracker.observe(&json!({
+            "msg": {
+                "type": "token_count",
+                "info": {
+                    "total_token_usage": {"total_tokens": 250000},
+                    "last_token_usage": {"input_tokens": 180000, "output_tokens": 2000, "total_tokens": 182000},
+                    "model_context_window": 200000
+                }
+            }
+        }));
+        assert_eq!(tracker.used_percent(), Some(91));
+        assert!(warning(91, DEFAULT_WARNING_PERCENT)
+            .unwrap()
+            .contains("91% of its context window"));
+        assert_eq!(warning(40, DEFAULT_WARNING_PERCENT), None);
+
+        let mut configured = ContextTracker::new(Some(10_000));
+        configured.observe(&json!({"type": "turn.completed", "usage": {"input_tokens": 4000, "output_tokens": 1000}}));
+        assert_eq!(configured.used_percent(), Some(50));
+    }
+}
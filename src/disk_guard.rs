@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Stop walking the workspace after this many entries; usage is then a lower bound
+const MAX_WALK_ENTRIES: u64 = 500_000;
+
+/// `disk_guard` section of `codex-mcp.config.json`; applies to write-mode runs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DiskGuardConfig {
+    /// Refuse to start when the working directory's filesystem has less free
+    /// space than this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_free_bytes: Option<u64>,
+    /// Warn when a run grows the working directory by more than this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_growth_bytes: Option<u64>,
+}
+
+/// Error returned when the working directory's filesystem is nearly full
+#[derive(Debug, Clone)]
+pub struct LowDiskSpace {
+    pub working_dir: PathBuf,
+    pub free_bytes: u64,
+    pub min_free_bytes: u64,
+}
+
+impl fmt::Display for LowDiskSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LowDiskSpace: {} has {} bytes free, below min_free_bytes ({})",
+            self.working_dir.display(),
+            self.free_bytes,
+            self.min_free_bytes
+        )
+    }
+}
+
+impl std::error::Error for LowDiskSpace {}
+
+/// Bytes used by the files under a directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirUsage {
+    pub bytes: u64,
+    /// True when the walk stopped early, so `bytes` is a lower bound
+    pub truncated: bool,
+}
+
+/// Fail if `working_dir`'s filesystem has less than `min_free_bytes` free.
+/// Filesystems whose free space cannot be read pass.
+pub fn check_free_space(working_dir: &Path, min_free_bytes: u64) -> Result<(), LowDiskSpace> {
+    match free_space(working_dir) {
+        Some(free_bytes) if free_bytes < min_free_bytes => Err(LowDiskSpace {
+            working_dir: working_dir.to_path_buf(),
+            free_bytes,
+            min_free_bytes,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Space available to unprivileged users on the filesystem holding `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Total size of the files under `root`, including ignored directories such
+/// as `node_modules` but not `.git`. Symlinks are not followed.
+pub fn dir_usage(root: &Path) -> DirUsage {
+    let mut usage = DirUsage::default();
+    let mut entries = 0u64;
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read_dir.flatten() {
+            entries += 1;
+            if entries > MAX_WALK_ENTRIES {
+                usage.truncated = true;
+                return usage;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if entry.file_name() != ".git" {
+                    stack.push(entry.path());
+                }
+            } else if file_type.is_file() {
+                usage.bytes += entry.metadata().map_or(0, |m| m.len());
+            }
+        }
+    }
+
+    usage
+}
+
+/// Warning text if the working directory grew by more than `max_growth_bytes`
+pub fn growth_warning(before: DirUsage, after: DirUsage, max_growth_bytes: u64) -> Option<String> {
+    if before.truncated || after.truncated {
+        return None;
+    }
+    let growth = after.bytes.saturating_sub(before.bytes);
+    (growth > max_growth_bytes).then(|| {
+        format!(
+            "The run grew the working directory by {} bytes ({} -> {}), more than max_growth_bytes ({}); check for downloaded dependencies or build output",
+            growth, before.bytes, after.bytes, max_growth_bytes
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dir_usage_skips_git_only() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join("a.txt"), vec![0u8; 100]).unwrap();
+        std::fs::write(root.join("node_modules/pkg/index.js"), vec![0u8; 50]).unwrap();
+        std::fs::write(root.join(".git/index"), vec![0u8; 1000]).unwrap();
+
+        assert_eq!(
+            dir_usage(root),
+            DirUsage {
+                bytes: 150,
+                truncated: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_growth_warning_and_free_space() {
+        let before = DirUsage {
+            bytes: 1000,
+            truncated: false,
+        };
+        let after = DirUsage {
+            bytes: 5000,
+            truncated: false,
+        };
+        assert!(growth_warning(before, after, 4000).is_none());
+        assert!(growth_warning(before, after, 3999)
+            .unwrap()
+            .contains("by 4000 bytes"));
+        let partial = DirUsage {
+            truncated: true,
+            ..after
+        };
+        assert!(growth_warning(before, partial, 0).is_none());
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert!(check_free_space(temp_dir.path(), 0).is_ok());
+        if free_space(temp_dir.path()).is_some() {
+            let err = check_free_space(temp_dir.path(), u64::MAX).unwrap_err();
+            assert!(err.to_string().starts_with("LowDiskSpace:"));
+        }
+    }
+}
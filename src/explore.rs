@@ -0,0 +1,153 @@
+use crate::codex::{self, CodexResult, Options, SandboxMode};
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+
+/// Default time box, in seconds, of the read-only exploration
+pub const DEFAULT_TIMEOUT_SECS: u64 = 300;
+
+/// Longest plan, in characters, quoted in the approval request
+pub const MAX_CONFIRM_PLAN_CHARS: usize = 4000;
+
+/// Sent before the caller's prompt in the exploration phase
+const EXPLORE_INSTRUCTIONS: &str = "Do not change any files yet. Explore the repository and \
+reply with a plan for the task below: the files to change, the steps you will take, the impact \
+on other code, and the risks. Keep it brief.";
+
+/// Sent to resume the session for the execution phase
+const EXECUTE_PROMPT: &str =
+    "Carry out the plan above now. Follow it, and explain any step you had to change.";
+
+/// `explore_first` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ExploreFirstConfig {
+    /// Seconds the read-only exploration may take
+    pub timeout_secs: u64,
+    /// Ask the user to approve the plan (an MCP elicitation) before the
+    /// write phase runs
+    pub confirm: bool,
+}
+
+impl Default for ExploreFirstConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            confirm: false,
+        }
+    }
+}
+
+/// The read-only phase of an `EXPLORE_FIRST` run
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct Exploration {
+    pub run_id: String,
+    /// Plan and impact analysis Codex returned
+    pub plan: String,
+    /// Whether the session was resumed to carry out the plan
+    pub executed: bool,
+}
+
+impl Exploration {
+    pub fn new(explored: &CodexResult, executed: bool) -> Self {
+        Self {
+            run_id: explored.run_id.clone(),
+            plan: explored.agent_messages.clone(),
+            executed,
+        }
+    }
+}
+
+/// Options of the exploration: `opts` in a read-only sandbox, time-boxed,
+/// with the prompt asking for a plan. Diffs, path scopes, the final schema,
+/// and the run log belong to the write phase.
+pub fn explore_options(opts: &Options, config: &ExploreFirstConfig) -> Options {
+    Options {
+        prompt: format!("{}\n\nTask:\n{}", EXPLORE_INSTRUCTIONS, opts.prompt),
+        additional_args: codex::with_sandbox(&opts.additional_args, SandboxMode::ReadOnly),
+        timeout_secs: Some(config.timeout_secs),
+        log_to_file: None,
+        allowed_paths: Vec::new(),
+        return_diff: false,
+        final_schema: None,
+        source: Some("explore".to_string()),
+        ..opts.clone()
+    }
+}
+
+/// Options resuming the explored session to carry out its plan, in the
+/// caller's sandbox if it allows writes and `workspace-write` otherwise.
+/// Attachments and primer messages are already in the session.
+pub fn execute_options(opts: Options, session_id: &str) -> Options {
+    let additional_args = if SandboxMode::from_args(&opts.additional_args).is_write() {
+        opts.additional_args.clone()
+    } else {
+        codex::with_sandbox(&opts.additional_args, SandboxMode::WorkspaceWrite)
+    };
+    Options {
+        prompt: EXECUTE_PROMPT.to_string(),
+        session_id: Some(session_id.to_string()),
+        resume_last: false,
+        additional_args,
+        image_paths: Vec::new(),
+        primer_messages: Vec::new(),
+        ..opts
+    }
+}
+
+/// The plan as quoted in the approval request, cut to
+/// `MAX_CONFIRM_PLAN_CHARS`
+pub fn confirm_message(plan: &str) -> String {
+    let plan = match plan.char_indices().nth(MAX_CONFIRM_PLAN_CHARS) {
+        Some((end, _)) => format!("{}...", &plan[..end]),
+        None => plan.to_string(),
+    };
+    format!(
+        "Codex proposes this plan. Carry it out with write access?\n\n{}",
+        plan
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_phases_switch_sandbox_and_session() {
+        let opts = Options {
+            prompt: "Rename the flag".to_string(),
+            working_dir: PathBuf::from("/repo"),
+            additional_args: vec!["--sandbox".to_string(), "workspace-write".to_string()],
+            image_paths: vec![PathBuf::from("/repo/screen.png")],
+            return_diff: true,
+            timeout_secs: Some(900),
+            ..Default::default()
+        };
+
+        let explore = explore_options(&opts, &ExploreFirstConfig::default());
+        assert_eq!(
+            SandboxMode::from_args(&explore.additional_args),
+            SandboxMode::ReadOnly
+        );
+        assert!(explore.prompt.ends_with("Task:\nRename the flag"));
+        assert_eq!(explore.timeout_secs, Some(DEFAULT_TIMEOUT_SECS));
+        assert_eq!(explore.image_paths.len(), 1);
+        assert!(!explore.return_diff);
+
+        let execute = execute_options(opts, "sid-1");
+        assert_eq!(
+            SandboxMode::from_args(&execute.additional_args),
+            SandboxMode::WorkspaceWrite
+        );
+        assert_eq!(execute.session_id.as_deref(), Some("sid-1"));
+        assert!(execute.image_paths.is_empty());
+        assert!(execute.return_diff);
+        assert_eq!(execute.timeout_secs, Some(900));
+
+        let read_only = execute_options(Options::default(), "sid-2");
+        assert_eq!(
+            SandboxMode::from_args(&read_only.additional_args),
+            SandboxMode::WorkspaceWrite
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Characters of the normalized message kept, and hashed
+const MAX_NORMALIZED_CHARS: usize = 200;
+
+/// Volatile parts of error messages, replaced so repeats of one failure
+/// normalize to the same text. Applied in order: URLs and paths before the
+/// numbers inside them.
+fn patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (r"https?://\S+", "<url>"),
+            (
+                r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+                "<id>",
+            ),
+            (r#""[^"]*"|'[^']*'|`[^`]*`"#, "<str>"),
+            (r"(?:[A-Za-z]:)?(?:[\\/][\w.\-]+)+[\\/]?", "<path>"),
+            (r"\b(?:0x)?[0-9a-fA-F]*\d[0-9a-fA-F]*\b", "<n>"),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| (Regex::new(pattern).expect("valid pattern"), replacement))
+        .collect()
+    })
+}
+
+/// The first line of `error` with ids, paths, URLs, quoted values, and
+/// numbers replaced by placeholders, lowercased. Appended stderr is left
+/// out; it varies far more than the error it explains.
+pub fn normalize(error: &str) -> String {
+    let error = error.split("\nStderr:").next().unwrap_or(error);
+    let line = error
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("unknown error");
+    let mut normalized = line.to_string();
+    for (pattern, replacement) in patterns() {
+        normalized = pattern.replace_all(&normalized, *replacement).into_owned();
+    }
+    let normalized = normalized
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    match normalized.char_indices().nth(MAX_NORMALIZED_CHARS) {
+        Some((end, _)) => normalized[..end].to_string(),
+        None => normalized,
+    }
+}
+
+/// Stable id of the failure behind `error`: FNV-1a of its normalized text,
+/// so it does not change between builds
+pub fn fingerprint(error: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in normalize(error).bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeats_of_one_failure_share_a_fingerprint() {
+        let first = "codex error: 401 Unauthorized for https://api.example.com/v1/responses \
+                     (request 0199a1b2-c3d4-7e5f-8a9b-0c1d2e3f4a5b)\nStderr: retrying in 3s";
+        let second = "codex error: 401 Unauthorized for https://api.example.com/v1/other \
+                      (request 11112222-3333-4444-5555-666677778888)\nStderr: boom";
+        assert_eq!(
+            normalize(first),
+            "codex error: <n> unauthorized for <url> (request <id>)"
+        );
+        assert_eq!(fingerprint(first), fingerprint(second));
+        assert_eq!(fingerprint(first).len(), 16);
+
+        assert_eq!(
+            normalize("Failed to read /home/a/repo/src/main.rs: \"No such file\" after 12 tries"),
+            "failed to read <path>: <str> after <n> tries"
+        );
+        assert_ne!(
+            fingerprint("codex error: stream disconnected"),
+            fingerprint(first)
+        );
+        assert_eq!(normalize("\n\n"), "unknown error");
+    }
+}
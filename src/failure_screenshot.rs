@@ -0,0 +1,305 @@
+use crate::codex::CodexResult;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MAX_LINES: usize = 40;
+const DEFAULT_MAX_COLUMNS: usize = 120;
+
+/// `failure_screenshot` section of `codex-mcp.config.json`. When present (and the
+/// server is built with the `failure-screenshot` feature), failed runs also return
+/// the tail of their output rendered as a PNG image.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FailureScreenshotConfig {
+    #[serde(default = "default_max_lines")]
+    pub max_lines: usize,
+    #[serde(default = "default_max_columns")]
+    pub max_columns: usize,
+}
+
+fn default_max_lines() -> usize {
+    DEFAULT_MAX_LINES
+}
+
+fn default_max_columns() -> usize {
+    DEFAULT_MAX_COLUMNS
+}
+
+impl Default for FailureScreenshotConfig {
+    fn default() -> Self {
+        Self {
+            max_lines: DEFAULT_MAX_LINES,
+            max_columns: DEFAULT_MAX_COLUMNS,
+        }
+    }
+}
+
+/// Last lines of agent output followed by the error (which carries stderr on
+/// failure), clipped to the configured width and made printable ASCII.
+pub fn tail_lines(result: &CodexResult, config: &FailureScreenshotConfig) -> Vec<String> {
+    let mut text = result.agent_messages.clone();
+    if let Some(ref error) = result.error {
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        text.push_str(error);
+    }
+
+    let lines: Vec<&str> = text.lines().collect();
+    let start = lines.len().saturating_sub(config.max_lines.max(1));
+    lines[start..]
+        .iter()
+        .map(|line| {
+            line.replace('\t', "    ")
+                .chars()
+                .map(|c| {
+                    if c == ' ' || c.is_ascii_graphic() {
+                        c
+                    } else {
+                        '?'
+                    }
+                })
+                .take(config.max_columns.max(1))
+                .collect()
+        })
+        .collect()
+}
+
+/// Render the output tail of a failed run as a base64-encoded PNG. Returns None
+/// when the run succeeded, there is nothing to show, or the feature is disabled.
+pub fn render_base64(result: &CodexResult, config: &FailureScreenshotConfig) -> Option<String> {
+    if result.success {
+        return None;
+    }
+    let lines = tail_lines(result, config);
+    if lines.iter().all(|line| line.is_empty()) {
+        return None;
+    }
+    encode_png_base64(&lines)
+}
+
+#[cfg(not(feature = "failure-screenshot"))]
+fn encode_png_base64(_lines: &[String]) -> Option<String> {
+    None
+}
+
+#[cfg(feature = "failure-screenshot")]
+fn encode_png_base64(lines: &[String]) -> Option<String> {
+    use base64::Engine;
+
+    let png = raster::render_png(lines)
+        .inspect_err(|e| eprintln!("Warning: Failed to render failure screenshot: {}", e))
+        .ok()?;
+    Some(base64::engine::general_purpose::STANDARD.encode(png))
+}
+
+#[cfg(feature = "failure-screenshot")]
+mod raster {
+    use image::codecs::png::PngEncoder;
+    use image::{DynamicImage, GrayImage, Luma};
+
+    /// Pixel scale applied to the 5x8 glyphs
+    const SCALE: u32 = 2;
+    const GLYPH_WIDTH: u32 = 5;
+    const GLYPH_HEIGHT: u32 = 8;
+    /// Glyph plus one column/row of spacing, before scaling
+    const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+    const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+    const MARGIN: u32 = 8;
+    const BACKGROUND: Luma<u8> = Luma([24]);
+    const FOREGROUND: Luma<u8> = Luma([230]);
+
+    /// Classic 5x8 terminal font for ASCII 0x20..=0x7E. Each glyph is five
+    /// columns, least significant bit at the top.
+    const FONT: [[u8; 5]; 95] = [
+        [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+        [0x00, 0x00, 0x5F, 0x00, 0x00], // '!'
+        [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+        [0x14, 0x7F, 0x14, 0x7F, 0x14], // '#'
+        [0x24, 0x2A, 0x7F, 0x2A, 0x12], // '$'
+        [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+        [0x36, 0x49, 0x56, 0x20, 0x50], // '&'
+        [0x00, 0x08, 0x07, 0x03, 0x00], // '\''
+        [0x00, 0x1C, 0x22, 0x41, 0x00], // '('
+        [0x00, 0x41, 0x22, 0x1C, 0x00], // ')'
+        [0x2A, 0x1C, 0x7F, 0x1C, 0x2A], // '*'
+        [0x08, 0x08, 0x3E, 0x08, 0x08], // '+'
+        [0x00, 0x80, 0x70, 0x30, 0x00], // ','
+        [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+        [0x00, 0x00, 0x60, 0x60, 0x00], // '.'
+        [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+        [0x3E, 0x51, 0x49, 0x45, 0x3E], // '0'
+        [0x00, 0x42, 0x7F, 0x40, 0x00], // '1'
+        [0x72, 0x49, 0x49, 0x49, 0x46], // '2'
+        [0x21, 0x41, 0x49, 0x4D, 0x33], // '3'
+        [0x18, 0x14, 0x12, 0x7F, 0x10], // '4'
+        [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+        [0x3C, 0x4A, 0x49, 0x49, 0x31], // '6'
+        [0x41, 0x21, 0x11, 0x09, 0x07], // '7'
+        [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+        [0x46, 0x49, 0x49, 0x29, 0x1E], // '9'
+        [0x00, 0x00, 0x14, 0x00, 0x00], // ':'
+        [0x00, 0x40, 0x34, 0x00, 0x00], // ';'
+        [0x00, 0x08, 0x14, 0x22, 0x41], // '<'
+        [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+        [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+        [0x02, 0x01, 0x59, 0x09, 0x06], // '?'
+        [0x3E, 0x41, 0x5D, 0x59, 0x4E], // '@'
+        [0x7C, 0x12, 0x11, 0x12, 0x7C], // 'A'
+        [0x7F, 0x49, 0x49, 0x49, 0x36], // 'B'
+        [0x3E, 0x41, 0x41, 0x41, 0x22], // 'C'
+        [0x7F, 0x41, 0x41, 0x41, 0x3E], // 'D'
+        [0x7F, 0x49, 0x49, 0x49, 0x41], // 'E'
+        [0x7F, 0x09, 0x09, 0x09, 0x01], // 'F'
+        [0x3E, 0x41, 0x41, 0x51, 0x73], // 'G'
+        [0x7F, 0x08, 0x08, 0x08, 0x7F], // 'H'
+        [0x00, 0x41, 0x7F, 0x41, 0x00], // 'I'
+        [0x20, 0x40, 0x41, 0x3F, 0x01], // 'J'
+        [0x7F, 0x08, 0x14, 0x22, 0x41], // 'K'
+        [0x7F, 0x40, 0x40, 0x40, 0x40], // 'L'
+        [0x7F, 0x02, 0x1C, 0x02, 0x7F], // 'M'
+        [0x7F, 0x04, 0x08, 0x10, 0x7F], // 'N'
+        [0x3E, 0x41, 0x41, 0x41, 0x3E], // 'O'
+        [0x7F, 0x09, 0x09, 0x09, 0x06], // 'P'
+        [0x3E, 0x41, 0x51, 0x21, 0x5E], // 'Q'
+        [0x7F, 0x09, 0x19, 0x29, 0x46], // 'R'
+        [0x26, 0x49, 0x49, 0x49, 0x32], // 'S'
+        [0x03, 0x01, 0x7F, 0x01, 0x03], // 'T'
+        [0x3F, 0x40, 0x40, 0x40, 0x3F], // 'U'
+        [0x1F, 0x20, 0x40, 0x20, 0x1F], // 'V'
+        [0x3F, 0x40, 0x38, 0x40, 0x3F], // 'W'
+        [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+        [0x03, 0x04, 0x78, 0x04, 0x03], // 'Y'
+        [0x61, 0x59, 0x49, 0x4D, 0x43], // 'Z'
+        [0x00, 0x7F, 0x41, 0x41, 0x41], // '['
+        [0x02, 0x04, 0x08, 0x10, 0x20], // '\\'
+        [0x00, 0x41, 0x41, 0x41, 0x7F], // ']'
+        [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+        [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+        [0x00, 0x03, 0x07, 0x08, 0x00], // '`'
+        [0x20, 0x54, 0x54, 0x78, 0x40], // 'a'
+        [0x7F, 0x28, 0x44, 0x44, 0x38], // 'b'
+        [0x38, 0x44, 0x44, 0x44, 0x28], // 'c'
+        [0x38, 0x44, 0x44, 0x28, 0x7F], // 'd'
+        [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+        [0x00, 0x08, 0x7E, 0x09, 0x02], // 'f'
+        [0x18, 0xA4, 0xA4, 0x9C, 0x78], // 'g'
+        [0x7F, 0x08, 0x04, 0x04, 0x78], // 'h'
+        [0x00, 0x44, 0x7D, 0x40, 0x00], // 'i'
+        [0x20, 0x40, 0x40, 0x3D, 0x00], // 'j'
+        [0x7F, 0x10, 0x28, 0x44, 0x00], // 'k'
+        [0x00, 0x41, 0x7F, 0x40, 0x00], // 'l'
+        [0x7C, 0x04, 0x78, 0x04, 0x78], // 'm'
+        [0x7C, 0x08, 0x04, 0x04, 0x78], // 'n'
+        [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+        [0xFC, 0x18, 0x24, 0x24, 0x18], // 'p'
+        [0x18, 0x24, 0x24, 0x18, 0xFC], // 'q'
+        [0x7C, 0x08, 0x04, 0x04, 0x08], // 'r'
+        [0x48, 0x54, 0x54, 0x54, 0x24], // 's'
+        [0x04, 0x04, 0x3F, 0x44, 0x24], // 't'
+        [0x3C, 0x40, 0x40, 0x20, 0x7C], // 'u'
+        [0x1C, 0x20, 0x40, 0x20, 0x1C], // 'v'
+        [0x3C, 0x40, 0x30, 0x40, 0x3C], // 'w'
+        [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+        [0x4C, 0x90, 0x90, 0x90, 0x7C], // 'y'
+        [0x44, 0x64, 0x54, 0x4C, 0x44], // 'z'
+        [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+        [0x00, 0x00, 0x77, 0x00, 0x00], // '|'
+        [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+        [0x02, 0x01, 0x02, 0x04, 0x02], // '~'
+    ];
+
+    fn glyph(c: char) -> &'static [u8; 5] {
+        let index = (c as u32).wrapping_sub(0x20) as usize;
+        FONT.get(index).unwrap_or(&FONT[('?' as usize) - 0x20])
+    }
+
+    /// Rasterize printable-ASCII lines into a grayscale PNG
+    pub(super) fn render_png(lines: &[String]) -> anyhow::Result<Vec<u8>> {
+        let columns = lines.iter().map(|l| l.len()).max().unwrap_or(0).max(1) as u32;
+        let rows = lines.len().max(1) as u32;
+        let width = MARGIN * 2 + columns * CELL_WIDTH * SCALE;
+        let height = MARGIN * 2 + rows * CELL_HEIGHT * SCALE;
+        let mut img = GrayImage::from_pixel(width, height, BACKGROUND);
+
+        for (row, line) in lines.iter().enumerate() {
+            let origin_y = MARGIN + row as u32 * CELL_HEIGHT * SCALE;
+            for (col, c) in line.chars().enumerate() {
+                let origin_x = MARGIN + col as u32 * CELL_WIDTH * SCALE;
+                for (gx, bits) in glyph(c).iter().enumerate() {
+                    for gy in 0..GLYPH_HEIGHT {
+                        if bits & (1 << gy) == 0 {
+                            continue;
+                        }
+                        for dx in 0..SCALE {
+                            for dy in 0..SCALE {
+                                img.put_pixel(
+                                    origin_x + gx as u32 * SCALE + dx,
+                                    origin_y + gy * SCALE + dy,
+                                    FOREGROUND,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut png = Vec::new();
+        DynamicImage::ImageLuma8(img).write_with_encoder(PngEncoder::new(&mut png))?;
+        Ok(png)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed_result(agent_messages: &str, error: &str) -> CodexResult {
+        CodexResult {
+            success: false,
+            session_id: String::new(),
+            agent_messages: agent_messages.to_string(),
+            agent_messages_truncated: false,
+            all_messages: Vec::new(),
+            all_messages_truncated: false,
+            error: Some(error.to_string()),
+            warnings: None,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tail_lines_keeps_last_lines_and_clips_width() {
+        let messages = (0..10)
+            .map(|i| format!("line {}", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let result = failed_result(&messages, "boom\tfailed ✗");
+        let config = FailureScreenshotConfig {
+            max_lines: 3,
+            max_columns: 12,
+        };
+
+        let lines = tail_lines(&result, &config);
+        assert_eq!(lines, vec!["line 9", "", "boom    fail"]);
+    }
+
+    #[test]
+    fn test_render_skips_successful_runs() {
+        let mut result = failed_result("ok", "");
+        result.success = true;
+        assert!(render_base64(&result, &FailureScreenshotConfig::default()).is_none());
+    }
+
+    #[cfg(feature = "failure-screenshot")]
+    #[test]
+    fn test_render_produces_png() {
+        use base64::Engine;
+
+        let result = failed_result("partial output", "codex error: quota exhausted");
+        let encoded = render_base64(&result, &FailureScreenshotConfig::default()).unwrap();
+        let png = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+}
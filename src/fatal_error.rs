@@ -0,0 +1,75 @@
+use crate::client_sampling::FailureKind;
+use crate::model_fallback;
+
+/// Error event messages after which Codex cannot recover, by kind. Transient
+/// errors (rate limits, dropped streams) are left out: the CLI retries those
+/// itself.
+const FATAL_MARKERS: &[(FailureKind, &[&str])] = &[
+    (
+        FailureKind::Auth,
+        &[
+            "401 unauthorized",
+            "403 forbidden",
+            "not logged in",
+            "codex login",
+            "invalid api key",
+            "incorrect api key",
+        ],
+    ),
+    (
+        FailureKind::RateLimit,
+        &[
+            "insufficient_quota",
+            "exceeded your current quota",
+            "quota exceeded",
+            "usage limit",
+        ],
+    ),
+    (
+        FailureKind::InvalidArguments,
+        &[
+            "unexpected argument",
+            "unknown option",
+            "unrecognized option",
+            "invalid value for",
+        ],
+    ),
+];
+
+/// Kind of the fatal error `message` reports, if it is one: an auth failure,
+/// an exhausted quota, a rejected model, or an invalid flag. The run is
+/// stopped as soon as such an event is parsed, instead of waiting for Codex
+/// to wind down or for the timeout.
+pub fn classify(message: &str) -> Option<FailureKind> {
+    if model_fallback::rejection(message).is_some() {
+        return Some(FailureKind::Model);
+    }
+    let lowered = message.to_lowercase();
+    FATAL_MARKERS
+        .iter()
+        .find(|(_, markers)| markers.iter().any(|marker| lowered.contains(marker)))
+        .map(|(kind, _)| *kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classifies_only_unrecoverable_errors() {
+        assert_eq!(
+            classify("unexpected status 401 Unauthorized: Missing bearer"),
+            Some(FailureKind::Auth)
+        );
+        assert_eq!(
+            classify("You exceeded your current quota, please check your plan"),
+            Some(FailureKind::RateLimit)
+        );
+        assert_eq!(
+            classify("error: unexpected argument '--foo' found"),
+            Some(FailureKind::InvalidArguments)
+        );
+        assert_eq!(classify("429 Too Many Requests, retrying in 2s"), None);
+        assert_eq!(classify("stream disconnected before completion"), None);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::path_denylist;
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// Most files reported per run; later reads are counted, not listed
+pub const MAX_FILES_READ: usize = 200;
+
+/// Commands whose file operands are files they read
+const READ_COMMANDS: &[&str] = &["cat", "bat", "head", "tail", "nl", "less", "more", "sed"];
+
+/// Shells Codex wraps commands in, as in `bash -lc '<command>'`
+const SHELLS: &[&str] = &["bash", "sh", "zsh"];
+
+/// Split `command` into words the way a shell would, with `|`, `&&`, `||`,
+/// `;`, and redirections as words of their own. Enough for the commands
+/// Codex runs; expansions are left as they are.
+fn words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            '|' | '&' | ';' | '<' | '>' => {
+                if in_word && !(c == '>' && word.chars().all(|c| c.is_ascii_digit())) {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                let mut operator = std::mem::take(&mut word);
+                operator.push(c);
+                if chars.peek() == Some(&c) || (c == '>' && chars.peek() == Some(&'&')) {
+                    operator.extend(chars.next());
+                }
+                words.push(operator);
+                in_word = false;
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+fn is_operator(word: &str) -> bool {
+    matches!(word, "|" | "||" | "&" | "&&" | ";")
+}
+
+fn is_redirection(word: &str) -> bool {
+    word.trim_start_matches(|c: char| c.is_ascii_digit())
+        .starts_with(['<', '>'])
+}
+
+/// Files `command` reads, as written in it: operands of `cat`, `sed -n
+/// 1,80p`, `head`, and the like, also inside `bash -lc '...'`
+pub fn from_command(command: &str) -> Vec<String> {
+    let mut words = words(command);
+    if words.len() >= 3
+        && SHELLS.contains(&words[0].rsplit('/').next().unwrap_or(""))
+        && matches!(words[1].as_str(), "-c" | "-lc")
+    {
+        words = self::words(&words[2]);
+    }
+
+    let mut files = Vec::new();
+    for segment in words.split(|word| is_operator(word)) {
+        // Skip `VAR=value` prefixes
+        let mut segment = segment
+            .iter()
+            .skip_while(|word| word.contains('=') && !word.starts_with(['-', '/', '.']));
+        let Some(program) = segment.next() else {
+            continue;
+        };
+        let program = program.rsplit('/').next().unwrap_or(program);
+        if !READ_COMMANDS.contains(&program) {
+            continue;
+        }
+        // sed's first operand is its script, unless given with -e or -f
+        let mut script_pending = program == "sed";
+        let mut skip_next = false;
+        for word in segment {
+            if std::mem::take(&mut skip_next) {
+                continue;
+            }
+            if is_redirection(word) {
+                // The target is written, or read by the shell, not the command
+                skip_next = !word.ends_with(|c: char| c != '<' && c != '>' && c != '&');
+                continue;
+            }
+            if word.starts_with('-') && word.len() > 1 {
+                skip_next = match program {
+                    "sed" => matches!(word.as_str(), "-e" | "-f" | "--expression" | "--file"),
+                    "head" | "tail" => matches!(word.as_str(), "-n" | "-c" | "--lines" | "--bytes"),
+                    _ => false,
+                };
+                script_pending &= !skip_next;
+                continue;
+            }
+            if std::mem::take(&mut script_pending) {
+                continue;
+            }
+            files.push(word.clone());
+        }
+    }
+    files
+}
+
+/// Collects the files Codex read during a run from its completed commands
+#[derive(Debug, Default)]
+pub struct FilesRead {
+    files: Vec<PathBuf>,
+    dropped: usize,
+}
+
+impl FilesRead {
+    pub fn observe(&mut self, event: &Value) {
+        if event.get("type").and_then(Value::as_str) != Some("item.completed") {
+            return;
+        }
+        let Some(item) = event.get("item") else {
+            return;
+        };
+        if item.get("type").and_then(Value::as_str) != Some("command_execution") {
+            return;
+        }
+        // A failed read may not have read anything
+        if item
+            .get("exit_code")
+            .and_then(Value::as_i64)
+            .is_some_and(|code| code != 0)
+        {
+            return;
+        }
+        let command = item.get("command").and_then(Value::as_str).unwrap_or("");
+        for file in from_command(command) {
+            let path = PathBuf::from(file);
+            if self.files.contains(&path) {
+                continue;
+            }
+            if self.files.len() < MAX_FILES_READ {
+                self.files.push(path);
+            } else {
+                self.dropped += 1;
+            }
+        }
+    }
+
+    /// Files read, in the order first read, and how many more were left out
+    pub fn finish(self) -> (Vec<PathBuf>, usize) {
+        (self.files, self.dropped)
+    }
+}
+
+/// Warning naming the files read that match `sensitive_paths`, if any
+pub fn sensitive_warning(files: &[PathBuf], sensitive_paths: &[String]) -> Option<String> {
+    let matched: Vec<String> = files
+        .iter()
+        .filter(|path| path_denylist::matching_pattern(path, sensitive_paths).is_some())
+        .map(|path| path.display().to_string())
+        .collect();
+    (!matched.is_empty()).then(|| {
+        format!(
+            "Codex read files matching sensitive_paths: {}",
+            matched.join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_finds_files_read_by_commands() {
+        assert_eq!(
+            from_command("bash -lc 'sed -n 1,120p src/lib.rs'"),
+            ["src/lib.rs"]
+        );
+        assert_eq!(
+            from_command("bash -lc \"cat Cargo.toml README.md | head -n 20 && tail -n 5 'a b.txt' 2>/dev/null\""),
+            ["Cargo.toml", "README.md", "a b.txt"]
+        );
+        assert_eq!(
+            from_command("sed -e 's/a/b/' -i.bak src/x.rs > out.rs"),
+            ["src/x.rs"]
+        );
+        assert!(from_command("rg -n parse src").is_empty());
+        assert!(from_command("cargo test").is_empty());
+
+        let mut files = FilesRead::default();
+        for (command, exit_code) in [
+            ("cat .env", 0),
+            ("cat src/lib.rs", 0),
+            ("cat missing.rs", 1),
+            ("cat -n src/lib.rs", 0),
+        ] {
+            files.observe(&json!({"type": "item.completed", "item": {
+                "type": "command_execution", "command": command, "exit_code": exit_code
+            }}));
+        }
+        let (files, dropped) = files.finish();
+        assert_eq!(files, [PathBuf::from(".env"), PathBuf::from("src/lib.rs")]);
+        assert_eq!(dropped, 0);
+        let sensitive = path_denylist::DEFAULT_SENSITIVE_PATHS
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sensitive_warning(&files, &sensitive).as_deref(),
+            Some("Codex read files matching sensitive_paths: .env")
+        );
+    }
+}
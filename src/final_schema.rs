@@ -0,0 +1,317 @@
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Errors listed before the rest are summarized
+const MAX_REPORTED_ERRORS: usize = 5;
+
+/// Root directory for the schema files of all runs
+pub fn schemas_dir() -> PathBuf {
+    std::env::temp_dir().join("codex-mcp-schemas")
+}
+
+/// `FINAL_SCHEMA` written out for `codex exec --output-schema`; removed on drop
+#[derive(Debug)]
+pub struct SchemaFile {
+    path: PathBuf,
+}
+
+impl SchemaFile {
+    pub fn write(schema: &Value, run_id: &str) -> std::io::Result<Self> {
+        let dir = schemas_dir();
+        std::fs::create_dir_all(&dir)?;
+        let file = SchemaFile {
+            path: dir.join(format!("{}.json", run_id)),
+        };
+        std::fs::write(&file.path, serde_json::to_vec(schema)?)?;
+        Ok(file)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SchemaFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Reject schemas that cannot describe a final answer
+pub fn check_schema(schema: &Value) -> Result<(), String> {
+    let Some(object) = schema.as_object() else {
+        return Err("must be a JSON Schema object".to_string());
+    };
+    if let Some(kind) = object.get("type") {
+        let valid = match kind {
+            Value::String(name) => is_type_name(name),
+            Value::Array(names) => names
+                .iter()
+                .all(|name| name.as_str().is_some_and(is_type_name)),
+            _ => false,
+        };
+        if !valid {
+            return Err(format!("unknown type {}", kind));
+        }
+    }
+    Ok(())
+}
+
+fn is_type_name(name: &str) -> bool {
+    matches!(
+        name,
+        "object" | "array" | "string" | "number" | "integer" | "boolean" | "null"
+    )
+}
+
+/// Parse the final agent message as JSON and check it against `schema`.
+/// A message wrapped in a Markdown code fence is accepted.
+pub fn parse_final(schema: &Value, message: Option<&str>) -> Result<Value, String> {
+    let message = message
+        .map(str::trim)
+        .filter(|message| !message.is_empty())
+        .ok_or("Codex returned no final message")?;
+    let value: Value = serde_json::from_str(unfence(message))
+        .map_err(|e| format!("final message is not JSON: {}", e))?;
+
+    let mut errors = Vec::new();
+    validate(schema, &value, "", &mut errors);
+    if errors.is_empty() {
+        return Ok(value);
+    }
+    let total = errors.len();
+    errors.truncate(MAX_REPORTED_ERRORS);
+    let mut summary = errors.join("; ");
+    if total > MAX_REPORTED_ERRORS {
+        summary.push_str(&format!(" (and {} more)", total - MAX_REPORTED_ERRORS));
+    }
+    Err(summary)
+}
+
+/// `message` without a surrounding Markdown code fence
+pub(crate) fn unfence(message: &str) -> &str {
+    let Some(rest) = message.strip_prefix("```") else {
+        return message;
+    };
+    let body = rest.split_once('\n').map_or("", |(_, body)| body);
+    body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+}
+
+/// Check `value` against the common JSON Schema keywords: `type`, `enum`,
+/// `const`, `properties`, `required`, `additionalProperties`, `items`,
+/// `minItems`/`maxItems`, `minLength`/`maxLength`, `minimum`/`maximum`,
+/// `anyOf`, `oneOf`, and `allOf`. Other keywords (`$ref`, `pattern`,
+/// `format`, ...) are left to the model and not checked.
+fn validate(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        // `true`/`false` schemas
+        if schema == &Value::Bool(false) {
+            errors.push(format!("{}: not allowed", pointer(path)));
+        }
+        return;
+    };
+    let mut fail = |message: String| errors.push(format!("{}: {}", pointer(path), message));
+
+    if let Some(kind) = schema.get("type") {
+        let names: Vec<&str> = match kind {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !names.is_empty() && !names.iter().any(|name| has_type(value, name)) {
+            fail(format!(
+                "expected {}, got {}",
+                names.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            fail(format!(
+                "{} is not one of {}",
+                value,
+                Value::from(allowed.clone())
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            fail(format!("expected {}", expected));
+        }
+    }
+
+    let bound = |key: &str| schema.get(key).and_then(Value::as_u64);
+    match value {
+        Value::String(text) => {
+            let chars = text.chars().count() as u64;
+            if let Some(min) = bound("minLength").filter(|min| chars < *min) {
+                fail(format!("shorter than {} characters", min));
+            }
+            if let Some(max) = bound("maxLength").filter(|max| chars > *max) {
+                fail(format!("longer than {} characters", max));
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or(0.0);
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < min {
+                    fail(format!("less than {}", min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > max {
+                    fail(format!("greater than {}", max));
+                }
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as u64;
+            if let Some(min) = bound("minItems").filter(|min| len < *min) {
+                fail(format!("fewer than {} items", min));
+            }
+            if let Some(max) = bound("maxItems").filter(|max| len > *max) {
+                fail(format!("more than {} items", max));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate(item_schema, item, &format!("{}/{}", path, index), errors);
+                }
+            }
+        }
+        Value::Object(fields) => {
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !fields.contains_key(name) {
+                        errors.push(format!("{}: missing property {}", pointer(path), name));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (name, field) in fields {
+                let field_path = format!("{}/{}", path, name);
+                match properties.and_then(|properties| properties.get(name)) {
+                    Some(field_schema) => validate(field_schema, field, &field_path, errors),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{}: unexpected property {}", pointer(path), name))
+                        }
+                        Some(extra @ Value::Object(_)) => {
+                            validate(extra, field, &field_path, errors)
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+        for sub in all {
+            validate(sub, value, path, errors);
+        }
+    }
+    let matching = |subs: &Vec<Value>| {
+        subs.iter()
+            .filter(|sub| {
+                let mut sub_errors = Vec::new();
+                validate(sub, value, path, &mut sub_errors);
+                sub_errors.is_empty()
+            })
+            .count()
+    };
+    if let Some(any) = schema.get("anyOf").and_then(Value::as_array) {
+        if matching(any) == 0 {
+            errors.push(format!("{}: matches none of anyOf", pointer(path)));
+        }
+    }
+    if let Some(one) = schema.get("oneOf").and_then(Value::as_array) {
+        let count = matching(one);
+        if count != 1 {
+            errors.push(format!(
+                "{}: matches {} of oneOf, expected exactly 1",
+                pointer(path),
+                count
+            ));
+        }
+    }
+}
+
+fn pointer(path: &str) -> &str {
+    if path.is_empty() {
+        "/"
+    } else {
+        path
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_final_validates_against_schema() {
+        let schema = json!({
+            "type": "object",
+            "required": ["verdict", "issues"],
+            "additionalProperties": false,
+            "properties": {
+                "verdict": {"enum": ["approve", "reject"]},
+                "issues": {
+                    "type": "array",
+                    "items": {"type": "object", "required": ["line"], "properties": {"line": {"type": "integer", "minimum": 1}}}
+                }
+            }
+        });
+        assert!(check_schema(&schema).is_ok());
+        assert!(check_schema(&json!({"type": "text"})).is_err());
+        assert!(check_schema(&json!("object")).is_err());
+
+        let ok = parse_final(
+            &schema,
+            Some("```json\n{\"verdict\": \"approve\", \"issues\": [{\"line\": 3}]}\n```"),
+        )
+        .unwrap();
+        assert_eq!(ok["issues"][0]["line"], 3);
+
+        let err = parse_final(
+            &schema,
+            Some(r#"{"verdict": "maybe", "issues": [{"line": 0}, {}], "extra": 1}"#),
+        )
+        .unwrap_err();
+        assert!(err.contains("/verdict: \"maybe\" is not one of"));
+        assert!(err.contains("/issues/0/line: less than 1"));
+        assert!(err.contains("/issues/1: missing property line"));
+        assert!(err.contains("/: unexpected property extra"));
+
+        assert!(parse_final(&schema, Some("Looks good to me"))
+            .unwrap_err()
+            .starts_with("final message is not JSON"));
+        assert_eq!(
+            parse_final(&schema, None).unwrap_err(),
+            "Codex returned no final message"
+        );
+    }
+}
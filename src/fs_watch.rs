@@ -0,0 +1,383 @@
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher as _};
+use rmcp::schemars;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Paths listed per category in `FsChanges`; the counts cover the rest
+const MAX_LISTED_PATHS: usize = 200;
+
+/// Paths named in a warning
+const MAX_WARNING_PATHS: usize = 5;
+
+/// Files a run changed on disk, compared with the changes Codex reported
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct FsChanges {
+    /// Files created, modified, or deleted during the run, relative to the
+    /// working directory; git-ignored files are left out
+    pub changed: Vec<String>,
+    /// Changed on disk without a reported patch, e.g. by a command Codex ran
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unreported: Vec<String>,
+    /// Reported as patched but unchanged on disk
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unconfirmed: Vec<String>,
+    pub changed_count: usize,
+    /// Set when the watch missed events (event queue overflow or a watcher
+    /// error); `unconfirmed` is then not computed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub incomplete: bool,
+}
+
+impl FsChanges {
+    /// Warnings for the discrepancies between disk and reported patches
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.unreported.is_empty() {
+            warnings.push(format!(
+                "{} file(s) changed on disk without a reported patch: {}",
+                self.unreported.len(),
+                name_some(&self.unreported)
+            ));
+        }
+        if !self.unconfirmed.is_empty() {
+            warnings.push(format!(
+                "Codex reported changes to {} file(s) that did not change on disk: {}",
+                self.unconfirmed.len(),
+                name_some(&self.unconfirmed)
+            ));
+        }
+        if self.incomplete {
+            warnings.push(
+                "The workspace watch missed events; fs_changes may be incomplete".to_string(),
+            );
+        }
+        warnings
+    }
+}
+
+fn name_some(paths: &[String]) -> String {
+    let mut named = paths[..paths.len().min(MAX_WARNING_PATHS)].join(", ");
+    if paths.len() > MAX_WARNING_PATHS {
+        named.push_str(&format!(" (+{} more)", paths.len() - MAX_WARNING_PATHS));
+    }
+    named
+}
+
+/// What a finished watch saw, relative to the watched directory
+#[derive(Debug, Default)]
+pub struct Observed {
+    /// Paths written, created, deleted, or renamed; a directory removed or
+    /// moved away is recorded as a whole
+    touched: BTreeSet<PathBuf>,
+    /// Paths that did not exist when the watch started
+    created: BTreeSet<PathBuf>,
+    incomplete: bool,
+}
+
+/// Paths Codex reported in `file_change` events, relative to `root`
+fn reported_paths(root: &Path, events: &[HashMap<String, Value>]) -> BTreeSet<PathBuf> {
+    events
+        .iter()
+        .filter(|event| event.get("type").and_then(Value::as_str) == Some("item.completed"))
+        .filter_map(|event| event.get("item"))
+        .filter(|item| item.get("type").and_then(Value::as_str) == Some("file_change"))
+        .filter_map(|item| item.get("changes").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|change| change.get("path").and_then(Value::as_str))
+        .map(|path| {
+            let path = Path::new(path);
+            path.strip_prefix(root).unwrap_or(path).to_path_buf()
+        })
+        .collect()
+}
+
+/// Compare what the watch saw with the changes Codex reported. Files created
+/// and removed again during the run (temp files) do not count, nor do files
+/// git ignores.
+pub async fn reconcile(
+    root: &Path,
+    observed: Observed,
+    events: &[HashMap<String, Value>],
+) -> FsChanges {
+    let touched: Vec<PathBuf> = observed
+        .touched
+        .into_iter()
+        .filter(|path| !observed.created.contains(path) || root.join(path).exists())
+        .collect();
+    let ignored = git_ignored(root, &touched).await;
+    let changed: BTreeSet<PathBuf> = touched
+        .into_iter()
+        .filter(|path| !ignored.contains(path))
+        .collect();
+    let reported = reported_paths(root, events);
+
+    let listed = |paths: &mut dyn Iterator<Item = &PathBuf>| -> Vec<String> {
+        paths
+            .take(MAX_LISTED_PATHS)
+            .map(|path| path.display().to_string())
+            .collect()
+    };
+    // A removed or moved-away directory is recorded as a whole, and covers
+    // the files Codex reported below it
+    let mut unreported = changed.iter().filter(|path| {
+        !reported
+            .range::<PathBuf, _>(*path..)
+            .next()
+            .is_some_and(|next| next.starts_with(path))
+    });
+    let mut unconfirmed = reported
+        .iter()
+        .filter(|path| !path.ancestors().any(|ancestor| changed.contains(ancestor)));
+    FsChanges {
+        changed: listed(&mut changed.iter()),
+        unreported: listed(&mut unreported),
+        unconfirmed: if observed.incomplete {
+            Vec::new()
+        } else {
+            listed(&mut unconfirmed)
+        },
+        changed_count: changed.len(),
+        incomplete: observed.incomplete,
+    }
+}
+
+/// Which of `paths` git ignores; none outside a repository
+async fn git_ignored(root: &Path, paths: &[PathBuf]) -> BTreeSet<PathBuf> {
+    if paths.is_empty() {
+        return BTreeSet::new();
+    }
+    let Ok(mut child) = Command::new("git")
+        .args(["check-ignore", "--stdin", "-z", "--no-index"])
+        .current_dir(root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+    else {
+        return BTreeSet::new();
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        let mut input = Vec::new();
+        for path in paths {
+            input.extend_from_slice(path.to_string_lossy().as_bytes());
+            input.push(0);
+        }
+        let _ = stdin.write_all(&input).await;
+    }
+    match child.wait_with_output().await {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+        Err(_) => BTreeSet::new(),
+    }
+}
+
+/// A watch of a directory tree; events are recorded on the watcher's own
+/// thread until `stop` is called
+pub struct Watcher {
+    watcher: notify::RecommendedWatcher,
+    observed: Arc<Mutex<Observed>>,
+}
+
+impl Watcher {
+    /// Stop watching and return what was seen
+    pub async fn stop(self) -> Observed {
+        drop(self.watcher);
+        std::mem::take(&mut *self.observed.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Watch `root` and every directory below it; anything under `.git` is ignored
+pub fn start(root: &Path) -> Result<Watcher, String> {
+    let observed = Arc::new(Mutex::new(Observed::default()));
+    let recorder = Recorder {
+        root: root.to_path_buf(),
+        // Some backends (FSEvents) report canonical paths
+        canonical_root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+        observed: Arc::clone(&observed),
+    };
+    let mut watcher = notify::recommended_watcher(move |event| recorder.record(event))
+        .map_err(|e| format!("failed to start watching the workspace: {}", e))?;
+    watcher
+        .watch(root, RecursiveMode::Recursive)
+        .map_err(|e| format!("failed to start watching the workspace: {}", e))?;
+    Ok(Watcher { watcher, observed })
+}
+
+struct Recorder {
+    root: PathBuf,
+    canonical_root: PathBuf,
+    observed: Arc<Mutex<Observed>>,
+}
+
+impl Recorder {
+    fn record(&self, event: notify::Result<Event>) {
+        let mut observed = self.observed.lock().unwrap_or_else(|e| e.into_inner());
+        let event = match event {
+            Ok(event) => event,
+            Err(_) => {
+                observed.incomplete = true;
+                return;
+            }
+        };
+        if event.need_rescan() {
+            observed.incomplete = true;
+        }
+        let created = match event.kind {
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => true,
+            EventKind::Modify(ModifyKind::Metadata(_)) | EventKind::Access(_) => return,
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                // Paths are [from, to]: the source is gone, the target is new
+                for (i, path) in event.paths.iter().enumerate() {
+                    self.record_path(&mut observed, path, i > 0);
+                }
+                return;
+            }
+            _ => false,
+        };
+        for path in &event.paths {
+            self.record_path(&mut observed, path, created);
+        }
+    }
+
+    /// Record `path`. A directory that appeared during the run has its files
+    /// recorded as created, since they may predate the watch on it; a
+    /// directory that was removed or moved away is recorded itself.
+    fn record_path(&self, observed: &mut Observed, path: &Path, created: bool) {
+        let Some(relative) = self.relative(path) else {
+            return;
+        };
+        if created && path.is_dir() {
+            record_new_tree(observed, path, &relative);
+            return;
+        }
+        if !created && path.is_dir() {
+            // A directory's own modify events say nothing about its files
+            return;
+        }
+        if created {
+            observed.created.insert(relative.clone());
+        }
+        observed.touched.insert(relative);
+    }
+
+    /// `path` relative to the root; `None` outside it or under `.git`
+    fn relative(&self, path: &Path) -> Option<PathBuf> {
+        let relative = path
+            .strip_prefix(&self.root)
+            .or_else(|_| path.strip_prefix(&self.canonical_root))
+            .ok()?;
+        let skipped = relative.as_os_str().is_empty()
+            || relative.components().any(|c| c.as_os_str() == ".git");
+        (!skipped).then(|| relative.to_path_buf())
+    }
+}
+
+/// Record every file below a directory that appeared during the run
+fn record_new_tree(observed: &mut Observed, dir: &Path, relative: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name();
+        if name == ".git" {
+            continue;
+        }
+        let child = relative.join(&name);
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => record_new_tree(observed, &entry.path(), &child),
+            Ok(_) => {
+                observed.created.insert(child.clone());
+                observed.touched.insert(child);
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_watch_reconciles_with_reported_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "old").unwrap();
+        std::fs::write(root.join("README.md"), "old").unwrap();
+
+        let watcher = start(&root).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "new").unwrap();
+        std::fs::create_dir_all(root.join("gen/out")).unwrap();
+        std::fs::write(root.join("gen/out/code.rs"), "generated").unwrap();
+        std::fs::write(root.join("scratch.tmp"), "temp").unwrap();
+        std::fs::remove_file(root.join("scratch.tmp")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let observed = watcher.stop().await;
+
+        let events = vec![HashMap::from([
+            ("type".to_string(), json!("item.completed")),
+            (
+                "item".to_string(),
+                json!({"type": "file_change", "changes": [
+                    {"path": root.join("src/lib.rs").display().to_string(), "kind": "update"},
+                    {"path": "README.md", "kind": "update"}
+                ]}),
+            ),
+        ])];
+        let changes = reconcile(&root, observed, &events).await;
+        assert_eq!(changes.changed, ["gen/out/code.rs", "src/lib.rs"]);
+        assert_eq!(changes.unreported, ["gen/out/code.rs"]);
+        assert_eq!(changes.unconfirmed, ["README.md"]);
+        assert!(!changes.incomplete);
+        assert_eq!(changes.warnings().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_watch_records_directories_moved_away() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        std::fs::write(root.join("src/nested/lib.rs"), "old").unwrap();
+        std::fs::create_dir(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs/guide.md"), "old").unwrap();
+
+        let watcher = start(&root).unwrap();
+        std::fs::rename(root.join("src"), elsewhere.path().join("src")).unwrap();
+        std::fs::remove_dir_all(root.join("docs")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let observed = watcher.stop().await;
+
+        let events = vec![HashMap::from([
+            ("type".to_string(), json!("item.completed")),
+            (
+                "item".to_string(),
+                json!({"type": "file_change", "changes": [
+                    {"path": "src/nested/lib.rs", "kind": "delete"}
+                ]}),
+            ),
+        ])];
+        let changes = reconcile(&root, observed, &events).await;
+        assert!(changes.changed.contains(&"src".to_string()));
+        assert!(changes.changed.iter().any(|path| path.starts_with("docs")));
+        assert!(changes
+            .unreported
+            .iter()
+            .all(|path| path.starts_with("docs")));
+        assert!(!changes.unreported.is_empty());
+        assert!(changes.unconfirmed.is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+/// How long collected metadata is reused for the same workspace
+const TTL: Duration = Duration::from_secs(2);
+
+/// Git metadata of a workspace, collected in one pass
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GitInfo {
+    /// Top of the working tree
+    pub repo_root: PathBuf,
+    /// The workspace relative to `repo_root`, with a trailing `/` (empty at
+    /// the top)
+    pub prefix: String,
+    /// Commit checked out; `None` before the first commit
+    pub head: Option<String>,
+    /// Branch checked out; `None` when HEAD is detached
+    pub branch: Option<String>,
+    /// Tracked files have uncommitted changes
+    pub dirty: bool,
+}
+
+fn cache() -> &'static Mutex<HashMap<PathBuf, (Instant, GitInfo)>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, (Instant, GitInfo)>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Metadata of the repository containing `working_dir`, reused for `TTL` so
+/// features that each need some of it share one collection
+pub async fn get(working_dir: &Path) -> Result<GitInfo, String> {
+    if let Some((collected, info)) = cache()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(working_dir)
+    {
+        if collected.elapsed() < TTL {
+            return Ok(info.clone());
+        }
+    }
+
+    let info = collect(working_dir).await?;
+    cache()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(working_dir.to_path_buf(), (Instant::now(), info.clone()));
+    Ok(info)
+}
+
+/// Drop cached metadata of `working_dir`, e.g. after a run that may have
+/// committed or switched branches
+pub fn invalidate(working_dir: &Path) {
+    cache()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(working_dir);
+}
+
+async fn collect(working_dir: &Path) -> Result<GitInfo, String> {
+    let (location, status) = tokio::join!(
+        git(
+            working_dir,
+            &["rev-parse", "--show-toplevel", "--show-prefix"]
+        ),
+        git(
+            working_dir,
+            &[
+                "status",
+                "--porcelain=v2",
+                "--branch",
+                "--untracked-files=no",
+                "-z"
+            ],
+        )
+    );
+    let location = String::from_utf8_lossy(&location?).into_owned();
+    let mut lines = location.lines();
+    let repo_root = lines
+        .next()
+        .filter(|root| !root.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| format!("{} is not in a git working tree", working_dir.display()))?;
+    let prefix = lines.next().unwrap_or_default().to_string();
+
+    let mut info = GitInfo {
+        repo_root,
+        prefix,
+        head: None,
+        branch: None,
+        dirty: false,
+    };
+    parse_status(&status?, &mut info);
+    Ok(info)
+}
+
+/// Fill in HEAD, branch, and dirtiness from `git status --porcelain=v2 --branch -z`
+fn parse_status(output: &[u8], info: &mut GitInfo) {
+    for entry in output.split(|b| *b == 0).filter(|e| !e.is_empty()) {
+        let entry = String::from_utf8_lossy(entry);
+        if let Some(oid) = entry.strip_prefix("# branch.oid ") {
+            info.head = (oid != "(initial)").then(|| oid.to_string());
+        } else if let Some(head) = entry.strip_prefix("# branch.head ") {
+            info.branch = (head != "(detached)").then(|| head.to_string());
+        } else if !entry.starts_with('#') {
+            info.dirty = true;
+        }
+    }
+}
+
+async fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let mut info = GitInfo {
+            repo_root: PathBuf::from("/repo"),
+            prefix: String::new(),
+            head: None,
+            branch: None,
+            dirty: false,
+        };
+        parse_status(
+            b"# branch.oid 1a2b3c\0# branch.head main\0# branch.upstream origin/main\0",
+            &mut info,
+        );
+        assert_eq!(info.head.as_deref(), Some("1a2b3c"));
+        assert_eq!(info.branch.as_deref(), Some("main"));
+        assert!(!info.dirty);
+
+        parse_status(
+            b"# branch.oid (initial)\0# branch.head (detached)\01 .M N... 100644 100644 100644 aa bb src/lib.rs\0",
+            &mut info,
+        );
+        assert_eq!(info.head, None);
+        assert_eq!(info.branch, None);
+        assert!(info.dirty);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::codex;
+use crate::warm_up;
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A blocking task that has not started within this long means the blocking
+/// thread pool, which runs log and workspace I/O, is exhausted
+const EXECUTOR_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Result of the self-check run on each MCP `ping`
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub healthy: bool,
+    /// One entry per failed check
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+    pub duration_ms: u64,
+}
+
+/// Check that blocking tasks still run, `runs_dir` is writable, and the Codex
+/// binary is on `PATH`
+pub async fn check() -> HealthReport {
+    let started = Instant::now();
+    let mut problems = Vec::new();
+    if let Err(e) = check_executor().await {
+        problems.push(e);
+    }
+    let runs_dir = codex::runs_dir();
+    match tokio::task::spawn_blocking(move || check_storage(&runs_dir)).await {
+        Ok(Err(e)) => problems.push(e),
+        Err(e) => problems.push(format!("storage check panicked: {}", e)),
+        Ok(Ok(())) => {}
+    }
+    if let Err(e) = check_binary(&codex::codex_bin()) {
+        problems.push(e);
+    }
+    HealthReport {
+        healthy: problems.is_empty(),
+        problems,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+async fn check_executor() -> Result<(), String> {
+    match tokio::time::timeout(EXECUTOR_TIMEOUT, tokio::task::spawn_blocking(|| ())).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(format!("blocking task failed: {}", e)),
+        Err(_) => Err(format!(
+            "executor: blocking thread pool did not run a task within {}s",
+            EXECUTOR_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+/// Write and remove a probe file in `dir`
+fn check_storage(dir: &Path) -> Result<(), String> {
+    let probe = dir.join(format!(".health-{}", std::process::id()));
+    std::fs::create_dir_all(dir)
+        .and_then(|()| std::fs::write(&probe, b"ok"))
+        .and_then(|()| std::fs::remove_file(&probe))
+        .map_err(|e| format!("storage: {} is not writable: {}", dir.display(), e))
+}
+
+fn check_binary(codex_bin: &str) -> Result<(), String> {
+    warm_up::resolve_binary(codex_bin)
+        .map(|_| ())
+        .ok_or_else(|| format!("codex binary: {} was not found on PATH", codex_bin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_storage() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let runs = temp_dir.path().join("runs");
+        assert!(check_storage(&runs).is_ok());
+        assert_eq!(std::fs::read_dir(&runs).unwrap().count(), 0);
+
+        let file = temp_dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        assert!(check_storage(&file.join("runs"))
+            .unwrap_err()
+            .starts_with("storage:"));
+    }
+
+    #[tokio::test]
+    async fn test_check_executor_and_binary() {
+        assert!(check_executor().await.is_ok());
+        assert!(check_binary("codex-mcp-test-no-such-binary")
+            .unwrap_err()
+            .contains("not found on PATH"));
+    }
+}
@@ -0,0 +1,797 @@
+use crate::classify::TaskKind;
+use crate::codex::{self, TokenUsage};
+use crate::failure_fingerprint;
+use crate::run_diff::FileStat;
+use crate::rusage::ResourceUsage;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Number of runs kept in memory; older runs are dropped first
+const MAX_RECORDS: usize = 200;
+
+/// Agent messages stored per run are capped at this many bytes (the end is kept)
+const MAX_STORED_MESSAGE_BYTES: usize = 1024 * 1024;
+
+/// Each `{{run:..}}` reference expands to at most this many bytes (the end is kept)
+const MAX_REFERENCE_BYTES: usize = 256 * 1024;
+
+/// Total bytes a prompt may gain from `{{run:..}}` references
+const MAX_EXPANDED_BYTES: usize = 1024 * 1024;
+
+/// Weight of the newest run in `RunStats::ema_duration_ms`
+const EMA_ALPHA: f64 = 0.2;
+
+/// Successful runs in a workspace needed before its durations are used to
+/// estimate progress
+const MIN_DURATION_SAMPLES: usize = 3;
+
+/// Recent runs a duration estimate is based on
+const DURATION_SAMPLE_WINDOW: usize = 20;
+
+/// Distinct failure fingerprints tallied; the one seen longest ago is
+/// dropped first
+const MAX_FINGERPRINTS: usize = 500;
+
+/// Failure times kept per fingerprint for windowed counts
+const MAX_FAILURE_TIMES: usize = 1000;
+
+/// A finished run, as recorded by `codex::run`
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+    pub run_id: String,
+    /// What started the run: `codex` for tool calls, `schedule:<name>` for schedules
+    pub source: String,
+    pub working_dir: PathBuf,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub success: bool,
+    pub timed_out: bool,
+    #[serde(rename = "SESSION_ID", skip_serializing_if = "String::is_empty")]
+    pub session_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Hash of the normalized error of a failed run; set by `record`, see
+    /// `failure_fingerprint`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_usage: Option<ResourceUsage>,
+    /// Caller-chosen id grouping related runs (`TASK_ID`)
+    #[serde(rename = "TASK_ID", skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    /// MCP `_meta` of the tool call that started the run
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_usage: Option<TokenUsage>,
+    /// Files changed by the run, when `RETURN_DIFF` was requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed_files: Vec<FileStat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff_file: Option<PathBuf>,
+    /// Extra attempts made because the run returned no agent messages
+    #[serde(skip_serializing_if = "is_zero")]
+    pub empty_response_retries: u32,
+    /// What the run was mainly spent on, guessed from its prompt and events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_kind: Option<TaskKind>,
+    /// Run this one repeated, when started by `codex_rerun`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rerun_of: Option<String>,
+    /// `PRIMER_MESSAGES` placed before the prompt
+    #[serde(skip_serializing_if = "is_zero")]
+    pub primer_messages: usize,
+    #[serde(skip)]
+    pub agent_messages: String,
+    /// What the run was started with; `None` for runs that cannot be rerun
+    #[serde(skip)]
+    pub invocation: Option<Invocation>,
+}
+
+/// Options a run was started with, after the server resolved presets,
+/// defaults, and `{{run:..}}` references, kept so `codex_rerun` can repeat it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Invocation {
+    pub prompt: String,
+    pub primer_messages: Vec<String>,
+    pub additional_args: Vec<String>,
+    pub image_paths: Vec<PathBuf>,
+    pub timeout_secs: Option<u64>,
+    pub allowed_paths: Vec<String>,
+    pub return_diff: bool,
+    pub final_schema: Option<serde_json::Value>,
+    pub codex_home: Option<PathBuf>,
+    pub response_language: Option<String>,
+}
+
+fn is_zero<T: Default + PartialEq>(n: &T) -> bool {
+    *n == T::default()
+}
+
+fn records() -> &'static Mutex<VecDeque<RunRecord>> {
+    static RECORDS: OnceLock<Mutex<VecDeque<RunRecord>>> = OnceLock::new();
+    RECORDS.get_or_init(|| Mutex::new(VecDeque::with_capacity(MAX_RECORDS)))
+}
+
+/// Store a finished run, evicting the oldest once `MAX_RECORDS` is reached
+pub fn record(mut run: RunRecord) {
+    if let Some(redactor) = codex::storage_redactor() {
+        run.agent_messages = redactor.redact(&run.agent_messages);
+        run.error = run.error.map(|error| redactor.redact(&error));
+        if let Some(invocation) = run.invocation.as_mut() {
+            invocation.prompt = redactor.redact(&invocation.prompt);
+            for message in &mut invocation.primer_messages {
+                *message = redactor.redact(message);
+            }
+        }
+    }
+    if !run.success {
+        let error = run.error.as_deref().unwrap_or("unknown error");
+        let fingerprint = failure_fingerprint::fingerprint(error);
+        let seen_at = run.started_at + run.duration_ms / 1000;
+        if let Ok(mut failures) = failures().lock() {
+            failures.add(&fingerprint, error, &run.run_id, seen_at);
+        }
+        run.failure_fingerprint = Some(fingerprint);
+    }
+    if run.agent_messages.len() > MAX_STORED_MESSAGE_BYTES {
+        let mut start = run.agent_messages.len() - MAX_STORED_MESSAGE_BYTES;
+        while !run.agent_messages.is_char_boundary(start) {
+            start += 1;
+        }
+        run.agent_messages.drain(..start);
+    }
+
+    if let Ok(mut records) = records().lock() {
+        if records.len() >= MAX_RECORDS {
+            records.pop_front();
+        }
+        records.push_back(run);
+    }
+}
+
+fn failures() -> &'static Mutex<FailureTallies> {
+    static FAILURES: OnceLock<Mutex<FailureTallies>> = OnceLock::new();
+    FAILURES.get_or_init(|| Mutex::new(FailureTallies::default()))
+}
+
+/// Failed runs sharing one failure fingerprint, as listed by `top_failures`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailureGroup {
+    pub fingerprint: String,
+    /// The normalized error all these runs failed with
+    pub message: String,
+    /// First line of the latest such error, as it was reported
+    pub example: String,
+    /// Failures within the requested window
+    pub count: usize,
+    /// Failures since the server started
+    pub total: u64,
+    /// Seconds since the Unix epoch
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub last_run_id: String,
+}
+
+#[derive(Debug, Clone)]
+struct FailureTally {
+    message: String,
+    example: String,
+    total: u64,
+    first_seen: u64,
+    /// Oldest first, at most `MAX_FAILURE_TIMES`
+    seen: VecDeque<u64>,
+    last_run_id: String,
+}
+
+/// Failure counts per fingerprint. Kept apart from the run records, so the
+/// counts outlive the runs evicted after `MAX_RECORDS`.
+#[derive(Debug, Default)]
+struct FailureTallies {
+    by_fingerprint: HashMap<String, FailureTally>,
+}
+
+impl FailureTallies {
+    fn add(&mut self, fingerprint: &str, error: &str, run_id: &str, seen_at: u64) {
+        if !self.by_fingerprint.contains_key(fingerprint)
+            && self.by_fingerprint.len() >= MAX_FINGERPRINTS
+        {
+            let stalest = self
+                .by_fingerprint
+                .iter()
+                .min_by_key(|(_, tally)| tally.seen.back().copied().unwrap_or(0))
+                .map(|(fingerprint, _)| fingerprint.clone());
+            if let Some(stalest) = stalest {
+                self.by_fingerprint.remove(&stalest);
+            }
+        }
+        let example = error
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("unknown error")
+            .to_string();
+        let tally = self
+            .by_fingerprint
+            .entry(fingerprint.to_string())
+            .or_insert_with(|| FailureTally {
+                message: failure_fingerprint::normalize(error),
+                example: String::new(),
+                total: 0,
+                first_seen: seen_at,
+                seen: VecDeque::new(),
+                last_run_id: String::new(),
+            });
+        tally.example = example;
+        tally.total += 1;
+        if tally.seen.len() >= MAX_FAILURE_TIMES {
+            tally.seen.pop_front();
+        }
+        tally.seen.push_back(seen_at);
+        tally.last_run_id = run_id.to_string();
+    }
+
+    fn top(&self, since: u64, limit: usize) -> Vec<FailureGroup> {
+        let mut groups: Vec<FailureGroup> = self
+            .by_fingerprint
+            .iter()
+            .filter_map(|(fingerprint, tally)| {
+                let count = tally.seen.iter().filter(|&&at| at >= since).count();
+                (count > 0).then(|| FailureGroup {
+                    fingerprint: fingerprint.clone(),
+                    message: tally.message.clone(),
+                    example: tally.example.clone(),
+                    count,
+                    total: tally.total,
+                    first_seen: tally.first_seen,
+                    last_seen: tally.seen.back().copied().unwrap_or(0),
+                    last_run_id: tally.last_run_id.clone(),
+                })
+            })
+            .collect();
+        groups.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then(b.last_seen.cmp(&a.last_seen))
+                .then_with(|| a.fingerprint.cmp(&b.fingerprint))
+        });
+        groups.truncate(limit);
+        groups
+    }
+}
+
+/// The most frequent failures since `since` (seconds since the Unix epoch),
+/// most failures first
+pub fn top_failures(since: u64, limit: usize) -> Vec<FailureGroup> {
+    match failures().lock() {
+        Ok(failures) => failures.top(since, limit),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Look up a run by id
+pub fn get(run_id: &str) -> Option<RunRecord> {
+    let records = records().lock().ok()?;
+    records.iter().rev().find(|r| r.run_id == run_id).cloned()
+}
+
+/// Median duration of the recent successful runs in `working_dir`, the
+/// expected length of another run there; `None` until there are enough runs
+pub fn expected_duration(working_dir: &Path) -> Option<Duration> {
+    let records = records().lock().ok()?;
+    let durations: Vec<u64> = records
+        .iter()
+        .rev()
+        .filter(|r| r.success && r.working_dir == working_dir)
+        .take(DURATION_SAMPLE_WINDOW)
+        .map(|r| r.duration_ms)
+        .collect();
+    median_duration(durations)
+}
+
+fn median_duration(mut durations: Vec<u64>) -> Option<Duration> {
+    if durations.len() < MIN_DURATION_SAMPLES {
+        return None;
+    }
+    durations.sort_unstable();
+    Some(Duration::from_millis(durations[durations.len() / 2]))
+}
+
+/// Most recent runs, newest first
+pub fn recent(limit: usize) -> Vec<RunRecord> {
+    match records().lock() {
+        Ok(records) => records.iter().rev().take(limit).cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Rolling statistics over the most recent runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunStats {
+    /// Runs the statistics cover (at most the requested window)
+    pub runs: usize,
+    pub avg_duration_ms: u64,
+    /// Exponential moving average of durations, weighted towards recent runs
+    pub ema_duration_ms: u64,
+    pub p95_duration_ms: u64,
+    pub max_duration_ms: u64,
+    /// Fraction (0-1) of runs that did not succeed, including timeouts
+    pub failure_rate: f64,
+    /// Fraction (0-1) of runs that hit the timeout
+    pub timeout_rate: f64,
+    /// Fraction (0-1) of runs retried by `retry_on_empty_response`
+    pub empty_response_retry_rate: f64,
+    /// Runs and tokens per task kind, most runs first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub task_kinds: Vec<TaskKindStats>,
+}
+
+/// Share of classified runs and their token usage for one task kind
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskKindStats {
+    pub kind: TaskKind,
+    pub runs: usize,
+    /// Fraction (0-1) of the classified runs
+    pub share: f64,
+    pub failure_rate: f64,
+    /// Input plus output tokens of these runs
+    pub total_tokens: u64,
+}
+
+/// Statistics over the last `window` runs
+pub fn stats(window: usize) -> RunStats {
+    let mut runs = recent(window);
+    runs.reverse();
+    compute_stats(&runs)
+}
+
+fn compute_stats(runs: &[RunRecord]) -> RunStats {
+    if runs.is_empty() {
+        return RunStats::default();
+    }
+
+    let mut durations: Vec<u64> = runs.iter().map(|r| r.duration_ms).collect();
+    let count = durations.len();
+    let total: u64 = durations.iter().sum();
+    let mut ema = durations[0] as f64;
+    for duration in &durations[1..] {
+        ema += EMA_ALPHA * (*duration as f64 - ema);
+    }
+    durations.sort_unstable();
+    // Nearest-rank percentile
+    let p95_index = (count * 95).div_ceil(100).saturating_sub(1);
+
+    let failures = runs.iter().filter(|r| !r.success).count();
+    let timeouts = runs.iter().filter(|r| r.timed_out).count();
+    let retried = runs.iter().filter(|r| r.empty_response_retries > 0).count();
+    RunStats {
+        runs: count,
+        avg_duration_ms: total / count as u64,
+        ema_duration_ms: ema.round() as u64,
+        p95_duration_ms: durations[p95_index],
+        max_duration_ms: durations[count - 1],
+        failure_rate: rate(failures, count),
+        timeout_rate: rate(timeouts, count),
+        empty_response_retry_rate: rate(retried, count),
+        task_kinds: task_kind_stats(runs),
+    }
+}
+
+fn task_kind_stats(runs: &[RunRecord]) -> Vec<TaskKindStats> {
+    let mut by_kind: BTreeMap<TaskKind, Vec<&RunRecord>> = BTreeMap::new();
+    for run in runs {
+        if let Some(kind) = run.task_kind {
+            by_kind.entry(kind).or_default().push(run);
+        }
+    }
+    let classified: usize = by_kind.values().map(Vec::len).sum();
+    let mut stats: Vec<TaskKindStats> = by_kind
+        .into_iter()
+        .map(|(kind, runs)| TaskKindStats {
+            kind,
+            runs: runs.len(),
+            share: rate(runs.len(), classified),
+            failure_rate: rate(runs.iter().filter(|r| !r.success).count(), runs.len()),
+            total_tokens: runs
+                .iter()
+                .filter_map(|r| r.token_usage.as_ref())
+                .map(|usage| usage.input_tokens + usage.output_tokens)
+                .sum(),
+        })
+        .collect();
+    stats.sort_by(|a, b| b.runs.cmp(&a.runs).then(a.kind.cmp(&b.kind)));
+    stats
+}
+
+fn rate(part: usize, total: usize) -> f64 {
+    (part as f64 / total as f64 * 1000.0).round() / 1000.0
+}
+
+/// One run of a task, as listed by `task_summary`
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskRun {
+    pub run_id: String,
+    pub source: String,
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub success: bool,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rerun_of: Option<String>,
+}
+
+/// Aggregate over the recorded runs sharing a `TASK_ID`
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskSummary {
+    #[serde(rename = "TASK_ID")]
+    pub task_id: String,
+    /// `succeeded` if the most recent run succeeded, otherwise `failed`
+    pub status: &'static str,
+    pub failed_runs: usize,
+    pub total_duration_ms: u64,
+    /// Summed over runs that reported token usage
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_tokens: Option<TokenUsage>,
+    /// Files changed by runs with `RETURN_DIFF`, line counts summed per file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed_files: Vec<FileStat>,
+    /// Diffs too large to return inline, in run order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub diff_files: Vec<PathBuf>,
+    /// Oldest first
+    pub runs: Vec<TaskRun>,
+}
+
+/// Summary of the runs recorded for `task_id`, or `None` if there are none.
+/// Only runs still held in history (the last `MAX_RECORDS`) are included.
+pub fn task_summary(task_id: &str) -> Option<TaskSummary> {
+    let runs: Vec<RunRecord> = match records().lock() {
+        Ok(records) => records
+            .iter()
+            .filter(|r| r.task_id.as_deref() == Some(task_id))
+            .cloned()
+            .collect(),
+        Err(_) => return None,
+    };
+    summarize_task(task_id, &runs)
+}
+
+fn summarize_task(task_id: &str, runs: &[RunRecord]) -> Option<TaskSummary> {
+    let last = runs.last()?;
+    let mut total_tokens: Option<TokenUsage> = None;
+    let mut changed: BTreeMap<String, FileStat> = BTreeMap::new();
+    for run in runs {
+        if let Some(usage) = &run.token_usage {
+            total_tokens
+                .get_or_insert_with(TokenUsage::default)
+                .add(usage);
+        }
+        for file in &run.changed_files {
+            let entry = changed
+                .entry(file.path.clone())
+                .or_insert_with(|| FileStat {
+                    path: file.path.clone(),
+                    added: Some(0),
+                    removed: Some(0),
+                });
+            // Binary in any run makes the totals meaningless
+            entry.added = entry.added.zip(file.added).map(|(a, b)| a + b);
+            entry.removed = entry.removed.zip(file.removed).map(|(a, b)| a + b);
+        }
+    }
+
+    Some(TaskSummary {
+        task_id: task_id.to_string(),
+        status: if last.success { "succeeded" } else { "failed" },
+        failed_runs: runs.iter().filter(|r| !r.success).count(),
+        total_duration_ms: runs.iter().map(|r| r.duration_ms).sum(),
+        total_tokens,
+        changed_files: changed.into_values().collect(),
+        diff_files: runs.iter().filter_map(|r| r.diff_file.clone()).collect(),
+        runs: runs
+            .iter()
+            .map(|r| TaskRun {
+                run_id: r.run_id.clone(),
+                source: r.source.clone(),
+                started_at: r.started_at,
+                duration_ms: r.duration_ms,
+                success: r.success,
+                meta: r.meta.clone(),
+                rerun_of: r.rerun_of.clone(),
+            })
+            .collect(),
+    })
+}
+
+/// Replace `{{run:<run_id>.<field>}}` references with the stored output of
+/// earlier runs. Fields: `agent_messages`, `error`, `session_id`.
+pub fn expand_references(prompt: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(prompt.len());
+    let mut added = 0;
+    let mut rest = prompt;
+    while let Some(start) = rest.find("{{run:") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let reference = &rest[start + "{{run:".len()..start + len];
+        let Some((run_id, field)) = reference.trim().rsplit_once('.') else {
+            return Err(format!(
+                "invalid run reference `{{{{run:{}}}}}`: expected `{{{{run:<run_id>.<field>}}}}`",
+                reference
+            ));
+        };
+        let run = get(run_id).ok_or_else(|| format!("unknown run_id in prompt: {}", run_id))?;
+        let value = match field {
+            "agent_messages" => run.agent_messages,
+            "error" => run.error.unwrap_or_default(),
+            "session_id" => run.session_id,
+            other => {
+                return Err(format!(
+                    "unknown run field `{}`: expected agent_messages, error, or session_id",
+                    other
+                ))
+            }
+        };
+        let value = keep_tail(value, MAX_REFERENCE_BYTES);
+
+        added += value.len();
+        if added > MAX_EXPANDED_BYTES {
+            return Err(format!(
+                "run references expand to more than {} bytes",
+                MAX_EXPANDED_BYTES
+            ));
+        }
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&value);
+        rest = &rest[start + len + "}}".len()..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn keep_tail(mut text: String, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut start = text.len() - max_bytes;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    let omitted = start;
+    text.drain(..start);
+    format!("[... {} bytes omitted]\n{}", omitted, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(run_id: &str, messages: &str) -> RunRecord {
+        RunRecord {
+            run_id: run_id.to_string(),
+            source: "codex".to_string(),
+            working_dir: PathBuf::from("/tmp"),
+            started_at: 0,
+            duration_ms: 10,
+            success: true,
+            timed_out: false,
+            session_id: String::new(),
+            error: None,
+            failure_fingerprint: None,
+            resource_usage: None,
+            task_id: None,
+            meta: None,
+            token_usage: None,
+            changed_files: Vec::new(),
+            diff_file: None,
+            empty_response_retries: 0,
+            task_kind: None,
+            rerun_of: None,
+            primer_messages: 0,
+            agent_messages: messages.to_string(),
+            invocation: None,
+        }
+    }
+
+    #[test]
+    fn test_record_and_get() {
+        record(run("history-test-a", "hello"));
+        record(run("history-test-b", "world"));
+
+        assert_eq!(get("history-test-a").unwrap().agent_messages, "hello");
+        assert!(get("history-test-missing").is_none());
+        assert!(recent(MAX_RECORDS)
+            .iter()
+            .any(|r| r.run_id == "history-test-b"));
+    }
+
+    #[test]
+    fn test_record_caps_stored_messages() {
+        let big = "x".repeat(MAX_STORED_MESSAGE_BYTES + 10) + "end";
+        record(run("history-test-big", &big));
+        let stored = get("history-test-big").unwrap().agent_messages;
+        assert_eq!(stored.len(), MAX_STORED_MESSAGE_BYTES);
+        assert!(stored.ends_with("end"));
+    }
+
+    #[test]
+    fn test_failure_tallies_group_and_rank() {
+        let mut tallies = FailureTallies::default();
+        let auth = "codex error: 401 Unauthorized (request 1)";
+        let disconnect = "codex error: stream disconnected";
+        for (i, (error, at)) in [(auth, 100), (disconnect, 150), (auth, 200), (auth, 300)]
+            .into_iter()
+            .enumerate()
+        {
+            let fingerprint = failure_fingerprint::fingerprint(error);
+            tallies.add(&fingerprint, error, &format!("run-{}", i), at);
+        }
+        tallies.add(
+            &failure_fingerprint::fingerprint("codex error: 401 Unauthorized (request 2)"),
+            "codex error: 401 Unauthorized (request 2)\nStderr: boom",
+            "run-4",
+            400,
+        );
+
+        let top = tallies.top(0, 10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].count, 4);
+        assert_eq!(
+            top[0].message,
+            "codex error: <n> unauthorized (request <n>)"
+        );
+        assert_eq!(top[0].example, "codex error: 401 Unauthorized (request 2)");
+        assert_eq!((top[0].first_seen, top[0].last_seen), (100, 400));
+        assert_eq!(top[0].last_run_id, "run-4");
+
+        // Counts follow the window; totals do not
+        let recent = tallies.top(160, 10);
+        assert_eq!(recent.len(), 1);
+        assert_eq!((recent[0].count, recent[0].total), (3, 4));
+        assert_eq!(tallies.top(0, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_median_duration_needs_enough_runs() {
+        assert_eq!(median_duration(vec![100, 200]), None);
+        assert_eq!(
+            median_duration(vec![900, 100, 300, 200]),
+            Some(Duration::from_millis(300))
+        );
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        assert_eq!(compute_stats(&[]), RunStats::default());
+
+        let mut runs: Vec<RunRecord> = (1..=20)
+            .map(|i| {
+                let mut r = run("history-test-stats", "");
+                r.duration_ms = i * 100;
+                r
+            })
+            .collect();
+        runs[3].success = false;
+        runs[19].success = false;
+        runs[19].timed_out = true;
+        runs[5].empty_response_retries = 1;
+        runs[0].task_kind = Some(TaskKind::Explain);
+        runs[1].task_kind = Some(TaskKind::Edit);
+        runs[2].task_kind = Some(TaskKind::Edit);
+        runs[3].task_kind = Some(TaskKind::Edit);
+        runs[2].token_usage = Some(TokenUsage {
+            input_tokens: 100,
+            cached_input_tokens: 50,
+            output_tokens: 20,
+        });
+
+        let stats = compute_stats(&runs);
+        assert_eq!(stats.runs, 20);
+        assert_eq!(stats.avg_duration_ms, 1050);
+        assert_eq!(stats.p95_duration_ms, 1900);
+        assert_eq!(stats.max_duration_ms, 2000);
+        assert!(stats.ema_duration_ms > stats.avg_duration_ms);
+        assert_eq!(stats.failure_rate, 0.1);
+        assert_eq!(stats.timeout_rate, 0.05);
+        assert_eq!(stats.empty_response_retry_rate, 0.05);
+        assert_eq!(
+            stats.task_kinds,
+            vec![
+                TaskKindStats {
+                    kind: TaskKind::Edit,
+                    runs: 3,
+                    share: 0.75,
+                    failure_rate: 0.333,
+                    total_tokens: 120,
+                },
+                TaskKindStats {
+                    kind: TaskKind::Explain,
+                    runs: 1,
+                    share: 0.25,
+                    failure_rate: 0.0,
+                    total_tokens: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summarize_task() {
+        assert!(summarize_task("t", &[]).is_none());
+
+        let stat = |path: &str, added: Option<u64>| FileStat {
+            path: path.to_string(),
+            added,
+            removed: added.map(|_| 1),
+        };
+        let mut plan = run("history-test-plan", "");
+        plan.token_usage = Some(TokenUsage {
+            input_tokens: 100,
+            cached_input_tokens: 10,
+            output_tokens: 5,
+        });
+        let mut edit = run("history-test-edit", "");
+        edit.token_usage = plan.token_usage;
+        edit.changed_files = vec![stat("src/lib.rs", Some(3)), stat("logo.png", None)];
+        let mut test = run("history-test-test", "");
+        test.success = false;
+        test.changed_files = vec![stat("src/lib.rs", Some(2))];
+
+        let summary = summarize_task("t", &[plan, edit, test]).unwrap();
+        assert_eq!(summary.status, "failed");
+        assert_eq!(summary.failed_runs, 1);
+        assert_eq!(summary.total_duration_ms, 30);
+        assert_eq!(summary.total_tokens.unwrap().input_tokens, 200);
+        assert_eq!(
+            summary.changed_files,
+            vec![
+                stat("logo.png", None),
+                FileStat {
+                    path: "src/lib.rs".to_string(),
+                    added: Some(5),
+                    removed: Some(2),
+                },
+            ]
+        );
+        assert_eq!(summary.runs.len(), 3);
+    }
+
+    #[test]
+    fn test_expand_references() {
+        let mut failed = run("history-test-ref", "previous answer");
+        failed.error = Some("boom".to_string());
+        record(failed);
+
+        assert_eq!(
+            expand_references(
+                "Review: {{run:history-test-ref.agent_messages}} ({{run:history-test-ref.error}})"
+            )
+            .unwrap(),
+            "Review: previous answer (boom)"
+        );
+        assert_eq!(expand_references("no refs {{x}}").unwrap(), "no refs {{x}}");
+        assert!(
+            expand_references("{{run:history-test-missing.agent_messages}}")
+                .unwrap_err()
+                .contains("unknown run_id")
+        );
+        assert!(expand_references("{{run:history-test-ref.stdout}}").is_err());
+    }
+
+    #[test]
+    fn test_expand_references_caps_size() {
+        let big = "y".repeat(MAX_REFERENCE_BYTES + 5) + "tail";
+        record(run("history-test-ref-big", &big));
+        let expanded = expand_references("{{run:history-test-ref-big.agent_messages}}").unwrap();
+        assert!(expanded.starts_with("[... 9 bytes omitted]"));
+        assert!(expanded.ends_with("tail"));
+
+        let many = "{{run:history-test-ref-big.agent_messages}}".repeat(5);
+        assert!(expand_references(&many).is_err());
+    }
+}
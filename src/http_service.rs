@@ -0,0 +1,157 @@
+//! The MCP handler as a Tower service, for mounting codex-mcp inside an
+//! existing HTTP server (e.g. with axum's `Router::nest_service`) behind the
+//! host application's own middleware.
+
+use crate::codex;
+use crate::server::CodexServer;
+use crate::tenants::{Denied, Tenants};
+use bytes::Bytes;
+use http::{header, Request, Response, StatusCode};
+use http_body::Body;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use rmcp::transport::streamable_http_server::session::local::LocalSessionManager;
+use rmcp::transport::streamable_http_server::{StreamableHttpServerConfig, StreamableHttpService};
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Largest request body read to check its tool calls
+const MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+
+/// Response body of the streamable HTTP transport
+type BoxBody = UnsyncBoxBody<Bytes, Infallible>;
+
+/// Streamable HTTP MCP endpoint serving a fresh `CodexServer` per session,
+/// behind the `api_keys` check
+pub type CodexHttpService = Authenticated<StreamableHttpService<CodexServer, LocalSessionManager>>;
+
+/// Service with rmcp's default streamable HTTP settings (stateful sessions,
+/// SSE keep-alive)
+pub fn service() -> CodexHttpService {
+    service_with_config(StreamableHttpServerConfig::default())
+}
+
+pub fn service_with_config(config: StreamableHttpServerConfig) -> CodexHttpService {
+    let inner = StreamableHttpService::new(
+        || Ok(CodexServer::new()),
+        Arc::new(LocalSessionManager::default()),
+        config,
+    );
+    let tenants = Tenants::new(codex::api_keys());
+    Authenticated {
+        inner,
+        tenants: (!tenants.is_empty()).then(|| Arc::new(tenants)),
+    }
+}
+
+/// Refuses requests `api_keys` does not allow before they reach `inner`:
+/// a missing or unknown bearer key with 401, a client over its rate limit
+/// with 429, and a tool call outside its workspaces or sandbox with 403.
+/// Without `api_keys` every request is passed through.
+#[derive(Debug, Clone)]
+pub struct Authenticated<S> {
+    inner: S,
+    tenants: Option<Arc<Tenants>>,
+}
+
+impl<S> Authenticated<S> {
+    pub fn new(inner: S, tenants: Tenants) -> Self {
+        Self {
+            inner,
+            tenants: Some(Arc::new(tenants)),
+        }
+    }
+}
+
+impl<S, B> tower_service::Service<Request<B>> for Authenticated<S>
+where
+    S: tower_service::Service<
+            Request<Full<Bytes>>,
+            Response = Response<BoxBody>,
+            Error = Infallible,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // Call the instance that was polled ready, leave a fresh one behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let tenants = self.tenants.clone();
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let body = match Limited::new(body, MAX_BODY_BYTES).collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(e) => {
+                    let status = if e.is::<LengthLimitError>() {
+                        StatusCode::PAYLOAD_TOO_LARGE
+                    } else {
+                        StatusCode::BAD_REQUEST
+                    };
+                    return Ok(reply(status, e.to_string()));
+                }
+            };
+            if let Some(tenants) = tenants {
+                let bearer = parts
+                    .headers
+                    .get(header::AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                    .map(str::trim);
+                if let Err(denied) = tenants.authorize_request(bearer, &body, Instant::now()) {
+                    return Ok(refused(&denied));
+                }
+            }
+            inner
+                .call(Request::from_parts(parts, Full::new(body)))
+                .await
+        })
+    }
+}
+
+fn reply(status: StatusCode, message: String) -> Response<BoxBody> {
+    let mut response = Response::new(Full::new(Bytes::from(message)).boxed_unsync());
+    *response.status_mut() = status;
+    response
+}
+
+fn refused(denied: &Denied) -> Response<BoxBody> {
+    let status = match denied {
+        Denied::UnknownKey => StatusCode::UNAUTHORIZED,
+        Denied::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        Denied::Workspace { .. } | Denied::Sandbox { .. } => StatusCode::FORBIDDEN,
+    };
+    let mut response = reply(status, denied.to_string());
+    let headers = response.headers_mut();
+    match denied {
+        Denied::UnknownKey => {
+            headers.insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Bearer"),
+            );
+        }
+        Denied::RateLimited {
+            retry_after_secs, ..
+        } => {
+            headers.insert(header::RETRY_AFTER, (*retry_after_secs).into());
+        }
+        _ => {}
+    }
+    response
+}
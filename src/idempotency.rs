@@ -0,0 +1,150 @@
+use crate::codex::CodexResult;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Completed runs are replayed for this long unless `idempotency_ttl_secs`
+/// is configured
+pub const DEFAULT_TTL_SECS: u64 = 600;
+
+#[derive(Debug)]
+enum Slot {
+    Running,
+    Done {
+        finished: Instant,
+        result: Box<CodexResult>,
+    },
+}
+
+type Key = (PathBuf, String);
+
+fn slots() -> &'static Mutex<HashMap<Key, Slot>> {
+    static SLOTS: OnceLock<Mutex<HashMap<Key, Slot>>> = OnceLock::new();
+    SLOTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Outcome of claiming an `IDEMPOTENCY_KEY`
+#[derive(Debug)]
+pub enum Claim {
+    /// No recent run used the key; start one and `complete` the guard
+    New(IdempotencyGuard),
+    /// A run with the key succeeded within the TTL; its result, marked as replayed
+    Done(Box<CodexResult>),
+    /// A run with the key is still in progress
+    Running,
+}
+
+/// Holds a claimed key while its run is in progress. Dropping the guard
+/// without `complete` releases the key, so a failed run can be retried.
+#[derive(Debug)]
+pub struct IdempotencyGuard {
+    key: Option<Key>,
+}
+
+impl IdempotencyGuard {
+    /// Store `result` for replay if the run succeeded; failed runs release
+    /// the key instead
+    pub fn complete(mut self, result: &CodexResult) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let mut slots = slots().lock().unwrap_or_else(|e| e.into_inner());
+        if result.success {
+            slots.insert(
+                key,
+                Slot::Done {
+                    finished: Instant::now(),
+                    result: Box::new(result.clone()),
+                },
+            );
+        } else {
+            slots.remove(&key);
+        }
+    }
+}
+
+impl Drop for IdempotencyGuard {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            slots()
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&key);
+        }
+    }
+}
+
+/// Claim `key` for a run in `working_dir`. Keys are scoped to the working
+/// directory; expired results are evicted first.
+pub fn claim(key: &str, working_dir: &Path, ttl: Duration) -> Claim {
+    let key = (working_dir.to_path_buf(), key.to_string());
+    let mut slots = slots().lock().unwrap_or_else(|e| e.into_inner());
+    slots.retain(|_, slot| match slot {
+        Slot::Running => true,
+        Slot::Done { finished, .. } => finished.elapsed() < ttl,
+    });
+    match slots.get(&key) {
+        Some(Slot::Running) => Claim::Running,
+        Some(Slot::Done { result, .. }) => Claim::Done(Box::new(replayed(result, &key.1))),
+        None => {
+            slots.insert(key.clone(), Slot::Running);
+            Claim::New(IdempotencyGuard { key: Some(key) })
+        }
+    }
+}
+
+fn replayed(result: &CodexResult, key: &str) -> CodexResult {
+    let note = format!(
+        "Returned the stored result of run {} for IDEMPOTENCY_KEY {}; no new run was started",
+        result.run_id, key
+    );
+    let mut result = result.clone();
+    result.warnings = Some(match result.warnings.take() {
+        Some(existing) => format!("{}\n{}", note, existing),
+        None => note,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_replays_successful_runs_only() {
+        let dir = Path::new("/tmp/idempotency-test");
+        let ttl = Duration::from_secs(60);
+
+        let Claim::New(guard) = claim("key-a", dir, ttl) else {
+            panic!("first claim should be new");
+        };
+        assert!(matches!(claim("key-a", dir, ttl), Claim::Running));
+        // Keys are scoped to the working directory
+        assert!(matches!(
+            claim("key-a", Path::new("/tmp/idempotency-other"), ttl),
+            Claim::New(_)
+        ));
+        guard.complete(&CodexResult {
+            run_id: "run-1".to_string(),
+            success: true,
+            agent_messages: "done".to_string(),
+            ..Default::default()
+        });
+        let Claim::Done(result) = claim("key-a", dir, ttl) else {
+            panic!("completed run should be replayed");
+        };
+        assert_eq!(result.agent_messages, "done");
+        assert!(result.warnings.unwrap().contains("run-1"));
+        assert!(matches!(claim("key-a", dir, Duration::ZERO), Claim::New(_)));
+
+        let Claim::New(guard) = claim("key-b", dir, ttl) else {
+            panic!("first claim should be new");
+        };
+        guard.complete(&CodexResult::default());
+        assert!(matches!(claim("key-b", dir, ttl), Claim::New(_)));
+
+        drop(claim("key-c", dir, ttl));
+        assert!(matches!(claim("key-c", dir, ttl), Claim::New(_)));
+    }
+}
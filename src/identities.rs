@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One entry of `identities` in `codex-mcp.config.json`: a Codex account,
+/// with its own `CODEX_HOME` (auth.json, config.toml), that runs can execute as
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Identity {
+    /// Name passed as `IDENTITY`, e.g. `work`
+    pub name: String,
+    /// `CODEX_HOME` of the account
+    pub codex_home: PathBuf,
+    /// Input plus output tokens the identity may use per UTC day (unlimited
+    /// when absent). Checked before each run, so the last run of a day may
+    /// overshoot it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_token_budget: Option<u64>,
+}
+
+/// Refusal of a run whose identity has used up its daily budget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    pub identity: String,
+    pub used: u64,
+    pub budget: u64,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BudgetExceeded: identity {} used {} of its {} tokens today (UTC)",
+            self.identity, self.used, self.budget
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Today's token use of one identity, for `codex_status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IdentityUsage {
+    pub name: String,
+    pub tokens_today: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily_token_budget: Option<u64>,
+}
+
+/// Tokens used per identity, with the UTC day they were counted on
+fn usage_by_identity() -> &'static Mutex<HashMap<String, (u64, u64)>> {
+    static USAGE: OnceLock<Mutex<HashMap<String, (u64, u64)>>> = OnceLock::new();
+    USAGE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 86_400)
+}
+
+/// The configured identity called `name`, if its `CODEX_HOME` exists
+pub fn find<'a>(identities: &'a [Identity], name: &str) -> Result<&'a Identity, String> {
+    let identity = identities
+        .iter()
+        .find(|identity| identity.name == name)
+        .ok_or_else(|| {
+            let names: Vec<&str> = identities.iter().map(|i| i.name.as_str()).collect();
+            if names.is_empty() {
+                format!("unknown identity {}; no identities are configured", name)
+            } else {
+                format!(
+                    "unknown identity {}; configured: {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+        })?;
+    if !identity.codex_home.is_dir() {
+        return Err(format!(
+            "CODEX_HOME {} of identity {} does not exist",
+            identity.codex_home.display(),
+            name
+        ));
+    }
+    Ok(identity)
+}
+
+fn used_on(name: &str, day: u64) -> u64 {
+    let usage = usage_by_identity()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    match usage.get(name) {
+        Some((counted_on, tokens)) if *counted_on == day => *tokens,
+        _ => 0,
+    }
+}
+
+fn check_budget_on(identity: &Identity, day: u64) -> Result<(), BudgetExceeded> {
+    let Some(budget) = identity.daily_token_budget else {
+        return Ok(());
+    };
+    let used = used_on(&identity.name, day);
+    if used >= budget {
+        return Err(BudgetExceeded {
+            identity: identity.name.clone(),
+            used,
+            budget,
+        });
+    }
+    Ok(())
+}
+
+/// Refuse a run once `identity` has used its daily token budget
+pub fn check_budget(identity: &Identity) -> Result<(), BudgetExceeded> {
+    check_budget_on(identity, today())
+}
+
+fn record_usage_on(name: &str, tokens: u64, day: u64) {
+    let mut usage = usage_by_identity()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let entry = usage.entry(name.to_string()).or_insert((day, 0));
+    if entry.0 != day {
+        *entry = (day, 0);
+    }
+    entry.1 += tokens;
+}
+
+/// Count a finished run's tokens against `name`
+pub fn record_usage(name: &str, tokens: u64) {
+    record_usage_on(name, tokens, today());
+}
+
+/// Today's usage of every configured identity
+pub fn usage(identities: &[Identity]) -> Vec<IdentityUsage> {
+    let day = today();
+    identities
+        .iter()
+        .map(|identity| IdentityUsage {
+            name: identity.name.clone(),
+            tokens_today: used_on(&identity.name, day),
+            daily_token_budget: identity.daily_token_budget,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daily_budget_resets_each_day() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let identity = Identity {
+            name: "budget-test".to_string(),
+            codex_home: temp_dir.path().to_path_buf(),
+            daily_token_budget: Some(1000),
+        };
+        let identities = [identity.clone()];
+        assert_eq!(find(&identities, "budget-test").unwrap(), &identity);
+        assert!(find(&identities, "personal")
+            .unwrap_err()
+            .contains("configured: budget-test"));
+
+        assert!(check_budget_on(&identity, 10).is_ok());
+        record_usage_on("budget-test", 600, 10);
+        assert!(check_budget_on(&identity, 10).is_ok());
+        record_usage_on("budget-test", 600, 10);
+        assert_eq!(
+            check_budget_on(&identity, 10),
+            Err(BudgetExceeded {
+                identity: "budget-test".to_string(),
+                used: 1200,
+                budget: 1000,
+            })
+        );
+        assert!(check_budget_on(&identity, 11).is_ok());
+        record_usage_on("budget-test", 5, 11);
+        assert_eq!(used_on("budget-test", 11), 5);
+    }
+}
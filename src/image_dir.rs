@@ -0,0 +1,119 @@
+use crate::path_denylist;
+use rmcp::schemars;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Attachments taken from one `IMAGE_DIR`
+pub const MAX_FILES: usize = 32;
+
+/// Combined size of the attachments taken from one `IMAGE_DIR`
+pub const MAX_TOTAL_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Extensions (lowercase) attached from an `IMAGE_DIR`; PDFs are expanded into
+/// page images like any other attached PDF
+const SUPPORTED_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp", "pdf"];
+
+/// What an `IMAGE_DIR` contributed to a run, by file name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct ImageDirListing {
+    pub dir: PathBuf,
+    pub included: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<SkippedFile>,
+    #[serde(skip)]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct SkippedFile {
+    pub file: String,
+    pub reason: String,
+}
+
+/// Supported files directly inside `dir`, in file name order, up to
+/// `MAX_FILES` and `MAX_TOTAL_BYTES`. Hidden files and subdirectories are
+/// ignored; other files that are not attached are listed as skipped.
+pub fn list(dir: &Path, sensitive_paths: &[String]) -> std::io::Result<ImageDirListing> {
+    let mut entries: Vec<(String, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            (!name.starts_with('.')).then(|| (name, entry.path()))
+        })
+        .collect();
+    entries.sort();
+
+    let mut listing = ImageDirListing {
+        dir: dir.to_path_buf(),
+        ..Default::default()
+    };
+    let mut total_bytes = 0u64;
+    for (name, path) in entries {
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+        let supported = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+        let reason = if !supported {
+            Some("not a supported image type".to_string())
+        } else if let Err(blocked) = path_denylist::check(&path, sensitive_paths) {
+            Some(format!("matches sensitive path {}", blocked.pattern))
+        } else if listing.included.len() >= MAX_FILES {
+            Some(format!("over the limit of {} files", MAX_FILES))
+        } else if total_bytes + metadata.len() > MAX_TOTAL_BYTES {
+            Some(format!(
+                "over the limit of {} MiB in total",
+                MAX_TOTAL_BYTES / (1024 * 1024)
+            ))
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => listing.skipped.push(SkippedFile { file: name, reason }),
+            None => {
+                total_bytes += metadata.len();
+                listing.included.push(name);
+                listing.paths.push(path);
+            }
+        }
+    }
+    Ok(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lists_supported_files_in_name_order() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        for name in ["b.PNG", "a.jpg", "notes.txt", ".hidden.png", "spec.pdf"] {
+            std::fs::write(dir.join(name), b"data").unwrap();
+        }
+        std::fs::create_dir(dir.join("nested.png")).unwrap();
+        for index in 0..MAX_FILES {
+            std::fs::write(dir.join(format!("shot-{:02}.png", index)), b"x").unwrap();
+        }
+
+        let listing = list(dir, &[]).unwrap();
+        assert_eq!(listing.included.len(), MAX_FILES);
+        assert_eq!(&listing.included[..3], ["a.jpg", "b.PNG", "shot-00.png"]);
+        assert_eq!(listing.paths[0], dir.join("a.jpg"));
+        let skipped: Vec<&str> = listing.skipped.iter().map(|s| s.file.as_str()).collect();
+        assert_eq!(
+            skipped,
+            ["notes.txt", "shot-30.png", "shot-31.png", "spec.pdf"]
+        );
+        assert!(listing.skipped[1].reason.contains("32 files"));
+
+        let listing = list(dir, &["*.jpg".to_string()]).unwrap();
+        assert!(listing.skipped[0].reason.contains("sensitive"));
+        assert!(list(&dir.join("missing"), &[]).is_err());
+    }
+}
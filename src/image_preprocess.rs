@@ -0,0 +1,303 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "image-preprocess")]
+use std::path::Path;
+use std::path::PathBuf;
+
+const DEFAULT_MAX_DIMENSION: u32 = 2048;
+const DEFAULT_QUALITY: u8 = 85;
+
+/// Output encoding for preprocessed images. Re-encoding drops EXIF and other
+/// metadata. WebP is not offered because the `image` crate only encodes lossless WebP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Jpeg,
+    Png,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Png => "png",
+        }
+    }
+}
+
+/// `image_preprocessing` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ImagePreprocessConfig {
+    /// Longest allowed edge in pixels; larger images are downscaled preserving aspect ratio
+    #[serde(default = "default_max_dimension")]
+    pub max_dimension: u32,
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// JPEG quality (1-100); ignored for PNG
+    #[serde(default = "default_quality")]
+    pub quality: u8,
+}
+
+fn default_max_dimension() -> u32 {
+    DEFAULT_MAX_DIMENSION
+}
+
+fn default_quality() -> u8 {
+    DEFAULT_QUALITY
+}
+
+impl Default for ImagePreprocessConfig {
+    fn default() -> Self {
+        Self {
+            max_dimension: DEFAULT_MAX_DIMENSION,
+            format: OutputFormat::default(),
+            quality: DEFAULT_QUALITY,
+        }
+    }
+}
+
+/// Images to pass to the Codex CLI for one run. Processed copies live in a
+/// per-run temp directory that is removed on drop.
+#[derive(Debug)]
+pub struct PreparedImages {
+    pub paths: Vec<PathBuf>,
+    pub warnings: Vec<String>,
+    dir: Option<PathBuf>,
+}
+
+impl Drop for PreparedImages {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.dir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// Root directory for processed images of all runs
+pub fn images_dir() -> PathBuf {
+    std::env::temp_dir().join("codex-mcp-images")
+}
+
+/// Downscale and re-encode `paths` according to `config`. Without a config the
+/// originals are passed through untouched; any image that fails to process or
+/// would not shrink is also passed through, with a warning.
+pub async fn prepare(
+    paths: &[PathBuf],
+    run_id: &str,
+    config: Option<&ImagePreprocessConfig>,
+) -> PreparedImages {
+    let Some(config) = config else {
+        return passthrough(paths, None);
+    };
+    if paths.is_empty() {
+        return passthrough(paths, None);
+    }
+
+    prepare_with(paths, images_dir().join(run_id), config.clone()).await
+}
+
+fn passthrough(paths: &[PathBuf], warning: Option<String>) -> PreparedImages {
+    PreparedImages {
+        paths: paths.to_vec(),
+        warnings: warning.into_iter().collect(),
+        dir: None,
+    }
+}
+
+#[cfg(not(feature = "image-preprocess"))]
+async fn prepare_with(
+    paths: &[PathBuf],
+    _dir: PathBuf,
+    _config: ImagePreprocessConfig,
+) -> PreparedImages {
+    passthrough(
+        paths,
+        Some(
+            "image_preprocessing is configured but this build lacks the `image-preprocess` feature; images were passed through unchanged."
+                .to_string(),
+        ),
+    )
+}
+
+#[cfg(feature = "image-preprocess")]
+async fn prepare_with(
+    paths: &[PathBuf],
+    dir: PathBuf,
+    config: ImagePreprocessConfig,
+) -> PreparedImages {
+    let originals = paths.to_vec();
+    let task_dir = dir.clone();
+    // Decoding and resizing are CPU-bound; keep them off the async workers
+    match tokio::task::spawn_blocking(move || process_all(originals, &task_dir, &config)).await {
+        Ok((paths, warnings)) => PreparedImages {
+            paths,
+            warnings,
+            dir: Some(dir),
+        },
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&dir);
+            passthrough(
+                paths,
+                Some(format!(
+                    "Image preprocessing failed, using originals: {}",
+                    e
+                )),
+            )
+        }
+    }
+}
+
+#[cfg(feature = "image-preprocess")]
+struct ProcessReport {
+    original_bytes: u64,
+    processed_bytes: u64,
+    original_dims: (u32, u32),
+    processed_dims: (u32, u32),
+}
+
+#[cfg(feature = "image-preprocess")]
+fn process_all(
+    paths: Vec<PathBuf>,
+    dir: &Path,
+    config: &ImagePreprocessConfig,
+) -> (Vec<PathBuf>, Vec<String>) {
+    let mut warnings = Vec::new();
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        warnings.push(format!(
+            "Image preprocessing skipped: failed to create {}: {}",
+            dir.display(),
+            e
+        ));
+        return (paths, warnings);
+    }
+
+    let mut prepared = Vec::with_capacity(paths.len());
+    for (index, path) in paths.into_iter().enumerate() {
+        let dest = dir.join(format!("{}.{}", index, config.format.extension()));
+        match process_one(&path, &dest, config) {
+            Ok(report) if report.processed_bytes < report.original_bytes => {
+                warnings.push(format!(
+                    "Image {}: {} bytes ({}x{}) -> {} bytes ({}x{})",
+                    path.display(),
+                    report.original_bytes,
+                    report.original_dims.0,
+                    report.original_dims.1,
+                    report.processed_bytes,
+                    report.processed_dims.0,
+                    report.processed_dims.1
+                ));
+                prepared.push(dest);
+            }
+            Ok(report) => {
+                warnings.push(format!(
+                    "Image {}: kept original ({} bytes); preprocessing would not shrink it",
+                    path.display(),
+                    report.original_bytes
+                ));
+                prepared.push(path);
+            }
+            Err(e) => {
+                warnings.push(format!(
+                    "Image {}: preprocessing failed, using original: {}",
+                    path.display(),
+                    e
+                ));
+                prepared.push(path);
+            }
+        }
+    }
+
+    (prepared, warnings)
+}
+
+#[cfg(feature = "image-preprocess")]
+fn process_one(
+    src: &Path,
+    dest: &Path,
+    config: &ImagePreprocessConfig,
+) -> anyhow::Result<ProcessReport> {
+    use image::codecs::{jpeg::JpegEncoder, png::PngEncoder};
+
+    let original_bytes = std::fs::metadata(src)?.len();
+    let img = image::open(src)?;
+    let original_dims = (img.width(), img.height());
+
+    let max = config.max_dimension.max(1);
+    let img = if img.width() > max || img.height() > max {
+        img.resize(max, max, image::imageops::FilterType::Lanczos3)
+    } else {
+        img
+    };
+    let processed_dims = (img.width(), img.height());
+
+    let file = std::io::BufWriter::new(std::fs::File::create(dest)?);
+    match config.format {
+        OutputFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(file, config.quality.clamp(1, 100));
+            image::DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder)?;
+        }
+        OutputFormat::Png => {
+            img.write_with_encoder(PngEncoder::new(file))?;
+        }
+    }
+
+    Ok(ProcessReport {
+        original_bytes,
+        processed_bytes: std::fs::metadata(dest)?.len(),
+        original_dims,
+        processed_dims,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_defaults() {
+        let cfg: ImagePreprocessConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(cfg, ImagePreprocessConfig::default());
+        assert_eq!(cfg.max_dimension, DEFAULT_MAX_DIMENSION);
+        assert_eq!(cfg.format, OutputFormat::Jpeg);
+
+        let cfg: ImagePreprocessConfig =
+            serde_json::from_str(r#"{"max_dimension":512,"format":"png"}"#).unwrap();
+        assert_eq!(cfg.max_dimension, 512);
+        assert_eq!(cfg.format, OutputFormat::Png);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_without_config_passes_through() {
+        let paths = vec![PathBuf::from("/tmp/a.png"), PathBuf::from("/tmp/b.png")];
+        let prepared = prepare(&paths, "run-1", None).await;
+        assert_eq!(prepared.paths, paths);
+        assert!(prepared.warnings.is_empty());
+    }
+
+    #[cfg(feature = "image-preprocess")]
+    #[tokio::test]
+    async fn test_prepare_downscales_large_images() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let src = temp_dir.path().join("wide.png");
+        let noisy = image::RgbImage::from_fn(1600, 400, |x, y| {
+            image::Rgb([(x % 251) as u8, (y % 241) as u8, ((x * y) % 239) as u8])
+        });
+        noisy.save(&src).unwrap();
+
+        let config = ImagePreprocessConfig {
+            max_dimension: 400,
+            ..ImagePreprocessConfig::default()
+        };
+        let prepared = prepare(std::slice::from_ref(&src), "test-downscale", Some(&config)).await;
+
+        assert_eq!(prepared.paths.len(), 1);
+        assert_ne!(prepared.paths[0], src);
+        let processed = image::open(&prepared.paths[0]).unwrap();
+        assert_eq!((processed.width(), processed.height()), (400, 100));
+        assert!(prepared.warnings[0].contains("1600x400"));
+
+        let dir = prepared.dir.clone().unwrap();
+        drop(prepared);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,237 @@
+/// Letters in an answer below which its language is not judged
+const MIN_LETTERS: usize = 40;
+
+/// Stop words matched in an answer below which a Latin-script language is
+/// not judged
+const MIN_STOP_WORDS: usize = 4;
+
+/// Languages `response_language` can check, by ISO 639-1 code and English
+/// name, with the stop words that tell Latin-script languages apart. Others
+/// are still asked for, but not verified.
+const LANGUAGES: &[(&str, &str, Script, &[&str])] = &[
+    ("en", "English", Script::Latin, EN),
+    ("de", "German", Script::Latin, DE),
+    ("fr", "French", Script::Latin, FR),
+    ("es", "Spanish", Script::Latin, ES),
+    ("it", "Italian", Script::Latin, IT),
+    ("pt", "Portuguese", Script::Latin, PT),
+    ("nl", "Dutch", Script::Latin, NL),
+    ("pl", "Polish", Script::Latin, PL),
+    ("ru", "Russian", Script::Cyrillic, &[]),
+    ("uk", "Ukrainian", Script::Cyrillic, &[]),
+    ("ja", "Japanese", Script::Kana, &[]),
+    ("zh", "Chinese", Script::Han, &[]),
+    ("ko", "Korean", Script::Hangul, &[]),
+    ("ar", "Arabic", Script::Arabic, &[]),
+    ("he", "Hebrew", Script::Hebrew, &[]),
+    ("el", "Greek", Script::Greek, &[]),
+    ("hi", "Hindi", Script::Devanagari, &[]),
+    ("th", "Thai", Script::Thai, &[]),
+];
+
+const EN: &[&str] = &[
+    "the", "and", "is", "are", "of", "to", "in", "that", "this", "with", "for", "it", "was", "not",
+    "be", "have", "you", "which", "can", "should",
+];
+const DE: &[&str] = &[
+    "der", "die", "das", "und", "ist", "nicht", "ein", "eine", "mit", "für", "auf", "den", "dem",
+    "sich", "wird", "auch", "ich", "sie", "wir", "werden",
+];
+const FR: &[&str] = &[
+    "le", "la", "les", "et", "est", "des", "une", "un", "pour", "dans", "que", "qui", "pas", "sur",
+    "avec", "sont", "du", "ce", "il", "vous",
+];
+const ES: &[&str] = &[
+    "el", "la", "los", "las", "y", "es", "que", "de", "en", "una", "un", "para", "por", "con",
+    "del", "se", "no", "está", "como", "pero",
+];
+const IT: &[&str] = &[
+    "il", "la", "e", "che", "di", "è", "un", "una", "per", "non", "sono", "con", "del", "della",
+    "gli", "le", "questo", "anche", "si", "nel",
+];
+const PT: &[&str] = &[
+    "o", "a", "os", "as", "e", "é", "que", "de", "um", "uma", "para", "não", "com", "do", "da",
+    "em", "se", "está", "mas", "são",
+];
+const NL: &[&str] = &[
+    "de", "het", "een", "en", "is", "van", "niet", "dat", "op", "met", "voor", "zijn", "ook",
+    "wordt", "je", "maar", "er", "dit", "bij", "naar",
+];
+const PL: &[&str] = &[
+    "i", "w", "na", "jest", "nie", "się", "z", "do", "że", "to", "czy", "jak", "oraz", "dla", "są",
+    "ale", "być", "po", "przez", "tylko",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    /// Japanese: hiragana and katakana, mixed with Han
+    Kana,
+    Han,
+    Hangul,
+    Arabic,
+    Hebrew,
+    Greek,
+    Devanagari,
+    Thai,
+}
+
+const SCRIPT_COUNT: usize = 10;
+
+impl Script {
+    fn of(c: char) -> Option<Self> {
+        Some(match c {
+            'a'..='z' | 'A'..='Z' | '\u{00c0}'..='\u{024f}' => Self::Latin,
+            '\u{0400}'..='\u{04ff}' => Self::Cyrillic,
+            '\u{3040}'..='\u{30ff}' => Self::Kana,
+            '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' => Self::Han,
+            '\u{ac00}'..='\u{d7af}' | '\u{1100}'..='\u{11ff}' => Self::Hangul,
+            '\u{0600}'..='\u{06ff}' => Self::Arabic,
+            '\u{0590}'..='\u{05ff}' => Self::Hebrew,
+            '\u{0370}'..='\u{03ff}' => Self::Greek,
+            '\u{0900}'..='\u{097f}' => Self::Devanagari,
+            '\u{0e00}'..='\u{0e7f}' => Self::Thai,
+            _ => return None,
+        })
+    }
+}
+
+/// Index of `language` in `LANGUAGES`
+fn lookup(language: &str) -> Option<usize> {
+    let language = language.trim();
+    // Accept locale-style codes such as `pt-BR` or `de_AT`
+    let code = language.split(['-', '_']).next().unwrap_or(language);
+    LANGUAGES.iter().position(|(known_code, name, _, _)| {
+        known_code.eq_ignore_ascii_case(code) || name.eq_ignore_ascii_case(language)
+    })
+}
+
+/// English name of `language` (a code or a name) for the instruction sent to
+/// Codex; unknown values are used as given
+pub fn name(language: &str) -> String {
+    match lookup(language) {
+        Some(index) => LANGUAGES[index].1.to_string(),
+        None => language.trim().to_string(),
+    }
+}
+
+/// Instruction appended to the prompt so the answer comes back in `language`
+pub fn instruction(language: &str) -> String {
+    format!(
+        "<response_language>\nWrite your final answer in {name}. Keep code, identifiers, \
+         file paths, and command output unchanged.\n</response_language>",
+        name = name(language)
+    )
+}
+
+/// Whether `text` reads as `language`, from its script and, for Latin-script
+/// languages, from stop words. `None` when there is too little prose to
+/// tell or the language is not one this check knows. Code blocks and inline
+/// code are ignored, since they stay in English whatever the language.
+pub fn is_written_in(text: &str, language: &str) -> Option<bool> {
+    let index = lookup(language)?;
+    let script = LANGUAGES[index].2;
+    let prose = strip_code(text);
+
+    let mut letters = [0usize; SCRIPT_COUNT];
+    for c in prose.chars() {
+        if let Some(found) = Script::of(c) {
+            letters[found as usize] += 1;
+        }
+    }
+    let total: usize = letters.iter().sum();
+    if total < MIN_LETTERS {
+        return None;
+    }
+    let share = |script: Script| letters[script as usize] * 100 / total;
+
+    match script {
+        // Japanese mixes kana and Han; CJK answers also carry Latin words
+        Script::Kana => {
+            Some(share(Script::Kana) > 0 && share(Script::Kana) + share(Script::Han) >= 40)
+        }
+        Script::Han => Some(share(Script::Han) >= 40 && share(Script::Kana) < 5),
+        Script::Latin => {
+            if share(Script::Latin) < 60 {
+                return Some(false);
+            }
+            let mut hits = [0usize; LANGUAGES.len()];
+            for word in prose
+                .split(|c: char| !c.is_alphabetic())
+                .filter(|word| !word.is_empty())
+            {
+                let word = word.to_lowercase();
+                for (candidate, (_, _, _, stop_words)) in LANGUAGES.iter().enumerate() {
+                    if stop_words.contains(&word.as_str()) {
+                        hits[candidate] += 1;
+                    }
+                }
+            }
+            if hits.iter().sum::<usize>() < MIN_STOP_WORDS {
+                return None;
+            }
+            Some(hits.iter().all(|&other| other <= hits[index]))
+        }
+        script => Some(share(script) >= 40),
+    }
+}
+
+/// `text` without fenced code blocks and inline code spans
+fn strip_code(text: &str) -> String {
+    let mut prose = String::with_capacity(text.len());
+    let mut in_fence = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        for (index, part) in line.split('`').enumerate() {
+            if index % 2 == 0 {
+                prose.push_str(part);
+                prose.push(' ');
+            }
+        }
+        prose.push('\n');
+    }
+    prose
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_requested_language() {
+        let german = "Ich habe die Funktion angepasst, damit sie nicht mehr abstürzt. \
+                      Der Test `cargo test` ist jetzt grün und die Änderung ist auf dem Branch.";
+        let english = "I changed the function so that it no longer crashes. The test is \
+                       green now and the change is on the branch, which you can review.";
+        assert_eq!(is_written_in(german, "de"), Some(true));
+        assert_eq!(is_written_in(english, "German"), Some(false));
+        assert_eq!(is_written_in(english, "en-US"), Some(true));
+
+        let japanese =
+            "関数を修正しました。テストはすべて成功しています。変更はブランチにあります。\
+                        確認してください。問題があれば教えてください。";
+        assert_eq!(is_written_in(japanese, "ja"), Some(true));
+        assert_eq!(is_written_in(english, "ja"), Some(false));
+        assert_eq!(
+            is_written_in(
+                "Готово. Я исправил функцию, и тесты теперь проходят успешно.",
+                "ru"
+            ),
+            Some(true)
+        );
+
+        // Code does not count as English prose
+        let code_only = "Готово.\n```rust\nfn main() { println!(\"the value is not set\"); }\n```";
+        assert_eq!(is_written_in(code_only, "ru"), None);
+        assert_eq!(is_written_in(german, "Klingon"), None);
+        assert_eq!(name("pt_BR"), "Portuguese");
+        assert!(instruction("es").contains("in Spanish"));
+    }
+}
@@ -1,2 +1,72 @@
+pub mod agents_sections;
+pub mod at_rest;
+pub mod child_wait;
+pub mod classify;
+pub mod cleanup;
+pub mod cli_version;
+pub mod client_sampling;
+pub mod cloud;
 pub mod codex;
+pub mod codex_home;
+pub mod consensus;
+pub mod context_budget;
+pub mod disk_guard;
+pub mod explore;
+pub mod failure_fingerprint;
+pub mod failure_screenshot;
+pub mod fatal_error;
+pub mod files_read;
+pub mod final_schema;
+pub mod fs_watch;
+pub mod git_info;
+pub mod glob;
+pub mod health;
+pub mod history;
+#[cfg(feature = "http-service")]
+pub mod http_service;
+pub mod idempotency;
+pub mod identities;
+pub mod image_dir;
+pub mod image_preprocess;
+pub mod language;
+pub mod lossy_utf8;
+pub mod model_fallback;
+pub mod next_steps;
+pub mod output_budget;
+pub mod parser;
+pub mod patch_preview;
+pub mod path_denylist;
+pub mod path_rewrite;
+pub mod pdf_pages;
+pub mod pipeline;
+pub mod plan;
+pub mod preflight;
+pub mod progress;
+pub mod prompt_budget;
+pub mod prompt_paths;
+pub mod prompt_secrets;
+pub mod provenance;
+pub mod raw_command;
+pub mod redact;
+pub mod repo_map;
+pub mod review_export;
+pub mod run_diff;
+pub mod run_handle;
+pub mod run_log;
+pub mod run_resources;
+pub mod run_summary;
+pub mod run_tail;
+pub mod rusage;
+pub mod sanitize;
+pub mod schedules;
 pub mod server;
+pub mod session_expiry;
+pub mod session_import;
+pub mod tenants;
+pub mod termination;
+pub mod tool_aliases;
+pub mod tool_toggle;
+pub mod trust;
+pub mod warm_up;
+pub mod workspace_lock;
+pub mod write_scope;
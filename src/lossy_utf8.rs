@@ -0,0 +1,103 @@
+use rmcp::schemars;
+use serde::Serialize;
+
+/// Runs with at least this many replacement characters get a warning unless
+/// `lossy_utf8_warning_threshold` is configured
+pub const DEFAULT_WARNING_THRESHOLD: usize = 8;
+
+/// Line numbers listed in the report; further lines are only counted
+const MAX_REPORTED_LINES: usize = 20;
+
+/// Invalid UTF-8 in Codex's stdout, which is replaced with U+FFFD before
+/// parsing. Usually a command dumping binary output.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct LossyUtf8 {
+    /// Replacement characters introduced, summed over all lines
+    pub replacement_chars: usize,
+    /// Number of stdout lines with invalid UTF-8
+    pub affected_lines: usize,
+    /// 1-based stdout line numbers with invalid UTF-8 (the first 20)
+    pub lines: Vec<usize>,
+}
+
+impl LossyUtf8 {
+    /// Count the replacement characters `String::from_utf8_lossy` introduces
+    /// for `line`
+    pub fn record(&mut self, line_number: usize, line: &[u8]) {
+        let mut introduced = 0;
+        let mut rest = line;
+        while let Err(e) = std::str::from_utf8(rest) {
+            introduced += 1;
+            match e.error_len() {
+                Some(len) => rest = &rest[e.valid_up_to() + len..],
+                // Truncated sequence at the end of the line
+                None => break,
+            }
+        }
+        if introduced == 0 {
+            return;
+        }
+        self.replacement_chars += introduced;
+        self.affected_lines += 1;
+        if self.lines.len() < MAX_REPORTED_LINES {
+            self.lines.push(line_number);
+        }
+    }
+
+    /// Warning text once `threshold` replacement characters were introduced
+    pub fn warning(&self, threshold: usize) -> Option<String> {
+        if self.replacement_chars == 0 || self.replacement_chars < threshold {
+            return None;
+        }
+        let lines: Vec<String> = self.lines.iter().map(usize::to_string).collect();
+        Some(format!(
+            "Codex output contained invalid UTF-8 ({} replacement characters on {} lines: {}{}); a command may have printed binary data",
+            self.replacement_chars,
+            self.affected_lines,
+            lines.join(", "),
+            if self.affected_lines > self.lines.len() {
+                ", ..."
+            } else {
+                ""
+            }
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_counts_replacements_per_line() {
+        let mut report = LossyUtf8::default();
+        report.record(1, "valid \u{FFFD} text".as_bytes());
+        report.record(2, b"bin\xff\xfe\x00ary");
+        report.record(3, b"ok");
+        report.record(4, b"\xc3");
+
+        // 0xff and 0xfe are replaced separately; a literal U+FFFD is not counted
+        assert_eq!(report.replacement_chars, 3);
+        assert_eq!(report.affected_lines, 2);
+        assert_eq!(report.lines, vec![2, 4]);
+        assert_eq!(
+            String::from_utf8_lossy(b"bin\xff\xfe\x00ary")
+                .matches('\u{FFFD}')
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_warning_threshold() {
+        let mut report = LossyUtf8::default();
+        assert!(report.warning(0).is_none());
+        for line in 1..=25 {
+            report.record(line, b"\xff");
+        }
+        assert!(report.warning(26).is_none());
+        let warning = report.warning(25).unwrap();
+        assert!(warning.contains("25 replacement characters on 25 lines"));
+        assert!(warning.contains("19, 20, ..."));
+    }
+}
@@ -73,6 +73,21 @@ async fn main() -> Result<()> {
     // Parse command-line arguments (this will handle -h/--help and --version)
     let _cli = Cli::parse();
 
+    // Run configured schedules in the background for the lifetime of the server
+    let _scheduler = codex_mcp_rs::schedules::start();
+    let _warm_up = codex_mcp_rs::warm_up::start();
+    let _cli_version = codex_mcp_rs::cli_version::start(codex_mcp_rs::codex::cli_version_config());
+    let _sweeper = codex_mcp_rs::run_log::start_sweeper(
+        codex_mcp_rs::codex::runs_dir(),
+        codex_mcp_rs::codex::runs_retention().clone(),
+    );
+    let _cleanup = codex_mcp_rs::cleanup::start(codex_mcp_rs::codex::cleanup_interval());
+    let _tool_toggle = codex_mcp_rs::tool_toggle::start(codex_mcp_rs::codex::config_path());
+
+    if let Err(e) = codex_mcp_rs::codex::at_rest_key() {
+        eprintln!("Warning: {}; run logs and diffs will not be stored", e);
+    }
+
     // Create an instance of our codex server
     let service = CodexServer::new().serve(stdio()).await.inspect_err(|e| {
         eprintln!("serving error: {:?}", e);
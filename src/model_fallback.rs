@@ -0,0 +1,139 @@
+use crate::provenance;
+use rmcp::schemars;
+use serde::Serialize;
+
+/// Error fragments (lowercase) the CLI and model providers use when a model
+/// does not exist or the account cannot use it
+const REJECTION_MARKERS: &[&str] = &[
+    "unknown model",
+    "model not available",
+    "model is not available",
+    "model_not_found",
+    "model not found",
+    "unsupported model",
+    "model is not supported",
+    "invalid model",
+    "does not exist or you do not have access",
+];
+
+/// A run retried with another model because the requested one was rejected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct ModelFallback {
+    pub requested: String,
+    /// Model of the retry; absent when Codex used its own configured default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used: Option<String>,
+    /// Why the requested model was rejected
+    pub reason: String,
+}
+
+impl ModelFallback {
+    pub fn warning(&self) -> String {
+        format!(
+            "Model {} was unavailable ({}); the run was retried with {}",
+            self.requested,
+            self.reason,
+            self.used
+                .as_deref()
+                .unwrap_or("the default model from the Codex config")
+        )
+    }
+}
+
+/// The line of `error` saying the requested model was rejected, if any
+pub fn rejection(error: &str) -> Option<&str> {
+    error.lines().map(str::trim).find(|line| {
+        let line = line.to_lowercase();
+        REJECTION_MARKERS.iter().any(|marker| line.contains(marker))
+    })
+}
+
+/// `args` without any model selection, plus `-m <fallback>` when a fallback
+/// model is configured. `None` when `args` select no model, or already
+/// select `fallback`.
+pub fn replace_model(args: &[String], fallback: Option<&str>) -> Option<Vec<String>> {
+    let requested = provenance::model_from_args(args)?;
+    if fallback == Some(requested.as_str()) {
+        return None;
+    }
+    let mut replaced = Vec::with_capacity(args.len());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg.starts_with("--model=") {
+            continue;
+        }
+        if arg == "-m" || arg == "--model" {
+            iter.next();
+            continue;
+        }
+        if arg == "-c" || arg == "--config" {
+            match iter.next() {
+                Some(value) if value.starts_with("model=") => {}
+                Some(value) => {
+                    replaced.push(arg.clone());
+                    replaced.push(value.clone());
+                }
+                None => replaced.push(arg.clone()),
+            }
+            continue;
+        }
+        replaced.push(arg.clone());
+    }
+    if let Some(fallback) = fallback {
+        replaced.push("-m".to_string());
+        replaced.push(fallback.to_string());
+    }
+    Some(replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_replaces_rejected_model() {
+        assert_eq!(
+            rejection("codex command failed with exit code: Some(1)\nStderr: Unknown model: gpt-9"),
+            Some("Stderr: Unknown model: gpt-9")
+        );
+        assert!(
+            rejection("The model `o5` does not exist or you do not have access to it.").is_some()
+        );
+        assert_eq!(rejection("Codex execution timed out"), None);
+
+        let requested = args(&[
+            "--sandbox",
+            "read-only",
+            "-m",
+            "gpt-9",
+            "-c",
+            "model_reasoning_effort=high",
+            "-c",
+            "model=\"gpt-9\"",
+        ]);
+        assert_eq!(
+            replace_model(&requested, Some("gpt-5")).unwrap(),
+            args(&[
+                "--sandbox",
+                "read-only",
+                "-c",
+                "model_reasoning_effort=high",
+                "-m",
+                "gpt-5"
+            ])
+        );
+        assert_eq!(
+            replace_model(&args(&["--model=gpt-9"]), None).unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(replace_model(&args(&["-m", "gpt-5"]), Some("gpt-5")), None);
+        assert_eq!(
+            replace_model(&args(&["--sandbox", "read-only"]), None),
+            None
+        );
+    }
+}
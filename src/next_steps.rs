@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of next steps returned per run
+const MAX_NEXT_STEPS: usize = 20;
+
+/// How `next_steps` are derived from agent messages, configured via
+/// `next_steps_extraction` in `codex-mcp.config.json`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NextStepsExtraction {
+    /// Parse the last "Next steps" list in the agent's answer
+    #[default]
+    Heuristic,
+    /// Do not extract next steps
+    Off,
+}
+
+/// Extract the items of the last "Next steps" section in `text`.
+///
+/// Recognizes headings such as `Next steps:`, `## Next Steps`, or
+/// `**Suggested next steps**` followed by a bulleted or numbered list.
+pub fn extract(text: &str) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let Some(heading) = lines.iter().rposition(|line| is_next_steps_heading(line)) else {
+        return Vec::new();
+    };
+
+    let mut steps: Vec<String> = Vec::new();
+    for line in &lines[heading + 1..] {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            if steps.is_empty() {
+                continue;
+            }
+            break;
+        }
+        match strip_list_marker(trimmed) {
+            Some(item) => {
+                let item = clean_markdown(item);
+                if !item.is_empty() {
+                    steps.push(item);
+                }
+            }
+            // Indented continuation of the previous item
+            None if !steps.is_empty() && line.starts_with([' ', '\t']) => {
+                if let Some(last) = steps.last_mut() {
+                    last.push(' ');
+                    last.push_str(&clean_markdown(trimmed));
+                }
+            }
+            None => break,
+        }
+        if steps.len() >= MAX_NEXT_STEPS {
+            break;
+        }
+    }
+
+    steps
+}
+
+fn is_next_steps_heading(line: &str) -> bool {
+    let normalized = clean_markdown(line.trim().trim_start_matches('#'))
+        .trim_end_matches(':')
+        .trim()
+        .to_ascii_lowercase();
+    if normalized.len() > 40 {
+        return false;
+    }
+    normalized.ends_with("next steps") || normalized.ends_with("next step")
+}
+
+/// Return the item text if `line` starts with `-`, `*`, `•`, `1.` or `1)`
+fn strip_list_marker(line: &str) -> Option<&str> {
+    for bullet in ["- ", "* ", "• "] {
+        if let Some(rest) = line.strip_prefix(bullet) {
+            return Some(rest.trim());
+        }
+    }
+
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    let rest = &line[digits..];
+    rest.strip_prefix(". ")
+        .or_else(|| rest.strip_prefix(") "))
+        .map(str::trim)
+}
+
+fn clean_markdown(text: &str) -> String {
+    text.replace("**", "").replace("__", "").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_bulleted_next_steps() {
+        let text = "Implemented the parser.\n\n## Next Steps\n- Add **fuzz** tests\n- Wire into CI\n\nThanks!";
+        assert_eq!(extract(text), vec!["Add fuzz tests", "Wire into CI"]);
+    }
+
+    #[test]
+    fn test_extract_numbered_next_steps_with_continuation() {
+        let text = "Done.\n\n**Suggested next steps:**\n\n1. Run the migration\n   on staging first\n2) Update the docs";
+        assert_eq!(
+            extract(text),
+            vec!["Run the migration on staging first", "Update the docs"]
+        );
+    }
+
+    #[test]
+    fn test_extract_uses_last_section() {
+        let text = "Next steps:\n- old\n\nMore work done.\n\nNext steps:\n- new";
+        assert_eq!(extract(text), vec!["new"]);
+    }
+
+    #[test]
+    fn test_extract_without_section_returns_empty() {
+        assert!(extract("All done, nothing else to do.").is_empty());
+        assert!(extract("The next steps in the algorithm are described below").is_empty());
+    }
+}
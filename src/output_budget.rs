@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// Default bytes of Codex output kept per run (stdout events and stderr)
+pub const DEFAULT_TOTAL_BYTES: usize = 51 * 1024 * 1024;
+
+/// Default share of the budget, in percent, stderr may use
+pub const DEFAULT_STDERR_PERCENT: u8 = 2;
+
+/// `output_budget` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OutputBudgetConfig {
+    /// Bytes of Codex output kept per run, stdout events and stderr together
+    pub total_bytes: usize,
+    /// Share of `total_bytes`, in percent, held for stderr; stdout gets the
+    /// rest, so neither stream can crowd out the other
+    pub stderr_percent: u8,
+}
+
+impl Default for OutputBudgetConfig {
+    fn default() -> Self {
+        Self {
+            total_bytes: DEFAULT_TOTAL_BYTES,
+            stderr_percent: DEFAULT_STDERR_PERCENT,
+        }
+    }
+}
+
+/// Bytes each stream has used of a run's budget
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub stdout: usize,
+    pub stderr: usize,
+}
+
+/// One run's output budget: a fixed share of the total for stderr and the
+/// rest for stdout events. Neither share can be borrowed by the other; the
+/// mutex only tracks what each used, for reporting.
+#[derive(Debug)]
+pub struct OutputBudget {
+    total: usize,
+    stderr_cap: usize,
+    used: Mutex<Usage>,
+}
+
+impl OutputBudget {
+    pub fn new(config: &OutputBudgetConfig) -> Self {
+        let percent = u128::from(config.stderr_percent.min(100));
+        Self {
+            total: config.total_bytes,
+            stderr_cap: (config.total_bytes as u128 * percent / 100) as usize,
+            used: Mutex::new(Usage::default()),
+        }
+    }
+
+    /// Account `bytes` of stdout; `false` once they would pass the total less
+    /// stderr's share
+    pub fn take_stdout(&self, bytes: usize) -> bool {
+        let mut used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        if used.stdout + bytes > self.total - self.stderr_cap {
+            return false;
+        }
+        used.stdout += bytes;
+        true
+    }
+
+    /// Account `bytes` of stderr; `false` once they would pass its share
+    pub fn take_stderr(&self, bytes: usize) -> bool {
+        let mut used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        if used.stderr + bytes > self.stderr_cap {
+            return false;
+        }
+        used.stderr += bytes;
+        true
+    }
+
+    pub fn usage(&self) -> Usage {
+        *self.used.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Warning for a run whose stdout events were cut at the budget, naming
+    /// what each stream used
+    pub fn stdout_truncated_warning(&self) -> String {
+        let usage = self.usage();
+        format!(
+            "all_messages stopped at the {} byte output budget (stdout {} bytes, stderr {} of {} bytes)",
+            self.total, usage.stdout, usage.stderr, self.stderr_cap
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_share_one_total_without_crowding_each_other() {
+        let budget = OutputBudget::new(&OutputBudgetConfig {
+            total_bytes: 1000,
+            stderr_percent: 10,
+        });
+        assert!(budget.take_stderr(60));
+        // Chatty stderr stops at its share instead of eating into stdout
+        assert!(!budget.take_stderr(60));
+        assert!(budget.take_stderr(40));
+        assert!(budget.take_stdout(900));
+        assert!(!budget.take_stdout(1));
+        assert_eq!(
+            budget.usage(),
+            Usage {
+                stdout: 900,
+                stderr: 100
+            }
+        );
+
+        // A full stdout still leaves stderr its share for diagnostics
+        let budget = OutputBudget::new(&OutputBudgetConfig {
+            total_bytes: 1000,
+            stderr_percent: 10,
+        });
+        assert!(!budget.take_stdout(901));
+        assert!(budget.take_stdout(890));
+        assert!(budget.take_stderr(10));
+        assert!(budget
+            .stdout_truncated_warning()
+            .contains("1000 byte output budget (stdout 890 bytes, stderr 10 of 100 bytes)"));
+    }
+}
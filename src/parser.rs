@@ -0,0 +1,388 @@
+use crate::run_log::RunLog;
+use serde_json::Value;
+use tokio::io::AsyncBufReadExt;
+
+/// Result of reading a line with length limit
+#[derive(Debug)]
+pub struct ReadLineResult {
+    pub bytes_read: usize,
+    pub truncated: bool,
+}
+
+/// Read a line from an async buffered reader with a maximum length limit to prevent memory spikes
+/// Returns the number of bytes read (0 on EOF) and whether the line was truncated
+/// Reads in chunks and enforces max_len during reading to prevent OOM from extremely long lines
+///
+/// Every consumed byte, including any past max_len, is also written to `log` if given.
+///
+/// After hitting max_len, continues reading until newline to properly consume the full line.
+/// This ensures the next read starts at the correct position. For subprocess stdout (our use case),
+/// this is appropriate because:
+/// 1. The Codex CLI always outputs newline-terminated JSON
+/// 2. Process-level timeout prevents indefinite blocking
+/// 3. We stop allocating memory once max_len is hit, preventing OOM
+///
+/// A carriage return not followed by a newline (a progress bar redrawing its
+/// line) discards what came before it, so only the final state of the line is
+/// returned and the redraws do not count towards max_len. JSON events never
+/// contain a raw carriage return.
+pub async fn read_line_with_limit<R: AsyncBufReadExt + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_len: usize,
+    mut log: Option<&mut RunLog>,
+) -> std::io::Result<ReadLineResult> {
+    let mut total_read = 0;
+    let mut truncated = false;
+    // A carriage return is held back until the next byte shows whether it
+    // ends the line or starts a redraw
+    let mut pending_cr = false;
+
+    loop {
+        // Fill the internal buffer if needed
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            break; // EOF
+        }
+
+        // Process available bytes
+        for (i, &byte) in available.iter().enumerate() {
+            total_read += 1;
+            if std::mem::take(&mut pending_cr) {
+                if byte == b'\n' {
+                    push_limited(buf, b'\r', max_len, &mut truncated);
+                } else {
+                    buf.clear();
+                    truncated = false;
+                }
+            }
+            if byte == b'\r' {
+                pending_cr = true;
+                continue;
+            }
+            push_limited(buf, byte, max_len, &mut truncated);
+
+            if byte == b'\n' {
+                if let Some(log) = log.as_deref_mut() {
+                    log.write(&available[..=i]).await;
+                }
+                reader.consume(i + 1);
+                return Ok(ReadLineResult {
+                    bytes_read: total_read,
+                    truncated,
+                });
+            }
+        }
+
+        if let Some(log) = log.as_deref_mut() {
+            log.write(available).await;
+        }
+        let consumed = available.len();
+        reader.consume(consumed);
+    }
+
+    Ok(ReadLineResult {
+        bytes_read: total_read,
+        truncated,
+    })
+}
+
+fn push_limited(buf: &mut Vec<u8>, byte: u8, max_len: usize, truncated: &mut bool) {
+    if *truncated {
+        return;
+    }
+    if buf.len() < max_len {
+        buf.push(byte);
+    } else {
+        *truncated = true;
+    }
+}
+
+/// One line of Codex's stdout, decoded
+#[derive(Debug)]
+pub enum Line {
+    /// Nothing but a line ending
+    Blank,
+    Event(Value),
+    /// Not JSON; `text` is the line as decoded
+    Invalid {
+        text: String,
+        error: serde_json::Error,
+    },
+}
+
+/// Decode one line read by `read_line_with_limit`: invalid UTF-8 is
+/// replaced, the line ending trimmed, and the rest parsed as JSON
+pub fn parse_line(raw: &[u8]) -> Line {
+    let text = String::from_utf8_lossy(raw);
+    let text = text.trim_end_matches('\n').trim_end_matches('\r');
+    if text.is_empty() {
+        return Line::Blank;
+    }
+    match serde_json::from_str(text) {
+        Ok(event) => Line::Event(event),
+        Err(error) => Line::Invalid {
+            text: text.to_string(),
+            error,
+        },
+    }
+}
+
+/// An `agent_message` item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AgentMessage<'a> {
+    pub id: Option<&'a str>,
+    pub text: &'a str,
+}
+
+/// What a run result takes from one event
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventFields<'a> {
+    /// Non-empty `thread_id`, the session id
+    pub thread_id: Option<&'a str>,
+    pub agent_message: Option<AgentMessage<'a>>,
+    /// `usage` of a `turn.completed` event
+    pub usage: Option<&'a Value>,
+    /// The event type names a failure or error
+    pub failed: bool,
+    /// Message of a failure event: `error.message` when `error` is an
+    /// object, else the top-level `message`
+    pub error_message: Option<&'a str>,
+}
+
+/// Pick the fields of `event` a run result is built from. Total: any JSON
+/// value yields fields, missing or mistyped ones are left out.
+pub fn extract(event: &Value) -> EventFields<'_> {
+    let event_type = event.get("type").and_then(Value::as_str);
+    let item = event.get("item").and_then(Value::as_object);
+    let agent_message = item
+        .filter(|item| item.get("type").and_then(Value::as_str) == Some("agent_message"))
+        .and_then(|item| {
+            Some(AgentMessage {
+                id: item.get("id").and_then(Value::as_str),
+                text: item.get("text").and_then(Value::as_str)?,
+            })
+        });
+    let failed = event_type.is_some_and(|t| t.contains("fail") || t.contains("error"));
+    let error_message = if !failed {
+        None
+    } else if let Some(error) = event.get("error").and_then(Value::as_object) {
+        error.get("message").and_then(Value::as_str)
+    } else {
+        event.get("message").and_then(Value::as_str)
+    };
+
+    EventFields {
+        thread_id: event
+            .get("thread_id")
+            .and_then(Value::as_str)
+            .filter(|id| !id.is_empty()),
+        agent_message,
+        usage: (event_type == Some("turn.completed"))
+            .then(|| event.get("usage"))
+            .flatten(),
+        failed,
+        error_message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::BufReader;
+
+    #[tokio::test]
+    async fn test_read_line_keeps_final_state_of_carriage_return_redraws() {
+        let input: &[u8] = b" 10%\r 50%\r100% done\r\n{\"type\":\"x\"}\nlast\r";
+        // A tiny buffer splits the carriage returns from the bytes after them
+        let mut reader = BufReader::with_capacity(3, input);
+        let mut buf = Vec::new();
+        let mut lines = Vec::new();
+        loop {
+            buf.clear();
+            let read = read_line_with_limit(&mut reader, &mut buf, 8, None)
+                .await
+                .unwrap();
+            if read.bytes_read == 0 {
+                break;
+            }
+            lines.push((String::from_utf8(buf.clone()).unwrap(), read.truncated));
+        }
+        assert_eq!(
+            lines,
+            vec![
+                ("100% don".to_string(), true),
+                ("{\"type\":".to_string(), true),
+                ("last".to_string(), false),
+            ]
+        );
+
+        // Redraws do not count towards the limit
+        let mut reader = BufReader::new(&b"aaaaaaa\rbbbbbbb\rccc\n"[..]);
+        buf.clear();
+        let read = read_line_with_limit(&mut reader, &mut buf, 8, None)
+            .await
+            .unwrap();
+        assert_eq!(buf, b"ccc\n");
+        assert!(!read.truncated);
+    }
+
+    #[test]
+    fn test_extract_fields() {
+        let message = json!({
+            "type": "item.completed",
+            "thread_id": "t-1",
+            "item": {"id": "item_0", "type": "agent_message", "text": "done"}
+        });
+        assert_eq!(
+            extract(&message),
+            EventFields {
+                thread_id: Some("t-1"),
+                agent_message: Some(AgentMessage {
+                    id: Some("item_0"),
+                    text: "done",
+                }),
+                ..Default::default()
+            }
+        );
+
+        let usage = json!({"type": "turn.completed", "usage": {"input_tokens": 3}});
+        assert_eq!(extract(&usage).usage, Some(&json!({"input_tokens": 3})));
+        assert_eq!(extract(&json!({"usage": {}})).usage, None);
+
+        let nested =
+            json!({"type": "turn.failed", "error": {"message": "quota"}, "message": "outer"});
+        assert!(extract(&nested).failed);
+        assert_eq!(extract(&nested).error_message, Some("quota"));
+        let flat = json!({"type": "error", "message": "boom"});
+        assert_eq!(extract(&flat).error_message, Some("boom"));
+        let no_message = json!({"type": "error", "error": {"code": 1}, "message": "ignored"});
+        assert_eq!(extract(&no_message).error_message, None);
+        assert_eq!(extract(&json!({"thread_id": ""})).thread_id, None);
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert!(matches!(parse_line(b"\r\n"), Line::Blank));
+        assert!(matches!(parse_line(b"{\"type\":\"x\"}\r\n"), Line::Event(e) if e["type"] == "x"));
+        match parse_line(b"warning: \xff noise\n") {
+            Line::Invalid { text, .. } => assert_eq!(text, "warning: \u{FFFD} noise"),
+            other => panic!("expected Invalid, got {:?}", other),
+        }
+    }
+
+    /// Random JSON value of bounded depth
+    fn random_value(rng: &mut fastrand::Rng, depth: u32) -> Value {
+        let kind = if depth == 0 {
+            rng.u8(0..4)
+        } else {
+            rng.u8(0..6)
+        };
+        match kind {
+            0 => Value::Null,
+            1 => Value::Bool(rng.bool()),
+            2 => json!(rng.i64(..)),
+            3 => Value::String(random_text(rng)),
+            4 => Value::Array(
+                (0..rng.usize(0..4))
+                    .map(|_| random_value(rng, depth - 1))
+                    .collect(),
+            ),
+            _ => {
+                let keys = [
+                    "type",
+                    "item",
+                    "text",
+                    "id",
+                    "thread_id",
+                    "usage",
+                    "error",
+                    "message",
+                ];
+                Value::Object(
+                    (0..rng.usize(0..5))
+                        .map(|_| {
+                            (
+                                rng.choice(keys).unwrap().to_string(),
+                                random_value(rng, depth - 1),
+                            )
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    fn random_text(rng: &mut fastrand::Rng) -> String {
+        let alphabet = [
+            'a', 'Z', ' ', '"', '\\', '\n', '\r', '\u{7f}', 'é', '\u{FFFD}', '🦀', 'f', 'e',
+        ];
+        (0..rng.usize(0..12))
+            .map(|_| *rng.choice(&alphabet).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_property_events_round_trip_and_extract_is_total() {
+        let mut rng = fastrand::Rng::with_seed(0x5eed);
+        for _ in 0..2000 {
+            let event = random_value(&mut rng, 3);
+            let line = format!("{}\n", event);
+            match parse_line(line.as_bytes()) {
+                Line::Event(parsed) => assert_eq!(parsed, event),
+                other => panic!("{} did not round-trip: {:?}", line, other),
+            }
+            let fields = extract(&event);
+            if let Some(thread_id) = fields.thread_id {
+                assert!(!thread_id.is_empty());
+            }
+            assert!(fields.error_message.is_none() || fields.failed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_property_read_line_splits_and_bounds_lines() {
+        let mut rng = fastrand::Rng::with_seed(0x11fe);
+        for _ in 0..300 {
+            // Lines without carriage returns come back exactly, cut to max_len
+            let max_len = rng.usize(1..40);
+            let lines: Vec<Vec<u8>> = (0..rng.usize(0..8))
+                .map(|_| {
+                    (0..rng.usize(0..60))
+                        .map(|_| loop {
+                            let byte = rng.u8(..);
+                            if byte != b'\n' && byte != b'\r' {
+                                break byte;
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+            let input: Vec<u8> = lines
+                .iter()
+                .flat_map(|line| line.iter().copied().chain([b'\n']))
+                .collect();
+            let mut reader = BufReader::with_capacity(rng.usize(1..16), &input[..]);
+            let mut buf = Vec::new();
+            for line in &lines {
+                buf.clear();
+                let read = read_line_with_limit(&mut reader, &mut buf, max_len, None)
+                    .await
+                    .unwrap();
+                assert!(read.bytes_read > 0);
+                assert!(buf.len() <= max_len);
+                let mut expected = line.clone();
+                expected.push(b'\n');
+                assert_eq!(read.truncated, expected.len() > max_len);
+                expected.truncate(max_len);
+                assert_eq!(buf, expected);
+            }
+            buf.clear();
+            let read = read_line_with_limit(&mut reader, &mut buf, max_len, None)
+                .await
+                .unwrap();
+            assert_eq!(read.bytes_read, 0);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use rmcp::schemars;
+use serde::Serialize;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Error lines kept per file
+const MAX_ERROR_LINES: usize = 10;
+
+/// Whether one file's changes apply to the working tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyStatus {
+    Applies,
+    /// The patch applies in reverse: the tree already has these changes
+    AlreadyApplied,
+    Conflicts,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct FilePreview {
+    pub path: String,
+    pub status: ApplyStatus,
+    /// `git apply` errors, e.g. the hunks that do not match
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct PatchPreview {
+    /// True when every file applies
+    pub applies_cleanly: bool,
+    pub files: Vec<FilePreview>,
+}
+
+/// One file's part of a unified diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FilePatch {
+    path: String,
+    text: String,
+}
+
+/// Check with `git apply --check` whether `diff` applies to `working_dir`,
+/// file by file. Paths are relative to `working_dir`, as in `RETURN_DIFF`
+/// diffs. Nothing is modified.
+pub async fn preview(working_dir: &Path, diff: &str) -> Result<PatchPreview, String> {
+    let files = split(diff);
+    if files.is_empty() {
+        return Err("no file changes found in the diff".to_string());
+    }
+
+    let mut previews = Vec::with_capacity(files.len());
+    for file in files {
+        let (applies, errors) = check(working_dir, &file.text, false).await?;
+        let status = if applies {
+            ApplyStatus::Applies
+        } else if check(working_dir, &file.text, true).await?.0 {
+            ApplyStatus::AlreadyApplied
+        } else {
+            ApplyStatus::Conflicts
+        };
+        previews.push(FilePreview {
+            path: file.path,
+            status,
+            errors: if status == ApplyStatus::Conflicts {
+                errors
+            } else {
+                Vec::new()
+            },
+        });
+    }
+    Ok(PatchPreview {
+        applies_cleanly: previews.iter().all(|f| f.status == ApplyStatus::Applies),
+        files: previews,
+    })
+}
+
+/// Split a diff into per-file patches, dropping any text before the first
+/// file (such as provenance trailers)
+fn split(diff: &str) -> Vec<FilePatch> {
+    let lines: Vec<&str> = diff.split_inclusive('\n').collect();
+    let git_format = lines.iter().any(|line| line.starts_with("diff --git "));
+    let starts_file = |i: usize| {
+        if git_format {
+            lines[i].starts_with("diff --git ")
+        } else {
+            lines[i].starts_with("--- ")
+                && lines
+                    .get(i + 1)
+                    .is_some_and(|next| next.starts_with("+++ "))
+        }
+    };
+
+    let mut files: Vec<FilePatch> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if starts_file(i) {
+            files.push(FilePatch {
+                path: String::new(),
+                text: String::new(),
+            });
+        }
+        if let Some(file) = files.last_mut() {
+            file.text.push_str(line);
+        }
+    }
+    for file in &mut files {
+        file.path = file_path(&file.text);
+    }
+    files
+}
+
+/// Path a file patch changes: the new name, or the old one for deletions
+fn file_path(text: &str) -> String {
+    let header = |prefix: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(prefix))
+            .map(|path| path.split('\t').next().unwrap_or(path).trim())
+            .filter(|path| *path != "/dev/null")
+    };
+    let path = header("+++ ")
+        .or_else(|| header("--- "))
+        .or_else(|| {
+            text.lines()
+                .next()
+                .and_then(|line| line.strip_prefix("diff --git "))
+                .and_then(|paths| paths.rsplit_once(" b/"))
+                .map(|(_, path)| path)
+        })
+        .unwrap_or_default();
+    path.strip_prefix("b/")
+        .or_else(|| path.strip_prefix("a/"))
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// Run `git apply --check` on `patch`; returns whether it applies and the
+/// error lines
+async fn check(dir: &Path, patch: &str, reverse: bool) -> Result<(bool, Vec<String>), String> {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(dir).args(["apply", "--check"]);
+    if reverse {
+        command.arg("--reverse");
+    }
+    let mut child = command
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // git stops reading early on malformed patches; its verdict is what counts
+        let _ = stdin.write_all(patch.as_bytes()).await;
+    }
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    let errors = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .take(MAX_ERROR_LINES)
+        .collect();
+    Ok((output.status.success(), errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_git_and_plain_diffs() {
+        let diff = "Codex-Run-Id: r1\n\n\
+            diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1 +1 @@\n-a\n+b\n\
+            diff --git a/old.txt b/old.txt\ndeleted file mode 100644\n--- a/old.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-x\n";
+        let files = split(diff);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "src/lib.rs");
+        assert!(files[0].text.starts_with("diff --git"));
+        assert_eq!(files[1].path, "old.txt");
+
+        let plain = split("--- a.txt\t2024-01-01\n+++ a.txt\t2024-01-02\n@@ -1 +1 @@\n-a\n+b\n");
+        assert_eq!(plain.len(), 1);
+        assert_eq!(plain[0].path, "a.txt");
+    }
+
+    #[tokio::test]
+    async fn test_preview_reports_per_file_status() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .output()
+                .map(|o| o.status.success())
+                .unwrap_or(false)
+        };
+        if !git(&["init", "-q"]) {
+            return; // git not available
+        }
+        std::fs::write(dir.join("a.txt"), "one\n").unwrap();
+        std::fs::write(dir.join("b.txt"), "two\n").unwrap();
+        std::fs::write(dir.join("c.txt"), "new\n").unwrap();
+
+        let diff = "\
+diff --git a/a.txt b/a.txt\n--- a/a.txt\n+++ b/a.txt\n@@ -1 +1 @@\n-one\n+uno\n\
+diff --git a/b.txt b/b.txt\n--- a/b.txt\n+++ b/b.txt\n@@ -1 +1 @@\n-zwei\n+dos\n\
+diff --git a/c.txt b/c.txt\n--- a/c.txt\n+++ b/c.txt\n@@ -1 +1 @@\n-old\n+new\n";
+        let report = preview(dir, diff).await.unwrap();
+        assert!(!report.applies_cleanly);
+        let statuses: Vec<_> = report.files.iter().map(|f| f.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ApplyStatus::Applies,
+                ApplyStatus::Conflicts,
+                ApplyStatus::AlreadyApplied
+            ]
+        );
+        assert!(!report.files[1].errors.is_empty());
+        // Nothing was changed
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "one\n");
+
+        assert!(preview(dir, "not a diff").await.is_err());
+    }
+}
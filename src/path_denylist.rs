@@ -0,0 +1,128 @@
+use crate::glob;
+use std::fmt;
+use std::path::Path;
+
+/// Patterns refused for attachments unless `sensitive_paths` is configured.
+/// Patterns without `/` match any file or directory name; patterns with `/`
+/// match the trailing components of a path.
+pub const DEFAULT_SENSITIVE_PATHS: &[&str] = &[
+    ".env",
+    ".env.*",
+    "id_rsa",
+    "id_dsa",
+    "id_ecdsa",
+    "id_ed25519",
+    "*.pem",
+    "*.key",
+    "*.p12",
+    "*.pfx",
+    ".netrc",
+    ".npmrc",
+    ".pgpass",
+    ".aws/credentials",
+    ".docker/config.json",
+    ".kube/config",
+    ".ssh/*",
+    ".gnupg/*",
+];
+
+/// Error returned when an attachment matches the sensitive-path denylist
+#[derive(Debug, Clone)]
+pub struct SensitivePath {
+    pub path: String,
+    pub pattern: String,
+}
+
+impl fmt::Display for SensitivePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SensitivePath: {} matches denylisted pattern `{}`",
+            self.path, self.pattern
+        )
+    }
+}
+
+impl std::error::Error for SensitivePath {}
+
+/// First pattern in `patterns` matching `path`, if any
+pub fn matching_pattern<'a>(path: &Path, patterns: &'a [String]) -> Option<&'a str> {
+    let components: Vec<String> = path
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    let components: Vec<&str> = components.iter().map(String::as_str).collect();
+
+    patterns
+        .iter()
+        .map(String::as_str)
+        .find(|pattern| matches(pattern, &components))
+}
+
+fn matches(pattern: &str, components: &[&str]) -> bool {
+    let pattern = pattern.trim().trim_end_matches('/');
+    if pattern.is_empty() {
+        return false;
+    }
+    if !pattern.contains('/') {
+        return components
+            .iter()
+            .any(|name| glob::match_segment(pattern.as_bytes(), name.as_bytes()));
+    }
+
+    let pattern: Vec<&str> = pattern.trim_start_matches('/').split('/').collect();
+    (0..components.len()).any(|start| glob::match_segments(&pattern, &components[start..]))
+}
+
+/// Refuse `path` if it matches any of `patterns`
+pub fn check(path: &Path, patterns: &[String]) -> Result<(), SensitivePath> {
+    match matching_pattern(path, patterns) {
+        Some(pattern) => Err(SensitivePath {
+            path: path.display().to_string(),
+            pattern: pattern.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Vec<String> {
+        DEFAULT_SENSITIVE_PATHS
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_default_denylist() {
+        let patterns = defaults();
+        let blocked = |p: &str| matching_pattern(Path::new(p), &patterns);
+
+        assert_eq!(blocked("/repo/.env"), Some(".env"));
+        assert_eq!(blocked("/repo/.env.production"), Some(".env.*"));
+        assert_eq!(blocked("/home/u/.ssh/id_rsa"), Some("id_rsa"));
+        assert_eq!(blocked("certs/server.pem"), Some("*.pem"));
+        assert_eq!(
+            blocked("/home/u/.aws/credentials"),
+            Some(".aws/credentials")
+        );
+        assert_eq!(blocked("/home/u/.ssh/known_hosts"), Some(".ssh/*"));
+
+        assert_eq!(blocked("/repo/screenshot.png"), None);
+        assert_eq!(blocked("/repo/environment.md"), None);
+        assert_eq!(blocked("/repo/credentials/readme.png"), None);
+    }
+
+    #[test]
+    fn test_check_reports_pattern() {
+        let err = check(Path::new("/repo/key.pem"), &defaults()).unwrap_err();
+        assert_eq!(err.pattern, "*.pem");
+        assert!(err.to_string().contains("/repo/key.pem"));
+    }
+}
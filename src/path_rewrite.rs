@@ -0,0 +1,215 @@
+use crate::codex::CodexResult;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+/// Rewrites absolute paths in run output: the working directory becomes
+/// workspace-relative (`/home/me/repo/src/a.rs` becomes `src/a.rs`, the
+/// directory itself `.`), and anything else under the home directory is
+/// shown under `~`, so transcripts do not carry local user names
+#[derive(Debug, Clone)]
+pub struct PathRewriter {
+    /// Prefix and its replacement, longest prefix first
+    roots: Vec<(String, &'static str)>,
+}
+
+impl PathRewriter {
+    /// Rewriter for runs in `working_dir`; its canonical form is matched too,
+    /// since Codex may report either
+    pub fn new(working_dir: &Path, home: Option<&Path>) -> Self {
+        let mut roots = Vec::new();
+        let mut add = |path: &Path, replacement: &'static str| {
+            let path = path.to_string_lossy();
+            let path = path.trim_end_matches('/');
+            // Rewriting `/` would touch every absolute path
+            if path.len() > 1 && !roots.iter().any(|(root, _)| root == path) {
+                roots.push((path.to_string(), replacement));
+            }
+        };
+        add(working_dir, ".");
+        if let Ok(canonical) = working_dir.canonicalize() {
+            add(&canonical, ".");
+        }
+        if let Some(home) = home {
+            add(home, "~");
+            if let Ok(canonical) = home.canonicalize() {
+                add(&canonical, "~");
+            }
+        }
+        roots.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        Self { roots }
+    }
+
+    /// `text` with every path under a root rewritten. A root only matches
+    /// as a whole path component, so `/repo` leaves `/repo2` alone.
+    pub fn rewrite<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for (root, replacement) in &self.roots {
+            if !text.contains(root.as_str()) {
+                continue;
+            }
+            let mut rewritten = String::with_capacity(text.len());
+            let mut rest: &str = &text;
+            while let Some(start) = rest.find(root.as_str()) {
+                let before = rest[..start].chars().next_back();
+                let after = &rest[start + root.len()..];
+                let whole = !before.is_some_and(is_path_char) && ends_component(after);
+                rewritten.push_str(&rest[..start]);
+                if !whole {
+                    rewritten.push_str(root);
+                } else if let Some(inner) = after.strip_prefix('/') {
+                    // Workspace paths become plain relative paths; home
+                    // paths, and the bare directory, keep the separator
+                    if *replacement == "~" || !inner.starts_with(|c| is_path_char(c) && c != '/') {
+                        rewritten.push_str(replacement);
+                        rewritten.push('/');
+                    }
+                    rest = inner;
+                    continue;
+                } else {
+                    rewritten.push_str(replacement);
+                }
+                rest = after;
+            }
+            rewritten.push_str(rest);
+            text = Cow::Owned(rewritten);
+        }
+        text
+    }
+
+    fn rewrite_in_place(&self, text: &mut String) {
+        if let Cow::Owned(rewritten) = self.rewrite(text) {
+            *text = rewritten;
+        }
+    }
+
+    fn rewrite_value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => self.rewrite_in_place(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.rewrite_value(item)),
+            Value::Object(fields) => fields
+                .values_mut()
+                .for_each(|field| self.rewrite_value(field)),
+            _ => {}
+        }
+    }
+
+    /// Rewrite the paths in everything a run returns as text: agent messages
+    /// and events (including file change lists), errors and warnings, next
+    /// steps, files read, and the diff
+    pub fn apply(&self, result: &mut CodexResult) {
+        if self.roots.is_empty() {
+            return;
+        }
+        self.rewrite_in_place(&mut result.agent_messages);
+        for message in &mut result.all_messages {
+            message
+                .values_mut()
+                .for_each(|value| self.rewrite_value(value));
+        }
+        for text in [
+            &mut result.error,
+            &mut result.warnings,
+            &mut result.operator_warnings,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            self.rewrite_in_place(text);
+        }
+        for step in &mut result.next_steps {
+            self.rewrite_in_place(step);
+        }
+        for path in &mut result.unresolved_paths {
+            self.rewrite_in_place(path);
+        }
+        for path in &mut result.files_read {
+            let text = path.to_string_lossy().into_owned();
+            if let Cow::Owned(rewritten) = self.rewrite(&text) {
+                *path = PathBuf::from(rewritten);
+            }
+        }
+        if let Some(diff) = result.diff.as_mut() {
+            for file in &mut diff.files {
+                self.rewrite_in_place(&mut file.path);
+            }
+            if let Some(text) = diff.diff.as_mut() {
+                self.rewrite_in_place(text);
+            }
+        }
+        if let Some(value) = result.final_value.as_mut() {
+            self.rewrite_value(value);
+        }
+    }
+}
+
+/// Characters that continue a path component
+fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | '/')
+}
+
+/// Whether a root followed by `after` ends at a component boundary: a
+/// separator, the end, or a character outside paths. A dot counts as
+/// sentence punctuation unless a path character follows it.
+fn ends_component(after: &str) -> bool {
+    let mut chars = after.chars();
+    match chars.next() {
+        None | Some('/') => true,
+        Some('.') => !chars.next().is_some_and(is_path_char),
+        Some(c) => !is_path_char(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_rewrites_workspace_and_home_paths() {
+        let rewriter = PathRewriter::new(
+            Path::new("/nonexistent/home/me/repo/"),
+            Some(Path::new("/nonexistent/home/me")),
+        );
+        assert_eq!(
+            rewriter.rewrite(
+                "Edited /nonexistent/home/me/repo/src/a.rs and /nonexistent/home/me/repo."
+            ),
+            "Edited src/a.rs and ."
+        );
+        assert_eq!(
+            rewriter.rewrite("cd /nonexistent/home/me/repo/ first"),
+            "cd ./ first"
+        );
+        assert_eq!(
+            rewriter.rewrite("see /nonexistent/home/me/.codex/config.toml"),
+            "see ~/.codex/config.toml"
+        );
+        // Only whole components match
+        assert_eq!(
+            rewriter.rewrite("/nonexistent/home/me/repo2/x and /x/nonexistent/home/me/repo/y"),
+            "~/repo2/x and /x/nonexistent/home/me/repo/y"
+        );
+        assert!(matches!(rewriter.rewrite("nothing here"), Cow::Borrowed(_)));
+
+        let mut result = CodexResult {
+            agent_messages: "Done in /nonexistent/home/me/repo".to_string(),
+            all_messages: vec![HashMap::from([(
+                "item".to_string(),
+                json!({"type": "file_change", "changes": [
+                    {"path": "/nonexistent/home/me/repo/src/lib.rs", "kind": "update"}
+                ]}),
+            )])],
+            error: Some("failed at /nonexistent/home/me/repo/build.rs:3".to_string()),
+            ..Default::default()
+        };
+        rewriter.apply(&mut result);
+        assert_eq!(result.agent_messages, "Done in .");
+        assert_eq!(
+            result.all_messages[0]["item"]["changes"][0]["path"],
+            "src/lib.rs"
+        );
+        assert_eq!(result.error.as_deref(), Some("failed at build.rs:3"));
+    }
+}
@@ -0,0 +1,173 @@
+use rmcp::schemars;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Pages rendered per run, across all attached PDFs, unless `pdf_max_pages`
+/// is configured
+pub const DEFAULT_MAX_PAGES: usize = 10;
+
+/// Rendering resolution; enough for text in specs and design docs to stay legible
+#[cfg(feature = "pdf-pages")]
+const RENDER_DPI: &str = "110";
+
+/// How much of one attached PDF was passed to Codex
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct PdfPages {
+    pub file: PathBuf,
+    /// Leading pages attached as images
+    pub pages_included: usize,
+    /// Page count of the document, when `pdfinfo` could read it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_pages: Option<usize>,
+}
+
+/// Attachments with each PDF replaced by images of its pages. The page
+/// images live in a per-run temp directory that is removed on drop.
+#[derive(Debug, Default)]
+pub struct ExpandedAttachments {
+    pub paths: Vec<PathBuf>,
+    pub pdfs: Vec<PdfPages>,
+    dir: Option<PathBuf>,
+}
+
+impl Drop for ExpandedAttachments {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.dir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// Root directory for rendered pages of all runs
+pub fn pages_dir() -> PathBuf {
+    std::env::temp_dir().join("codex-mcp-pdf-pages")
+}
+
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+/// Replace PDFs in `paths` with PNG images of their first pages, at most
+/// `max_pages` in total. Other attachments are kept in place.
+pub async fn expand(
+    paths: &[PathBuf],
+    run_id: &str,
+    max_pages: usize,
+) -> Result<ExpandedAttachments, String> {
+    let mut expanded = ExpandedAttachments::default();
+    if !paths.iter().any(|path| is_pdf(path)) {
+        expanded.paths = paths.to_vec();
+        return Ok(expanded);
+    }
+
+    let dir = pages_dir().join(run_id);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    expanded.dir = Some(dir.clone());
+
+    let mut remaining = max_pages;
+    for (index, path) in paths.iter().enumerate() {
+        if !is_pdf(path) {
+            expanded.paths.push(path.clone());
+            continue;
+        }
+        let total_pages = page_count(path).await;
+        let pages = if remaining == 0 {
+            Vec::new()
+        } else {
+            render(path, &dir.join(index.to_string()), remaining).await?
+        };
+        remaining -= pages.len().min(remaining);
+        expanded.pdfs.push(PdfPages {
+            file: path.clone(),
+            pages_included: pages.len(),
+            total_pages,
+        });
+        expanded.paths.extend(pages);
+    }
+    Ok(expanded)
+}
+
+/// Render pages `1..=max_pages` of `pdf` into `dir` with poppler's `pdftoppm`
+#[cfg(feature = "pdf-pages")]
+async fn render(pdf: &Path, dir: &Path, max_pages: usize) -> Result<Vec<PathBuf>, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    let output = tokio::process::Command::new("pdftoppm")
+        .args(["-png", "-r", RENDER_DPI, "-f", "1", "-l"])
+        .arg(max_pages.to_string())
+        .arg(pdf)
+        .arg(dir.join("page"))
+        .output()
+        .await
+        .map_err(|e| format!("failed to run pdftoppm (install poppler-utils): {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "pdftoppm could not render {}: {}",
+            pdf.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    // Page numbers are zero-padded, so name order is page order
+    let mut pages: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| format!("failed to read {}: {}", dir.display(), e))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    pages.sort();
+    pages.truncate(max_pages);
+    Ok(pages)
+}
+
+#[cfg(not(feature = "pdf-pages"))]
+async fn render(pdf: &Path, _dir: &Path, _max_pages: usize) -> Result<Vec<PathBuf>, String> {
+    Err(format!(
+        "cannot attach {}: PDF attachments require a build with the `pdf-pages` feature",
+        pdf.display()
+    ))
+}
+
+/// `Pages:` reported by poppler's `pdfinfo`
+async fn page_count(pdf: &Path) -> Option<usize> {
+    let output = tokio::process::Command::new("pdfinfo")
+        .arg(pdf)
+        .output()
+        .await
+        .ok()?;
+    parse_page_count(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_page_count(pdfinfo: &str) -> Option<usize> {
+    pdfinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("Pages:"))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_pdf_and_page_count() {
+        assert!(is_pdf(Path::new("docs/Spec.PDF")));
+        assert!(!is_pdf(Path::new("screenshot.png")));
+        assert_eq!(
+            parse_page_count("Title:  Spec\nPages:          42\nEncrypted:      no\n"),
+            Some(42)
+        );
+        assert_eq!(parse_page_count("Syntax Error"), None);
+    }
+
+    #[tokio::test]
+    async fn test_expand_passes_other_attachments_through() {
+        let paths = vec![PathBuf::from("a.png"), PathBuf::from("b.jpg")];
+        let expanded = expand(&paths, "pdf-test-run", 10).await.unwrap();
+        assert_eq!(expanded.paths, paths);
+        assert!(expanded.pdfs.is_empty());
+        assert!(expanded.dir.is_none());
+    }
+}
@@ -0,0 +1,282 @@
+use crate::codex::{self, Options, SandboxMode};
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Longest pipeline accepted in one call
+pub const MAX_PIPELINE_STEPS: usize = 10;
+
+/// Verify command output kept per step (the end is kept)
+const MAX_VERIFY_OUTPUT_BYTES: usize = 8 * 1024;
+
+/// One step of a `codex_pipeline` call
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
+pub struct PipelineStep {
+    /// Label used in the report, e.g. `plan` or `test`
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Prompt for this step, sent in the pipeline's shared session
+    #[serde(rename = "PROMPT")]
+    pub prompt: String,
+    /// `read-only`, `workspace-write`, or `danger-full-access`; defaults to the
+    /// sandbox implied by the server's `additional_args`
+    #[serde(default)]
+    pub sandbox: Option<String>,
+    /// Shell command run in the working directory after the step; a non-zero
+    /// exit fails the step. Requires `allow_verify_commands`.
+    #[serde(default)]
+    pub verify: Option<String>,
+}
+
+/// Outcome of a step's verify command
+#[derive(Debug, Serialize)]
+pub struct VerifyResult {
+    pub command: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Combined stdout and stderr, truncated to the end
+    pub output: String,
+}
+
+/// Outcome of one step
+#[derive(Debug, Serialize)]
+pub struct StepResult {
+    pub step: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub success: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub run_id: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify: Option<VerifyResult>,
+}
+
+/// Per-step results plus the overall status
+#[derive(Debug, Serialize)]
+pub struct PipelineResult {
+    pub success: bool,
+    #[serde(rename = "SESSION_ID", skip_serializing_if = "String::is_empty")]
+    pub session_id: String,
+    /// Number of the step that failed (1-based); later steps were not run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_step: Option<usize>,
+    pub steps: Vec<StepResult>,
+}
+
+/// Check `steps` before anything runs: sandboxes must parse and may not exceed
+/// `ceiling`, and verify commands need `allow_verify`
+pub fn validate(
+    steps: &[PipelineStep],
+    ceiling: SandboxMode,
+    allow_verify: bool,
+) -> Result<(), String> {
+    if steps.is_empty() {
+        return Err("steps must not be empty".to_string());
+    }
+    if steps.len() > MAX_PIPELINE_STEPS {
+        return Err(format!("at most {} steps are allowed", MAX_PIPELINE_STEPS));
+    }
+    for (index, step) in steps.iter().enumerate() {
+        let number = index + 1;
+        if step.prompt.trim().is_empty() {
+            return Err(format!("step {}: PROMPT must not be empty", number));
+        }
+        if let Some(sandbox) = &step.sandbox {
+            let mode = SandboxMode::parse(sandbox)
+                .ok_or_else(|| format!("step {}: invalid sandbox: {}", number, sandbox))?;
+            if mode > ceiling {
+                return Err(format!(
+                    "step {}: sandbox {} exceeds the server's {}",
+                    number,
+                    sandbox,
+                    ceiling.as_str()
+                ));
+            }
+        }
+        if step.verify.is_some() && !allow_verify {
+            return Err(format!(
+                "step {}: verify commands require allow_verify_commands in codex-mcp.config.json",
+                number
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Run `steps` in order in one Codex session, stopping at the first failed
+/// step or verify command
+pub async fn run(working_dir: PathBuf, steps: Vec<PipelineStep>) -> PipelineResult {
+    let base_args = codex::default_additional_args();
+    let mut pipeline = PipelineResult {
+        success: true,
+        session_id: String::new(),
+        failed_step: None,
+        steps: Vec::with_capacity(steps.len()),
+    };
+
+    for (index, step) in steps.into_iter().enumerate() {
+        let additional_args = match step.sandbox.as_deref().and_then(SandboxMode::parse) {
+            Some(mode) => codex::with_sandbox(&base_args, mode),
+            None => base_args.clone(),
+        };
+        let opts = Options {
+            prompt: step.prompt,
+            working_dir: working_dir.clone(),
+            session_id: (!pipeline.session_id.is_empty()).then(|| pipeline.session_id.clone()),
+            additional_args,
+            source: Some("pipeline".to_string()),
+            ..Default::default()
+        };
+
+        let mut outcome = match codex::run(opts).await {
+            Ok(result) => {
+                if !result.session_id.is_empty() {
+                    pipeline.session_id = result.session_id.clone();
+                }
+                StepResult {
+                    step: index + 1,
+                    name: step.name,
+                    success: result.success,
+                    run_id: result.run_id,
+                    message: result.agent_messages,
+                    error: result.error,
+                    verify: None,
+                }
+            }
+            Err(e) => StepResult {
+                step: index + 1,
+                name: step.name,
+                success: false,
+                run_id: String::new(),
+                message: String::new(),
+                error: Some(format!("Failed to execute codex: {}", e)),
+                verify: None,
+            },
+        };
+
+        if outcome.success {
+            if let Some(command) = step.verify {
+                let verify = run_verify(&working_dir, &command).await;
+                outcome.success = verify.success;
+                outcome.verify = Some(verify);
+            }
+        }
+
+        let failed = !outcome.success;
+        pipeline.steps.push(outcome);
+        if failed {
+            pipeline.success = false;
+            pipeline.failed_step = Some(index + 1);
+            break;
+        }
+    }
+
+    pipeline
+}
+
+async fn run_verify(working_dir: &Path, command: &str) -> VerifyResult {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(command)
+        .current_dir(working_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let timeout = Duration::from_secs(codex::default_timeout_secs());
+    let (success, exit_code, output) = match tokio::time::timeout(timeout, cmd.output()).await {
+        Ok(Ok(output)) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            (output.status.success(), output.status.code(), text)
+        }
+        Ok(Err(e)) => (false, None, format!("Failed to run verify command: {}", e)),
+        Err(_) => (
+            false,
+            None,
+            format!(
+                "Verify command timed out after {} seconds",
+                timeout.as_secs()
+            ),
+        ),
+    };
+
+    VerifyResult {
+        command: command.to_string(),
+        success,
+        exit_code,
+        output: keep_tail(output, MAX_VERIFY_OUTPUT_BYTES),
+    }
+}
+
+fn keep_tail(mut text: String, max_bytes: usize) -> String {
+    if text.len() > max_bytes {
+        let mut start = text.len() - max_bytes;
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        text.drain(..start);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(sandbox: Option<&str>, verify: Option<&str>) -> PipelineStep {
+        PipelineStep {
+            name: None,
+            prompt: "do it".to_string(),
+            sandbox: sandbox.map(str::to_string),
+            verify: verify.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_validate_enforces_ceiling_and_verify_gate() {
+        let ceiling = SandboxMode::WorkspaceWrite;
+        assert!(validate(&[step(Some("read-only"), None)], ceiling, false).is_ok());
+        assert!(validate(&[step(Some("workspace-write"), None)], ceiling, false).is_ok());
+        assert!(
+            validate(&[step(Some("danger-full-access"), None)], ceiling, false)
+                .unwrap_err()
+                .contains("exceeds")
+        );
+        assert!(validate(&[step(Some("bogus"), None)], ceiling, false).is_err());
+        assert!(validate(&[step(None, Some("cargo test"))], ceiling, false)
+            .unwrap_err()
+            .contains("allow_verify_commands"));
+        assert!(validate(&[step(None, Some("cargo test"))], ceiling, true).is_ok());
+        assert!(validate(&[], ceiling, true).is_err());
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_run_verify_reports_exit_status() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let ok = run_verify(temp_dir.path(), "echo fine").await;
+        assert!(ok.success);
+        assert_eq!(ok.output, "fine\n");
+
+        let failed = run_verify(temp_dir.path(), "echo broken >&2; exit 3").await;
+        assert!(!failed.success);
+        assert_eq!(failed.exit_code, Some(3));
+        assert!(failed.output.contains("broken"));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::codex;
+use crate::raw_command;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::OnceCell;
+
+/// Flag that gives `codex exec` its plan tool
+pub const PLAN_TOOL_FLAG: &str = "--include-plan-tool";
+
+/// Where a plan step is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Pending,
+    InProgress,
+    Completed,
+}
+
+/// One step of the agent's plan
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct PlanStep {
+    pub step: String,
+    pub status: StepStatus,
+}
+
+/// A step that appeared or changed status between two plan updates
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Transition {
+    pub step: String,
+    /// `None` for a step the update added
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<StepStatus>,
+    pub to: StepStatus,
+}
+
+/// A changed plan, as sent to clients while the run is in progress
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlanUpdate {
+    pub steps: Vec<PlanStep>,
+    pub transitions: Vec<Transition>,
+}
+
+/// Whether the installed CLI offers the plan tool, from `codex exec --help`;
+/// probed once per server
+pub async fn tool_available() -> bool {
+    static AVAILABLE: OnceCell<bool> = OnceCell::const_new();
+    *AVAILABLE
+        .get_or_init(|| async {
+            raw_command::run(&codex::codex_bin(), &["exec", "--help"])
+                .await
+                .is_ok_and(|output| output.stdout.contains(PLAN_TOOL_FLAG))
+        })
+        .await
+}
+
+/// The plan carried by `event`: a `todo_list` item (`text` and `completed`
+/// per entry), or a plan tool call (`step` and `status` per entry)
+pub fn from_event(event: &Value) -> Option<Vec<PlanStep>> {
+    let item = event.get("item")?;
+    let entries = match item.get("type").and_then(Value::as_str)? {
+        "todo_list" => item.get("items"),
+        "plan_update" => item.get("plan"),
+        _ => None,
+    }?
+    .as_array()?;
+
+    let mut steps: Vec<PlanStep> = entries
+        .iter()
+        .filter_map(|entry| {
+            let step = entry
+                .get("text")
+                .or_else(|| entry.get("step"))
+                .and_then(Value::as_str)?
+                .trim();
+            let status = match entry.get("status").and_then(Value::as_str) {
+                Some("completed") => StepStatus::Completed,
+                Some("in_progress") => StepStatus::InProgress,
+                Some(_) => StepStatus::Pending,
+                None if entry.get("completed").and_then(Value::as_bool) == Some(true) => {
+                    StepStatus::Completed
+                }
+                None => StepStatus::Pending,
+            };
+            (!step.is_empty()).then(|| PlanStep {
+                step: step.to_string(),
+                status,
+            })
+        })
+        .collect();
+    // A todo list only says what is done; the first open step is the one
+    // being worked on
+    if !steps
+        .iter()
+        .any(|step| step.status == StepStatus::InProgress)
+    {
+        if let Some(next) = steps
+            .iter_mut()
+            .find(|step| step.status == StepStatus::Pending)
+        {
+            next.status = StepStatus::InProgress;
+        }
+    }
+    (!steps.is_empty()).then_some(steps)
+}
+
+/// Steps of `new` that `old` did not have, or had with another status
+pub fn transitions(old: &[PlanStep], new: &[PlanStep]) -> Vec<Transition> {
+    new.iter()
+        .filter_map(|step| {
+            let from = old
+                .iter()
+                .find(|previous| previous.step == step.step)
+                .map(|previous| previous.status);
+            (from != Some(step.status)).then(|| Transition {
+                step: step.step.clone(),
+                from,
+                to: step.status,
+            })
+        })
+        .collect()
+}
+
+/// Progress message for a plan update, e.g. `Plan: 1 of 3 steps done;
+/// completed: Write tests; now: Update README`
+pub fn message(update: &PlanUpdate) -> String {
+    let done = update
+        .steps
+        .iter()
+        .filter(|step| step.status == StepStatus::Completed)
+        .count();
+    let mut message = format!("Plan: {} of {} steps done", done, update.steps.len());
+    let completed: Vec<&str> = update
+        .transitions
+        .iter()
+        .filter(|transition| transition.to == StepStatus::Completed)
+        .map(|transition| transition.step.as_str())
+        .collect();
+    if !completed.is_empty() {
+        message.push_str(&format!("; completed: {}", completed.join(", ")));
+    }
+    if let Some(current) = update
+        .steps
+        .iter()
+        .find(|step| step.status == StepStatus::InProgress)
+    {
+        message.push_str(&format!("; now: {}", current.step));
+    }
+    message
+}
+
+/// Follows the plan through a run, yielding an update whenever it changes
+#[derive(Debug, Default)]
+pub struct PlanTracker {
+    steps: Vec<PlanStep>,
+}
+
+impl PlanTracker {
+    pub fn observe(&mut self, event: &Value) -> Option<PlanUpdate> {
+        let steps = from_event(event)?;
+        let transitions = transitions(&self.steps, &steps);
+        if transitions.is_empty() && steps.len() == self.steps.len() {
+            return None;
+        }
+        self.steps = steps.clone();
+        Some(PlanUpdate { steps, transitions })
+    }
+
+    /// The last plan seen
+    pub fn finish(self) -> Vec<PlanStep> {
+        self.steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn todo_list(done: [bool; 3]) -> Value {
+        json!({"type": "item.updated", "item": {"type": "todo_list", "items": [
+            {"text": "Read the parser", "completed": done[0]},
+            {"text": "Write tests", "completed": done[1]},
+            {"text": "Update README", "completed": done[2]}
+        ]}})
+    }
+
+    #[test]
+    fn test_tracks_step_transitions() {
+        let mut tracker = PlanTracker::default();
+        let first = tracker.observe(&todo_list([false; 3])).unwrap();
+        assert_eq!(first.transitions.len(), 3);
+        assert_eq!(first.steps[0].status, StepStatus::InProgress);
+        assert_eq!(
+            message(&first),
+            "Plan: 0 of 3 steps done; now: Read the parser"
+        );
+        assert!(tracker.observe(&todo_list([false; 3])).is_none());
+
+        let second = tracker.observe(&todo_list([true, false, false])).unwrap();
+        assert_eq!(
+            second.transitions,
+            [
+                Transition {
+                    step: "Read the parser".to_string(),
+                    from: Some(StepStatus::InProgress),
+                    to: StepStatus::Completed,
+                },
+                Transition {
+                    step: "Write tests".to_string(),
+                    from: Some(StepStatus::Pending),
+                    to: StepStatus::InProgress,
+                },
+            ]
+        );
+        assert_eq!(
+            message(&second),
+            "Plan: 1 of 3 steps done; completed: Read the parser; now: Write tests"
+        );
+
+        // The plan tool's own shape, with explicit statuses
+        let update = tracker
+            .observe(
+                &json!({"type": "item.completed", "item": {"type": "plan_update", "plan": [
+                    {"step": "Read the parser", "status": "completed"},
+                    {"step": "Write tests", "status": "completed"},
+                    {"step": "Update README", "status": "in_progress"}
+                ]}}),
+            )
+            .unwrap();
+        assert_eq!(update.transitions.len(), 2);
+        assert_eq!(tracker.finish()[2].status, StepStatus::InProgress);
+        assert!(from_event(&json!({"item": {"type": "agent_message"}})).is_none());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::codex::{self, SandboxMode};
+use crate::git_info::{self, GitInfo};
+use crate::workspace_lock::{self, LockInfo, LockPolicy};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Stop walking the workspace after this many entries; the size is then a lower bound
+const MAX_WALK_ENTRIES: u64 = 200_000;
+
+/// Directories skipped when estimating repository size
+const SKIPPED_DIRS: &[&str] = &[".git", "target", "node_modules"];
+
+/// Prompts estimated above this many tokens produce a warning
+const PROMPT_TOKEN_WARNING: u64 = 100_000;
+
+/// Rough bytes-per-token ratio used for prompt estimates
+const BYTES_PER_TOKEN: u64 = 4;
+
+/// Flag Codex accepts to run outside a git repository
+const SKIP_GIT_REPO_CHECK_FLAG: &str = "--skip-git-repo-check";
+
+/// Result of checking a workspace before a run
+#[derive(Debug, Serialize)]
+pub struct PreflightReport {
+    pub ready: bool,
+    pub working_dir: PathBuf,
+    /// Problems that would make a run fail; empty when `ready`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+    /// Issues that do not block a run but are worth knowing about
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// `None` when the git check was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_repo: Option<bool>,
+    /// Branch, HEAD, and whether tracked files have uncommitted changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitInfo>,
+    /// `absent`, `loaded`, `truncated`, or `skipped`
+    pub agents_md: &'static str,
+    pub agents_md_bytes: u64,
+    pub repo_files: u64,
+    pub repo_bytes: u64,
+    /// True when the walk stopped early, so the size is a lower bound
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub repo_size_truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock_holder: Option<LockInfo>,
+    pub estimated_prompt_bytes: u64,
+    pub estimated_prompt_tokens: u64,
+}
+
+/// Validate `working_dir` for a run of `prompt` with the server's configured
+/// arguments. Nothing is modified and Codex is not started.
+pub async fn check(working_dir: &Path, prompt: &str, skip_git_check: bool) -> PreflightReport {
+    let mut report = PreflightReport {
+        ready: false,
+        working_dir: working_dir.to_path_buf(),
+        reasons: Vec::new(),
+        warnings: Vec::new(),
+        git_repo: None,
+        git: None,
+        agents_md: "absent",
+        agents_md_bytes: 0,
+        repo_files: 0,
+        repo_bytes: 0,
+        repo_size_truncated: false,
+        lock_holder: None,
+        estimated_prompt_bytes: 0,
+        estimated_prompt_tokens: 0,
+    };
+
+    let working_dir = match working_dir.canonicalize() {
+        Ok(dir) if dir.is_dir() => dir,
+        Ok(_) => {
+            report
+                .reasons
+                .push(format!("{} is not a directory", working_dir.display()));
+            return report;
+        }
+        Err(e) => {
+            report.reasons.push(format!(
+                "{} does not exist or is not accessible ({})",
+                working_dir.display(),
+                e
+            ));
+            return report;
+        }
+    };
+    report.working_dir = working_dir.clone();
+
+    let additional_args = codex::default_additional_args();
+    let skip_git_check = skip_git_check
+        || additional_args
+            .iter()
+            .any(|arg| arg == SKIP_GIT_REPO_CHECK_FLAG);
+    if !skip_git_check {
+        let is_repo = is_git_repo(&working_dir);
+        report.git_repo = Some(is_repo);
+        if !is_repo {
+            report.reasons.push(format!(
+                "{} is not inside a git repository; Codex refuses to run there without {}",
+                working_dir.display(),
+                SKIP_GIT_REPO_CHECK_FLAG
+            ));
+        }
+    }
+    if report.git_repo != Some(false) {
+        report.git = git_info::get(&working_dir).await.ok();
+    }
+
+    let sandbox = SandboxMode::from_args(&additional_args);
+    let (agents_content, agents_warning) = codex::read_agents_for(&working_dir, sandbox).await;
+    report.agents_md = match (&agents_content, &agents_warning) {
+        (Some(_), Some(_)) => "truncated",
+        (Some(_), None) => "loaded",
+        (None, Some(_)) => "skipped",
+        (None, None) => "absent",
+    };
+    report.agents_md_bytes = agents_content.as_ref().map_or(0, |c| c.len() as u64);
+    report.warnings.extend(agents_warning);
+
+    let walk_dir = working_dir.clone();
+    match tokio::task::spawn_blocking(move || repo_size(&walk_dir)).await {
+        Ok(size) => {
+            report.repo_files = size.files;
+            report.repo_bytes = size.bytes;
+            report.repo_size_truncated = size.truncated;
+        }
+        Err(e) => report
+            .warnings
+            .push(format!("Failed to estimate repository size: {}", e)),
+    }
+
+    if let Some(holder) = workspace_lock::current_holder(&working_dir) {
+        let write_mode = sandbox.is_write();
+        let message = format!(
+            "workspace is locked by run {} (pid {})",
+            holder.run_id, holder.pid
+        );
+        match codex::workspace_lock_policy() {
+            LockPolicy::FailFast if write_mode => report.reasons.push(message),
+            LockPolicy::Queue if write_mode => report
+                .warnings
+                .push(format!("{}; a new run will queue behind it", message)),
+            _ => report.warnings.push(message),
+        }
+        report.lock_holder = Some(holder);
+    }
+
+    // Mirrors how `codex::run` wraps AGENTS.md around the prompt
+    report.estimated_prompt_bytes = match &agents_content {
+        Some(content) => {
+            format!("<system_prompt>\n{}\n</system_prompt>\n\n", content).len() + prompt.len()
+        }
+        None => prompt.len(),
+    } as u64;
+    report.estimated_prompt_tokens = report.estimated_prompt_bytes.div_ceil(BYTES_PER_TOKEN);
+    if report.estimated_prompt_tokens > PROMPT_TOKEN_WARNING {
+        report.warnings.push(format!(
+            "Estimated prompt is ~{} tokens, which may exceed the model context",
+            report.estimated_prompt_tokens
+        ));
+    }
+
+    report.ready = report.reasons.is_empty();
+    report
+}
+
+/// True if `dir` or one of its ancestors contains `.git` (directory or worktree file)
+fn is_git_repo(dir: &Path) -> bool {
+    dir.ancestors().any(|d| d.join(".git").exists())
+}
+
+#[derive(Debug, Default)]
+struct RepoSize {
+    files: u64,
+    bytes: u64,
+    truncated: bool,
+}
+
+fn repo_size(root: &Path) -> RepoSize {
+    let mut size = RepoSize::default();
+    let mut entries = 0u64;
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read_dir.flatten() {
+            entries += 1;
+            if entries > MAX_WALK_ENTRIES {
+                size.truncated = true;
+                return size;
+            }
+            // Symlinks are not followed, so loops and out-of-tree targets are ignored
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                let skipped = entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| SKIPPED_DIRS.contains(&name));
+                if !skipped {
+                    stack.push(entry.path());
+                }
+            } else if file_type.is_file() {
+                size.files += 1;
+                size.bytes += entry.metadata().map_or(0, |m| m.len());
+            }
+        }
+    }
+
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_missing_directory_is_not_ready() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let missing = temp_dir.path().join("missing");
+
+        let report = check(&missing, "hello", true).await;
+        assert!(!report.ready);
+        assert!(report.reasons[0].contains("does not exist"));
+    }
+
+    #[tokio::test]
+    async fn test_check_requires_git_unless_skipped() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+
+        let report = check(temp_dir.path(), "hello", false).await;
+        if report.git_repo == Some(false) {
+            assert!(!report.ready);
+        }
+
+        let report = check(temp_dir.path(), "hello", true).await;
+        assert!(report.ready);
+        assert_eq!(report.git_repo, None);
+        assert_eq!(report.repo_files, 1);
+        assert_eq!(report.repo_bytes, 12);
+    }
+
+    #[tokio::test]
+    async fn test_check_counts_agents_md_in_prompt_estimate() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join(".git")).unwrap();
+        std::fs::write(temp_dir.path().join("AGENTS.md"), "Be brief.").unwrap();
+
+        let report = check(temp_dir.path(), "hello", false).await;
+        assert!(report.ready);
+        assert_eq!(report.git_repo, Some(true));
+        assert_eq!(report.agents_md, "loaded");
+        assert_eq!(report.agents_md_bytes, 9);
+        assert!(report.estimated_prompt_bytes > 14);
+        assert!(report.estimated_prompt_tokens > 0);
+    }
+}
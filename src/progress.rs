@@ -0,0 +1,319 @@
+use crate::plan::PlanUpdate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// Payload excerpts are cut to this many bytes unless configured otherwise
+const DEFAULT_EXCERPT_BYTES: usize = 200;
+
+/// A time-based estimate never claims more than this, since the run may
+/// take longer than the ones before it
+const MAX_TIME_PERCENT: f64 = 95.0;
+
+/// A plan-based estimate stops short of 100 until the run actually ends
+const MAX_PLAN_PERCENT: f64 = 99.0;
+
+/// One progress message, with an estimate of how far the run is
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressUpdate {
+    pub message: String,
+    /// 0-100, when the run can be estimated
+    pub percent: Option<f64>,
+    /// The agent's plan, when this update reports a change to it
+    pub plan: Option<PlanUpdate>,
+}
+
+/// Receives progress messages while a run is in progress
+pub type ProgressSender = tokio::sync::mpsc::UnboundedSender<ProgressUpdate>;
+
+/// Estimates how far a run is: from the plan's completed items once Codex
+/// has a plan (`todo_list` items), otherwise from the time elapsed against
+/// the typical duration of earlier runs in the same workspace
+#[derive(Debug, Clone)]
+pub struct ProgressEstimator {
+    started: Instant,
+    expected: Option<Duration>,
+    /// Completed and total plan items
+    plan: Option<(usize, usize)>,
+}
+
+impl ProgressEstimator {
+    pub fn new(expected: Option<Duration>) -> Self {
+        Self {
+            started: Instant::now(),
+            expected: expected.filter(|expected| !expected.is_zero()),
+            plan: None,
+        }
+    }
+
+    /// Take the plan from a `todo_list` item; returns whether it changed
+    pub fn observe(&mut self, event: &Value) -> bool {
+        let Some(item) = event
+            .get("item")
+            .filter(|item| item.get("type").and_then(Value::as_str) == Some("todo_list"))
+        else {
+            return false;
+        };
+        let Some(items) = item.get("items").and_then(Value::as_array) else {
+            return false;
+        };
+        let completed = items
+            .iter()
+            .filter(|entry| entry.get("completed").and_then(Value::as_bool) == Some(true))
+            .count();
+        let plan = (!items.is_empty()).then_some((completed, items.len()));
+        let changed = plan.is_some() && plan != self.plan;
+        if plan.is_some() {
+            self.plan = plan;
+        }
+        changed
+    }
+
+    /// Message describing the plan's state
+    pub fn plan_message(&self) -> Option<String> {
+        self.plan
+            .map(|(completed, total)| format!("Plan: {} of {} steps done", completed, total))
+    }
+
+    pub fn percent(&self) -> Option<f64> {
+        self.percent_at(self.started.elapsed())
+    }
+
+    fn percent_at(&self, elapsed: Duration) -> Option<f64> {
+        if let Some((completed, total)) = self.plan {
+            return Some((completed as f64 * 100.0 / total as f64).min(MAX_PLAN_PERCENT));
+        }
+        let expected = self.expected?;
+        Some((elapsed.as_secs_f64() * 100.0 / expected.as_secs_f64()).min(MAX_TIME_PERCENT))
+    }
+}
+
+/// Maps one Codex event to a progress message. `event` is the event type
+/// (`turn.started`), optionally followed by `:` and the item type
+/// (`item.completed:command_execution`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProgressRule {
+    pub event: String,
+    /// Message text; `{field}` is replaced with that field of the event's
+    /// item, or of the event itself. Arrays are joined with `, ` (objects by
+    /// their `path`).
+    pub template: String,
+    /// Append an excerpt of the item's output or text
+    #[serde(default)]
+    pub include_excerpt: bool,
+}
+
+/// `progress` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProgressConfig {
+    /// Events reported as progress (defaults to `default_rules()`); `[]`
+    /// turns progress notifications off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<Vec<ProgressRule>>,
+    /// Maximum length of payload excerpts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excerpt_bytes: Option<usize>,
+}
+
+impl ProgressConfig {
+    pub fn rules(&self) -> Vec<ProgressRule> {
+        self.rules.clone().unwrap_or_else(default_rules)
+    }
+
+    /// Progress message for `event`, if a rule matches it
+    pub fn message(&self, event: &Value) -> Option<String> {
+        let event_type = event.get("type")?.as_str()?;
+        let item = event.get("item");
+        let item_type = item.and_then(|i| i.get("type")).and_then(Value::as_str);
+        let key = item_type.map(|t| format!("{}:{}", event_type, t));
+
+        let rules = self.rules();
+        let rule = rules
+            .iter()
+            .find(|rule| key.as_deref() == Some(rule.event.as_str()))
+            .or_else(|| rules.iter().find(|rule| rule.event == event_type))?;
+
+        let mut message = render(&rule.template, event);
+        if rule.include_excerpt {
+            let limit = self.excerpt_bytes.unwrap_or(DEFAULT_EXCERPT_BYTES);
+            if let Some(excerpt) = item.and_then(excerpt).map(|e| truncate(e.trim(), limit)) {
+                if !excerpt.is_empty() {
+                    message.push_str(": ");
+                    message.push_str(&excerpt);
+                }
+            }
+        }
+        Some(message)
+    }
+}
+
+/// Commands, file patches, tool calls, and web searches
+pub fn default_rules() -> Vec<ProgressRule> {
+    [
+        (
+            "item.completed:command_execution",
+            "Ran `{command}` (exit {exit_code})",
+        ),
+        ("item.completed:file_change", "Patched {changes}"),
+        ("item.completed:mcp_tool_call", "Called {server}.{tool}"),
+        ("item.completed:web_search", "Searched the web for {query}"),
+    ]
+    .into_iter()
+    .map(|(event, template)| ProgressRule {
+        event: event.to_string(),
+        template: template.to_string(),
+        include_excerpt: false,
+    })
+    .collect()
+}
+
+fn render(template: &str, event: &Value) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 1..start + len];
+        rendered.push_str(&rest[..start]);
+        let value = event
+            .get("item")
+            .and_then(|item| item.get(name))
+            .or_else(|| event.get(name));
+        match value {
+            Some(value) => rendered.push_str(&display(value)),
+            None => rendered.push_str(&rest[start..start + len + 1]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item.get("path") {
+                Some(path) => display(path),
+                None => display(item),
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => other.to_string(),
+    }
+}
+
+fn excerpt(item: &Value) -> Option<&str> {
+    ["aggregated_output", "text", "output"]
+        .iter()
+        .find_map(|field| item.get(*field).and_then(Value::as_str))
+}
+
+fn truncate(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &text[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_estimates_from_plan_before_time() {
+        let mut estimator = ProgressEstimator::new(Some(Duration::from_secs(100)));
+        assert_eq!(estimator.percent_at(Duration::from_secs(25)), Some(25.0));
+        assert_eq!(estimator.percent_at(Duration::from_secs(500)), Some(95.0));
+
+        let plan = |done: [bool; 4]| {
+            json!({
+                "type": "item.updated",
+                "item": {
+                    "type": "todo_list",
+                    "items": done.iter().map(|d| json!({"text": "step", "completed": d})).collect::<Vec<_>>()
+                }
+            })
+        };
+        assert!(estimator.observe(&plan([true, false, false, false])));
+        assert!(!estimator.observe(&plan([true, false, false, false])));
+        assert_eq!(estimator.percent_at(Duration::from_secs(90)), Some(25.0));
+        assert!(estimator.observe(&plan([true; 4])));
+        assert_eq!(estimator.percent_at(Duration::ZERO), Some(99.0));
+        assert_eq!(
+            estimator.plan_message().as_deref(),
+            Some("Plan: 4 of 4 steps done")
+        );
+        assert!(!estimator.observe(&json!({"type": "turn.started"})));
+
+        assert_eq!(ProgressEstimator::new(None).percent(), None);
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let config = ProgressConfig::default();
+        let command = json!({
+            "type": "item.completed",
+            "item": {"type": "command_execution", "command": "cargo test", "exit_code": 0}
+        });
+        assert_eq!(
+            config.message(&command).as_deref(),
+            Some("Ran `cargo test` (exit 0)")
+        );
+        let patch = json!({
+            "type": "item.completed",
+            "item": {"type": "file_change", "changes": [{"path": "a.rs"}, {"path": "b.rs"}]}
+        });
+        assert_eq!(
+            config.message(&patch).as_deref(),
+            Some("Patched a.rs, b.rs")
+        );
+        assert!(config.message(&json!({"type": "turn.started"})).is_none());
+    }
+
+    #[test]
+    fn test_custom_rules_with_excerpt() {
+        let config = ProgressConfig {
+            rules: Some(vec![
+                ProgressRule {
+                    event: "item.completed:agent_message".to_string(),
+                    template: "Agent said".to_string(),
+                    include_excerpt: true,
+                },
+                ProgressRule {
+                    event: "turn.completed".to_string(),
+                    template: "Turn done ({missing})".to_string(),
+                    include_excerpt: false,
+                },
+            ]),
+            excerpt_bytes: Some(5),
+        };
+        let message = json!({
+            "type": "item.completed",
+            "item": {"type": "agent_message", "text": "hello world"}
+        });
+        assert_eq!(
+            config.message(&message).as_deref(),
+            Some("Agent said: hello...")
+        );
+        assert_eq!(
+            config
+                .message(&json!({"type": "turn.completed"}))
+                .as_deref(),
+            Some("Turn done ({missing})")
+        );
+        let command = json!({
+            "type": "item.completed",
+            "item": {"type": "command_execution", "command": "ls"}
+        });
+        assert!(config.message(&command).is_none());
+    }
+}
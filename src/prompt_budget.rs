@@ -0,0 +1,309 @@
+use crate::agents_sections;
+use rmcp::schemars;
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Bytes added around AGENTS.md when it is prepended as a system prompt
+const AGENTS_WRAPPER_BYTES: usize = "<system_prompt>\n\n</system_prompt>\n\n".len();
+
+const AGENTS_TRUNCATION_MARKER: &str = "\n[... AGENTS.md truncated to fit the prompt budget]";
+
+/// Something removed to keep a run within `prompt_budget_bytes`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BudgetTrim {
+    /// An attached image was not sent
+    Image { path: PathBuf, bytes: u64 },
+    /// The end of AGENTS.md was cut; `bytes` were removed
+    AgentsMdTail { bytes: usize },
+    /// Whole sections of AGENTS.md were left out, by heading
+    AgentsMdSections { bytes: usize, sections: Vec<String> },
+    /// AGENTS.md was left out entirely
+    AgentsMd { bytes: usize },
+}
+
+impl BudgetTrim {
+    pub fn describe(&self) -> String {
+        match self {
+            BudgetTrim::Image { path, bytes } => {
+                format!("dropped image {} ({} bytes)", path.display(), bytes)
+            }
+            BudgetTrim::AgentsMdTail { bytes } => {
+                format!("truncated the last {} bytes of AGENTS.md", bytes)
+            }
+            BudgetTrim::AgentsMdSections { bytes, sections } => format!(
+                "left out AGENTS.md sections {} ({} bytes)",
+                sections.join(", "),
+                bytes
+            ),
+            BudgetTrim::AgentsMd { bytes } => format!("left out AGENTS.md ({} bytes)", bytes),
+        }
+    }
+}
+
+/// Size of one part of the final prompt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PromptPart {
+    /// `prompt`, `agents_md`, `attachment`, or `template` (preambles and the
+    /// AGENTS.md wrapper added by the server)
+    pub part: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    pub bytes: u64,
+}
+
+/// Error returned when a run's prompt exceeds `max_prompt_bytes` after
+/// preprocessing
+#[derive(Debug, Clone)]
+pub struct PromptTooLarge {
+    pub max_bytes: usize,
+    pub total_bytes: u64,
+    /// Largest first
+    pub parts: Vec<PromptPart>,
+}
+
+impl fmt::Display for PromptTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .parts
+            .iter()
+            .map(|part| {
+                let name = match (part.part, &part.path) {
+                    ("prompt", _) => "PROMPT".to_string(),
+                    ("agents_md", _) => "AGENTS.md".to_string(),
+                    ("attachment", Some(path)) => format!("attachment {}", path.display()),
+                    (other, _) => other.to_string(),
+                };
+                format!("{} {} bytes", name, part.bytes)
+            })
+            .collect();
+        write!(
+            f,
+            "PromptTooLarge: the prompt is {} bytes after preprocessing, exceeding max_prompt_bytes ({}): {}",
+            self.total_bytes,
+            self.max_bytes,
+            parts.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for PromptTooLarge {}
+
+/// Refuse a run whose prompt, AGENTS.md, attachments, and server-added
+/// `template_bytes` together exceed `max_bytes`
+pub fn check_max(
+    max_bytes: usize,
+    prompt: &str,
+    template_bytes: usize,
+    agents: Option<&str>,
+    images: &[PathBuf],
+) -> Result<(), PromptTooLarge> {
+    let part = |part, path, bytes| PromptPart { part, path, bytes };
+    let mut parts = vec![part("prompt", None, prompt.len() as u64)];
+    let mut template_bytes = template_bytes as u64;
+    if let Some(content) = agents {
+        parts.push(part("agents_md", None, content.len() as u64));
+        template_bytes += AGENTS_WRAPPER_BYTES as u64;
+    }
+    for path in images {
+        let bytes = std::fs::metadata(path).map_or(0, |m| m.len());
+        parts.push(part("attachment", Some(path.clone()), bytes));
+    }
+    if template_bytes > 0 {
+        parts.push(part("template", None, template_bytes));
+    }
+
+    let total_bytes = parts.iter().map(|part| part.bytes).sum();
+    if total_bytes <= max_bytes as u64 {
+        return Ok(());
+    }
+    parts.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+    Err(PromptTooLarge {
+        max_bytes,
+        total_bytes,
+        parts,
+    })
+}
+
+/// Fit the run into `budget` bytes by trimming, in order: image attachments
+/// (last attached first), then AGENTS.md, by whole sections with those in
+/// `agents_priority` kept first, or from its end when it has no sections. The
+/// user prompt itself is never trimmed. `images` pairs each path with its
+/// size in bytes.
+pub fn fit(
+    budget: usize,
+    prompt: &str,
+    agents: &mut Option<String>,
+    agents_priority: &[String],
+    images: &mut Vec<(PathBuf, u64)>,
+) -> Vec<BudgetTrim> {
+    let agents_bytes = |agents: &Option<String>| {
+        agents
+            .as_ref()
+            .map_or(0, |content| content.len() + AGENTS_WRAPPER_BYTES)
+    };
+    let image_bytes = |images: &Vec<(PathBuf, u64)>| {
+        images
+            .iter()
+            .map(|(_, size)| usize::try_from(*size).unwrap_or(usize::MAX))
+            .fold(0usize, usize::saturating_add)
+    };
+
+    let mut trims = Vec::new();
+    while prompt.len() + agents_bytes(agents) + image_bytes(images) > budget {
+        let Some((path, bytes)) = images.pop() else {
+            break;
+        };
+        trims.push(BudgetTrim::Image { path, bytes });
+    }
+
+    let used = prompt.len() + agents_bytes(agents);
+    if used <= budget {
+        return trims;
+    }
+    let Some(content) = agents.as_mut() else {
+        return trims;
+    };
+    let section_room = budget.saturating_sub(prompt.len() + AGENTS_WRAPPER_BYTES);
+    if let Some(fitted) = agents_sections::fit(content, section_room, agents_priority) {
+        trims.push(BudgetTrim::AgentsMdSections {
+            bytes: content.len().saturating_sub(fitted.content.len()),
+            sections: fitted.dropped,
+        });
+        *content = fitted.content;
+        return trims;
+    }
+    let room =
+        budget.saturating_sub(prompt.len() + AGENTS_WRAPPER_BYTES + AGENTS_TRUNCATION_MARKER.len());
+    let mut cut = room.min(content.len());
+    while !content.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    // Prefer ending on a whole line
+    if let Some(newline) = content[..cut].rfind('\n') {
+        cut = newline;
+    }
+
+    if cut == 0 {
+        trims.push(BudgetTrim::AgentsMd {
+            bytes: content.len(),
+        });
+        *agents = None;
+    } else {
+        trims.push(BudgetTrim::AgentsMdTail {
+            bytes: content.len() - cut,
+        });
+        content.truncate(cut);
+        content.push_str(AGENTS_TRUNCATION_MARKER);
+    }
+    trims
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_drops_images_before_agents() {
+        let mut agents = Some("rule one\nrule two\n".to_string());
+        let mut images = vec![(PathBuf::from("a.png"), 100), (PathBuf::from("b.png"), 100)];
+        let budget = 10 + agents.as_ref().unwrap().len() + AGENTS_WRAPPER_BYTES + 150;
+
+        let trims = fit(budget, "0123456789", &mut agents, &[], &mut images);
+        assert_eq!(
+            trims,
+            vec![BudgetTrim::Image {
+                path: PathBuf::from("b.png"),
+                bytes: 100
+            }]
+        );
+        assert_eq!(images.len(), 1);
+        assert_eq!(agents.as_deref(), Some("rule one\nrule two\n"));
+    }
+
+    #[test]
+    fn test_fit_truncates_agents_tail_on_line_boundary() {
+        let content = format!("keep me\n{}", "x".repeat(500));
+        let mut agents = Some(content.clone());
+        let mut images = Vec::new();
+        let budget = 5 + AGENTS_WRAPPER_BYTES + AGENTS_TRUNCATION_MARKER.len() + 100;
+
+        let trims = fit(budget, "hello", &mut agents, &[], &mut images);
+        let trimmed = agents.unwrap();
+        assert!(trimmed.starts_with("keep me"));
+        assert!(trimmed.ends_with(AGENTS_TRUNCATION_MARKER));
+        assert_eq!(
+            trims,
+            vec![BudgetTrim::AgentsMdTail {
+                bytes: content.len() - "keep me".len()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_check_max_reports_each_part() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image = temp_dir.path().join("shot.png");
+        std::fs::write(&image, vec![0u8; 300]).unwrap();
+        let images = vec![image.clone()];
+
+        assert!(check_max(1000, "hello", 20, Some("rules"), &images).is_ok());
+
+        let err = check_max(100, "hello", 20, Some("rules"), &images).unwrap_err();
+        assert_eq!(
+            err.total_bytes,
+            5 + 20 + 5 + AGENTS_WRAPPER_BYTES as u64 + 300
+        );
+        assert_eq!(err.parts[0].part, "attachment");
+        assert_eq!(err.parts[0].path.as_deref(), Some(image.as_path()));
+        assert_eq!(err.parts[1].part, "template");
+        let message = err.to_string();
+        assert!(message.contains("exceeding max_prompt_bytes (100)"));
+        assert!(message.contains("PROMPT 5 bytes"));
+        assert!(message.contains("AGENTS.md 5 bytes"));
+    }
+
+    #[test]
+    fn test_fit_leaves_out_agents_sections_by_priority() {
+        let content = format!(
+            "# Rules\n## Style\n{}\n## Security\nNever commit secrets.\n",
+            "Prefer small functions. ".repeat(10)
+        );
+        let mut agents = Some(content.clone());
+        let mut images = Vec::new();
+        let budget = 5 + content.len() + AGENTS_WRAPPER_BYTES - 20;
+        let trims = fit(
+            budget,
+            "hello",
+            &mut agents,
+            &["Security".to_string()],
+            &mut images,
+        );
+        let trimmed = agents.unwrap();
+        assert!(trimmed.starts_with("# Rules\n## Security\nNever commit secrets.\n"));
+        assert!(trimmed.ends_with("left out to fit the limit: Style]\n"));
+        assert_eq!(
+            trims,
+            vec![BudgetTrim::AgentsMdSections {
+                bytes: content.len() - trimmed.len(),
+                sections: vec!["Style".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fit_drops_agents_when_no_room() {
+        let mut agents = Some("x".repeat(100));
+        let mut images = Vec::new();
+        let trims = fit(
+            10,
+            "a prompt longer than the budget",
+            &mut agents,
+            &[],
+            &mut images,
+        );
+        assert_eq!(trims, vec![BudgetTrim::AgentsMd { bytes: 100 }]);
+        assert!(agents.is_none());
+    }
+}
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+/// At most this many unresolved paths are reported per run
+const MAX_REPORTED_PATHS: usize = 20;
+
+/// File extensions that mark a token as a file path rather than prose like `and/or`
+const FILE_EXTENSIONS: &[&str] = &[
+    "c", "cc", "cpp", "cs", "css", "go", "h", "hpp", "html", "java", "js", "json", "jsx", "kt",
+    "lock", "md", "php", "proto", "py", "rb", "rs", "scss", "sh", "sql", "swift", "toml", "ts",
+    "tsx", "txt", "vue", "xml", "yaml", "yml",
+];
+
+/// Repository-relative paths mentioned in `prompt`, in order of first mention.
+/// Only tokens containing `/` count: a directory ending in `/`, or a file with
+/// a common source extension. `path:line` and `path#L10` suffixes are dropped.
+pub fn mentioned_paths(prompt: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for token in prompt.split_whitespace() {
+        let token = token
+            .trim_start_matches(|c: char| "`'\"([{<*".contains(c))
+            .trim_end_matches(|c: char| "`'\")]}>*,;.!?".contains(c));
+        let token = token.split([':', '#']).next().unwrap_or_default();
+        if is_relative_path(token) && !paths.iter().any(|p| p == token) {
+            paths.push(token.to_string());
+        }
+    }
+    paths
+}
+
+fn is_relative_path(token: &str) -> bool {
+    if !token.contains('/') || token.starts_with('/') || token.contains("//") {
+        return false;
+    }
+    if !token
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "._-/@+".contains(c))
+    {
+        return false;
+    }
+    if token.ends_with('/') {
+        return token.chars().any(|c| c.is_ascii_alphabetic());
+    }
+    let name = token.rsplit('/').next().unwrap_or_default();
+    match name.rsplit_once('.') {
+        Some((stem, ext)) => !stem.is_empty() && FILE_EXTENSIONS.contains(&ext),
+        None => false,
+    }
+}
+
+/// Paths mentioned in `prompt` that do not exist under `working_dir`
+pub fn unresolved(working_dir: &Path, prompt: &str) -> Vec<String> {
+    mentioned_paths(prompt)
+        .into_iter()
+        .filter(|path| !working_dir.join(path).exists())
+        .take(MAX_REPORTED_PATHS)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mentioned_paths() {
+        let prompt = "Fix `src/lib.rs:42` and src/codex.rs, see docs/ and (tests/a_test.rs). \
+                      Use and/or https://example.com/x.rs, not /etc/hosts.txt or README.md \
+                      or src/lib.rs#L10 or v1.2/3.4.";
+        assert_eq!(
+            mentioned_paths(prompt),
+            vec!["src/lib.rs", "src/codex.rs", "docs/", "tests/a_test.rs"]
+        );
+    }
+
+    #[test]
+    fn test_unresolved_reports_missing_paths() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join("src")).unwrap();
+        std::fs::write(temp_dir.path().join("src/lib.rs"), "").unwrap();
+
+        assert_eq!(
+            unresolved(
+                temp_dir.path(),
+                "Edit src/lib.rs and src/libb.rs under src/"
+            ),
+            vec!["src/libb.rs"]
+        );
+    }
+}
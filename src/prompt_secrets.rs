@@ -0,0 +1,122 @@
+use crate::codex::CodexResult;
+use crate::history::Invocation;
+use crate::redact::Redactor;
+use std::collections::BTreeSet;
+
+/// Secrets a caller pasted into a run's prompt. Codex still gets the prompt
+/// as sent; the secrets are kept out of what the server stores or echoes.
+#[derive(Debug, Clone)]
+pub struct PromptSecrets {
+    /// Secret text and the pattern it matched, longest first so a secret
+    /// containing another is replaced whole
+    secrets: Vec<(String, String)>,
+}
+
+impl PromptSecrets {
+    /// Secrets in `prompt` and `primer_messages`, if there are any
+    pub fn detect(redactor: &Redactor, prompt: &str, primer_messages: &[String]) -> Option<Self> {
+        let mut secrets: Vec<(String, String)> = Vec::new();
+        for text in std::iter::once(prompt).chain(primer_messages.iter().map(String::as_str)) {
+            for (pattern, secret) in redactor.find(text) {
+                if !secrets.iter().any(|(known, _)| known == secret) {
+                    secrets.push((secret.to_string(), pattern.to_string()));
+                }
+            }
+        }
+        secrets.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        (!secrets.is_empty()).then_some(Self { secrets })
+    }
+
+    /// `text` with every secret replaced by `[REDACTED:<pattern>]`
+    pub fn scrub(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (secret, pattern) in &self.secrets {
+            if text.contains(secret.as_str()) {
+                text = text.replace(secret.as_str(), &format!("[REDACTED:{}]", pattern));
+            }
+        }
+        text
+    }
+
+    fn scrub_in_place(&self, text: &mut String) {
+        if self
+            .secrets
+            .iter()
+            .any(|(secret, _)| text.contains(secret.as_str()))
+        {
+            *text = self.scrub(text);
+        }
+    }
+
+    /// Scrub the prompt and primer messages kept in run history
+    pub fn scrub_invocation(&self, invocation: &mut Invocation) {
+        self.scrub_in_place(&mut invocation.prompt);
+        for message in &mut invocation.primer_messages {
+            self.scrub_in_place(message);
+        }
+    }
+
+    /// Scrub the error and warnings of `result`, which may quote the prompt
+    /// (e.g. a CLI complaining about an argument)
+    pub fn apply(&self, result: &mut CodexResult) {
+        for text in [
+            &mut result.error,
+            &mut result.warnings,
+            &mut result.operator_warnings,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            self.scrub_in_place(text);
+        }
+    }
+
+    /// Warning telling the caller which kinds of secret were found
+    pub fn warning(&self) -> String {
+        let kinds: BTreeSet<&str> = self
+            .secrets
+            .iter()
+            .map(|(_, pattern)| pattern.as_str())
+            .collect();
+        format!(
+            "PROMPT appears to contain a secret ({}); it was sent to Codex but redacted from run history, the run log, and error messages. Consider rotating it.",
+            kinds.into_iter().collect::<Vec<_>>().join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrubs_prompt_secrets_from_stored_and_echoed_text() {
+        let (redactor, _) = Redactor::new(&[]);
+        assert!(PromptSecrets::detect(&redactor, "fix the parser", &[]).is_none());
+
+        let prompt =
+            "deploy with password=hunter2hunter2 and key sk-abcdefghijklmnopqrstuvwxyz123456";
+        let secrets = PromptSecrets::detect(&redactor, prompt, &[]).unwrap();
+        let mut invocation = Invocation {
+            prompt: prompt.to_string(),
+            ..Default::default()
+        };
+        secrets.scrub_invocation(&mut invocation);
+        assert_eq!(
+            invocation.prompt,
+            "deploy with password=[REDACTED:secret_assignment] and key [REDACTED:openai_key]"
+        );
+
+        let mut result = CodexResult {
+            error: Some(format!("error: unexpected argument '{}'", prompt)),
+            ..Default::default()
+        };
+        secrets.apply(&mut result);
+        let error = result.error.unwrap();
+        assert!(!error.contains("hunter2"), "{}", error);
+        assert!(!error.contains("sk-abc"), "{}", error);
+        assert!(secrets
+            .warning()
+            .contains("secret (openai_key, secret_assignment)"));
+    }
+}
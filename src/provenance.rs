@@ -0,0 +1,127 @@
+use rmcp::schemars;
+use serde::Serialize;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// What produced a run's changes, for tying them back to the run in audits
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct Provenance {
+    pub run_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Model selected by the run's arguments; absent when Codex used its default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Git blob id of the caller's prompt (`git hash-object --stdin`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_hash: Option<String>,
+    /// The above as git trailers, ready to append to a commit message
+    pub trailers: String,
+}
+
+impl Provenance {
+    pub fn new(
+        run_id: &str,
+        session_id: &str,
+        model: Option<String>,
+        prompt_hash: Option<String>,
+    ) -> Self {
+        let session_id = (!session_id.is_empty()).then(|| session_id.to_string());
+        let mut trailers = format!("Codex-Run-Id: {}\n", run_id);
+        let optional = [
+            ("Codex-Session-Id", &session_id),
+            ("Codex-Model", &model),
+            ("Codex-Prompt-Hash", &prompt_hash),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                trailers.push_str(&format!("{}: {}\n", key, value));
+            }
+        }
+        Self {
+            run_id: run_id.to_string(),
+            session_id,
+            model,
+            prompt_hash,
+            trailers,
+        }
+    }
+
+    /// Lines put before a run's unified diff. `git apply` and `patch` skip
+    /// text before the first file header, so the diff still applies.
+    pub fn diff_header(&self) -> String {
+        format!("{}\n", self.trailers)
+    }
+}
+
+/// Model chosen by `-m`/`--model` or a `-c model=...` override; the last one wins
+pub fn model_from_args(args: &[String]) -> Option<String> {
+    let mut model = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(value) = arg.strip_prefix("--model=") {
+            model = Some(value.to_string());
+        } else if arg == "-m" || arg == "--model" {
+            model = iter.next().cloned();
+        } else if arg == "-c" || arg == "--config" {
+            if let Some(value) = iter.next().and_then(|v| v.strip_prefix("model=")) {
+                model = Some(value.trim_matches('"').to_string());
+            }
+        }
+    }
+    model.filter(|model| !model.is_empty())
+}
+
+/// Git blob id of `prompt`, so an auditor holding the prompt can check it
+/// with `git hash-object`
+pub async fn prompt_hash(working_dir: &Path, prompt: &str) -> Option<String> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(working_dir)
+        .args(["hash-object", "--stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .ok()?;
+    let mut stdin = child.stdin.take()?;
+    stdin.write_all(prompt.as_bytes()).await.ok()?;
+    drop(stdin);
+    let output = child.wait_with_output().await.ok()?;
+    let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !hash.is_empty()).then_some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trailers_and_model() {
+        let args: Vec<String> = [
+            "--sandbox",
+            "read-only",
+            "-m",
+            "o3",
+            "-c",
+            "model=\"gpt-5\"",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let provenance = Provenance::new("run-1", "", model_from_args(&args), Some("abc".into()));
+        assert_eq!(provenance.session_id, None);
+        assert_eq!(
+            provenance.trailers,
+            "Codex-Run-Id: run-1\nCodex-Model: gpt-5\nCodex-Prompt-Hash: abc\n"
+        );
+        assert_eq!(
+            model_from_args(&["--model=o3".to_string()]).as_deref(),
+            Some("o3")
+        );
+        assert_eq!(model_from_args(&[]), None);
+    }
+}
@@ -0,0 +1,100 @@
+use serde::Serialize;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Subcommands `codex_raw` may run, as typed by the caller
+pub const ALLOWED_RAW_COMMANDS: &[&str] = &["--version", "login status", "features list"];
+
+/// Raw commands are diagnostic and should return quickly
+const RAW_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// stdout and stderr are each capped at this many bytes
+const MAX_RAW_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Captured output of a raw Codex CLI invocation
+#[derive(Debug, Serialize)]
+pub struct RawOutput {
+    pub command: String,
+    /// Process exit code; absent if killed by a signal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Split `command` into CLI arguments if it is one of `ALLOWED_RAW_COMMANDS`
+pub fn parse(command: &str) -> Result<Vec<&'static str>, String> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    ALLOWED_RAW_COMMANDS
+        .iter()
+        .find(|allowed| allowed.split_whitespace().eq(words.iter().copied()))
+        .map(|allowed| allowed.split_whitespace().collect())
+        .ok_or_else(|| {
+            format!(
+                "unsupported command `{}`; allowed: {}",
+                command,
+                ALLOWED_RAW_COMMANDS.join(", ")
+            )
+        })
+}
+
+/// Run `codex_bin` with `args` and capture its output
+pub async fn run(codex_bin: &str, args: &[&str]) -> Result<RawOutput, String> {
+    let mut cmd = Command::new(codex_bin);
+    cmd.args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let output = match tokio::time::timeout(RAW_COMMAND_TIMEOUT, cmd.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("failed to run {}: {}", codex_bin, e)),
+        Err(_) => {
+            return Err(format!(
+                "`{} {}` did not finish within {} seconds",
+                codex_bin,
+                args.join(" "),
+                RAW_COMMAND_TIMEOUT.as_secs()
+            ))
+        }
+    };
+
+    Ok(RawOutput {
+        command: args.join(" "),
+        exit_code: output.status.code(),
+        stdout: capped(&output.stdout),
+        stderr: capped(&output.stderr),
+    })
+}
+
+fn capped(bytes: &[u8]) -> String {
+    let mut text = String::from_utf8_lossy(bytes).into_owned();
+    if text.len() > MAX_RAW_OUTPUT_BYTES {
+        let mut end = MAX_RAW_OUTPUT_BYTES;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("\n[... output truncated]");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_allows_only_vetted_commands() {
+        assert_eq!(parse("--version").unwrap(), vec!["--version"]);
+        assert_eq!(parse("  login   status ").unwrap(), vec!["login", "status"]);
+        assert_eq!(parse("features list").unwrap(), vec!["features", "list"]);
+
+        assert!(parse("login").is_err());
+        assert!(parse("logout").is_err());
+        assert!(parse("exec -- rm -rf /").is_err());
+        assert!(parse("").is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use crate::git_info;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tokio::process::Command;
+
+/// Directories listed in the map; the largest ones are kept
+const MAX_DIRECTORIES: usize = 40;
+
+/// Directory depth shown in the map
+const MAX_DEPTH: usize = 2;
+
+/// Languages listed in the map, by line count
+const MAX_LANGUAGES: usize = 12;
+
+/// Files larger than this are counted but not read for line counts
+const MAX_COUNTED_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Manifests and docs worth pointing Codex at, matched by file name
+const KEY_FILES: &[&str] = &[
+    "README.md",
+    "AGENTS.md",
+    "CONTRIBUTING.md",
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "Gemfile",
+    "composer.json",
+    "CMakeLists.txt",
+    "Makefile",
+    "Dockerfile",
+    "docker-compose.yml",
+    "main.rs",
+    "lib.rs",
+    "main.go",
+    "main.py",
+    "index.ts",
+    "index.js",
+];
+
+/// File extensions mapped to language names
+const LANGUAGES: &[(&str, &str)] = &[
+    ("c", "C"),
+    ("h", "C"),
+    ("cc", "C++"),
+    ("cpp", "C++"),
+    ("hpp", "C++"),
+    ("cs", "C#"),
+    ("css", "CSS"),
+    ("scss", "CSS"),
+    ("go", "Go"),
+    ("html", "HTML"),
+    ("java", "Java"),
+    ("js", "JavaScript"),
+    ("jsx", "JavaScript"),
+    ("mjs", "JavaScript"),
+    ("json", "JSON"),
+    ("kt", "Kotlin"),
+    ("md", "Markdown"),
+    ("php", "PHP"),
+    ("proto", "Protobuf"),
+    ("py", "Python"),
+    ("rb", "Ruby"),
+    ("rs", "Rust"),
+    ("sh", "Shell"),
+    ("sql", "SQL"),
+    ("swift", "Swift"),
+    ("toml", "TOML"),
+    ("ts", "TypeScript"),
+    ("tsx", "TypeScript"),
+    ("vue", "Vue"),
+    ("xml", "XML"),
+    ("yaml", "YAML"),
+    ("yml", "YAML"),
+];
+
+/// Files and lines of one language
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LanguageStats {
+    pub language: String,
+    pub files: u64,
+    pub lines: u64,
+}
+
+/// Files and lines below one directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirectoryStats {
+    pub path: String,
+    pub files: u64,
+    pub lines: u64,
+}
+
+/// Compact overview of a repository's tracked files
+#[derive(Debug, Clone, Serialize)]
+pub struct RepoMap {
+    pub working_dir: PathBuf,
+    /// Commit the map was built for; it is reused until HEAD moves
+    pub head: String,
+    /// True when the map came from the cache
+    pub cached: bool,
+    pub files: u64,
+    pub lines: u64,
+    pub languages: Vec<LanguageStats>,
+    /// The largest directories up to two levels deep, in tree order
+    pub directories: Vec<DirectoryStats>,
+    pub key_files: Vec<String>,
+    /// The map as plain text, ready to paste into a prompt
+    pub map: String,
+}
+
+fn cache() -> &'static Mutex<HashMap<PathBuf, RepoMap>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, RepoMap>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Map of the git repository containing `working_dir`, reusing the cached map
+/// while HEAD is unchanged unless `refresh` is set. Only tracked files are
+/// listed, so uncommitted new files show up after they are committed.
+pub async fn get(working_dir: &Path, refresh: bool) -> Result<RepoMap, String> {
+    let working_dir = working_dir
+        .canonicalize()
+        .map_err(|e| format!("{} is not accessible ({})", working_dir.display(), e))?;
+    let head = git_info::get(&working_dir)
+        .await
+        .ok()
+        .and_then(|info| info.head)
+        .ok_or_else(|| {
+            format!(
+                "{} is not a git repository with commits",
+                working_dir.display()
+            )
+        })?;
+
+    if !refresh {
+        let cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(map) = cache.get(&working_dir).filter(|map| map.head == head) {
+            return Ok(RepoMap {
+                cached: true,
+                ..map.clone()
+            });
+        }
+    }
+
+    let listed = git_text(&working_dir, &["ls-files", "-z"]).await?;
+    let paths: Vec<String> = listed
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect();
+    let dir = working_dir.clone();
+    let map = tokio::task::spawn_blocking(move || build(&dir, head, &paths))
+        .await
+        .map_err(|e| format!("repository map task failed: {}", e))?;
+
+    cache()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(working_dir, map.clone());
+    Ok(map)
+}
+
+fn build(working_dir: &Path, head: String, paths: &[String]) -> RepoMap {
+    let mut languages: HashMap<&str, (u64, u64)> = HashMap::new();
+    let mut directories: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    let mut key_files = Vec::new();
+    let mut total_lines = 0;
+
+    for path in paths {
+        let lines = count_lines(&working_dir.join(path));
+        total_lines += lines;
+
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if let Some(language) = language(name) {
+            let entry = languages.entry(language).or_default();
+            entry.0 += 1;
+            entry.1 += lines;
+        }
+        if KEY_FILES.contains(&name) && path.matches('/').count() < MAX_DEPTH {
+            key_files.push(path.clone());
+        }
+
+        let parts: Vec<&str> = path.split('/').collect();
+        for depth in 1..parts.len().min(MAX_DEPTH + 1) {
+            let entry = directories
+                .entry(format!("{}/", parts[..depth].join("/")))
+                .or_default();
+            entry.0 += 1;
+            entry.1 += lines;
+        }
+    }
+
+    let mut languages: Vec<LanguageStats> = languages
+        .into_iter()
+        .map(|(language, (files, lines))| LanguageStats {
+            language: language.to_string(),
+            files,
+            lines,
+        })
+        .collect();
+    languages.sort_by(|a, b| b.lines.cmp(&a.lines).then(a.language.cmp(&b.language)));
+    languages.truncate(MAX_LANGUAGES);
+
+    // Keep the largest directories, then list them in tree order
+    let mut directories: Vec<DirectoryStats> = directories
+        .into_iter()
+        .map(|(path, (files, lines))| DirectoryStats { path, files, lines })
+        .collect();
+    directories.sort_by(|a, b| b.files.cmp(&a.files).then(a.path.cmp(&b.path)));
+    directories.truncate(MAX_DIRECTORIES);
+    directories.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut map = RepoMap {
+        working_dir: working_dir.to_path_buf(),
+        head,
+        cached: false,
+        files: paths.len() as u64,
+        lines: total_lines,
+        languages,
+        directories,
+        key_files,
+        map: String::new(),
+    };
+    map.map = render(&map);
+    map
+}
+
+fn render(map: &RepoMap) -> String {
+    let mut text = format!(
+        "Repository map (HEAD {}): {} files, {} lines\n",
+        &map.head[..map.head.len().min(12)],
+        map.files,
+        map.lines
+    );
+    if !map.languages.is_empty() {
+        let languages: Vec<String> = map
+            .languages
+            .iter()
+            .map(|l| format!("{} ({} files, {} lines)", l.language, l.files, l.lines))
+            .collect();
+        text.push_str(&format!("Languages: {}\n", languages.join(", ")));
+    }
+    if !map.key_files.is_empty() {
+        text.push_str(&format!("Key files: {}\n", map.key_files.join(", ")));
+    }
+    if !map.directories.is_empty() {
+        text.push_str("Directories:\n");
+        for dir in &map.directories {
+            let indent = "  ".repeat(dir.path.matches('/').count());
+            text.push_str(&format!(
+                "{}{} ({} files, {} lines)\n",
+                indent, dir.path, dir.files, dir.lines
+            ));
+        }
+    }
+    text
+}
+
+fn language(name: &str) -> Option<&'static str> {
+    let (_, ext) = name.rsplit_once('.')?;
+    LANGUAGES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(ext))
+        .map(|(_, language)| *language)
+}
+
+/// Newlines in a text file; binary and very large files count as zero lines
+fn count_lines(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_file() || metadata.len() > MAX_COUNTED_FILE_BYTES {
+        return 0;
+    }
+    let mut content = Vec::new();
+    if std::fs::File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut content))
+        .is_err()
+        || content.contains(&0)
+    {
+        return 0;
+    }
+    let newlines = content.iter().filter(|b| **b == b'\n').count() as u64;
+    // A last line without a trailing newline still counts
+    newlines + u64::from(content.last().is_some_and(|b| *b != b'\n'))
+}
+
+async fn git_text(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} is not a git repository with commits: {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_summarizes_languages_and_directories() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir.join("src/server")).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "[package]\nname = \"x\"\n").unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "mod a;\nmod b;\nfn c() {}").unwrap();
+        std::fs::write(dir.join("src/server/mod.rs"), "fn d() {}\n").unwrap();
+        std::fs::write(dir.join("logo.png"), [0u8, 1, 2]).unwrap();
+        let paths: Vec<String> = ["Cargo.toml", "logo.png", "src/lib.rs", "src/server/mod.rs"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+
+        let map = build(dir, "0123456789abcdef0123".to_string(), &paths);
+        assert_eq!(map.files, 4);
+        assert_eq!(map.lines, 6);
+        assert_eq!(map.languages[0].language, "Rust");
+        assert_eq!(map.languages[0].files, 2);
+        assert_eq!(map.languages[0].lines, 4);
+        assert_eq!(map.key_files, vec!["Cargo.toml", "src/lib.rs"]);
+        assert_eq!(
+            map.directories,
+            vec![
+                DirectoryStats {
+                    path: "src/".to_string(),
+                    files: 2,
+                    lines: 4,
+                },
+                DirectoryStats {
+                    path: "src/server/".to_string(),
+                    files: 1,
+                    lines: 1,
+                },
+            ]
+        );
+        assert!(map
+            .map
+            .starts_with("Repository map (HEAD 0123456789ab): 4 files"));
+        assert!(map.map.contains("\n    src/server/ (1 files, 1 lines)\n"));
+    }
+}
@@ -0,0 +1,318 @@
+use crate::final_schema;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Environment variable holding the token when `token_env` is not set
+const DEFAULT_TOKEN_ENV: &str = "GITHUB_TOKEN";
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// Posting a review should not hold up the tool call for long
+const POST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `github_review` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GithubReviewConfig {
+    /// Environment variable with the token used to post reviews
+    /// (`GITHUB_TOKEN` when unset)
+    #[serde(default)]
+    pub token_env: Option<String>,
+    /// API root, for GitHub Enterprise (`https://api.github.com` when unset)
+    #[serde(default)]
+    pub api_url: Option<String>,
+    /// Label shown before a comment per finding severity, e.g.
+    /// `{"critical": "Blocker"}`; other severities are shown as given
+    #[serde(default)]
+    pub severity_labels: BTreeMap<String, String>,
+    /// `curl` binary used to post reviews
+    #[serde(default)]
+    pub curl_bin: Option<PathBuf>,
+}
+
+/// One review finding, as reported by a review run
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct Finding {
+    /// File the finding is about, relative to the repository root
+    #[serde(alias = "file")]
+    pub path: String,
+    /// Line in the new version of the file; findings without one go into
+    /// the review body
+    #[serde(default)]
+    pub line: Option<u64>,
+    #[serde(alias = "message", alias = "comment")]
+    pub body: String,
+    /// e.g. `critical`, `high`, `medium`, `low`
+    #[serde(default)]
+    pub severity: Option<String>,
+}
+
+/// A line comment of a GitHub pull request review
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReviewComment {
+    pub path: String,
+    pub line: u64,
+    pub side: &'static str,
+    pub body: String,
+}
+
+/// Body of `POST /repos/{owner}/{repo}/pulls/{number}/reviews`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GithubReview {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_id: Option<String>,
+    pub body: String,
+    pub event: &'static str,
+    pub comments: Vec<ReviewComment>,
+}
+
+/// Findings from a review run's final message: a JSON array of findings, or
+/// an object with a `findings` array, optionally in a code fence
+pub fn parse_findings(message: &str) -> Result<Vec<Finding>, String> {
+    let value: Value = serde_json::from_str(final_schema::unfence(message.trim()))
+        .map_err(|e| format!("the run's answer is not JSON: {}", e))?;
+    let findings = match value {
+        Value::Object(mut object) => object
+            .remove("findings")
+            .ok_or("the run's answer has no findings array")?,
+        array => array,
+    };
+    serde_json::from_value(findings).map_err(|e| format!("invalid findings: {}", e))
+}
+
+fn label<'a>(severity: &'a str, labels: &'a BTreeMap<String, String>) -> &'a str {
+    labels
+        .get(severity)
+        .or_else(|| labels.get(&severity.to_lowercase()))
+        .map_or(severity, String::as_str)
+}
+
+fn comment_body(finding: &Finding, labels: &BTreeMap<String, String>) -> String {
+    match finding.severity.as_deref().filter(|s| !s.is_empty()) {
+        Some(severity) => format!("**{}:** {}", label(severity, labels), finding.body),
+        None => finding.body.clone(),
+    }
+}
+
+/// GitHub review for `findings`. Findings with a line become line comments;
+/// the rest are listed in the review body after `summary`.
+pub fn build(
+    findings: &[Finding],
+    summary: Option<&str>,
+    commit_id: Option<String>,
+    labels: &BTreeMap<String, String>,
+) -> GithubReview {
+    let mut body = summary.unwrap_or_default().trim().to_string();
+    let mut comments = Vec::new();
+    for finding in findings {
+        let text = comment_body(finding, labels);
+        match finding.line.filter(|line| *line > 0) {
+            Some(line) => comments.push(ReviewComment {
+                path: finding.path.clone(),
+                line,
+                side: "RIGHT",
+                body: text,
+            }),
+            None => {
+                if !body.is_empty() {
+                    body.push('\n');
+                }
+                body.push_str(&format!("- `{}`: {}", finding.path, text));
+            }
+        }
+    }
+    if body.is_empty() {
+        body = format!("{} finding(s)", findings.len());
+    }
+    GithubReview {
+        commit_id,
+        body,
+        event: "COMMENT",
+        comments,
+    }
+}
+
+/// Pull request named `owner/repo#number`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRequest {
+    pub repo: String,
+    pub number: u64,
+}
+
+impl PullRequest {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("expected owner/repo#number, got {}", value);
+        let (repo, number) = value.trim().split_once('#').ok_or_else(invalid)?;
+        let valid_repo = repo.split('/').count() == 2
+            && repo.split('/').all(|part| {
+                // `.` and `..` would step out of the API path
+                !matches!(part, "" | "." | "..")
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            });
+        if !valid_repo {
+            return Err(invalid());
+        }
+        Ok(Self {
+            repo: repo.to_string(),
+            number: number.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Post `review` to `pull_request` with `curl`; returns the review's URL.
+/// The token is passed on curl's stdin, never on its command line.
+pub async fn post(
+    review: &GithubReview,
+    pull_request: &PullRequest,
+    config: &GithubReviewConfig,
+) -> Result<String, String> {
+    let token_env = config.token_env.as_deref().unwrap_or(DEFAULT_TOKEN_ENV);
+    let token = std::env::var(token_env)
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+        .ok_or_else(|| format!("no token to post the review; set {}", token_env))?;
+    let url = format!(
+        "{}/repos/{}/pulls/{}/reviews",
+        config
+            .api_url
+            .as_deref()
+            .unwrap_or(DEFAULT_API_URL)
+            .trim_end_matches('/'),
+        pull_request.repo,
+        pull_request.number
+    );
+    let body =
+        serde_json::to_string(review).map_err(|e| format!("failed to encode review: {}", e))?;
+    let curl_config = format!(
+        "header = \"Authorization: Bearer {}\"\nheader = \"Accept: application/vnd.github+json\"\nheader = \"Content-Type: application/json\"\ndata-binary = \"{}\"\n",
+        curl_quote(token.trim()),
+        curl_quote(&body)
+    );
+
+    let curl_bin = config
+        .curl_bin
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("curl"));
+    let mut child = Command::new(&curl_bin)
+        .args(["-sS", "-X", "POST", "-K", "-", "-w", "\n%{http_code}"])
+        .arg(&url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", curl_bin.display(), e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(curl_config.as_bytes())
+            .await
+            .map_err(|e| format!("failed to pass the request to curl: {}", e))?;
+    }
+    let output = tokio::time::timeout(POST_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            format!(
+                "posting the review did not finish within {} seconds",
+                POST_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| format!("failed to run {}: {}", curl_bin.display(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "curl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (response, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+    let response: Value = serde_json::from_str(response).unwrap_or(Value::Null);
+    if !status.trim().starts_with('2') {
+        let message = response
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or("no message");
+        return Err(format!("GitHub returned {}: {}", status.trim(), message));
+    }
+    Ok(response
+        .get("html_url")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// Quote a value for a double-quoted curl config string
+fn curl_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builds_review_from_findings() {
+        let findings = parse_findings(
+            "```json\n{\"findings\": [\
+             {\"file\": \"src/lib.rs\", \"line\": 12, \"message\": \"Unchecked unwrap\", \"severity\": \"critical\"},\
+             {\"path\": \"README.md\", \"body\": \"Outdated example\"}]}\n```",
+        )
+        .unwrap();
+        let labels = BTreeMap::from([("critical".to_string(), "Blocker".to_string())]);
+        let review = build(
+            &findings,
+            Some("Two issues."),
+            Some("abc123".into()),
+            &labels,
+        );
+
+        assert_eq!(
+            review.comments,
+            vec![ReviewComment {
+                path: "src/lib.rs".to_string(),
+                line: 12,
+                side: "RIGHT",
+                body: "**Blocker:** Unchecked unwrap".to_string(),
+            }]
+        );
+        assert_eq!(review.body, "Two issues.\n- `README.md`: Outdated example");
+        let json = serde_json::to_value(&review).unwrap();
+        assert_eq!(json["event"], "COMMENT");
+        assert_eq!(json["commit_id"], "abc123");
+
+        assert!(parse_findings("LGTM").is_err());
+        assert_eq!(
+            PullRequest::parse("jakvbs/codex-mcp-rs#42").unwrap(),
+            PullRequest {
+                repo: "jakvbs/codex-mcp-rs".to_string(),
+                number: 42,
+            }
+        );
+        assert!(PullRequest::parse("codex-mcp-rs#42").is_err());
+        assert!(PullRequest::parse("a/b#x").is_err());
+        assert!(PullRequest::parse("../x#1").is_err());
+        assert!(PullRequest::parse("owner/..#1").is_err());
+        assert!(PullRequest::parse("./x#1").is_err());
+        assert!(PullRequest::parse("owner/.github#1").is_ok());
+        assert_eq!(curl_quote("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
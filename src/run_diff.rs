@@ -0,0 +1,275 @@
+use crate::at_rest;
+use crate::codex;
+use crate::provenance::Provenance;
+use crate::write_scope::{self, Snapshot};
+use rmcp::schemars;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Diffs larger than this are stored on disk instead of returned, unless
+/// `max_inline_diff_bytes` is configured
+pub const DEFAULT_MAX_INLINE_DIFF_BYTES: usize = 64 * 1024;
+
+/// Lines added and removed in one file; both are absent for binary files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct FileStat {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub added: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed: Option<u64>,
+}
+
+/// Changes a run made, for `RETURN_DIFF`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct RunDiff {
+    pub files: Vec<FileStat>,
+    /// Size of the full unified diff
+    pub diff_bytes: usize,
+    /// The unified diff, when it fits in `max_inline_diff_bytes`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// Where the full diff was written when it was too large to return
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff_file: Option<PathBuf>,
+    /// The run that made the changes; also written at the top of the diff
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Diff against HEAD of the files that changed since `before`. Diffs over
+/// `max_inline_bytes` are written to `store_dir/<run_id>.diff` and only the
+/// per-file stats are returned. The diff starts with `provenance`'s trailers.
+pub async fn collect(
+    working_dir: &Path,
+    before: &Snapshot,
+    max_inline_bytes: usize,
+    store_dir: &Path,
+    provenance: Provenance,
+) -> Result<RunDiff, String> {
+    let run_id = provenance.run_id.as_str();
+    let after = write_scope::snapshot(working_dir).await?;
+    let paths = write_scope::changed_paths(before, &after);
+    if paths.is_empty() {
+        return Ok(RunDiff::default());
+    }
+
+    let mut untracked_args = vec!["ls-files", "--others", "--exclude-standard", "--"];
+    untracked_args.extend(paths.iter().map(String::as_str));
+    let listed = git(working_dir, &untracked_args).await?;
+    let listed = String::from_utf8_lossy(&listed);
+    let untracked: Vec<&str> = paths
+        .iter()
+        .map(String::as_str)
+        .filter(|path| listed.lines().any(|line| line == *path))
+        .collect();
+    let tracked: Vec<&str> = paths
+        .iter()
+        .map(String::as_str)
+        .filter(|path| !untracked.contains(path))
+        .collect();
+
+    let mut files = Vec::new();
+    let mut patch = provenance.diff_header().into_bytes();
+    if !tracked.is_empty() {
+        let base = ["diff", "HEAD", "--no-renames", "--relative"];
+        let mut args: Vec<&str> = base.to_vec();
+        args.extend(["--numstat", "--"]);
+        args.extend(&tracked);
+        files.extend(parse_numstat(&git(working_dir, &args).await?));
+
+        let mut args: Vec<&str> = base.to_vec();
+        args.push("--");
+        args.extend(&tracked);
+        patch.extend(git(working_dir, &args).await?);
+    }
+    for path in untracked {
+        let args = ["diff", "--no-index", "--", "/dev/null", path];
+        patch.extend(git(working_dir, &args).await?);
+        files.push(new_file_stat(&working_dir.join(path), path));
+    }
+
+    let diff_bytes = patch.len();
+    let diff = String::from_utf8_lossy(&patch).into_owned();
+    if diff_bytes <= max_inline_bytes {
+        return Ok(RunDiff {
+            files,
+            diff_bytes,
+            diff: Some(diff),
+            diff_file: None,
+            provenance: Some(provenance),
+        });
+    }
+
+    Ok(RunDiff {
+        files,
+        diff_bytes,
+        diff: None,
+        diff_file: Some(store(store_dir, run_id, &patch)?),
+        provenance: Some(provenance),
+    })
+}
+
+/// Where `store` writes the diff of `run_id`
+pub fn stored_path(store_dir: &Path, run_id: &str) -> PathBuf {
+    store_dir.join(format!("{}.diff", run_id))
+}
+
+/// The diff `store` wrote for `run_id`, encrypted or not
+pub fn find_stored(store_dir: &Path, run_id: &str) -> Option<PathBuf> {
+    let plain = stored_path(store_dir, run_id);
+    let encrypted = at_rest::encrypted_path(&plain);
+    [encrypted, plain].into_iter().find(|path| path.is_file())
+}
+
+/// Write a run's diff to `store_dir/<run_id>.diff`, or `<run_id>.diff.age`
+/// when encryption is configured
+pub fn store(store_dir: &Path, run_id: &str, diff: &[u8]) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(store_dir)
+        .map_err(|e| format!("failed to create {}: {}", store_dir.display(), e))?;
+    let diff_file = stored_path(store_dir, run_id);
+    let written = match codex::at_rest_key()? {
+        Some(key) => {
+            let diff_file = at_rest::encrypted_path(&diff_file);
+            key.write(&diff_file, diff).map(|_| diff_file)
+        }
+        None => std::fs::write(&diff_file, diff).map(|_| diff_file.clone()),
+    };
+    written.map_err(|e| format!("failed to write {}: {}", diff_file.display(), e))
+}
+
+/// `git diff --numstat` lines: `added<TAB>removed<TAB>path`, with `-` for
+/// binary files
+fn parse_numstat(output: &[u8]) -> Vec<FileStat> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let added = fields.next()?;
+            let removed = fields.next()?;
+            let path = fields.next()?;
+            Some(FileStat {
+                path: path.to_string(),
+                added: added.parse().ok(),
+                removed: removed.parse().ok(),
+            })
+        })
+        .collect()
+}
+
+fn new_file_stat(full_path: &Path, path: &str) -> FileStat {
+    let content = std::fs::read(full_path).unwrap_or_default();
+    let binary = content.contains(&0);
+    let lines = content
+        .split(|b| *b == b'\n')
+        .filter(|l| !l.is_empty())
+        .count() as u64;
+    FileStat {
+        path: path.to_string(),
+        added: (!binary).then_some(lines),
+        removed: (!binary).then_some(0),
+    }
+}
+
+async fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    // `git diff --no-index` exits with 1 when the files differ
+    match output.status.code() {
+        Some(0) | Some(1) => Ok(output.stdout),
+        _ => Err(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_numstat() {
+        assert_eq!(
+            parse_numstat(b"3\t1\tsrc/lib.rs\n-\t-\tlogo.png\n"),
+            vec![
+                FileStat {
+                    path: "src/lib.rs".to_string(),
+                    added: Some(3),
+                    removed: Some(1),
+                },
+                FileStat {
+                    path: "logo.png".to_string(),
+                    added: None,
+                    removed: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_stores_large_diffs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("repo");
+        std::fs::create_dir(&dir).unwrap();
+        let init = |args: &[&str]| {
+            std::process::Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(args)
+                .output()
+                .map(|o| o.status.success())
+                .unwrap_or(false)
+        };
+        if !init(&["init", "-q"]) {
+            return; // git not available
+        }
+        std::fs::write(dir.join("a.txt"), "one\n").unwrap();
+        init(&["add", "."]);
+        init(&[
+            "-c",
+            "user.name=t",
+            "-c",
+            "user.email=t@t",
+            "commit",
+            "-qm",
+            "init",
+        ]);
+
+        let before = write_scope::snapshot(&dir).await.unwrap();
+        std::fs::write(dir.join("a.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(dir.join("new.txt"), "x\ny\n").unwrap();
+
+        let store = temp_dir.path().join("runs");
+        let provenance = |run_id: &str| Provenance::new(run_id, "thread-1", None, None);
+        let inline = collect(&dir, &before, 1 << 20, &store, provenance("run-a"))
+            .await
+            .unwrap();
+        assert_eq!(inline.files.len(), 2);
+        assert_eq!(inline.files[0].added, Some(1));
+        assert_eq!(inline.files[1].path, "new.txt");
+        assert_eq!(inline.files[1].added, Some(2));
+        let diff = inline.diff.unwrap();
+        assert!(diff.starts_with("Codex-Run-Id: run-a\nCodex-Session-Id: thread-1\n\n"));
+        assert!(diff.contains("+two"));
+        assert!(diff.contains("+y"));
+
+        let stored = collect(&dir, &before, 10, &store, provenance("run-b"))
+            .await
+            .unwrap();
+        assert!(stored.diff.is_none());
+        let diff_file = stored.diff_file.unwrap();
+        assert_eq!(diff_file, store.join("run-b.diff"));
+        assert_eq!(
+            std::fs::metadata(&diff_file).unwrap().len() as usize,
+            stored.diff_bytes
+        );
+    }
+}
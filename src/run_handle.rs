@@ -0,0 +1,89 @@
+use crate::codex::{self, CodexResult, Options};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Lifecycle state of a spawned run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Finished,
+    Aborted,
+}
+
+/// Handle to a run started with `spawn_run`. Dropping the handle detaches the
+/// run, which keeps going in the background and is still recorded in history.
+#[derive(Debug)]
+pub struct CodexRunHandle {
+    run_id: String,
+    task: JoinHandle<Result<CodexResult>>,
+    aborted: AtomicBool,
+    cancel: CancellationToken,
+}
+
+/// Start `opts` on the Tokio runtime and return immediately. The handle's
+/// `run_id` matches the `run_id` of the eventual result.
+pub fn spawn_run(mut opts: Options) -> CodexRunHandle {
+    let run_id = Uuid::new_v4().to_string();
+    let cancel = opts
+        .cancel
+        .get_or_insert_with(CancellationToken::new)
+        .clone();
+    let task = tokio::spawn(codex::run_with_id(opts, run_id.clone()));
+    CodexRunHandle {
+        run_id,
+        task,
+        aborted: AtomicBool::new(false),
+        cancel,
+    }
+}
+
+impl CodexRunHandle {
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Stop the run: the Codex process is killed and the workspace lock released.
+    /// Aborted runs are not recorded in history. No effect once finished.
+    pub fn abort(&self) {
+        if !self.task.is_finished() {
+            self.aborted.store(true, Ordering::SeqCst);
+            self.task.abort();
+        }
+    }
+
+    /// Ask the run to stop: the Codex process is killed and the run finishes
+    /// with a cancellation error, recorded in history like any failed run.
+    /// Unlike `abort`, the result can still be awaited.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Token that cancels the run, for callers that stop runs from elsewhere
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    pub fn status(&self) -> RunStatus {
+        if self.aborted.load(Ordering::SeqCst) {
+            RunStatus::Aborted
+        } else if self.task.is_finished() {
+            RunStatus::Finished
+        } else {
+            RunStatus::Running
+        }
+    }
+
+    /// Wait for the run to finish
+    pub async fn await_result(self) -> Result<CodexResult> {
+        match self.task.await {
+            Ok(result) => result,
+            Err(e) if e.is_cancelled() => Err(anyhow!("run {} was aborted", self.run_id)),
+            Err(e) => Err(anyhow!("run {} panicked: {}", self.run_id, e)),
+        }
+    }
+}
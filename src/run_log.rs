@@ -0,0 +1,533 @@
+use crate::at_rest::{self, AgeKey, Encryptor};
+use crate::redact::Redactor;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+
+/// How often the retention sweeper runs
+const SWEEP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// With redaction on, a line longer than this is redacted in pieces
+const MAX_PENDING_BYTES: usize = 16 * 1024 * 1024;
+
+/// Compression for stored run logs, configured via `run_log_compression` in
+/// `codex-mcp.config.json`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+impl Compression {
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "jsonl",
+            Compression::Gzip => "jsonl.gz",
+        }
+    }
+}
+
+/// `runs_retention` section of `codex-mcp.config.json`; limits apply to
+/// auto-named logs in `runs_dir`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RetentionConfig {
+    /// Delete logs older than this many days
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+    /// Delete the oldest logs until the directory is within this many bytes
+    #[serde(default)]
+    pub max_total_bytes: Option<u64>,
+}
+
+/// Where to tee the raw JSONL stream of a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogTarget {
+    /// `<runs_dir>/<run_id>.jsonl` (`.jsonl.gz` when compressed, plus `.age`
+    /// when encrypted)
+    Auto,
+    /// Explicit file path; relative paths are resolved against the working directory
+    Path(PathBuf),
+}
+
+impl LogTarget {
+    /// Resolve to a concrete file path for `run_id`
+    pub fn resolve(
+        &self,
+        runs_dir: &Path,
+        working_dir: &Path,
+        run_id: &str,
+        compression: Compression,
+    ) -> PathBuf {
+        match self {
+            LogTarget::Auto => runs_dir.join(format!("{}.{}", run_id, compression.extension())),
+            LogTarget::Path(path) if path.is_absolute() => path.clone(),
+            LogTarget::Path(path) => working_dir.join(path),
+        }
+    }
+}
+
+/// Default directory for auto-named run logs
+pub fn default_runs_dir() -> PathBuf {
+    std::env::temp_dir().join("codex-mcp-runs")
+}
+
+fn is_gzip_path(path: &Path) -> bool {
+    at_rest::plain_path(path)
+        .extension()
+        .is_some_and(|ext| ext == "gz")
+}
+
+#[derive(Debug)]
+enum Writer {
+    // Unbuffered so the file stays current even if the run is cut short
+    Plain(tokio::fs::File),
+    // Sync-flushed after every write so the file decodes up to the last line
+    // even without a gzip trailer
+    Gzip(GzEncoder<std::fs::File>),
+    // Piped through `age`, which writes whole 64 KiB chunks
+    Encrypted(Encryptor),
+    GzipEncrypted(GzEncoder<Encryptor>),
+}
+
+impl Writer {
+    /// Whether writes go through the blocking pipe to `age`
+    fn is_encrypted(&self) -> bool {
+        matches!(self, Writer::Encrypted(_) | Writer::GzipEncrypted(_))
+    }
+
+    fn write_blocking(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match self {
+            Writer::Encrypted(encryptor) => encryptor.write_all(bytes),
+            Writer::GzipEncrypted(encoder) => encoder.write_all(bytes),
+            Writer::Plain(_) | Writer::Gzip(_) => unreachable!("only encrypted writers block"),
+        }
+    }
+
+    fn finish_blocking(&mut self) -> std::io::Result<()> {
+        match self {
+            Writer::Encrypted(encryptor) => encryptor.finish(),
+            Writer::GzipEncrypted(encoder) => encoder
+                .try_finish()
+                .and_then(|_| encoder.get_mut().finish()),
+            Writer::Plain(_) | Writer::Gzip(_) => unreachable!("only encrypted writers block"),
+        }
+    }
+}
+
+/// File sink receiving every byte Codex writes to stdout, regardless of the
+/// in-memory line and message limits. Paths ending in `.gz` are written
+/// gzip-compressed. Write errors disable the sink and are reported once via
+/// `error()` instead of failing the run. Paths ending in `.age` are encrypted.
+#[derive(Debug)]
+pub struct RunLog {
+    path: PathBuf,
+    writer: Option<Writer>,
+    error: Option<String>,
+    redactor: Option<&'static Redactor>,
+    /// Bytes of an incomplete line, held back until it can be redacted whole
+    pending: Vec<u8>,
+}
+
+impl RunLog {
+    /// Create the log at `path`; `key` is required for `.age` paths
+    pub async fn create(path: &Path, key: Option<&AgeKey>) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let writer = if at_rest::is_encrypted(path) {
+            let key = key.ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "no encryption key is configured",
+                )
+            })?;
+            let encryptor = key.encryptor(path)?;
+            if is_gzip_path(path) {
+                Writer::GzipEncrypted(GzEncoder::new(encryptor, flate2::Compression::default()))
+            } else {
+                Writer::Encrypted(encryptor)
+            }
+        } else if is_gzip_path(path) {
+            let file = std::fs::File::create(path)?;
+            Writer::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+        } else {
+            Writer::Plain(tokio::fs::File::create(path).await?)
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: Some(writer),
+            error: None,
+            redactor: None,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Redact each line with `redactor` before it is written
+    pub fn redacted_with(mut self, redactor: Option<&'static Redactor>) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record the tool call's MCP `_meta` as a `codex_mcp.meta` line ahead
+    /// of the Codex stream
+    pub async fn write_meta(&mut self, meta: &serde_json::Value) {
+        let line = serde_json::json!({ "type": "codex_mcp.meta", "_meta": meta });
+        self.write(format!("{}\n", line).as_bytes()).await;
+    }
+
+    pub async fn write(&mut self, bytes: &[u8]) {
+        let Some(redactor) = self.redactor else {
+            self.write_raw(bytes).await;
+            return;
+        };
+        self.pending.extend_from_slice(bytes);
+        let end = match self.pending.iter().rposition(|b| *b == b'\n') {
+            Some(newline) => newline + 1,
+            None if self.pending.len() >= MAX_PENDING_BYTES => self.pending.len(),
+            None => return,
+        };
+        let lines: Vec<u8> = self.pending.drain(..end).collect();
+        let redacted = redactor.redact(&String::from_utf8_lossy(&lines));
+        self.write_raw(redacted.as_bytes()).await;
+    }
+
+    async fn write_raw(&mut self, bytes: &[u8]) {
+        let outcome = match self.writer.as_mut() {
+            Some(Writer::Plain(file)) => file.write_all(bytes).await,
+            Some(Writer::Gzip(encoder)) => encoder.write_all(bytes).and_then(|_| encoder.flush()),
+            Some(writer) if writer.is_encrypted() => {
+                let bytes = bytes.to_vec();
+                self.off_runtime(move |writer| writer.write_blocking(&bytes))
+                    .await
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = outcome {
+            self.fail(e);
+        }
+    }
+
+    /// Flush pending writes (and the gzip trailer); call once the stream has ended
+    pub async fn finish(&mut self) {
+        if let Some(redactor) = self.redactor {
+            if !self.pending.is_empty() {
+                let rest = std::mem::take(&mut self.pending);
+                let redacted = redactor.redact(&String::from_utf8_lossy(&rest));
+                self.write_raw(redacted.as_bytes()).await;
+            }
+        }
+        let outcome = match self.writer.as_mut() {
+            Some(Writer::Plain(file)) => file.flush().await,
+            Some(Writer::Gzip(encoder)) => encoder.try_finish(),
+            Some(writer) if writer.is_encrypted() => {
+                self.off_runtime(Writer::finish_blocking).await
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = outcome {
+            self.fail(e);
+        }
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Run `op` on the writer in a blocking task, handing the writer back
+    /// afterwards, so pipe writes to `age` and waiting for it to exit never
+    /// stall the async runtime
+    async fn off_runtime(
+        &mut self,
+        op: impl FnOnce(&mut Writer) -> std::io::Result<()> + Send + 'static,
+    ) -> std::io::Result<()> {
+        let Some(mut writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let (writer, outcome) = tokio::task::spawn_blocking(move || {
+            let outcome = op(&mut writer);
+            (writer, outcome)
+        })
+        .await
+        .map_err(std::io::Error::other)?;
+        self.writer = Some(writer);
+        outcome
+    }
+
+    fn fail(&mut self, e: std::io::Error) {
+        self.writer = None;
+        self.error = Some(format!(
+            "Run log {} is incomplete: {}",
+            self.path.display(),
+            e
+        ));
+    }
+}
+
+impl Drop for RunLog {
+    fn drop(&mut self) {
+        // An unfinished `age` writer waits for the process when dropped
+        let Some(writer) = self.writer.take().filter(Writer::is_encrypted) else {
+            return;
+        };
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn_blocking(move || drop(writer));
+        }
+    }
+}
+
+/// Open a run log for streaming reads, decompressing gzip files on the fly.
+/// Logs of runs that were cut short may lack a gzip trailer; readers should
+/// treat `UnexpectedEof` as the end of the log. `.age` logs are decrypted
+/// with `key`.
+pub fn open_reader(path: &Path, key: Option<&AgeKey>) -> std::io::Result<Box<dyn BufRead + Send>> {
+    let mut reader: Box<dyn BufRead + Send> = if at_rest::is_encrypted(path) {
+        let key = key.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is encrypted and no key is configured", path.display()),
+            )
+        })?;
+        Box::new(BufReader::new(key.decryptor(path)?))
+    } else {
+        Box::new(BufReader::new(std::fs::File::open(path)?))
+    };
+    let gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+    if gzip {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(reader)
+    }
+}
+
+/// Files removed by one retention sweep
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SweepReport {
+    pub removed_files: usize,
+    pub freed_bytes: u64,
+}
+
+/// Apply `retention` to the run logs in `dir`: first by age, then oldest-first
+/// until the size budget is met
+pub fn sweep(dir: &Path, retention: &RetentionConfig) -> std::io::Result<SweepReport> {
+    let mut logs: Vec<(PathBuf, SystemTime, u64)> = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let name = name.strip_suffix(".age").unwrap_or(name);
+        if !name.ends_with(".jsonl") && !name.ends_with(".jsonl.gz") {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            logs.push((entry.path(), modified, metadata.len()));
+        }
+    }
+    logs.sort_by_key(|(_, modified, _)| *modified);
+
+    let mut report = SweepReport::default();
+
+    if let Some(days) = retention.max_age_days {
+        let max_age = Duration::from_secs(days.saturating_mul(86_400));
+        let now = SystemTime::now();
+        logs.retain(|(path, modified, size)| {
+            let expired = now.duration_since(*modified).is_ok_and(|age| age > max_age);
+            if expired {
+                remove_log(path, *size, &mut report);
+            }
+            !expired
+        });
+    }
+
+    if let Some(budget) = retention.max_total_bytes {
+        let mut total: u64 = logs.iter().map(|(_, _, size)| size).sum();
+        for (path, _, size) in &logs {
+            if total <= budget {
+                break;
+            }
+            remove_log(path, *size, &mut report);
+            total -= size;
+        }
+    }
+
+    Ok(report)
+}
+
+fn remove_log(path: &Path, size: u64, report: &mut SweepReport) {
+    if std::fs::remove_file(path).is_ok() {
+        report.removed_files += 1;
+        report.freed_bytes += size;
+    }
+}
+
+/// Sweep `dir` now and then hourly, if any retention limit is configured
+pub fn start_sweeper(
+    dir: PathBuf,
+    retention: RetentionConfig,
+) -> Option<tokio::task::JoinHandle<()>> {
+    if retention == RetentionConfig::default() {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let dir = dir.clone();
+            let retention = retention.clone();
+            let outcome = tokio::task::spawn_blocking(move || sweep(&dir, &retention)).await;
+            if let Ok(Err(e)) = outcome {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("Warning: run log retention sweep failed: {}", e);
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::sync::OnceLock;
+
+    #[test]
+    fn test_resolve_targets() {
+        let runs = Path::new("/var/runs");
+        let work = Path::new("/work");
+        assert_eq!(
+            LogTarget::Auto.resolve(runs, work, "abc", Compression::None),
+            PathBuf::from("/var/runs/abc.jsonl")
+        );
+        assert_eq!(
+            LogTarget::Auto.resolve(runs, work, "abc", Compression::Gzip),
+            PathBuf::from("/var/runs/abc.jsonl.gz")
+        );
+        assert_eq!(
+            LogTarget::Path("out/run.jsonl".into()).resolve(runs, work, "abc", Compression::Gzip),
+            PathBuf::from("/work/out/run.jsonl")
+        );
+        assert_eq!(
+            LogTarget::Path("/tmp/run.jsonl".into()).resolve(runs, work, "abc", Compression::None),
+            PathBuf::from("/tmp/run.jsonl")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_log_writes_all_bytes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("nested").join("run.jsonl");
+
+        let mut log = RunLog::create(&path, None).await.unwrap();
+        log.write(b"{\"type\":\"a\"}\n").await;
+        log.write(b"{\"type\":\"b\"}\n").await;
+        log.finish().await;
+
+        assert!(log.error().is_none());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"type\":\"a\"}\n{\"type\":\"b\"}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_log_starts_with_call_meta() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("run.jsonl");
+
+        let mut log = RunLog::create(&path, None).await.unwrap();
+        log.write_meta(&serde_json::json!({"traceId": "t-1"})).await;
+        log.write(b"{\"type\":\"a\"}\n").await;
+        log.finish().await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(lines[0]).unwrap(),
+            serde_json::json!({"type": "codex_mcp.meta", "_meta": {"traceId": "t-1"}})
+        );
+        assert_eq!(lines[1..], ["{\"type\":\"a\"}"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_log_redacts_lines_split_across_writes() {
+        static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+        let redactor = REDACTOR.get_or_init(|| Redactor::new(&[]).0);
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("run.jsonl");
+
+        let mut log = RunLog::create(&path, None)
+            .await
+            .unwrap()
+            .redacted_with(Some(redactor));
+        log.write(b"{\"text\":\"key sk-abcdefghij").await;
+        log.write(b"klmnopqrstuvwxyz\"}\n{\"type\":\"b\"}").await;
+        log.finish().await;
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"text\":\"key [REDACTED:openai_key]\"}\n{\"type\":\"b\"}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gzip_run_log_round_trips() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("run.jsonl.gz");
+
+        let mut log = RunLog::create(&path, None).await.unwrap();
+        log.write(b"{\"type\":\"a\"}\n").await;
+        log.write(b"{\"type\":\"b\"}\n").await;
+
+        // Readable before the trailer is written
+        let partial: Vec<String> = open_reader(&path, None)
+            .unwrap()
+            .lines()
+            .map_while(Result::ok)
+            .collect();
+        assert_eq!(partial, vec!["{\"type\":\"a\"}", "{\"type\":\"b\"}"]);
+
+        log.finish().await;
+        assert!(log.error().is_none());
+        let mut text = String::new();
+        open_reader(&path, None)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "{\"type\":\"a\"}\n{\"type\":\"b\"}\n");
+    }
+
+    #[test]
+    fn test_sweep_enforces_size_budget_oldest_first() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        for name in ["old.jsonl", "mid.jsonl.gz", "new.jsonl"] {
+            std::fs::write(dir.join(name), vec![b'x'; 100]).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::fs::write(dir.join("keep.txt"), vec![b'x'; 1000]).unwrap();
+
+        let retention = RetentionConfig {
+            max_age_days: None,
+            max_total_bytes: Some(150),
+        };
+        let report = sweep(dir, &retention).unwrap();
+
+        assert_eq!(report.removed_files, 2);
+        assert_eq!(report.freed_bytes, 200);
+        assert!(dir.join("new.jsonl").exists());
+        assert!(dir.join("keep.txt").exists());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::at_rest;
+use crate::codex;
+use crate::history;
+use crate::run_diff;
+
+/// Scheme and prefix of run resource URIs: `codex-mcp://runs/<run_id>/<kind>`
+const URI_PREFIX: &str = "codex-mcp://runs/";
+
+/// Full output of a run that a `codex` result may link to instead of
+/// returning inline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunResource {
+    /// Agent messages, as kept in run history
+    Messages,
+    /// Diff stored on disk for `RETURN_DIFF`
+    Diff,
+}
+
+impl RunResource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RunResource::Messages => "messages",
+            RunResource::Diff => "diff",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            RunResource::Messages => "text/plain",
+            RunResource::Diff => "text/x-diff",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            RunResource::Messages => "Full agent messages of the run",
+            RunResource::Diff => "Unified diff of the files the run changed",
+        }
+    }
+
+    pub fn uri(self, run_id: &str) -> String {
+        format!("{}{}/{}", URI_PREFIX, run_id, self.as_str())
+    }
+}
+
+/// Split a run resource URI into run id and kind
+pub fn parse(uri: &str) -> Option<(&str, RunResource)> {
+    let (run_id, kind) = uri.strip_prefix(URI_PREFIX)?.split_once('/')?;
+    let kind = match kind {
+        "messages" => RunResource::Messages,
+        "diff" => RunResource::Diff,
+        _ => return None,
+    };
+    let valid = !run_id.is_empty()
+        && run_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid.then_some((run_id, kind))
+}
+
+/// Resources of the runs still in history, newest first
+pub fn list() -> Vec<(String, RunResource)> {
+    let mut resources = Vec::new();
+    for run in history::recent(usize::MAX) {
+        if run.source == "import" {
+            continue;
+        }
+        resources.push((run.run_id.clone(), RunResource::Messages));
+        if run_diff::find_stored(&codex::runs_dir(), &run.run_id).is_some() {
+            resources.push((run.run_id, RunResource::Diff));
+        }
+    }
+    resources
+}
+
+/// Content of a run resource
+pub fn read(uri: &str) -> Result<String, String> {
+    let (run_id, kind) = parse(uri).ok_or_else(|| format!("unknown resource: {}", uri))?;
+    match kind {
+        RunResource::Messages => history::get(run_id)
+            .map(|run| run.agent_messages)
+            .ok_or_else(|| format!("run {} is no longer in history", run_id)),
+        RunResource::Diff => {
+            let path = run_diff::find_stored(&codex::runs_dir(), run_id)
+                .unwrap_or_else(|| run_diff::stored_path(&codex::runs_dir(), run_id));
+            at_rest::read(&path, codex::at_rest_key().ok().flatten())
+                .map(|diff| String::from_utf8_lossy(&diff).into_owned())
+                .map_err(|e| format!("cannot read {}: {}", path.display(), e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_round_trip() {
+        let uri = RunResource::Diff.uri("0199-abc");
+        assert_eq!(uri, "codex-mcp://runs/0199-abc/diff");
+        assert_eq!(parse(&uri), Some(("0199-abc", RunResource::Diff)));
+        assert_eq!(
+            parse("codex-mcp://runs/r1/messages"),
+            Some(("r1", RunResource::Messages))
+        );
+        assert_eq!(parse("codex-mcp://runs/r1/log"), None);
+        assert_eq!(parse("codex-mcp://runs/../etc/diff"), None);
+        assert_eq!(parse("file:///r1/diff"), None);
+    }
+}
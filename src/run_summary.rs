@@ -0,0 +1,155 @@
+use crate::classify;
+use crate::codex::CodexResult;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+
+/// Characters of the error kept in a failed run's summary
+const MAX_ERROR_CHARS: usize = 80;
+
+/// Characters of the session id shown
+const SESSION_ID_PREFIX_CHARS: usize = 8;
+
+/// One-line status of a run for chat clients, e.g. `Edited 3 files, tests
+/// passing, session 0199a1b2… resumable`, built from the structured result
+/// rather than the agent's answer
+pub fn summarize(result: &CodexResult) -> String {
+    let mut parts = Vec::new();
+    let changed = changed_files(result);
+    if !result.success {
+        let error = result
+            .error
+            .as_deref()
+            .and_then(|error| error.lines().find(|line| !line.trim().is_empty()))
+            .unwrap_or("unknown error");
+        parts.push(format!(
+            "Failed: {}",
+            shorten(error.trim(), MAX_ERROR_CHARS)
+        ));
+    }
+    match changed {
+        0 if result.success => parts.push("Answered without changing files".to_string()),
+        0 => {}
+        1 => parts.push("edited 1 file".to_string()),
+        n => parts.push(format!("edited {} files", n)),
+    }
+    match last_test_outcome(&result.all_messages) {
+        Some(true) => parts.push("tests passing".to_string()),
+        Some(false) => parts.push("tests failing".to_string()),
+        None => {}
+    }
+    if !result.session_id.is_empty() {
+        parts.push(format!(
+            "session {} resumable",
+            shorten(&result.session_id, SESSION_ID_PREFIX_CHARS)
+        ));
+    }
+
+    let mut summary = parts.join(", ");
+    if let Some(first) = summary.chars().next() {
+        summary.replace_range(..first.len_utf8(), &first.to_uppercase().to_string());
+    }
+    summary
+}
+
+/// Files the run changed: from its diff when `RETURN_DIFF` was requested,
+/// otherwise from the `file_change` events
+fn changed_files(result: &CodexResult) -> usize {
+    if let Some(diff) = &result.diff {
+        return diff.files.len();
+    }
+    let mut paths = BTreeSet::new();
+    for item in completed_items(&result.all_messages) {
+        if item.get("type").and_then(Value::as_str) != Some("file_change") {
+            continue;
+        }
+        let changes = item.get("changes").and_then(Value::as_array);
+        for change in changes.into_iter().flatten() {
+            if let Some(path) = change.get("path").and_then(Value::as_str) {
+                paths.insert(path);
+            }
+        }
+    }
+    paths.len()
+}
+
+/// Whether the last test command the run finished exited successfully
+fn last_test_outcome(events: &[HashMap<String, Value>]) -> Option<bool> {
+    completed_items(events)
+        .filter(|item| item.get("type").and_then(Value::as_str) == Some("command_execution"))
+        .filter(|item| {
+            item.get("command")
+                .and_then(Value::as_str)
+                .is_some_and(classify::is_test_command)
+        })
+        .filter_map(|item| item.get("exit_code").and_then(Value::as_i64))
+        .last()
+        .map(|exit_code| exit_code == 0)
+}
+
+fn completed_items(events: &[HashMap<String, Value>]) -> impl Iterator<Item = &Value> {
+    events
+        .iter()
+        .filter(|event| event.get("type").and_then(Value::as_str) == Some("item.completed"))
+        .filter_map(|event| event.get("item"))
+}
+
+fn shorten(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(item: Value) -> HashMap<String, Value> {
+        HashMap::from([
+            ("type".to_string(), json!("item.completed")),
+            ("item".to_string(), item),
+        ])
+    }
+
+    #[test]
+    fn test_summarizes_changes_tests_and_session() {
+        let mut result = CodexResult {
+            success: true,
+            session_id: "0199a1b2-c3d4-7e5f-8a9b-0c1d2e3f4a5b".to_string(),
+            all_messages: vec![
+                event(json!({"type": "file_change", "changes": [
+                    {"path": "src/a.rs", "kind": "update"},
+                    {"path": "src/b.rs", "kind": "add"}
+                ]})),
+                event(
+                    json!({"type": "file_change", "changes": [{"path": "src/a.rs", "kind": "update"}]}),
+                ),
+                event(
+                    json!({"type": "command_execution", "command": "cargo test", "exit_code": 101}),
+                ),
+                event(
+                    json!({"type": "command_execution", "command": "cargo test", "exit_code": 0}),
+                ),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            summarize(&result),
+            "Edited 2 files, tests passing, session 0199a1b2… resumable"
+        );
+
+        result.success = false;
+        result.error = Some("\ncodex error: stream disconnected\nStderr: boom".to_string());
+        result.all_messages.clear();
+        result.session_id.clear();
+        assert_eq!(
+            summarize(&result),
+            "Failed: codex error: stream disconnected"
+        );
+
+        result.success = true;
+        result.error = None;
+        assert_eq!(summarize(&result), "Answered without changing files");
+    }
+}
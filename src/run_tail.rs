@@ -0,0 +1,263 @@
+use crate::parser;
+use rmcp::schemars;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Events buffered per run; older ones are dropped first
+const MAX_BUFFERED_EVENTS: usize = 500;
+
+/// Finished runs whose buffers are kept for a last poll
+const MAX_FINISHED_RUNS: usize = 20;
+
+/// Text kept per event
+const MAX_EVENT_TEXT_BYTES: usize = 4096;
+
+/// One event of a run's stream, as returned by `codex_tail`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct TailEvent {
+    /// Position in the run's stream; pass the next one as CURSOR to continue
+    pub cursor: u64,
+    /// Event type, with the item type for item events, e.g.
+    /// `item.completed:agent_message`
+    pub kind: String,
+    /// Agent or reasoning text, or the command being run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+#[derive(Debug)]
+struct Buffer {
+    working_dir: PathBuf,
+    started_at: u64,
+    events: VecDeque<TailEvent>,
+    next_cursor: u64,
+    finished: bool,
+}
+
+fn registry() -> &'static Mutex<HashMap<String, Buffer>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, Buffer>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Buffers a run's events while it is in progress; the run is marked
+/// finished when this is dropped
+#[derive(Debug)]
+pub struct Tail {
+    run_id: String,
+}
+
+/// Start buffering events of `run_id`
+pub fn start(run_id: &str, working_dir: &Path) -> Tail {
+    let started_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    if let Ok(mut runs) = registry().lock() {
+        runs.insert(
+            run_id.to_string(),
+            Buffer {
+                working_dir: working_dir.to_path_buf(),
+                started_at,
+                events: VecDeque::new(),
+                next_cursor: 0,
+                finished: false,
+            },
+        );
+    }
+    Tail {
+        run_id: run_id.to_string(),
+    }
+}
+
+impl Tail {
+    pub fn push(&self, event: &Value) {
+        let Ok(mut runs) = registry().lock() else {
+            return;
+        };
+        let Some(buffer) = runs.get_mut(&self.run_id) else {
+            return;
+        };
+        if buffer.events.len() >= MAX_BUFFERED_EVENTS {
+            buffer.events.pop_front();
+        }
+        let (kind, text) = describe(event);
+        buffer.events.push_back(TailEvent {
+            cursor: buffer.next_cursor,
+            kind,
+            text,
+        });
+        buffer.next_cursor += 1;
+    }
+}
+
+impl Drop for Tail {
+    fn drop(&mut self) {
+        let Ok(mut runs) = registry().lock() else {
+            return;
+        };
+        if let Some(buffer) = runs.get_mut(&self.run_id) {
+            buffer.finished = true;
+        }
+        // Keep only the most recently started finished runs
+        let mut finished: Vec<(u64, String)> = runs
+            .iter()
+            .filter(|(_, buffer)| buffer.finished)
+            .map(|(run_id, buffer)| (buffer.started_at, run_id.clone()))
+            .collect();
+        if finished.len() > MAX_FINISHED_RUNS {
+            finished.sort();
+            for (_, run_id) in &finished[..finished.len() - MAX_FINISHED_RUNS] {
+                runs.remove(run_id);
+            }
+        }
+    }
+}
+
+fn describe(event: &Value) -> (String, Option<String>) {
+    let event_type = event
+        .get("type")
+        .and_then(Value::as_str)
+        .unwrap_or("unknown");
+    let item = event.get("item");
+    let item_type = item
+        .and_then(|item| item.get("type"))
+        .and_then(Value::as_str);
+    let kind = match item_type {
+        Some(item_type) => format!("{}:{}", event_type, item_type),
+        None => event_type.to_string(),
+    };
+    let fields = parser::extract(event);
+    let text = fields
+        .agent_message
+        .map(|message| message.text)
+        .or_else(|| {
+            let item = item?;
+            item.get("text")
+                .or_else(|| item.get("command"))
+                .and_then(Value::as_str)
+        })
+        .or(fields.error_message)
+        .map(|text| {
+            let mut end = text.len().min(MAX_EVENT_TEXT_BYTES);
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text[..end].to_string()
+        });
+    (kind, text)
+}
+
+/// Events of a run from a cursor on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct TailPage {
+    pub run_id: String,
+    /// The run ended; no more events will follow `next_cursor`
+    pub finished: bool,
+    pub events: Vec<TailEvent>,
+    /// CURSOR for the next poll
+    pub next_cursor: u64,
+    /// Events after the requested cursor that were dropped from the buffer
+    /// before this poll
+    #[serde(skip_serializing_if = "is_zero")]
+    pub missed: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// Up to `max_events` events of `run_id` at or after `cursor`; `None` for
+/// runs that are unknown or no longer buffered
+pub fn read(run_id: &str, cursor: u64, max_events: usize) -> Option<TailPage> {
+    let runs = registry().lock().ok()?;
+    let buffer = runs.get(run_id)?;
+    let oldest = buffer
+        .events
+        .front()
+        .map_or(buffer.next_cursor, |e| e.cursor);
+    let events: Vec<TailEvent> = buffer
+        .events
+        .iter()
+        .filter(|event| event.cursor >= cursor)
+        .take(max_events)
+        .cloned()
+        .collect();
+    let next_cursor = events.last().map_or(buffer.next_cursor, |e| e.cursor + 1);
+    Some(TailPage {
+        run_id: run_id.to_string(),
+        finished: buffer.finished && next_cursor >= buffer.next_cursor,
+        events,
+        next_cursor,
+        missed: oldest.saturating_sub(cursor),
+    })
+}
+
+/// A run whose events can be tailed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct TailedRun {
+    pub run_id: String,
+    pub working_dir: PathBuf,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub finished: bool,
+    pub events: u64,
+}
+
+/// Buffered runs, newest first
+pub fn runs() -> Vec<TailedRun> {
+    let Ok(runs) = registry().lock() else {
+        return Vec::new();
+    };
+    let mut listed: Vec<TailedRun> = runs
+        .iter()
+        .map(|(run_id, buffer)| TailedRun {
+            run_id: run_id.clone(),
+            working_dir: buffer.working_dir.clone(),
+            started_at: buffer.started_at,
+            finished: buffer.finished,
+            events: buffer.next_cursor,
+        })
+        .collect();
+    listed.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+    listed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tail_pages_through_events() {
+        let tail = start("tail-test-run", Path::new("/tmp/repo"));
+        tail.push(&json!({"type": "thread.started", "thread_id": "t1"}));
+        tail.push(&json!({"type": "item.completed", "item": {"id": "i0", "type": "reasoning", "text": "Looking around"}}));
+        tail.push(&json!({"type": "item.completed", "item": {"id": "i1", "type": "agent_message", "text": "Done"}}));
+
+        let first = read("tail-test-run", 0, 2).unwrap();
+        assert_eq!(first.events.len(), 2);
+        assert_eq!(first.events[0].kind, "thread.started");
+        assert_eq!(first.events[1].kind, "item.completed:reasoning");
+        assert_eq!(first.events[1].text.as_deref(), Some("Looking around"));
+        assert!(!first.finished);
+
+        let second = read("tail-test-run", first.next_cursor, 10).unwrap();
+        assert_eq!(second.events.len(), 1);
+        assert_eq!(second.events[0].text.as_deref(), Some("Done"));
+        assert_eq!(second.next_cursor, 3);
+
+        // Polling at the end returns nothing new until the run finishes
+        let idle = read("tail-test-run", 3, 10).unwrap();
+        assert!(idle.events.is_empty());
+        assert_eq!(idle.next_cursor, 3);
+        assert!(!idle.finished);
+        assert!(runs().iter().any(|run| run.run_id == "tail-test-run"));
+
+        drop(tail);
+        assert!(read("tail-test-run", 3, 10).unwrap().finished);
+        assert!(!read("tail-test-run", 0, 1).unwrap().finished);
+        assert_eq!(read("no-such-run", 0, 10), None);
+    }
+}
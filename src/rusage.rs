@@ -0,0 +1,84 @@
+use rmcp::schemars;
+use serde::Serialize;
+
+/// CPU time and peak memory of a finished Codex process, including the
+/// commands it ran and waited for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct ResourceUsage {
+    pub user_cpu_ms: u64,
+    pub system_cpu_ms: u64,
+    /// Peak resident set size of the largest process, in KiB
+    pub max_rss_kb: u64,
+}
+
+/// Block until process `pid` exits and return its resource usage. The process
+/// is left unreaped, so the caller's own `wait()` still gets its exit status.
+#[cfg(target_os = "linux")]
+pub fn wait_exited(pid: u32) -> Option<ResourceUsage> {
+    let pid = libc::id_t::try_from(pid).ok()?;
+    // glibc's waitid() has no rusage argument, but the syscall does, and with
+    // WNOWAIT the kernel reports the zombie's usage without reaping it
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        let rc = unsafe {
+            libc::syscall(
+                libc::SYS_waitid,
+                libc::P_PID,
+                pid,
+                &mut info as *mut libc::siginfo_t,
+                libc::WEXITED | libc::WNOWAIT,
+                &mut usage as *mut libc::rusage,
+            )
+        };
+        if rc == 0 {
+            break;
+        }
+        if std::io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+            return None;
+        }
+    }
+
+    Some(ResourceUsage::from_rusage(&usage))
+}
+
+#[cfg(target_os = "linux")]
+impl ResourceUsage {
+    pub(crate) fn from_rusage(usage: &libc::rusage) -> Self {
+        Self {
+            user_cpu_ms: timeval_ms(usage.ru_utime),
+            system_cpu_ms: timeval_ms(usage.ru_stime),
+            max_rss_kb: u64::try_from(usage.ru_maxrss).unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn wait_exited(_pid: u32) -> Option<ResourceUsage> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn timeval_ms(tv: libc::timeval) -> u64 {
+    let secs = u64::try_from(tv.tv_sec).unwrap_or(0);
+    let micros = u64::try_from(tv.tv_usec).unwrap_or(0);
+    secs * 1000 + micros / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_wait_exited_leaves_child_for_caller() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "i=0; while [ $i -lt 20000 ]; do i=$((i+1)); done"])
+            .spawn()
+            .unwrap();
+
+        let usage = super::wait_exited(child.id()).unwrap();
+        assert!(usage.max_rss_kb > 0);
+
+        // Still reapable with its real exit status
+        assert!(child.wait().unwrap().success());
+    }
+}
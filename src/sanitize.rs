@@ -0,0 +1,99 @@
+use std::borrow::Cow;
+
+/// Agent message text without terminal control output. Some CLI builds leak
+/// ANSI colour codes and spinner frames into agent messages; this removes
+/// escape sequences, keeps only what a terminal would show after carriage
+/// returns, drops lines holding nothing but spinner frames, and collapses
+/// runs of blank lines into one.
+pub fn agent_text(text: &str) -> Cow<'_, str> {
+    if !text.contains(['\x1b', '\r']) && !text.chars().any(is_spinner_frame) {
+        return Cow::Borrowed(text);
+    }
+
+    let stripped = strip_escapes(text);
+    let mut out = String::with_capacity(stripped.len());
+    let mut blank_run = false;
+    for (index, line) in stripped.split('\n').enumerate() {
+        // A carriage return rewinds the line; the last update is what shows.
+        // A trailing one (CRLF) ends the line instead.
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let line = line.rsplit('\r').next().unwrap_or(line);
+        let blank = line
+            .chars()
+            .all(|c| c.is_whitespace() || is_spinner_frame(c));
+        if blank && blank_run {
+            continue;
+        }
+        blank_run = blank;
+        if index > 0 {
+            out.push('\n');
+        }
+        if !blank {
+            out.push_str(line);
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Braille patterns, which CLI spinners cycle through
+fn is_spinner_frame(c: char) -> bool {
+    ('\u{2800}'..='\u{28ff}').contains(&c)
+}
+
+/// Remove CSI (`ESC [ .. final`), OSC (`ESC ] .. BEL` or `ESC ] .. ESC \`),
+/// and two-byte escape sequences
+fn strip_escapes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                // Parameter and intermediate bytes, then one final byte
+                for c in chars.by_ref() {
+                    if ('\u{40}'..='\u{7e}').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strips_escapes_and_spinner_updates() {
+        assert!(matches!(
+            agent_text("plain **markdown**\n\n- item"),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            agent_text("\x1b[1;32mDone\x1b[0m: fixed \x1b]8;;https://x.test\x07link\x1b]8;;\x1b\\"),
+            "Done: fixed link"
+        );
+        assert_eq!(
+            agent_text("⠋ Working\r⠙ Working\r\x1b[2KFinished\r\n\n\n⠹\n\nNext"),
+            "Finished\n\nNext"
+        );
+        assert_eq!(agent_text("a\r\nb\r\n"), "a\nb\n");
+    }
+}
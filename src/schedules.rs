@@ -0,0 +1,492 @@
+use crate::codex::{self, Options, SandboxMode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// `next_run` is searched this far ahead (one leap year of minutes)
+const MAX_LOOKAHEAD_MINUTES: u64 = 366 * 24 * 60;
+
+/// One entry of `schedules` in `codex-mcp.config.json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScheduleConfig {
+    pub name: String,
+    /// Five-field cron expression (minute hour day-of-month month day-of-week),
+    /// evaluated in UTC, or one of `@hourly`, `@daily`, `@weekly`, `@monthly`, `@yearly`
+    pub cron: String,
+    /// Prompt sent to Codex; `{{name}}`, `{{date}}`, and `{{datetime}}` are expanded
+    pub prompt: String,
+    pub working_dir: PathBuf,
+    /// `read-only`, `workspace-write`, or `danger-full-access`; defaults to the
+    /// sandbox implied by the server's `additional_args`
+    #[serde(default)]
+    pub sandbox: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Parsed cron expression; each field is a bitmask of allowed values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+impl CronExpr {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expanded = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "cron expression must have 5 fields, got {}: {}",
+                fields.len(),
+                expr
+            ));
+        }
+
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        // Both 0 and 7 mean Sunday
+        if days_of_week & (1u64 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1u64 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            dom_restricted: fields[2] != "*",
+            dow_restricted: fields[4] != "*",
+        })
+    }
+
+    /// Whether the minute starting at `unix_secs` matches
+    pub fn matches(&self, unix_secs: u64) -> bool {
+        let t = UtcTime::from_unix(unix_secs);
+        let bit = |mask: u64, value: u32| mask & (1u64 << value) != 0;
+        if !bit(self.minutes, t.minute) || !bit(self.hours, t.hour) || !bit(self.months, t.month) {
+            return false;
+        }
+        // Standard cron: if both day fields are restricted, either may match
+        let dom = bit(self.days_of_month, t.day);
+        let dow = bit(self.days_of_week, t.weekday);
+        match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        }
+    }
+
+    /// Start of the first matching minute strictly after `unix_secs`
+    pub fn next_after(&self, unix_secs: u64) -> Option<u64> {
+        let first = (unix_secs / 60 + 1) * 60;
+        (0..MAX_LOOKAHEAD_MINUTES)
+            .map(|i| first + i * 60)
+            .find(|t| self.matches(*t))
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format!("invalid cron step: {}", part))?;
+                if step == 0 {
+                    return Err(format!("invalid cron step: {}", part));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (parse_value(a, min, max)?, parse_value(b, min, max)?)
+        } else {
+            let value = parse_value(range, min, max)?;
+            // `5/15` means every 15 starting at 5
+            (value, if step > 1 { max } else { value })
+        };
+        if start > end {
+            return Err(format!("invalid cron range: {}", part));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1u64 << value;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_value(value: &str, min: u32, max: u32) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(v) if (min..=max).contains(&v) => Ok(v),
+        _ => Err(format!("cron value {} is outside {}-{}", value, min, max)),
+    }
+}
+
+/// Calendar fields of a UTC timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UtcTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    /// 0 = Sunday
+    weekday: u32,
+}
+
+impl UtcTime {
+    pub(crate) fn from_unix(secs: u64) -> Self {
+        let days = (secs / 86_400) as i64;
+        let rem = secs % 86_400;
+        // Civil-from-days (H. Hinnant), valid for the proleptic Gregorian calendar
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        Self {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u32,
+            minute: (rem % 3600 / 60) as u32,
+            second: (rem % 60) as u32,
+            // 1970-01-01 was a Thursday
+            weekday: (days + 4).rem_euclid(7) as u32,
+        }
+    }
+
+    fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    fn datetime(&self) -> String {
+        format!(
+            "{}T{:02}:{:02}:{:02}Z",
+            self.date(),
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+
+    /// e.g. `Friday, 2024-03-15 09:30 UTC`
+    pub(crate) fn human(&self) -> String {
+        const WEEKDAYS: [&str; 7] = [
+            "Sunday",
+            "Monday",
+            "Tuesday",
+            "Wednesday",
+            "Thursday",
+            "Friday",
+            "Saturday",
+        ];
+        format!(
+            "{}, {} {:02}:{:02} UTC",
+            WEEKDAYS[self.weekday as usize],
+            self.date(),
+            self.hour,
+            self.minute
+        )
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Expand `{{name}}`, `{{date}}`, and `{{datetime}}` in a schedule prompt
+fn render_prompt(schedule: &ScheduleConfig, unix_secs: u64) -> String {
+    let t = UtcTime::from_unix(unix_secs);
+    schedule
+        .prompt
+        .replace("{{name}}", &schedule.name)
+        .replace("{{datetime}}", &t.datetime())
+        .replace("{{date}}", &t.date())
+}
+
+/// Runtime state of a schedule; `enabled` starts from the config and can be
+/// toggled with `codex_schedules`
+#[derive(Debug, Clone, Default)]
+struct ScheduleState {
+    enabled: bool,
+    running: bool,
+    last_run_id: Option<String>,
+    last_started_at: Option<u64>,
+    last_success: Option<bool>,
+}
+
+fn states() -> &'static Mutex<HashMap<String, ScheduleState>> {
+    static STATES: OnceLock<Mutex<HashMap<String, ScheduleState>>> = OnceLock::new();
+    STATES.get_or_init(|| {
+        Mutex::new(
+            codex::schedules()
+                .iter()
+                .map(|s| {
+                    let state = ScheduleState {
+                        enabled: s.enabled,
+                        ..Default::default()
+                    };
+                    (s.name.clone(), state)
+                })
+                .collect(),
+        )
+    })
+}
+
+/// Schedule as reported by `codex_schedules`
+#[derive(Debug, Serialize)]
+pub struct ScheduleStatus {
+    pub name: String,
+    pub cron: String,
+    pub working_dir: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<String>,
+    pub enabled: bool,
+    pub running: bool,
+    /// Next run time (UTC), absent when disabled or the expression is invalid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_run_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_started_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Status of every configured schedule
+pub fn list() -> Vec<ScheduleStatus> {
+    let now = now_secs();
+    let states = states().lock().map(|s| s.clone()).unwrap_or_default();
+    codex::schedules()
+        .iter()
+        .map(|schedule| {
+            let state = states.get(&schedule.name).cloned().unwrap_or_default();
+            let cron = CronExpr::parse(&schedule.cron);
+            let next_run = match &cron {
+                Ok(cron) if state.enabled => cron
+                    .next_after(now)
+                    .map(|t| UtcTime::from_unix(t).datetime()),
+                _ => None,
+            };
+            ScheduleStatus {
+                name: schedule.name.clone(),
+                cron: schedule.cron.clone(),
+                working_dir: schedule.working_dir.clone(),
+                sandbox: schedule.sandbox.clone(),
+                enabled: state.enabled,
+                running: state.running,
+                next_run,
+                last_run_id: state.last_run_id,
+                last_started_at: state
+                    .last_started_at
+                    .map(|t| UtcTime::from_unix(t).datetime()),
+                last_success: state.last_success,
+                error: validate(schedule).err(),
+            }
+        })
+        .collect()
+}
+
+/// Enable or disable a schedule until the server restarts
+pub fn set_enabled(name: &str, enabled: bool) -> Result<(), String> {
+    let mut states = states()
+        .lock()
+        .map_err(|_| "schedule state is unavailable".to_string())?;
+    match states.get_mut(name) {
+        Some(state) => {
+            state.enabled = enabled;
+            Ok(())
+        }
+        None => Err(format!("unknown schedule: {}", name)),
+    }
+}
+
+fn validate(schedule: &ScheduleConfig) -> Result<CronExpr, String> {
+    if let Some(sandbox) = &schedule.sandbox {
+        if SandboxMode::parse(sandbox).is_none() {
+            return Err(format!("invalid sandbox: {}", sandbox));
+        }
+    }
+    CronExpr::parse(&schedule.cron)
+}
+
+/// Start the scheduler if any schedules are configured. Invalid schedules are
+/// reported on stderr and skipped.
+pub fn start() -> Option<tokio::task::JoinHandle<()>> {
+    let mut valid = Vec::new();
+    for schedule in codex::schedules() {
+        match validate(schedule) {
+            Ok(cron) => valid.push((schedule.clone(), cron)),
+            Err(e) => eprintln!("Warning: schedule {} is disabled: {}", schedule.name, e),
+        }
+    }
+    if valid.is_empty() {
+        return None;
+    }
+
+    Some(tokio::spawn(async move {
+        let mut last_minute = now_secs() / 60;
+        loop {
+            let now = now_secs();
+            let wait = (last_minute + 1) * 60 - now.min((last_minute + 1) * 60);
+            tokio::time::sleep(Duration::from_secs(wait.max(1))).await;
+
+            let minute = now_secs() / 60;
+            if minute <= last_minute {
+                continue;
+            }
+            last_minute = minute;
+
+            for (schedule, cron) in &valid {
+                if cron.matches(minute * 60) {
+                    launch(schedule.clone(), minute * 60);
+                }
+            }
+        }
+    }))
+}
+
+/// Start a run for `schedule` unless it is disabled or its previous run is still going
+fn launch(schedule: ScheduleConfig, fired_at: u64) {
+    {
+        let Ok(mut states) = states().lock() else {
+            return;
+        };
+        let state = states.entry(schedule.name.clone()).or_default();
+        if !state.enabled || state.running {
+            return;
+        }
+        state.running = true;
+        state.last_started_at = Some(fired_at);
+    }
+
+    tokio::spawn(async move {
+        let mut additional_args = codex::default_additional_args();
+        if let Some(mode) = schedule.sandbox.as_deref().and_then(SandboxMode::parse) {
+            additional_args = codex::with_sandbox(&additional_args, mode);
+        }
+        let opts = Options {
+            prompt: render_prompt(&schedule, fired_at),
+            working_dir: schedule.working_dir.clone(),
+            additional_args,
+            source: Some(format!("schedule:{}", schedule.name)),
+            ..Default::default()
+        };
+
+        let outcome = codex::run(opts).await;
+        if let Err(e) = &outcome {
+            eprintln!("Warning: scheduled run {} failed: {}", schedule.name, e);
+        }
+
+        if let Ok(mut states) = states().lock() {
+            let state = states.entry(schedule.name.clone()).or_default();
+            state.running = false;
+            match outcome {
+                Ok(result) => {
+                    state.last_run_id = Some(result.run_id);
+                    state.last_success = Some(result.success);
+                }
+                Err(_) => state.last_success = Some(false),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-03-15T09:30:00Z, a Friday
+    const FRIDAY_0930: u64 = 1_710_495_000;
+
+    #[test]
+    fn test_utc_time_from_unix() {
+        let t = UtcTime::from_unix(FRIDAY_0930);
+        assert_eq!(t.datetime(), "2024-03-15T09:30:00Z");
+        assert_eq!(t.weekday, 5);
+        assert_eq!(t.human(), "Friday, 2024-03-15 09:30 UTC");
+        assert_eq!(UtcTime::from_unix(0).datetime(), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_cron_matches() {
+        assert!(CronExpr::parse("30 9 * * *").unwrap().matches(FRIDAY_0930));
+        assert!(CronExpr::parse("*/15 8-10 * * 1-5")
+            .unwrap()
+            .matches(FRIDAY_0930));
+        assert!(!CronExpr::parse("30 9 * * 0,6")
+            .unwrap()
+            .matches(FRIDAY_0930));
+        // Day-of-month OR day-of-week when both are restricted
+        assert!(CronExpr::parse("30 9 1 * 5").unwrap().matches(FRIDAY_0930));
+        assert!(CronExpr::parse("30 9 * * 7")
+            .unwrap()
+            .matches(FRIDAY_0930 + 2 * 86_400));
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        let daily = CronExpr::parse("@daily").unwrap();
+        let next = daily.next_after(FRIDAY_0930).unwrap();
+        assert_eq!(UtcTime::from_unix(next).datetime(), "2024-03-16T00:00:00Z");
+    }
+
+    #[test]
+    fn test_cron_rejects_invalid_expressions() {
+        assert!(CronExpr::parse("* * * *").is_err());
+        assert!(CronExpr::parse("60 * * * *").is_err());
+        assert!(CronExpr::parse("*/0 * * * *").is_err());
+        assert!(CronExpr::parse("5-1 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_render_prompt() {
+        let schedule = ScheduleConfig {
+            name: "nightly".to_string(),
+            cron: "@daily".to_string(),
+            prompt: "{{name}} run for {{date}} at {{datetime}}".to_string(),
+            working_dir: PathBuf::from("/tmp"),
+            sandbox: None,
+            enabled: true,
+        };
+        assert_eq!(
+            render_prompt(&schedule, FRIDAY_0930),
+            "nightly run for 2024-03-15 at 2024-03-15T09:30:00Z"
+        );
+    }
+}
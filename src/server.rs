@@ -1,15 +1,83 @@
-use crate::codex::{self, Options};
+use crate::cleanup;
+use crate::cli_version::UnsupportedCliVersion;
+use crate::client_sampling::{self, FailureKind, Insights};
+use crate::cloud;
+use crate::codex::{self, Options, SandboxDowngrade, SandboxMode, WarningLevel};
+use crate::consensus;
+use crate::disk_guard::LowDiskSpace;
+use crate::explore::{self, Exploration};
+use crate::failure_screenshot;
+use crate::final_schema;
+use crate::fs_watch::FsChanges;
+use crate::health;
+use crate::history::{self, FailureGroup, RunStats};
+use crate::idempotency::{self, Claim};
+use crate::identities::{self, IdentityUsage};
+use crate::image_dir::{self, ImageDirListing};
+use crate::lossy_utf8::LossyUtf8;
+use crate::model_fallback::ModelFallback;
+use crate::patch_preview;
+use crate::path_denylist;
+use crate::pdf_pages::PdfPages;
+use crate::pipeline::{self, PipelineStep};
+use crate::plan::PlanStep;
+use crate::preflight;
+use crate::progress::{ProgressSender, ProgressUpdate};
+use crate::prompt_budget::{BudgetTrim, PromptTooLarge};
+use crate::raw_command;
+use crate::redact::PatternMatches;
+use crate::repo_map;
+use crate::review_export::{self, Finding, PullRequest};
+use crate::run_diff::{self, RunDiff};
+use crate::run_log::LogTarget;
+use crate::run_resources::{self, RunResource};
+use crate::run_summary;
+use crate::run_tail;
+use crate::rusage::ResourceUsage;
+use crate::schedules;
+use crate::session_expiry::SessionExpired;
+use crate::session_import;
+use crate::termination::Termination;
+use crate::tool_aliases::{self, AliasArgs, ToolAlias};
+use crate::tool_toggle;
+use crate::trust::TrustedWorkspace;
+use crate::warm_up::{self, WarmUpReport};
+use crate::workspace_lock::WorkspaceBusy;
 use rmcp::{
-    handler::server::{router::tool::ToolRouter, wrapper::Parameters},
+    handler::server::{
+        router::tool::{ToolRoute, ToolRouter},
+        tool::ToolCallContext,
+        wrapper::Parameters,
+    },
     model::*,
-    schemars, tool, tool_handler, tool_router, ErrorData as McpError, ServerHandler,
+    schemars,
+    service::{NotificationContext, RequestContext},
+    tool, tool_router, ErrorData as McpError, RoleServer, ServerHandler,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+/// Runs covered by `codex_status` when no window is given
+const DEFAULT_STATUS_WINDOW: usize = 50;
+
+/// Failures listed by `codex_top_failures` when no limit is given
+const DEFAULT_TOP_FAILURES: usize = 10;
+
+/// Window `codex_top_failures` counts over when none is given: one day
+const DEFAULT_TOP_FAILURES_SECS: u64 = 24 * 60 * 60;
+
+/// Longest accepted `TASK_ID`
+const MAX_TASK_ID_LEN: usize = 128;
+
+/// Most `PRIMER_MESSAGES` accepted per call
+const MAX_PRIMER_MESSAGES: usize = 20;
+
+/// A tool call's `_meta` larger than this, serialized, is not kept with the run
+const MAX_META_BYTES: usize = 8 * 1024;
+
 mod serialize_as_os_string_vec {
     use serde::{Deserialize, Deserializer, Serializer};
     use std::path::PathBuf;
@@ -39,34 +107,416 @@ mod serialize_as_os_string_vec {
     }
 }
 
+/// Output mode for the codex tool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RunMode {
+    #[default]
+    Json,
+    Plain,
+}
+
 /// Input parameters for codex tool
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct CodexArgs {
-    /// Instruction for task to send to codex
+    /// Instruction for task to send to codex. `{{run:<run_id>.agent_messages}}`
+    /// is replaced with the output of an earlier run of this server.
     #[serde(rename = "PROMPT")]
     pub prompt: String,
-    /// Attach one or more image files to the initial prompt.
+    /// Role or context messages placed before PROMPT, each in its own
+    /// delimited block, when starting a new session. Cannot be combined with
+    /// SESSION_ID or RESUME_LAST.
+    #[serde(rename = "PRIMER_MESSAGES", default)]
+    pub primer_messages: Vec<String>,
+    /// Repository to run Codex in, so one server can serve several projects.
+    /// Relative paths resolve against the server's current working directory,
+    /// which is also the default.
+    #[serde(rename = "WORKING_DIR", default)]
+    pub working_dir: Option<PathBuf>,
+    /// Attach one or more image files to the initial prompt. PDFs are attached
+    /// as images of their first pages, up to `pdf_max_pages`.
     #[serde(
         serialize_with = "serialize_as_os_string_vec::serialize",
         deserialize_with = "serialize_as_os_string_vec::deserialize",
         default
     )]
     pub images: Vec<PathBuf>,
+    /// Attach every supported image (and PDF) directly inside this directory,
+    /// in file name order, up to 32 files and 64 MiB. Relative paths resolve
+    /// against the working directory. The files included and skipped are
+    /// returned as `image_dir`.
+    #[serde(rename = "IMAGE_DIR", default)]
+    pub image_dir: Option<PathBuf>,
     /// Resume a previously started Codex session. Must be the exact `SESSION_ID`
     /// string returned by an earlier `codex` tool call (typically a UUID). If
     /// omitted, a new session is created. Do not pass custom labels here, and
     /// never send an empty string value: when starting a new session, omit the
-    /// `SESSION_ID` field entirely instead of passing `""`.
+    /// `SESSION_ID` field entirely instead of passing `""`. The special value
+    /// `"last"` resumes the most recent session, like `RESUME_LAST`.
+    #[serde(rename = "SESSION_ID", default)]
+    pub session_id: Option<String>,
+    /// Resume the most recent Codex session (`codex exec resume --last`). The
+    /// resumed `SESSION_ID` is returned as usual. Cannot be combined with an
+    /// explicit `SESSION_ID`.
+    #[serde(rename = "RESUME_LAST", default)]
+    pub resume_last: bool,
+    /// Tee the raw JSONL stream from Codex to disk as it arrives, independent of
+    /// in-memory limits. `true` writes to `<runs_dir>/<run_id>.jsonl`; a string is
+    /// used as the file path (relative paths resolve against the working directory).
+    /// The path is returned as `log_file`.
+    #[serde(rename = "LOG_TO_FILE", default)]
+    pub log_to_file: Option<LogToFileArg>,
+    /// Restrict which files the run may change: globs relative to the working
+    /// directory (`*`, `?`, `**`; a plain directory name covers everything below
+    /// it). Changes outside the scope are reported, reverted, or fail the run
+    /// depending on server configuration. Requires a git repository.
+    #[serde(rename = "ALLOWED_PATHS", default)]
+    pub allowed_paths: Vec<String>,
+    /// Return the changes the run made: per-file line counts and the unified
+    /// diff against HEAD. Diffs larger than the server's
+    /// `max_inline_diff_bytes` are stored on disk and returned as `diff_file`
+    /// with the stats only. Requires a git repository.
+    #[serde(rename = "RETURN_DIFF", default)]
+    pub return_diff: bool,
+    /// Codex config overrides, e.g. `{"model_reasoning_effort": "high"}`, passed
+    /// as repeated `-c key=value` flags. Keys must be on the server's allowlist
+    /// (see `codex_config`); values may be strings, numbers, booleans, or arrays.
+    #[serde(rename = "CONFIG_OVERRIDES", default)]
+    pub config_overrides: BTreeMap<String, Value>,
+    /// Sampling temperature (0-2) for more reproducible runs. Only available
+    /// when the server maps it to a Codex config key (`sampling_config_keys`).
+    #[serde(rename = "TEMPERATURE", default)]
+    pub temperature: Option<f64>,
+    /// Sampling seed, mapped like `TEMPERATURE`
+    #[serde(rename = "SEED", default)]
+    pub seed: Option<u64>,
+    /// `json` (default) streams Codex events and returns the full result;
+    /// `plain` runs a read-only question in plain-text mode and returns only the
+    /// answer text, with no SESSION_ID. Plain mode cannot be combined with
+    /// SESSION_ID, RESUME_LAST, LOG_TO_FILE, ALLOWED_PATHS, or RETURN_DIFF.
+    #[serde(rename = "MODE", default)]
+    pub mode: RunMode,
+    /// Submit the task to this Codex Cloud environment (`codex cloud exec
+    /// --env`) instead of running it locally. Returns the task id and URL;
+    /// poll with `codex_cloud_status`. Requires a Codex CLI with cloud support.
+    #[serde(rename = "CLOUD_ENV", default)]
+    pub cloud_env: Option<String>,
+    /// Attempts (1-4) Codex Cloud runs for a `CLOUD_ENV` task
+    #[serde(rename = "CLOUD_ATTEMPTS", default)]
+    pub cloud_attempts: Option<u8>,
+    /// Caller-chosen id grouping related runs (plan, edit, test, review) into
+    /// one logical task; see `codex_task_summary`
+    #[serde(rename = "TASK_ID", default)]
+    pub task_id: Option<String>,
+    /// Prepend the `codex_repo_map` overview of the repository to PROMPT, so
+    /// Codex starts with a picture of the layout
+    #[serde(rename = "ATTACH_REPO_MAP", default)]
+    pub attach_repo_map: bool,
+    /// Caller-chosen key making retries safe: if a successful run with the
+    /// same key and working directory finished within `idempotency_ttl_secs`,
+    /// its result is returned instead of starting another run
+    #[serde(rename = "IDEMPOTENCY_KEY", default)]
+    pub idempotency_key: Option<String>,
+    /// Entry of the server's `identities` (a Codex account with its own
+    /// CODEX_HOME and daily token budget) to run as; defaults to the server's
+    /// own CODEX_HOME
+    #[serde(rename = "IDENTITY", default)]
+    pub identity: Option<String>,
+    /// Inline JSON Schema the final answer must match (`codex exec
+    /// --output-schema`). The answer is validated and returned parsed as
+    /// `final`; a mismatch fails the run.
+    #[serde(rename = "FINAL_SCHEMA", default)]
+    pub final_schema: Option<Value>,
+    /// Language the final answer must be in, as an ISO 639-1 code (`de`,
+    /// `ja`) or English name. Overrides the server's `response_language`; an
+    /// answer detected in another language is asked for again once.
+    #[serde(rename = "RESPONSE_LANGUAGE", default)]
+    pub response_language: Option<String>,
+    /// Start with a short, time-boxed read-only run that returns a plan and
+    /// impact analysis as `exploration`, then resume the same session with
+    /// write access to carry it out. The server may ask the user to approve
+    /// the plan first. Only for new sessions.
+    #[serde(rename = "EXPLORE_FIRST", default)]
+    pub explore_first: bool,
+    /// Arguments not listed above; rejected when `strict_arguments` is enabled
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub unknown_fields: BTreeMap<String, Value>,
+    /// Set by `tool_aliases` tools; callers cannot pass it
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub preset: Option<ToolAlias>,
+}
+
+/// `LOG_TO_FILE` accepts either a flag or an explicit path
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+pub enum LogToFileArg {
+    Enabled(bool),
+    Path(PathBuf),
+}
+
+impl LogToFileArg {
+    fn into_target(self) -> Option<LogTarget> {
+        match self {
+            LogToFileArg::Enabled(true) => Some(LogTarget::Auto),
+            LogToFileArg::Enabled(false) => None,
+            LogToFileArg::Path(path) if path.as_os_str().is_empty() => None,
+            LogToFileArg::Path(path) => Some(LogTarget::Path(path)),
+        }
+    }
+}
+
+/// Input parameters for codex_consensus tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ConsensusArgs {
+    /// Question to send to every profile. Runs are always read-only.
+    #[serde(rename = "PROMPT")]
+    pub prompt: String,
+    /// Names of configured profiles to query. Defaults to all of them.
+    #[serde(default)]
+    pub profiles: Vec<String>,
+    /// Repository to ask about. Relative paths resolve against the server's
+    /// current working directory, which is also the default.
+    #[serde(rename = "WORKING_DIR", default)]
+    pub working_dir: Option<PathBuf>,
+}
+
+/// Input parameters for codex_pipeline tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct PipelineArgs {
+    /// Steps to run in order in one Codex session, e.g. plan, implement, test,
+    /// summarize. The pipeline stops at the first failed step.
+    pub steps: Vec<PipelineStep>,
+    /// Repository to run the steps in. Relative paths resolve against the
+    /// server's current working directory, which is also the default.
+    #[serde(rename = "WORKING_DIR", default)]
+    pub working_dir: Option<PathBuf>,
+}
+
+/// Action for codex_schedules tool
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleAction {
+    #[default]
+    List,
+    Enable,
+    Disable,
+}
+
+/// Input parameters for codex_schedules tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SchedulesArgs {
+    /// `list` (default), `enable`, or `disable`
+    #[serde(default)]
+    pub action: ScheduleAction,
+    /// Schedule to enable or disable
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Input parameters for codex_status tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct StatusArgs {
+    /// Number of most recent runs to compute statistics over (default 50).
+    #[serde(default)]
+    pub window: Option<usize>,
+}
+
+/// Input parameters for codex_top_failures tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct TopFailuresArgs {
+    /// Failure groups to list (default 10).
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Count failures from the last this many seconds (default 86400, one day).
+    #[serde(default)]
+    pub since_secs: Option<u64>,
+}
+
+/// Output of codex_top_failures
+#[derive(Debug, Serialize)]
+struct TopFailuresOutput {
+    /// Start of the counted window, in seconds since the Unix epoch
+    since: u64,
+    failures: Vec<FailureGroup>,
+}
+
+/// Output of codex_status
+#[derive(Debug, Serialize)]
+struct StatusOutput {
+    #[serde(flatten)]
+    stats: RunStats,
+    /// Startup check of the Codex CLI, when `warm_up` is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warm_up: Option<WarmUpReport>,
+    /// Today's token use per configured identity
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    identities: Vec<IdentityUsage>,
+}
+
+/// Input parameters for codex_raw tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RawArgs {
+    /// One of `--version`, `login status`, `features list`
+    pub command: String,
+}
+
+/// Input parameters for codex_redact_test tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RedactTestArgs {
+    /// Sample text to run through the redaction patterns
+    pub text: String,
+}
+
+/// Output of codex_redact_test
+#[derive(Debug, Serialize)]
+struct RedactTestOutput {
+    /// Whether stored run logs and history are redacted (`redaction.enabled`)
+    enabled: bool,
+    redacted: String,
+    matches: Vec<PatternMatches>,
+}
+
+/// Input parameters for codex_cloud_status tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct CloudStatusArgs {
+    /// Task id returned by a `codex` call with `CLOUD_ENV`
+    pub task_id: String,
+}
+
+/// Input parameters for codex_task_summary tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct TaskSummaryArgs {
+    /// `TASK_ID` given to earlier codex calls
+    #[serde(rename = "TASK_ID")]
+    pub task_id: String,
+}
+
+/// Input parameters for codex_review_export tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ReviewExportArgs {
+    /// Review run whose final answer lists the findings, as a JSON array or
+    /// an object with a `findings` array
+    #[serde(rename = "RUN_ID", default)]
+    pub run_id: Option<String>,
+    /// Findings to export instead of reading them from a run
+    #[serde(rename = "FINDINGS", default)]
+    pub findings: Option<Vec<Finding>>,
+    /// Summary placed at the top of the review body
+    #[serde(rename = "BODY", default)]
+    pub body: Option<String>,
+    /// Commit the line numbers refer to; GitHub uses the pull request's head
+    /// when omitted
+    #[serde(rename = "COMMIT_ID", default)]
+    pub commit_id: Option<String>,
+    /// Post the review to this pull request (`owner/repo#number`) using the
+    /// configured token; otherwise the review is only returned
+    #[serde(rename = "PULL_REQUEST", default)]
+    pub pull_request: Option<String>,
+}
+
+/// Output from the codex_review_export tool
+#[derive(Debug, Serialize)]
+struct ReviewExportOutput {
+    review: review_export::GithubReview,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    posted_url: Option<String>,
+}
+
+/// Input parameters for codex_import_session tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ImportSessionArgs {
+    /// Session started in the terminal; its rollout file is looked up under
+    /// `$CODEX_HOME/sessions`
     #[serde(rename = "SESSION_ID", default)]
     pub session_id: Option<String>,
+    /// Rollout file to import instead; must be under `$CODEX_HOME/sessions`
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+}
+
+/// Input parameters for codex_preview_patch tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct PreviewPatchArgs {
+    /// Unified diff to check, with paths relative to the working directory
+    #[serde(rename = "DIFF", default)]
+    pub diff: Option<String>,
+    /// Run whose stored `RETURN_DIFF` diff to check instead of DIFF
+    #[serde(rename = "RUN_ID", default)]
+    pub run_id: Option<String>,
+    /// Working tree to check against. Defaults to the server's current working directory.
+    #[serde(rename = "WORKING_DIR", default)]
+    pub working_dir: Option<PathBuf>,
+}
+
+/// Input parameters for codex_rerun tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RerunArgs {
+    /// Run to repeat, from run history
+    #[serde(rename = "RUN_ID")]
+    pub run_id: String,
+}
+
+/// Input parameters for codex_tail tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct TailArgs {
+    /// Run to read events of; omit to list the runs that can be tailed
+    #[serde(rename = "RUN_ID", default)]
+    pub run_id: Option<String>,
+    /// `next_cursor` of the previous poll; 0 reads from the start
+    #[serde(rename = "CURSOR", default)]
+    pub cursor: u64,
+    /// Events returned per poll (default 100, max 500)
+    #[serde(rename = "MAX_EVENTS", default)]
+    pub max_events: Option<usize>,
+}
+
+/// Input parameters for codex_preflight tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct PreflightArgs {
+    /// Workspace to check. Defaults to the server's current working directory.
+    #[serde(rename = "WORKING_DIR", default)]
+    pub working_dir: Option<PathBuf>,
+    /// Prompt you intend to send, used to estimate the final prompt size
+    /// (including AGENTS.md). May be omitted.
+    #[serde(rename = "PROMPT", default)]
+    pub prompt: String,
+    /// Do not require the workspace to be inside a git repository.
+    #[serde(default)]
+    pub skip_git_check: bool,
+}
+
+/// Input parameters for codex_repo_map tool
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RepoMapArgs {
+    /// Repository to map. Defaults to the server's current working directory.
+    #[serde(rename = "WORKING_DIR", default)]
+    pub working_dir: Option<PathBuf>,
+    /// Rebuild the map even if HEAD has not moved since it was cached
+    #[serde(default)]
+    pub refresh: bool,
 }
 
 /// Output from the codex tool
 #[derive(Debug, Serialize, schemars::JsonSchema)]
 struct CodexOutput {
+    run_id: String,
     success: bool,
     #[serde(rename = "SESSION_ID")]
     session_id: String,
+    /// Short title of the task, for run lists in chat clients
+    #[serde(skip_serializing_if = "String::is_empty")]
+    title: String,
+    /// One-line status for chat clients, e.g. "Edited 3 files, tests passing"
+    summary: String,
+    /// Coarse reason a failed run failed, e.g. `rate_limit` or `task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failure_kind: Option<FailureKind>,
+    /// Client model that wrote `title`, `summary`, and `failure_kind`, when
+    /// `client_sampling` is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generated_by: Option<String>,
     message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     agent_messages_truncated: Option<bool>,
@@ -78,6 +528,60 @@ struct CodexOutput {
     error: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     warnings: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unresolved_paths: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sandbox_downgrade: Option<SandboxDowngrade>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model_fallback: Option<ModelFallback>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    termination: Option<Termination>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resource_usage: Option<ResourceUsage>,
+    /// Share of the model's context window the session fills
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context_used_percent: Option<u8>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    trimmed: Vec<BudgetTrim>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<RunDiff>,
+    /// Files changed on disk, when `watch_workspace` is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fs_changes: Option<FsChanges>,
+    /// The agent's final plan, when it kept one with the plan tool
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    plan: Vec<PlanStep>,
+    /// Files Codex read with shell commands, in the order first read
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    files_read: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files_read_truncated: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    next_steps: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lossy_utf8: Option<LossyUtf8>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pdf_pages: Vec<PdfPages>,
+    /// Files attached from IMAGE_DIR, and those left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_dir: Option<ImageDirListing>,
+    /// The final answer parsed as JSON, when FINAL_SCHEMA was given
+    #[serde(rename = "final", skip_serializing_if = "Option::is_none")]
+    final_value: Option<Value>,
+    /// The read-only phase, when EXPLORE_FIRST was set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exploration: Option<Exploration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_file: Option<String>,
+}
+
+impl CodexOutput {
+    fn apply_insights(&mut self, insights: Insights) {
+        self.title = insights.title;
+        self.summary = insights.summary;
+        self.failure_kind = insights.failure_kind;
+        self.generated_by = insights.generated_by;
+    }
 }
 
 fn build_codex_output(
@@ -86,8 +590,13 @@ fn build_codex_output(
     warnings: Option<String>,
 ) -> CodexOutput {
     CodexOutput {
+        run_id: result.run_id.clone(),
         success: result.success,
         session_id: result.session_id.clone(),
+        title: String::new(),
+        summary: run_summary::summarize(result),
+        failure_kind: (!result.success).then(|| client_sampling::classify_failure(result)),
+        generated_by: None,
         message: result.agent_messages.clone(),
         agent_messages_truncated: result.agent_messages_truncated.then_some(true),
         all_messages: return_all_messages.then_some(result.all_messages.clone()),
@@ -95,7 +604,698 @@ fn build_codex_output(
             .then_some(true),
         error: result.error.clone(),
         warnings,
+        unresolved_paths: result.unresolved_paths.clone(),
+        sandbox_downgrade: result.sandbox_downgrade,
+        model_fallback: result.model_fallback.clone(),
+        termination: result.termination.clone(),
+        resource_usage: result
+            .resource_usage
+            .filter(|_| codex::include_resource_usage()),
+        context_used_percent: result.context_used_percent,
+        trimmed: result.budget_trims.clone(),
+        diff: result.diff.clone(),
+        fs_changes: result.fs_changes.clone(),
+        plan: result.plan.clone(),
+        files_read: result.files_read.clone(),
+        files_read_truncated: result.files_read_truncated.then_some(true),
+        next_steps: result.next_steps.clone(),
+        lossy_utf8: result.lossy_utf8.clone(),
+        pdf_pages: result.pdf_pages.clone(),
+        image_dir: None,
+        final_value: result.final_value.clone(),
+        exploration: None,
+        log_file: result
+            .log_file
+            .as_ref()
+            .map(|path| path.display().to_string()),
+    }
+}
+
+/// Readable text of `output` for clients that only render text: the summary,
+/// then the agent message, or the error when there is none
+fn human_text(output: &CodexOutput) -> String {
+    let body = match &output.error {
+        Some(error) if output.message.is_empty() => format!("Error: {}", error),
+        _ => output.message.clone(),
+    };
+    if body.is_empty() {
+        output.summary.clone()
+    } else {
+        format!("{}\n\n{}", output.summary, body)
+    }
+}
+
+/// The `codex` response: `encoded` output followed by `extra` contents, or
+/// with `dual_output` the readable text in its place and the output as
+/// structured content
+fn codex_response(
+    output: &CodexOutput,
+    encoded: String,
+    extra: Vec<Content>,
+) -> Result<CallToolResult, McpError> {
+    if !codex::dual_output() {
+        let mut contents = vec![Content::text(encoded)];
+        contents.extend(extra);
+        return Ok(CallToolResult::success(contents));
+    }
+    let structured = serde_json::to_value(output).map_err(|e| {
+        McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+    })?;
+    let mut contents = vec![Content::text(human_text(output))];
+    contents.extend(extra);
+    let mut response = CallToolResult::success(contents);
+    response.structured_content = Some(structured);
+    Ok(response)
+}
+
+/// Encode `output`, downgrading it until it fits in `max_bytes`: drop
+/// `all_messages`, move the diff to disk, then cut the start of `message`.
+/// Downgrades are reported in `warnings`.
+fn encode_capped(output: &mut CodexOutput, max_bytes: Option<usize>) -> Result<String, McpError> {
+    let encode = |output: &CodexOutput| {
+        toon_format::encode_default(output).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })
+    };
+    let mut encoded = encode(output)?;
+    let Some(max_bytes) = max_bytes else {
+        return Ok(encoded);
+    };
+
+    let base_warnings = output.warnings.clone();
+    let mut downgrades: Vec<String> = Vec::new();
+    let mut cuts = 0;
+    while encoded.len() > max_bytes {
+        if output.all_messages.take().is_some() {
+            output.all_messages_truncated = None;
+            downgrades.push("dropped all_messages".to_string());
+        } else if let Some(diff) = output.diff.as_mut().filter(|diff| diff.diff.is_some()) {
+            let text = diff.diff.take().unwrap_or_default();
+            match run_diff::store(&codex::runs_dir(), &output.run_id, text.as_bytes()) {
+                Ok(path) => {
+                    downgrades.push(format!("moved the diff to {}", path.display()));
+                    diff.diff_file = Some(path);
+                }
+                Err(e) => downgrades.push(format!("dropped the diff ({})", e)),
+            }
+        } else if cuts < 4 && !output.message.is_empty() {
+            // Escaping can make the encoded message longer than the raw one,
+            // so the cut may take a few rounds
+            cuts += 1;
+            let overflow = encoded.len() - max_bytes;
+            let mut start = (overflow + 128).min(output.message.len());
+            while !output.message.is_char_boundary(start) {
+                start += 1;
+            }
+            output.message.drain(..start);
+            output.agent_messages_truncated = Some(true);
+            if cuts == 1 {
+                downgrades.push("cut the start of message".to_string());
+            }
+        } else {
+            break;
+        }
+
+        let warning = format!(
+            "Response exceeded max_response_bytes ({}): {}",
+            max_bytes,
+            downgrades.join("; ")
+        );
+        output.warnings = Some(match &base_warnings {
+            Some(existing) => format!("{}\n{}", existing, warning),
+            None => warning,
+        });
+        encoded = encode(output)?;
+    }
+    Ok(encoded)
+}
+
+/// Links to output left out of `output`: a diff stored on disk, or agent
+/// messages cut to fit `max_response_bytes`
+fn resource_links(output: &CodexOutput, full_message_bytes: usize) -> Vec<Content> {
+    let mut kinds = Vec::new();
+    if output
+        .diff
+        .as_ref()
+        .is_some_and(|diff| diff.diff_file.is_some())
+    {
+        kinds.push(RunResource::Diff);
+    }
+    if output.message.len() < full_message_bytes {
+        kinds.push(RunResource::Messages);
+    }
+    kinds
+        .into_iter()
+        .map(|kind| {
+            Annotated::new(
+                RawContent::ResourceLink(run_resource(&output.run_id, kind)),
+                None,
+            )
+        })
+        .collect()
+}
+
+fn run_resource(run_id: &str, kind: RunResource) -> RawResource {
+    let mut resource = RawResource::new(kind.uri(run_id), format!("{}/{}", run_id, kind.as_str()));
+    resource.description = Some(kind.description().to_string());
+    resource.mime_type = Some(kind.mime_type().to_string());
+    resource
+}
+
+/// What this deployment supports, as described in the server instructions
+#[derive(Debug, Clone, Default)]
+struct Features {
+    sandbox: SandboxMode,
+    sessions: bool,
+    progress: bool,
+    consensus_profiles: Vec<String>,
+    schedules: usize,
+    pipeline_verify: bool,
+    codex_raw: bool,
+    strict_arguments: bool,
+}
+
+impl Features {
+    fn from_config(config: &codex::EffectiveConfig) -> Self {
+        Features {
+            sandbox: SandboxMode::from_args(&config.additional_args).min(
+                config
+                    .sandbox_ceiling
+                    .unwrap_or(SandboxMode::DangerFullAccess),
+            ),
+            sessions: !config.isolated_codex_home,
+            progress: !matches!(&config.progress.rules, Some(rules) if rules.is_empty()),
+            consensus_profiles: config
+                .consensus_profiles
+                .iter()
+                .map(|profile| profile.name.clone())
+                .collect(),
+            schedules: config.schedules.len(),
+            pipeline_verify: config.allow_verify_commands,
+            codex_raw: config.enable_codex_raw,
+            strict_arguments: config.strict_arguments,
+        }
     }
+
+    /// Guidance for the client's model, listing only what is enabled here
+    fn instructions(&self) -> String {
+        let mut lines = vec![format!(
+            "This server runs the Codex CLI for AI-assisted coding tasks. Use the codex tool to run a task in the working directory; runs use the {} sandbox{}.",
+            self.sandbox.as_str(),
+            if self.sandbox.is_write() {
+                ""
+            } else {
+                ", so Codex can read but not modify files"
+            }
+        )];
+        lines.push(if self.sessions {
+            "Continue a conversation by passing the returned SESSION_ID, or RESUME_LAST for the most recent session.".to_string()
+        } else {
+            "Every run starts a fresh session; SESSION_ID and RESUME_LAST are unavailable.".to_string()
+        });
+        if offered("codex_task_summary") {
+            lines.push(
+                "Group related runs with TASK_ID and review them with codex_task_summary."
+                    .to_string(),
+            );
+        }
+        lines.push(format!(
+            "RETURN_DIFF returns the changes a run made{}.",
+            if offered("codex_preview_patch") {
+                ", and codex_preview_patch checks whether a diff still applies"
+            } else {
+                ""
+            }
+        ));
+        if self.progress {
+            lines.push("Send a progress token to receive progress notifications while a run is in progress.".to_string());
+        }
+        if offered("codex_tail") {
+            lines.push("codex_tail polls the events of runs in progress, for clients without progress notifications.".to_string());
+        }
+        if offered("codex_rerun") {
+            lines.push("codex_rerun repeats an earlier run with the same options, to compare its outcome against the current workspace.".to_string());
+        }
+        if !self.consensus_profiles.is_empty() && offered("codex_consensus") {
+            lines.push(format!(
+                "codex_consensus asks the same read-only question of several models ({}) and reports how much they agree.",
+                self.consensus_profiles.join(", ")
+            ));
+        }
+        if offered("codex_pipeline") {
+            lines.push(format!(
+                "codex_pipeline runs ordered steps in one session{}.",
+                if self.pipeline_verify {
+                    ", each optionally checked by a verify command"
+                } else {
+                    ""
+                }
+            ));
+        }
+        if self.schedules > 0 && offered("codex_schedules") {
+            lines.push("codex_schedules lists and toggles scheduled runs.".to_string());
+        }
+        if self.codex_raw && offered("codex_raw") {
+            lines.push("codex_raw runs vetted Codex CLI diagnostics.".to_string());
+        }
+        if self.strict_arguments {
+            lines.push("Unknown codex arguments are rejected.".to_string());
+        }
+        let checks: Vec<&str> = [
+            (
+                "codex_preflight",
+                "codex_preflight checks a workspace before a run",
+            ),
+            ("codex_status", "codex_status reports run statistics"),
+            (
+                "codex_config",
+                "codex_config shows the effective configuration",
+            ),
+        ]
+        .into_iter()
+        .filter(|(tool, _)| offered(tool))
+        .map(|(_, text)| text)
+        .collect();
+        match checks.as_slice() {
+            [] => {}
+            [only] => lines.push(format!("{}.", only)),
+            [first, second] => lines.push(format!("{} and {}.", first, second)),
+            [rest @ .., last] => lines.push(format!("{}, and {}.", rest.join(", "), last)),
+        }
+        lines.join(" ")
+    }
+}
+
+/// Whether the instructions should mention `tool`, which is left out while
+/// `disabled_tools` hides it
+fn offered(tool: &str) -> bool {
+    !tool_toggle::is_disabled(tool)
+}
+
+/// Input plus output tokens a run reported
+fn total_tokens(result: &codex::CodexResult) -> u64 {
+    result
+        .token_usage
+        .as_ref()
+        .map_or(0, |usage| usage.input_tokens + usage.output_tokens)
+}
+
+/// Map a failed run to an MCP error, keeping the details of errors callers can
+/// act on
+fn run_error(e: anyhow::Error) -> McpError {
+    if let Some(busy) = e.downcast_ref::<WorkspaceBusy>() {
+        return McpError::invalid_request(
+            busy.to_string(),
+            Some(serde_json::json!({
+                "kind": "WorkspaceBusy",
+                "pid": busy.holder.pid,
+                "run_id": busy.holder.run_id,
+            })),
+        );
+    }
+    if let Some(too_large) = e.downcast_ref::<PromptTooLarge>() {
+        return McpError::invalid_params(
+            too_large.to_string(),
+            Some(serde_json::json!({
+                "kind": "PromptTooLarge",
+                "max_bytes": too_large.max_bytes,
+                "total_bytes": too_large.total_bytes,
+                "parts": too_large.parts,
+            })),
+        );
+    }
+    if let Some(low) = e.downcast_ref::<LowDiskSpace>() {
+        return McpError::invalid_request(
+            low.to_string(),
+            Some(serde_json::json!({
+                "kind": "LowDiskSpace",
+                "free_bytes": low.free_bytes,
+                "min_free_bytes": low.min_free_bytes,
+            })),
+        );
+    }
+    if let Some(unsupported) = e.downcast_ref::<UnsupportedCliVersion>() {
+        return McpError::invalid_request(
+            unsupported.to_string(),
+            Some(serde_json::json!({
+                "kind": "UnsupportedCliVersion",
+                "found": unsupported.found,
+                "minimum": unsupported.minimum,
+                "reason": unsupported.reason,
+            })),
+        );
+    }
+    if let Some(expired) = e.downcast_ref::<SessionExpired>() {
+        return McpError::invalid_params(
+            expired.to_string(),
+            Some(serde_json::json!({
+                "kind": "SessionExpired",
+                "session_id": expired.session_id,
+                "idle_secs": expired.idle_secs,
+                "suggestion": "start a new session by omitting SESSION_ID",
+            })),
+        );
+    }
+    McpError::internal_error(format!("Failed to execute codex: {}", e), None)
+}
+
+/// The tool call's `_meta` without its progress token, to correlate the run
+/// with the upstream request; `None` when empty or over `MAX_META_BYTES`
+fn call_meta(context: &RequestContext<RoleServer>) -> Option<Value> {
+    let Ok(Value::Object(mut meta)) = serde_json::to_value(&context.meta) else {
+        return None;
+    };
+    meta.remove("progressToken");
+    let meta = Value::Object(meta);
+    let keep =
+        meta.as_object().is_some_and(|m| !m.is_empty()) && meta.to_string().len() <= MAX_META_BYTES;
+    keep.then_some(meta)
+}
+
+/// Forward progress messages from a run as `notifications/progress`, if the
+/// client asked for them with a progress token. Plan changes are also sent
+/// as a `codex-mcp-rs.plan` log message carrying every step's status and the
+/// steps that moved, for clients that render a live checklist.
+fn progress_forwarder(context: &RequestContext<RoleServer>) -> Option<ProgressSender> {
+    let token = context.meta.get_progress_token()?;
+    let peer = context.peer.clone();
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<ProgressUpdate>();
+    // Ends once the run drops its sender
+    tokio::spawn(async move {
+        let mut step = 0u32;
+        // Once a percentage was reported, progress stays on that scale and
+        // never goes back
+        let mut percent: Option<f64> = None;
+        while let Some(update) = receiver.recv().await {
+            step += 1;
+            if let Some(estimate) = update.percent {
+                percent = Some(percent.map_or(estimate, |last| last.max(estimate)));
+            }
+            let (progress, total) = match percent {
+                Some(percent) => (percent, Some(100.0)),
+                None => (f64::from(step), None),
+            };
+            if let Some(plan) = &update.plan {
+                let _ = peer
+                    .notify_logging_message(LoggingMessageNotificationParam {
+                        level: LoggingLevel::Info,
+                        logger: Some("codex-mcp-rs.plan".to_string()),
+                        data: serde_json::to_value(plan).unwrap_or_default(),
+                    })
+                    .await;
+            }
+            let _ = peer
+                .notify_progress(ProgressNotificationParam {
+                    progress_token: token.clone(),
+                    progress,
+                    total,
+                    message: Some(update.message),
+                })
+                .await;
+        }
+    });
+    Some(sender)
+}
+
+/// `dir`, or the current directory, resolved to a canonical path, as the
+/// `codex` tool resolves WORKING_DIR before its trust check
+fn resolve_working_dir(dir: Option<PathBuf>) -> Result<PathBuf, McpError> {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => std::env::current_dir().map_err(|e| {
+            McpError::invalid_params(
+                format!("failed to resolve current working directory: {}", e),
+                None,
+            )
+        })?,
+    };
+    let canonical = dir.canonicalize().map_err(|e| {
+        McpError::invalid_params(
+            format!(
+                "working directory does not exist or is not accessible: {} ({})",
+                dir.display(),
+                e
+            ),
+            None,
+        )
+    })?;
+    if !canonical.is_dir() {
+        return Err(McpError::invalid_params(
+            format!("working directory is not a directory: {}", dir.display()),
+            None,
+        ));
+    }
+    Ok(canonical)
+}
+
+/// Trust-on-first-use check of `dir` when `workspace_trust` is enabled: an
+/// allowlisted or previously confirmed directory passes, any other is put to
+/// the client as an elicitation and remembered once accepted. Returns the
+/// directory's default sandbox.
+async fn ensure_trusted(
+    context: &RequestContext<RoleServer>,
+    dir: &Path,
+) -> Result<Option<SandboxMode>, McpError> {
+    let config = codex::workspace_trust();
+    if !config.enabled {
+        return Ok(None);
+    }
+    if let Some(entry) = config.lookup(dir) {
+        return Ok(entry.sandbox);
+    }
+
+    let untrusted = |reason: &str| {
+        McpError::invalid_request(
+            format!(
+                "working directory {} is not trusted: {}",
+                dir.display(),
+                reason
+            ),
+            Some(serde_json::json!({
+                "kind": "UntrustedWorkspace",
+                "path": dir.display().to_string(),
+            })),
+        )
+    };
+    if !can_elicit(context) {
+        return Err(untrusted(
+            "add it to workspace_trust.allowlist, or use a client that supports elicitation to confirm it",
+        ));
+    }
+
+    // Built from JSON so the request follows the protocol's wire format
+    let request: CreateElicitationRequestParam = serde_json::from_value(serde_json::json!({
+        "message": format!("Allow codex-mcp to operate in {}?", dir.display()),
+        "requestedSchema": {
+            "type": "object",
+            "properties": {
+                "allow": {
+                    "type": "boolean",
+                    "title": "Allow",
+                    "description": "Let Codex read and run commands in this directory",
+                },
+                "sandbox": {
+                    "type": "string",
+                    "title": "Default sandbox",
+                    "enum": ["read-only", "workspace-write"],
+                },
+            },
+            "required": ["allow"],
+        },
+    }))
+    .map_err(|e| McpError::internal_error(format!("Failed to build elicitation: {}", e), None))?;
+    let response = context
+        .peer
+        .create_elicitation(request)
+        .await
+        .map_err(|e| untrusted(&format!("asking the client failed: {}", e)))?;
+    let response = serde_json::to_value(&response).unwrap_or_default();
+    let content = &response["content"];
+    if response["action"].as_str() != Some("accept") || content["allow"] != Value::Bool(true) {
+        return Err(untrusted("the client declined"));
+    }
+
+    let sandbox = content["sandbox"].as_str().and_then(SandboxMode::parse);
+    if let Err(e) = config.remember(TrustedWorkspace {
+        path: dir.to_path_buf(),
+        sandbox,
+    }) {
+        eprintln!(
+            "Warning: failed to remember trusted workspace {}: {}",
+            dir.display(),
+            e
+        );
+    }
+    Ok(sandbox)
+}
+
+/// Whether the client can answer elicitations
+fn can_elicit(context: &RequestContext<RoleServer>) -> bool {
+    context
+        .peer
+        .peer_info()
+        .and_then(|info| serde_json::to_value(&info.capabilities).ok())
+        .is_some_and(|capabilities| !capabilities["elicitation"].is_null())
+}
+
+/// Ask the user to approve the plan of an `EXPLORE_FIRST` run; `None` when
+/// the client cannot be asked
+async fn confirm_plan(context: &RequestContext<RoleServer>, plan: &str) -> Option<bool> {
+    if !can_elicit(context) {
+        return None;
+    }
+    let request: CreateElicitationRequestParam = serde_json::from_value(serde_json::json!({
+        "message": explore::confirm_message(plan),
+        "requestedSchema": {
+            "type": "object",
+            "properties": {
+                "approve": {
+                    "type": "boolean",
+                    "title": "Approve",
+                    "description": "Let Codex change files to carry out this plan",
+                },
+            },
+            "required": ["approve"],
+        },
+    }))
+    .ok()?;
+    let response = context.peer.create_elicitation(request).await.ok()?;
+    let response = serde_json::to_value(&response).unwrap_or_default();
+    Some(
+        response["action"].as_str() == Some("accept")
+            && response["content"]["approve"] == Value::Bool(true),
+    )
+}
+
+/// Run `opts` as an `EXPLORE_FIRST` run: a read-only exploration returning a
+/// plan, then, once approved when `explore_first.confirm` is set, the same
+/// session resumed with write access to carry it out. Returns the
+/// exploration and the execution, if it ran; otherwise the exploration says
+/// why in its warnings.
+async fn explore_then_execute(
+    opts: Options,
+    context: &RequestContext<RoleServer>,
+) -> Result<(codex::CodexResult, Option<codex::CodexResult>), McpError> {
+    let config = codex::explore_first_config();
+    let mut explored = codex::run(explore::explore_options(&opts, config))
+        .await
+        .map_err(run_error)?;
+    if !explored.success || explored.session_id.is_empty() {
+        return Ok((explored, None));
+    }
+
+    let approved = if config.confirm {
+        confirm_plan(context, &explored.agent_messages).await
+    } else {
+        Some(true)
+    };
+    let skipped = match approved {
+        Some(true) => {
+            let executed = codex::run(explore::execute_options(opts, &explored.session_id))
+                .await
+                .map_err(run_error)?;
+            return Ok((explored, Some(executed)));
+        }
+        Some(false) => "The plan was not approved, so nothing was changed",
+        None => {
+            "The plan needs approval, but the client cannot be asked; resume SESSION_ID with a write sandbox to carry it out"
+        }
+    };
+    explored.warnings = Some(match explored.warnings.take() {
+        Some(existing) => format!("{}\n{}", existing, skipped),
+        None => skipped.to_string(),
+    });
+    Ok((explored, None))
+}
+
+/// Warnings to return for `level`; the rest go to stderr for operators
+fn client_warnings(result: &codex::CodexResult, level: WarningLevel) -> Option<String> {
+    let mut hidden = Vec::new();
+    let visible = match level {
+        WarningLevel::All => match (&result.operator_warnings, &result.warnings) {
+            (Some(operator), Some(client)) => Some(format!("{}\n{}", operator, client)),
+            (operator, client) => operator.clone().or_else(|| client.clone()),
+        },
+        WarningLevel::Client => {
+            hidden.extend(result.operator_warnings.as_deref());
+            result.warnings.clone()
+        }
+        WarningLevel::None => {
+            hidden.extend(result.operator_warnings.as_deref());
+            hidden.extend(result.warnings.as_deref());
+            None
+        }
+    };
+    for warning in hidden {
+        eprintln!("Warning: run {}: {}", result.run_id, warning);
+    }
+    visible
+}
+
+/// Reject `unknown` argument names, suggesting the closest parameter of `T`
+fn check_unknown_fields<T: schemars::JsonSchema>(
+    unknown: &BTreeMap<String, Value>,
+) -> Result<(), McpError> {
+    if unknown.is_empty() {
+        return Ok(());
+    }
+
+    let schema = schemars::schema_for!(T);
+    let known: Vec<&String> = schema
+        .as_object()
+        .and_then(|schema| schema.get("properties"))
+        .and_then(Value::as_object)
+        .map(|properties| properties.keys().collect())
+        .unwrap_or_default();
+
+    let mut descriptions = Vec::new();
+    let mut details = Vec::new();
+    for name in unknown.keys() {
+        let suggestion = nearest_name(name, &known);
+        match suggestion {
+            Some(s) => descriptions.push(format!("{} (did you mean {}?)", name, s)),
+            None => descriptions.push(name.clone()),
+        }
+        details.push(serde_json::json!({ "name": name, "suggestion": suggestion }));
+    }
+
+    Err(McpError::invalid_params(
+        format!("unknown arguments: {}", descriptions.join(", ")),
+        Some(serde_json::json!({
+            "kind": "UnknownArguments",
+            "unknown": details,
+        })),
+    ))
+}
+
+/// Closest of `candidates` to `name` by case-insensitive edit distance, if close
+/// enough to be a plausible typo
+fn nearest_name<'a>(name: &str, candidates: &[&'a String]) -> Option<&'a str> {
+    let name = name.to_ascii_lowercase();
+    let max_distance = (name.chars().count() / 3).max(2);
+    candidates
+        .iter()
+        .map(|candidate| {
+            let distance = edit_distance(&name, &candidate.to_ascii_lowercase());
+            (distance, candidate.as_str())
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
 }
 
 #[derive(Clone)]
@@ -111,12 +1311,60 @@ impl Default for CodexServer {
 
 impl CodexServer {
     pub fn new() -> Self {
-        Self {
-            tool_router: Self::tool_router(),
+        let mut tool_router = Self::tool_router();
+        let ceiling = SandboxMode::from_args(&codex::default_additional_args());
+        for alias in codex::tool_aliases() {
+            let taken: Vec<String> = tool_router
+                .list_all()
+                .into_iter()
+                .map(|tool| tool.name.to_string())
+                .collect();
+            match tool_aliases::validate(alias, &taken, ceiling) {
+                Ok(()) => tool_router.add_route(alias_route(alias.clone())),
+                Err(e) => eprintln!("Warning: tool alias {} is disabled: {}", alias.name, e),
+            }
+        }
+        let names: Vec<String> = tool_router
+            .list_all()
+            .into_iter()
+            .map(|tool| tool.name.to_string())
+            .collect();
+        for name in tool_toggle::unknown(names.iter().map(String::as_str)) {
+            eprintln!("Warning: disabled_tools names unknown tool {}", name);
         }
+        Self { tool_router }
     }
 }
 
+/// Route for a `tool_aliases` entry: runs the codex tool with the alias's
+/// prompt template, sandbox, model, and timeout
+fn alias_route(alias: ToolAlias) -> ToolRoute<CodexServer> {
+    let schema = serde_json::to_value(schemars::schema_for!(AliasArgs))
+        .ok()
+        .and_then(|schema| schema.as_object().cloned())
+        .unwrap_or_default();
+    let tool = Tool::new(alias.name.clone(), alias.description(), schema);
+    ToolRoute::new_dyn(tool, move |context| {
+        let alias = alias.clone();
+        Box::pin(async move {
+            let arguments = Value::Object(context.arguments.unwrap_or_default());
+            let args: AliasArgs = serde_json::from_value(arguments)
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+            let mut codex_args: CodexArgs = serde_json::from_value(serde_json::json!({
+                "PROMPT": alias.prompt(&args.input),
+                "TASK_ID": args.task_id,
+            }))
+            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+            codex_args.identity = alias.identity.clone();
+            codex_args.preset = Some(alias);
+            context
+                .service
+                .codex(Parameters(codex_args), context.request_context)
+                .await
+        })
+    })
+}
+
 #[tool_router]
 impl CodexServer {
     /// Executes a non-interactive Codex session via CLI to perform AI-assisted coding tasks.
@@ -129,7 +1377,12 @@ impl CodexServer {
     async fn codex(
         &self,
         Parameters(args): Parameters<CodexArgs>,
+        context: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
+        if codex::strict_arguments() {
+            check_unknown_fields::<CodexArgs>(&args.unknown_fields)?;
+        }
+
         // Validate required parameters
         if args.prompt.is_empty() {
             return Err(McpError::invalid_params(
@@ -137,53 +1390,265 @@ impl CodexServer {
                 None,
             ));
         }
+        let prompt = history::expand_references(&args.prompt)
+            .map_err(|e| McpError::invalid_params(format!("PROMPT: {}", e), None))?;
 
-        // Normalize empty string session_id to None so that clients should
-        // either omit the field or provide a real session id.
-        let session_id = args.session_id.filter(|s| !s.is_empty());
-
-        if let Some(ref id) = session_id {
-            if Uuid::parse_str(id).is_err() {
+        if args.mode == RunMode::Plain {
+            let conflicting = [
+                (
+                    "SESSION_ID",
+                    args.session_id.as_ref().is_some_and(|s| !s.is_empty()),
+                ),
+                ("RESUME_LAST", args.resume_last),
+                ("LOG_TO_FILE", args.log_to_file.is_some()),
+                ("ALLOWED_PATHS", !args.allowed_paths.is_empty()),
+                ("RETURN_DIFF", args.return_diff),
+                ("IDEMPOTENCY_KEY", args.idempotency_key.is_some()),
+                ("FINAL_SCHEMA", args.final_schema.is_some()),
+                ("EXPLORE_FIRST", args.explore_first),
+            ];
+            if let Some((name, _)) = conflicting.iter().find(|(_, set)| *set) {
                 return Err(McpError::invalid_params(
-                    "SESSION_ID must be a valid UUID string",
+                    format!("{} cannot be combined with MODE \"plain\"", name),
                     None,
                 ));
             }
         }
 
-        // Resolve and validate working directory based on the current process directory.
-        let working_dir = std::env::current_dir().map_err(|e| {
-            McpError::invalid_params(
-                format!("failed to resolve current working directory: {}", e),
-                None,
-            )
-        })?;
-        let canonical_working_dir = working_dir.canonicalize().map_err(|e| {
-            McpError::invalid_params(
-                format!(
-                    "working directory does not exist or is not accessible: {} ({})",
-                    working_dir.display(),
-                    e
+        if let Some(environment) = &args.cloud_env {
+            let conflicting = [
+                (
+                    "SESSION_ID",
+                    args.session_id.as_ref().is_some_and(|s| !s.is_empty()),
                 ),
-                None,
+                ("RESUME_LAST", args.resume_last),
+                ("LOG_TO_FILE", args.log_to_file.is_some()),
+                ("ALLOWED_PATHS", !args.allowed_paths.is_empty()),
+                ("RETURN_DIFF", args.return_diff),
+                ("MODE", args.mode == RunMode::Plain),
+                ("WORKING_DIR", args.working_dir.is_some()),
+                ("image", !args.images.is_empty()),
+                ("IMAGE_DIR", args.image_dir.is_some()),
+                ("PRIMER_MESSAGES", !args.primer_messages.is_empty()),
+                ("TASK_ID", args.task_id.is_some()),
+                ("ATTACH_REPO_MAP", args.attach_repo_map),
+                ("IDEMPOTENCY_KEY", args.idempotency_key.is_some()),
+                ("IDENTITY", args.identity.is_some()),
+                ("FINAL_SCHEMA", args.final_schema.is_some()),
+                ("RESPONSE_LANGUAGE", args.response_language.is_some()),
+                ("EXPLORE_FIRST", args.explore_first),
+            ];
+            if let Some((name, _)) = conflicting.iter().find(|(_, set)| *set) {
+                return Err(McpError::invalid_params(
+                    format!("{} cannot be combined with CLOUD_ENV", name),
+                    None,
+                ));
+            }
+            let task = cloud::submit(
+                &codex::codex_bin(),
+                environment,
+                args.cloud_attempts,
+                &prompt,
             )
-        })?;
+            .await
+            .map_err(|e| McpError::invalid_request(e, None))?;
 
-        if !canonical_working_dir.is_dir() {
-            return Err(McpError::invalid_params(
-                format!(
-                    "working directory is not a directory: {}",
-                    working_dir.display()
-                ),
-                None,
-            ));
+            let toon_output = toon_format::encode_default(&task).map_err(|e| {
+                McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+            })?;
+            return Ok(if task.output.exit_code == Some(0) {
+                CallToolResult::success(vec![Content::text(toon_output)])
+            } else {
+                CallToolResult::error(vec![Content::text(toon_output)])
+            });
         }
 
-        // Validate image files exist and are regular files
-        let mut canonical_image_paths = Vec::new();
-        for img_path in &args.images {
-            // Resolve image path relative to the working directory first, then canonicalize
-            let resolved_path = if img_path.is_absolute() {
+        let task_id = match args.task_id.as_deref().map(str::trim) {
+            Some("") => {
+                return Err(McpError::invalid_params(
+                    "TASK_ID must be a non-empty string",
+                    None,
+                ))
+            }
+            Some(id) if id.len() > MAX_TASK_ID_LEN => {
+                return Err(McpError::invalid_params(
+                    format!("TASK_ID must be at most {} bytes", MAX_TASK_ID_LEN),
+                    None,
+                ))
+            }
+            other => other.map(str::to_string),
+        };
+        let idempotency_key = match args.idempotency_key.as_deref().map(str::trim) {
+            Some("") => {
+                return Err(McpError::invalid_params(
+                    "IDEMPOTENCY_KEY must be a non-empty string",
+                    None,
+                ))
+            }
+            Some(key) if key.len() > MAX_TASK_ID_LEN => {
+                return Err(McpError::invalid_params(
+                    format!("IDEMPOTENCY_KEY must be at most {} bytes", MAX_TASK_ID_LEN),
+                    None,
+                ))
+            }
+            other => other.map(str::to_string),
+        };
+
+        if let Some(schema) = &args.final_schema {
+            final_schema::check_schema(schema)
+                .map_err(|e| McpError::invalid_params(format!("FINAL_SCHEMA {}", e), None))?;
+        }
+
+        let identity = match args.identity.as_deref().map(str::trim) {
+            Some(name) => {
+                let identity = identities::find(codex::identities(), name)
+                    .map_err(|e| McpError::invalid_params(format!("IDENTITY: {}", e), None))?;
+                identities::check_budget(identity).map_err(|e| {
+                    McpError::invalid_request(
+                        e.to_string(),
+                        Some(serde_json::json!({
+                            "kind": "BudgetExceeded",
+                            "identity": e.identity,
+                            "used": e.used,
+                            "budget": e.budget,
+                        })),
+                    )
+                })?;
+                Some(identity)
+            }
+            None => None,
+        };
+
+        // Normalize empty string session_id to None so that clients should
+        // either omit the field or provide a real session id.
+        let mut session_id = args.session_id.filter(|s| !s.is_empty());
+        let mut resume_last = args.resume_last;
+        if session_id.as_deref() == Some("last") {
+            session_id = None;
+            resume_last = true;
+        } else if resume_last && session_id.is_some() {
+            return Err(McpError::invalid_params(
+                "RESUME_LAST cannot be combined with SESSION_ID",
+                None,
+            ));
+        }
+
+        if let Some(ref id) = session_id {
+            if Uuid::parse_str(id).is_err() {
+                return Err(McpError::invalid_params(
+                    "SESSION_ID must be a valid UUID string",
+                    None,
+                ));
+            }
+        }
+
+        // The plan is made in a session of its own
+        if args.explore_first && (session_id.is_some() || resume_last) {
+            return Err(McpError::invalid_params(
+                "EXPLORE_FIRST can only be used when starting a new session",
+                None,
+            ));
+        }
+
+        // Primer messages set up a new session; a resumed one already has its context
+        if !args.primer_messages.is_empty() && (session_id.is_some() || resume_last) {
+            return Err(McpError::invalid_params(
+                "PRIMER_MESSAGES can only be used when starting a new session",
+                None,
+            ));
+        }
+        if args.primer_messages.len() > MAX_PRIMER_MESSAGES {
+            return Err(McpError::invalid_params(
+                format!(
+                    "PRIMER_MESSAGES accepts at most {} messages",
+                    MAX_PRIMER_MESSAGES
+                ),
+                None,
+            ));
+        }
+        let mut primer_messages = Vec::with_capacity(args.primer_messages.len());
+        for (index, message) in args.primer_messages.iter().enumerate() {
+            if message.trim().is_empty() {
+                return Err(McpError::invalid_params(
+                    format!("PRIMER_MESSAGES[{}] must be a non-empty string", index),
+                    None,
+                ));
+            }
+            primer_messages.push(history::expand_references(message).map_err(|e| {
+                McpError::invalid_params(format!("PRIMER_MESSAGES[{}]: {}", index, e), None)
+            })?);
+        }
+
+        // Resolve and validate the working directory: WORKING_DIR if given,
+        // else the current process directory.
+        let working_dir = match args.working_dir {
+            Some(dir) if dir.as_os_str().is_empty() => {
+                return Err(McpError::invalid_params(
+                    "WORKING_DIR must be a non-empty path",
+                    None,
+                ))
+            }
+            Some(dir) => dir,
+            None => std::env::current_dir().map_err(|e| {
+                McpError::invalid_params(
+                    format!("failed to resolve current working directory: {}", e),
+                    None,
+                )
+            })?,
+        };
+        let canonical_working_dir = working_dir.canonicalize().map_err(|e| {
+            McpError::invalid_params(
+                format!(
+                    "working directory does not exist or is not accessible: {} ({})",
+                    working_dir.display(),
+                    e
+                ),
+                None,
+            )
+        })?;
+
+        if !canonical_working_dir.is_dir() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "working directory is not a directory: {}",
+                    working_dir.display()
+                ),
+                None,
+            ));
+        }
+
+        let prompt = if args.attach_repo_map {
+            let map = repo_map::get(&canonical_working_dir, false)
+                .await
+                .map_err(|e| McpError::invalid_params(format!("ATTACH_REPO_MAP: {}", e), None))?;
+            format!("{}\n{}", map.map, prompt)
+        } else {
+            prompt
+        };
+
+        for pattern in &args.allowed_paths {
+            let path = std::path::Path::new(pattern);
+            if path.is_absolute()
+                || path
+                    .components()
+                    .any(|c| matches!(c, std::path::Component::ParentDir))
+            {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "ALLOWED_PATHS entries must be relative to the working directory: {}",
+                        pattern
+                    ),
+                    None,
+                ));
+            }
+        }
+
+        // Validate image files exist, are regular files, and are not sensitive
+        let sensitive_paths = codex::sensitive_paths();
+        let mut canonical_image_paths = Vec::new();
+        for img_path in &args.images {
+            // Resolve image path relative to the working directory first, then canonicalize
+            let resolved_path = if img_path.is_absolute() {
                 img_path.clone()
             } else {
                 canonical_working_dir.join(img_path)
@@ -207,49 +1672,910 @@ impl CodexServer {
                 ));
             }
 
+            // Check the canonical path too, so symlinks cannot smuggle in a key file
+            for candidate in [&resolved_path, &canonical] {
+                if let Err(blocked) = path_denylist::check(candidate, &sensitive_paths) {
+                    return Err(McpError::invalid_params(
+                        blocked.to_string(),
+                        Some(serde_json::json!({
+                            "kind": "SensitivePath",
+                            "path": blocked.path,
+                            "pattern": blocked.pattern,
+                        })),
+                    ));
+                }
+            }
+
             canonical_image_paths.push(canonical);
         }
 
+        let image_dir_listing = match &args.image_dir {
+            Some(dir) => {
+                let resolved = if dir.is_absolute() {
+                    dir.clone()
+                } else {
+                    canonical_working_dir.join(dir)
+                };
+                let listing = resolved
+                    .canonicalize()
+                    .and_then(|dir| image_dir::list(&dir, &sensitive_paths))
+                    .map_err(|e| {
+                        McpError::invalid_params(
+                            format!(
+                                "IMAGE_DIR is not a readable directory: {} ({})",
+                                resolved.display(),
+                                e
+                            ),
+                            None,
+                        )
+                    })?;
+                if listing.included.is_empty() {
+                    return Err(McpError::invalid_params(
+                        format!(
+                            "IMAGE_DIR contains no supported images: {}",
+                            resolved.display()
+                        ),
+                        None,
+                    ));
+                }
+                canonical_image_paths.extend(listing.paths.iter().cloned());
+                Some(listing)
+            }
+            None => None,
+        };
+
+        let mut additional_args = codex::default_additional_args();
+        if let Some(mode) = ensure_trusted(&context, &canonical_working_dir).await? {
+            additional_args = codex::with_sandbox(&additional_args, mode);
+        }
+        additional_args.extend(
+            codex::config_override_args(&args.config_overrides, &codex::config_override_keys())
+                .map_err(|e| McpError::invalid_params(format!("CONFIG_OVERRIDES: {}", e), None))?,
+        );
+        additional_args.extend(
+            codex::sampling_args(args.temperature, args.seed)
+                .map_err(|e| McpError::invalid_params(e, None))?,
+        );
+        if let Some(preset) = &args.preset {
+            additional_args = preset.additional_args(&additional_args);
+        }
+        let timeout_secs = args.preset.as_ref().and_then(|preset| preset.timeout_secs);
+
+        // A retried call with a known key reuses the earlier run's result
+        let (replayed, guard) = match idempotency_key {
+            Some(key) => {
+                match idempotency::claim(&key, &canonical_working_dir, codex::idempotency_ttl()) {
+                    Claim::New(guard) => (None, Some(guard)),
+                    Claim::Done(result) => (Some(result), None),
+                    Claim::Running => {
+                        return Err(McpError::invalid_request(
+                            format!("a run with IDEMPOTENCY_KEY {} is still in progress", key),
+                            Some(serde_json::json!({ "kind": "DuplicateRun" })),
+                        ))
+                    }
+                }
+            }
+            None => (None, None),
+        };
+
         // Create options for codex client
         let opts = Options {
-            prompt: args.prompt,
+            prompt,
             working_dir: canonical_working_dir,
             session_id,
-            additional_args: codex::default_additional_args(),
+            resume_last,
+            additional_args,
             image_paths: canonical_image_paths,
-            timeout_secs: None,
+            timeout_secs,
+            codex_home: identity.map(|identity| identity.codex_home.clone()),
+            log_to_file: args.log_to_file.and_then(LogToFileArg::into_target),
+            allowed_paths: args.allowed_paths,
+            source: None,
+            progress: progress_forwarder(&context),
+            return_diff: args.return_diff,
+            task_id,
+            // Stop the run when the client cancels the request
+            cancel: Some(context.ct.clone()),
+            final_schema: args.final_schema,
+            meta: call_meta(&context),
+            rerun_of: None,
+            primer_messages,
+            response_language: args
+                .response_language
+                .filter(|language| !language.trim().is_empty()),
+            // Turned on by `codex::run` when the CLI offers the plan tool
+            include_plan_tool: false,
+            // Turned on by `codex::run` when the prompt contains a secret
+            redact_log: false,
         };
 
+        if args.mode == RunMode::Plain {
+            let result = codex::run_plain(opts).await.map_err(run_error)?;
+            if let Some(identity) = identity {
+                identities::record_usage(&identity.name, total_tokens(&result));
+            }
+            return Ok(if result.success {
+                CallToolResult::success(vec![Content::text(result.agent_messages)])
+            } else {
+                CallToolResult::error(vec![Content::text(result.error.unwrap_or_default())])
+            });
+        }
+
         // Execute codex
-        let result = codex::run(opts).await.map_err(|e| {
-            McpError::internal_error(format!("Failed to execute codex: {}", e), None)
-        })?;
+        let mut exploration = None;
+        let result = match replayed {
+            Some(result) => *result,
+            None => {
+                let result = if args.explore_first {
+                    let (explored, executed) = explore_then_execute(opts, &context).await?;
+                    exploration = Some(Exploration::new(&explored, executed.is_some()));
+                    match executed {
+                        Some(result) => {
+                            if let Some(identity) = identity {
+                                identities::record_usage(&identity.name, total_tokens(&explored));
+                            }
+                            result
+                        }
+                        None => explored,
+                    }
+                } else {
+                    codex::run(opts).await.map_err(run_error)?
+                };
+                if let Some(guard) = guard {
+                    guard.complete(&result);
+                }
+                if let Some(identity) = identity {
+                    identities::record_usage(&identity.name, total_tokens(&result));
+                }
+                result
+            }
+        };
 
-        let combined_warnings = result.warnings.clone();
+        let combined_warnings = client_warnings(&result, codex::client_warning_level());
 
         // Prepare the response using TOON format for token efficiency
-        let output = build_codex_output(&result, false, combined_warnings);
+        let mut output = build_codex_output(&result, false, combined_warnings);
+        output.image_dir = image_dir_listing;
+        output.exploration = exploration;
+        let insights = client_sampling::insights(
+            &context.peer,
+            codex::client_sampling_config(),
+            &result,
+            &args.prompt,
+        )
+        .await;
+        output.apply_insights(insights);
+        let toon_output = encode_capped(&mut output, codex::max_response_bytes())?;
+
+        let mut contents = resource_links(&output, result.agent_messages.len());
+
+        // Some chat clients display images far better than long text blobs
+        if let Some(config) = codex::failure_screenshot_config() {
+            if let Some(png) = failure_screenshot::render_base64(&result, config) {
+                contents.push(Content::image(png, "image/png"));
+            }
+        }
+
+        // Return structured content so callers can inspect success, error, and warning fields
+        codex_response(&output, toon_output, contents)
+    }
+
+    /// Runs the same read-only prompt against every configured consensus profile
+    /// concurrently, returning each answer plus agreement signals.
+    #[tool(
+        name = "codex_consensus",
+        description = "Ask several configured Codex models/profiles the same read-only question concurrently; returns each answer, an agreement score, and the most representative answer"
+    )]
+    async fn codex_consensus(
+        &self,
+        Parameters(args): Parameters<ConsensusArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if args.prompt.is_empty() {
+            return Err(McpError::invalid_params(
+                "PROMPT is required and must be a non-empty string",
+                None,
+            ));
+        }
+        let prompt = history::expand_references(&args.prompt)
+            .map_err(|e| McpError::invalid_params(format!("PROMPT: {}", e), None))?;
+
+        let configured = codex::consensus_profiles();
+        if configured.is_empty() {
+            return Err(McpError::invalid_request(
+                "codex_consensus requires consensus_profiles in codex-mcp.config.json",
+                None,
+            ));
+        }
+
+        let mut profiles = Vec::new();
+        for name in &args.profiles {
+            match configured.iter().find(|p| &p.name == name) {
+                Some(profile) => profiles.push(profile.clone()),
+                None => {
+                    return Err(McpError::invalid_params(
+                        format!("unknown consensus profile: {}", name),
+                        None,
+                    ))
+                }
+            }
+        }
+        if profiles.is_empty() {
+            profiles = configured.to_vec();
+        }
+
+        let working_dir = resolve_working_dir(args.working_dir)?;
+        // Consensus runs are read-only whatever the directory's default
+        ensure_trusted(&context, &working_dir).await?;
+
+        let result = consensus::run(&prompt, working_dir, &profiles).await;
+
+        let toon_output = toon_format::encode_default(&result).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Runs several prompts in one Codex session, each optionally followed by a
+    /// verify command, and reports every step plus the overall status.
+    #[tool(
+        name = "codex_pipeline",
+        description = "Run an ordered list of codex steps (PROMPT, optional sandbox, optional verify command) in one session, stopping at the first failure; returns per-step results and overall status"
+    )]
+    async fn codex_pipeline(
+        &self,
+        Parameters(args): Parameters<PipelineArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let ceiling = SandboxMode::from_args(&codex::default_additional_args());
+        pipeline::validate(&args.steps, ceiling, codex::allow_verify_commands())
+            .map_err(|e| McpError::invalid_params(e, None))?;
+
+        let mut steps = args.steps;
+        for (index, step) in steps.iter_mut().enumerate() {
+            step.prompt = history::expand_references(&step.prompt).map_err(|e| {
+                McpError::invalid_params(format!("step {}: PROMPT: {}", index + 1, e), None)
+            })?;
+        }
+
+        let working_dir = resolve_working_dir(args.working_dir)?;
+        if let Some(mode) = ensure_trusted(&context, &working_dir).await? {
+            for step in steps.iter_mut().filter(|step| step.sandbox.is_none()) {
+                step.sandbox = Some(mode.as_str().to_string());
+            }
+        }
+
+        let result = pipeline::run(working_dir, steps).await;
+
+        let toon_output = toon_format::encode_default(&result).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Lists configured schedules with their next and last runs, or enables or
+    /// disables one until the server restarts.
+    #[tool(
+        name = "codex_schedules",
+        description = "List scheduled Codex runs (cron, next run, last run id/result) or enable/disable a schedule at runtime"
+    )]
+    async fn codex_schedules(
+        &self,
+        Parameters(args): Parameters<SchedulesArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let enabled = match args.action {
+            ScheduleAction::List => None,
+            ScheduleAction::Enable => Some(true),
+            ScheduleAction::Disable => Some(false),
+        };
+        if let Some(enabled) = enabled {
+            let name = args.name.as_deref().ok_or_else(|| {
+                McpError::invalid_params("name is required to enable or disable a schedule", None)
+            })?;
+            schedules::set_enabled(name, enabled).map_err(|e| McpError::invalid_params(e, None))?;
+        }
+
+        let toon_output = toon_format::encode_default(&schedules::list()).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Reports rolling statistics over recent runs so clients can tune their own
+    /// timeouts and retries.
+    #[tool(
+        name = "codex_status",
+        description = "Show rolling statistics over the last N codex runs (average, moving average, and p95 duration; failure and timeout rates; runs and tokens per task kind), today's tokens per identity, and the startup warm-up result"
+    )]
+    async fn codex_status(
+        &self,
+        Parameters(args): Parameters<StatusArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let window = args.window.unwrap_or(DEFAULT_STATUS_WINDOW);
+        if window == 0 {
+            return Err(McpError::invalid_params("window must be at least 1", None));
+        }
+
+        let output = StatusOutput {
+            stats: history::stats(window),
+            warm_up: warm_up::status(),
+            identities: identities::usage(codex::identities()),
+        };
 
         let toon_output = toon_format::encode_default(&output).map_err(|e| {
             McpError::internal_error(format!("Failed to serialize output: {}", e), None)
         })?;
 
-        // Return structured content so callers can inspect success, error, and warning fields
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Groups failed runs by the fingerprint of their normalized error, so
+    /// a repeated failure shows up as one line with a count
+    #[tool(
+        name = "codex_top_failures",
+        description = "List the most frequent failures of recent codex runs, grouped by a fingerprint of the normalized error (ids, paths, and numbers masked), with counts over the last since_secs seconds (default one day), the total since startup, and the latest run id"
+    )]
+    async fn codex_top_failures(
+        &self,
+        Parameters(args): Parameters<TopFailuresArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = args.limit.unwrap_or(DEFAULT_TOP_FAILURES);
+        if limit == 0 {
+            return Err(McpError::invalid_params("limit must be at least 1", None));
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let since = now.saturating_sub(args.since_secs.unwrap_or(DEFAULT_TOP_FAILURES_SECS));
+
+        let output = TopFailuresOutput {
+            since,
+            failures: history::top_failures(since, limit),
+        };
+
+        let toon_output = toon_format::encode_default(&output).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Aggregates the runs recorded under one `TASK_ID`
+    #[tool(
+        name = "codex_task_summary",
+        description = "Summarize all runs tagged with a TASK_ID: latest status, failed runs, total duration and tokens, and files changed"
+    )]
+    async fn codex_task_summary(
+        &self,
+        Parameters(args): Parameters<TaskSummaryArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let summary = history::task_summary(args.task_id.trim()).ok_or_else(|| {
+            McpError::invalid_params(
+                format!("no runs recorded for TASK_ID {}", args.task_id),
+                None,
+            )
+        })?;
+
+        let toon_output = toon_format::encode_default(&summary).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Converts review findings into a GitHub pull request review
+    #[tool(
+        name = "codex_review_export",
+        description = "Convert review findings (from a run's JSON answer via RUN_ID, or FINDINGS) into GitHub pull request review JSON with line comments labeled by severity; with PULL_REQUEST, post it using the configured token"
+    )]
+    async fn codex_review_export(
+        &self,
+        Parameters(args): Parameters<ReviewExportArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let findings = match (args.findings, args.run_id.as_deref()) {
+            (Some(_), Some(_)) => {
+                return Err(McpError::invalid_params(
+                    "RUN_ID and FINDINGS cannot be combined",
+                    None,
+                ))
+            }
+            (Some(findings), None) => findings,
+            (None, Some(run_id)) => {
+                let run = history::get(run_id.trim()).ok_or_else(|| {
+                    McpError::invalid_params(format!("unknown RUN_ID {}", run_id), None)
+                })?;
+                review_export::parse_findings(&run.agent_messages)
+                    .map_err(|e| McpError::invalid_params(e, None))?
+            }
+            (None, None) => {
+                return Err(McpError::invalid_params(
+                    "one of RUN_ID or FINDINGS is required",
+                    None,
+                ))
+            }
+        };
+        let pull_request = args
+            .pull_request
+            .as_deref()
+            .map(PullRequest::parse)
+            .transpose()
+            .map_err(|e| McpError::invalid_params(format!("PULL_REQUEST: {}", e), None))?;
+
+        let config = codex::github_review();
+        let review = review_export::build(
+            &findings,
+            args.body.as_deref(),
+            args.commit_id,
+            &config.severity_labels,
+        );
+        let posted_url = match &pull_request {
+            Some(pull_request) => Some(
+                review_export::post(&review, pull_request, config)
+                    .await
+                    .map_err(|e| McpError::internal_error(e, None))?,
+            ),
+            None => None,
+        };
+
+        let output = ReviewExportOutput { review, posted_url };
+        let toon_output = toon_format::encode_default(&output).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Repeats a recorded run with the options it was started with, against
+    /// the workspace as it is now
+    #[tool(
+        name = "codex_rerun",
+        description = "Re-execute an earlier codex run from run history (RUN_ID) with exactly the same resolved prompt, arguments, sandbox, and model, in a new session against the current workspace. The new run is recorded with rerun_of and the original's TASK_ID"
+    )]
+    async fn codex_rerun(
+        &self,
+        Parameters(args): Parameters<RerunArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let run_id = args.run_id.trim();
+        let original = history::get(run_id).ok_or_else(|| {
+            McpError::invalid_params(format!("run {} is not in run history", run_id), None)
+        })?;
+        let invocation = original.invocation.ok_or_else(|| {
+            McpError::invalid_params(
+                format!(
+                    "run {} cannot be rerun; only codex runs in the default mode keep their options",
+                    run_id
+                ),
+                None,
+            )
+        })?;
+        if !original.working_dir.is_dir() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "working directory of run {} no longer exists: {}",
+                    run_id,
+                    original.working_dir.display()
+                ),
+                None,
+            ));
+        }
+        ensure_trusted(&context, &original.working_dir).await?;
+
+        let prompt = invocation.prompt.clone();
+        let opts = Options {
+            prompt: invocation.prompt,
+            primer_messages: invocation.primer_messages,
+            working_dir: original.working_dir,
+            additional_args: invocation.additional_args,
+            image_paths: invocation.image_paths,
+            timeout_secs: invocation.timeout_secs,
+            codex_home: invocation.codex_home,
+            allowed_paths: invocation.allowed_paths,
+            source: Some("rerun".to_string()),
+            progress: progress_forwarder(&context),
+            return_diff: invocation.return_diff,
+            task_id: original.task_id,
+            cancel: Some(context.ct.clone()),
+            final_schema: invocation.final_schema,
+            meta: call_meta(&context),
+            rerun_of: Some(original.run_id),
+            response_language: invocation.response_language,
+            ..Default::default()
+        };
+        let result = codex::run(opts).await.map_err(run_error)?;
+
+        let combined_warnings = client_warnings(&result, codex::client_warning_level());
+        let mut output = build_codex_output(&result, false, combined_warnings);
+        let insights = client_sampling::insights(
+            &context.peer,
+            codex::client_sampling_config(),
+            &result,
+            &prompt,
+        )
+        .await;
+        output.apply_insights(insights);
+        let toon_output = encode_capped(&mut output, codex::max_response_bytes())?;
+
+        codex_response(&output, toon_output, Vec::new())
+    }
+
+    /// Returns the events a run produced since a cursor, for clients that
+    /// poll instead of receiving progress notifications
+    #[tool(
+        name = "codex_tail",
+        description = "Poll the live event stream of a codex run: returns events and agent text since CURSOR plus the next cursor, and whether the run finished. Without RUN_ID, lists runs that can be tailed"
+    )]
+    async fn codex_tail(
+        &self,
+        Parameters(args): Parameters<TailArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let toon_output = match args.run_id {
+            Some(run_id) => {
+                let max_events = args.max_events.unwrap_or(100).clamp(1, 500);
+                let page =
+                    run_tail::read(run_id.trim(), args.cursor, max_events).ok_or_else(|| {
+                        McpError::invalid_params(
+                            format!("run {} is not running or no longer buffered", run_id),
+                            None,
+                        )
+                    })?;
+                toon_format::encode_default(&page)
+            }
+            None => toon_format::encode_default(&serde_json::json!({ "runs": run_tail::runs() })),
+        }
+        .map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Reports whether a diff applies to the working tree, file by file,
+    /// without changing anything
+    #[tool(
+        name = "codex_preview_patch",
+        description = "Check whether a diff (DIFF, or the stored diff of RUN_ID) applies cleanly to the current working tree without modifying it; returns per-file status: applies, already_applied, or conflicts with git's errors"
+    )]
+    async fn codex_preview_patch(
+        &self,
+        Parameters(args): Parameters<PreviewPatchArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let diff = match (args.diff, args.run_id) {
+            (Some(_), Some(_)) => {
+                return Err(McpError::invalid_params(
+                    "RUN_ID and DIFF cannot be combined",
+                    None,
+                ))
+            }
+            (Some(diff), None) => diff,
+            (None, Some(run_id)) => {
+                let run_id = run_id.trim();
+                if run_diff::find_stored(&codex::runs_dir(), run_id).is_none() {
+                    return Err(McpError::invalid_params(
+                        format!(
+                            "run {} has no stored diff; pass the diff it returned as DIFF",
+                            run_id
+                        ),
+                        None,
+                    ));
+                }
+                run_resources::read(&RunResource::Diff.uri(run_id))
+                    .map_err(|e| McpError::invalid_params(e, None))?
+            }
+            (None, None) => {
+                return Err(McpError::invalid_params(
+                    "one of DIFF or RUN_ID is required",
+                    None,
+                ))
+            }
+        };
+        let working_dir = resolve_working_dir(args.working_dir)?;
+        ensure_trusted(&context, &working_dir).await?;
+
+        let report = patch_preview::preview(&working_dir, &diff)
+            .await
+            .map_err(|e| McpError::invalid_params(e, None))?;
+
+        let toon_output = toon_format::encode_default(&report).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Imports a session started with the Codex CLI in a terminal
+    #[tool(
+        name = "codex_import_session",
+        description = "Import a Codex CLI session from its rollout file in ~/.codex/sessions so it can be resumed with SESSION_ID or RESUME_LAST; returns the transcript and a run_id for {{run:..}} references"
+    )]
+    async fn codex_import_session(
+        &self,
+        Parameters(args): Parameters<ImportSessionArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let imported = session_import::import(args.session_id.as_deref(), args.file.as_deref())
+            .map_err(|e| McpError::invalid_params(e, None))?;
+
+        let toon_output = toon_format::encode_default(&imported).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Summarizes the repository layout for clients to put into their prompts
+    #[tool(
+        name = "codex_repo_map",
+        description = "Summarize a git repository for prompting: top directories, key files, line counts, and languages; cached until HEAD moves. Pass ATTACH_REPO_MAP to the codex tool to prepend it automatically"
+    )]
+    async fn codex_repo_map(
+        &self,
+        Parameters(args): Parameters<RepoMapArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let working_dir = resolve_working_dir(args.working_dir)?;
+        ensure_trusted(&context, &working_dir).await?;
+
+        let map = repo_map::get(&working_dir, args.refresh)
+            .await
+            .map_err(|e| McpError::invalid_params(e, None))?;
+
+        let toon_output = toon_format::encode_default(&map).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Runs one of a few vetted, read-only Codex CLI subcommands and returns its
+    /// output. Disabled unless `enable_codex_raw` is set in the config file.
+    #[tool(
+        name = "codex_raw",
+        description = "Troubleshoot the Codex CLI: run a vetted subcommand (--version, login status, features list) and return its exit code, stdout, and stderr. Requires enable_codex_raw in the server config"
+    )]
+    async fn codex_raw(
+        &self,
+        Parameters(args): Parameters<RawArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        if !codex::codex_raw_enabled() {
+            return Err(McpError::invalid_request(
+                "codex_raw is disabled; set \"enable_codex_raw\": true in codex-mcp.config.json",
+                None,
+            ));
+        }
+        let cli_args =
+            raw_command::parse(&args.command).map_err(|e| McpError::invalid_params(e, None))?;
+
+        let output = raw_command::run(&codex::codex_bin(), &cli_args)
+            .await
+            .map_err(|e| McpError::internal_error(e, None))?;
+
+        let toon_output = toon_format::encode_default(&output).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Checks a workspace without starting Codex: the directory exists, is a git
+    /// repository, AGENTS.md loads, no other run holds the workspace lock, and the
+    /// prompt is a reasonable size.
+    #[tool(
+        name = "codex_preflight",
+        description = "Check whether a workspace is ready for a codex run (directory, git repo, AGENTS.md, repo size, workspace locks, estimated prompt size) and return a ready/not-ready verdict with reasons"
+    )]
+    async fn codex_preflight(
+        &self,
+        Parameters(args): Parameters<PreflightArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut working_dir = match args.working_dir {
+            Some(dir) => dir,
+            None => std::env::current_dir().map_err(|e| {
+                McpError::invalid_params(
+                    format!("failed to resolve current working directory: {}", e),
+                    None,
+                )
+            })?,
+        };
+        // A missing directory is one of the checks, so it is reported rather
+        // than refused; there is nothing in it to protect
+        if let Ok(canonical) = working_dir.canonicalize() {
+            ensure_trusted(&context, &canonical).await?;
+            working_dir = canonical;
+        }
+
+        let report = preflight::check(&working_dir, &args.prompt, args.skip_git_check).await;
+
+        let toon_output = toon_format::encode_default(&report).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Reports the state of a Codex Cloud task submitted via `CLOUD_ENV`
+    #[tool(
+        name = "codex_cloud_status",
+        description = "Poll a Codex Cloud task started by the codex tool with CLOUD_ENV (runs `codex cloud status <task_id>`)"
+    )]
+    async fn codex_cloud_status(
+        &self,
+        Parameters(args): Parameters<CloudStatusArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let output = cloud::status(&codex::codex_bin(), &args.task_id)
+            .await
+            .map_err(|e| McpError::invalid_request(e, None))?;
+
+        let toon_output = toon_format::encode_default(&output).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(if output.exit_code == Some(0) {
+            CallToolResult::success(vec![Content::text(toon_output)])
+        } else {
+            CallToolResult::error(vec![Content::text(toon_output)])
+        })
+    }
+
+    /// Runs sample text through the configured redaction patterns, whether or
+    /// not storage redaction is enabled, so patterns can be checked before
+    /// relying on them.
+    #[tool(
+        name = "codex_redact_test",
+        description = "Check the secret-redaction patterns: return the sample text as it would be stored in run logs and history, with match counts per pattern"
+    )]
+    async fn codex_redact_test(
+        &self,
+        Parameters(args): Parameters<RedactTestArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let (redacted, matches) = codex::configured_redactor().redact_counting(&args.text);
+        let output = RedactTestOutput {
+            enabled: codex::storage_redactor().is_some(),
+            redacted,
+            matches,
+        };
+
+        let toon_output = toon_format::encode_default(&output).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Removes files earlier runs left behind and reports what was reclaimed
+    #[tool(
+        name = "codex_cleanup",
+        description = "Remove artifacts this server left behind: stale workspace locks, temp directories of finished runs (isolated CODEX_HOMEs, processed images, PDF pages), old spilled parse-error lines, and run logs past runs_retention. Reports files removed and bytes freed per category"
+    )]
+    async fn codex_cleanup(&self) -> Result<CallToolResult, McpError> {
+        let report = tokio::task::spawn_blocking(cleanup::run)
+            .await
+            .map_err(|e| McpError::internal_error(format!("cleanup failed: {}", e), None))?;
+
+        let toon_output = toon_format::encode_default(&report).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(toon_output)]))
+    }
+
+    /// Reports the configuration the server is actually using, after applying
+    /// environment variables, the config file, and built-in defaults. Secret-looking
+    /// CLI flag values are masked.
+    #[tool(
+        name = "codex_config",
+        description = "Show the effective codex-mcp configuration (config file path, resolved additional_args with secrets masked, timeout, lock policy)"
+    )]
+    async fn codex_config(&self) -> Result<CallToolResult, McpError> {
+        let config = codex::effective_config();
+
+        let toon_output = toon_format::encode_default(&config).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize output: {}", e), None)
+        })?;
+
         Ok(CallToolResult::success(vec![Content::text(toon_output)]))
     }
 }
 
-#[tool_handler]
 impl ServerHandler for CodexServer {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
             capabilities: ServerCapabilities::builder()
                 .enable_tools()
+                .enable_tool_list_changed()
+                .enable_resources()
+                .enable_logging()
                 .build(),
             server_info: Implementation::from_build_env(),
-            instructions: Some("This server provides a codex tool for AI-assisted coding tasks. Use the codex tool to execute coding tasks via the Codex CLI.".to_string()),
+            instructions: Some(Features::from_config(&codex::effective_config()).instructions()),
+        }
+    }
+
+    /// Remember the session, so it hears about `disabled_tools` changes
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        tool_toggle::register(context.peer);
+    }
+
+    /// Registered tools, minus those in `disabled_tools`
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(tool_toggle::enabled(
+            self.tool_router.list_all(),
+        )))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if tool_toggle::is_disabled(&request.name) {
+            return Err(McpError::invalid_request(
+                format!(
+                    "{} is disabled by disabled_tools in the server config",
+                    request.name
+                ),
+                None,
+            ));
+        }
+        let tool_context = ToolCallContext::new(self, request, context);
+        self.tool_router.call(tool_context).await
+    }
+
+    /// Answer pings with a self-check. Failed checks are sent as a warning log
+    /// message, so orchestrators can restart a degraded server; the ping
+    /// itself still succeeds.
+    async fn ping(&self, context: RequestContext<RoleServer>) -> Result<(), McpError> {
+        let report = health::check().await;
+        if !report.healthy {
+            eprintln!(
+                "Warning: health check failed: {}",
+                report.problems.join("; ")
+            );
+            let _ = context
+                .peer
+                .notify_logging_message(LoggingMessageNotificationParam {
+                    level: LoggingLevel::Warning,
+                    logger: Some("codex-mcp-rs.health".to_string()),
+                    data: serde_json::to_value(&report).unwrap_or_default(),
+                })
+                .await;
         }
+        Ok(())
+    }
+
+    /// Full messages and stored diffs of the runs still in history
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let resources = run_resources::list()
+            .into_iter()
+            .map(|(run_id, kind)| Annotated::new(run_resource(&run_id, kind), None))
+            .collect();
+        Ok(ListResourcesResult::with_all_items(resources))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let text =
+            run_resources::read(&request.uri).map_err(|e| McpError::resource_not_found(e, None))?;
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::text(text, request.uri)],
+        })
     }
 }
 
@@ -257,4 +2583,128 @@ impl ServerHandler for CodexServer {
 mod tests {
     #[allow(unused_imports)]
     use super::*;
+
+    #[test]
+    fn test_check_unknown_fields_suggests_nearest() {
+        let args: CodexArgs =
+            serde_json::from_value(serde_json::json!({"PROMPT": "x", "SESION_ID": "y", "zzz": 1}))
+                .unwrap();
+        assert_eq!(args.unknown_fields.len(), 2);
+
+        let err = check_unknown_fields::<CodexArgs>(&args.unknown_fields).unwrap_err();
+        assert!(err.message.contains("SESION_ID (did you mean SESSION_ID?)"));
+        assert!(err.message.contains("zzz"));
+        assert!(check_unknown_fields::<CodexArgs>(&BTreeMap::new()).is_ok());
+    }
+
+    #[test]
+    fn test_working_dir_is_a_known_field() {
+        let args: CodexArgs = serde_json::from_value(
+            serde_json::json!({"PROMPT": "x", "WORKING_DIR": "/repo", "WORKNG_DIR": "/repo"}),
+        )
+        .unwrap();
+        assert_eq!(args.working_dir, Some(PathBuf::from("/repo")));
+        let err = check_unknown_fields::<CodexArgs>(&args.unknown_fields).unwrap_err();
+        assert!(err
+            .message
+            .contains("WORKNG_DIR (did you mean WORKING_DIR?)"));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("session_id", "session_id"), 0);
+        assert_eq!(edit_distance("sesion_id", "session_id"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_client_warnings_filters_by_level() {
+        let result = codex::CodexResult {
+            warnings: Some("scope".to_string()),
+            operator_warnings: Some("stderr noise".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            client_warnings(&result, WarningLevel::All).as_deref(),
+            Some("stderr noise\nscope")
+        );
+        assert_eq!(
+            client_warnings(&result, WarningLevel::Client).as_deref(),
+            Some("scope")
+        );
+        assert_eq!(client_warnings(&result, WarningLevel::None), None);
+    }
+
+    #[test]
+    fn test_encode_capped_downgrades_until_it_fits() {
+        let mut message = HashMap::new();
+        message.insert("text".to_string(), Value::String("x".repeat(4000)));
+        let result = codex::CodexResult {
+            run_id: "run-1".to_string(),
+            success: true,
+            agent_messages: format!("{}final answer", "a".repeat(4000)),
+            all_messages: vec![message],
+            ..Default::default()
+        };
+
+        let mut output = build_codex_output(&result, true, None);
+        let uncapped = encode_capped(&mut output, None).unwrap();
+        assert!(uncapped.len() > 8000);
+
+        let mut output = build_codex_output(&result, true, None);
+        let encoded = encode_capped(&mut output, Some(1000)).unwrap();
+        assert!(encoded.len() <= 1000);
+        assert!(output.all_messages.is_none());
+        assert!(output.message.ends_with("final answer"));
+        assert_eq!(output.agent_messages_truncated, Some(true));
+        let warnings = output.warnings.unwrap();
+        assert!(warnings.contains("dropped all_messages; cut the start of message"));
+    }
+
+    #[test]
+    fn test_human_text_shows_summary_and_answer() {
+        let result = codex::CodexResult {
+            success: true,
+            agent_messages: "Renamed the flag.".to_string(),
+            ..Default::default()
+        };
+        let output = build_codex_output(&result, false, None);
+        assert_eq!(
+            human_text(&output),
+            format!("{}\n\nRenamed the flag.", output.summary)
+        );
+
+        let result = codex::CodexResult {
+            error: Some("codex error: 401 Unauthorized".to_string()),
+            ..Default::default()
+        };
+        let output = build_codex_output(&result, false, None);
+        assert!(human_text(&output).ends_with("\n\nError: codex error: 401 Unauthorized"));
+    }
+
+    #[test]
+    fn test_instructions_follow_enabled_features() {
+        let minimal = Features::default().instructions();
+        assert!(minimal.contains("read-only sandbox"));
+        assert!(minimal.contains("Continue a conversation by passing the returned SESSION_ID"));
+        assert!(!minimal.contains("codex_consensus"));
+        assert!(!minimal.contains("codex_raw"));
+
+        let full = Features {
+            sandbox: SandboxMode::WorkspaceWrite,
+            sessions: false,
+            progress: true,
+            consensus_profiles: vec!["o3".to_string(), "gpt-5".to_string()],
+            schedules: 1,
+            pipeline_verify: true,
+            codex_raw: true,
+            strict_arguments: true,
+        }
+        .instructions();
+        assert!(full.contains("workspace-write sandbox."));
+        assert!(full.contains("SESSION_ID and RESUME_LAST are unavailable"));
+        assert!(full.contains("several models (o3, gpt-5)"));
+        assert!(full.contains("verify command"));
+        assert!(full.contains("codex_raw"));
+    }
 }
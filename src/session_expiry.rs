@@ -0,0 +1,147 @@
+use crate::session_import;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+/// `session_expiry` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SessionExpiryConfig {
+    /// Sessions unused for longer than this are not resumed
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+    /// Refuse to resume sessions whose rollout file cannot be found
+    #[serde(default)]
+    pub require_rollout: bool,
+}
+
+impl SessionExpiryConfig {
+    fn enabled(&self) -> bool {
+        self.max_age_secs.is_some() || self.require_rollout
+    }
+}
+
+/// Error returned instead of resuming a session the CLI can no longer resume
+#[derive(Debug, Clone)]
+pub struct SessionExpired {
+    pub session_id: String,
+    pub reason: String,
+    /// Seconds since the session was last used, when known
+    pub idle_secs: Option<u64>,
+}
+
+impl fmt::Display for SessionExpired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SessionExpired: session {} {}; start a new session by omitting SESSION_ID",
+            self.session_id, self.reason
+        )
+    }
+}
+
+impl std::error::Error for SessionExpired {}
+
+/// When this server last ran each session
+fn last_used() -> &'static Mutex<HashMap<String, SystemTime>> {
+    static LAST_USED: OnceLock<Mutex<HashMap<String, SystemTime>>> = OnceLock::new();
+    LAST_USED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Note that `session_id` was just started or resumed
+pub fn touch(session_id: &str) {
+    if let Ok(mut sessions) = last_used().lock() {
+        sessions.insert(session_id.to_string(), SystemTime::now());
+    }
+}
+
+/// Check that `session_id` can still be resumed. Its last use is the later of
+/// this server's own record and its rollout file's modification time, which
+/// the CLI updates on every turn, so sessions continued elsewhere count too.
+pub fn check(
+    config: &SessionExpiryConfig,
+    session_id: &str,
+    sessions_dir: Option<&Path>,
+) -> Result<(), SessionExpired> {
+    if !config.enabled() {
+        return Ok(());
+    }
+    let rollout = sessions_dir.and_then(|dir| session_import::find(dir, session_id));
+    let recorded = last_used()
+        .lock()
+        .ok()
+        .and_then(|sessions| sessions.get(session_id).copied());
+    let modified = rollout
+        .as_ref()
+        .and_then(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok());
+    let used = recorded.max(modified);
+    let idle = used.map(|used| SystemTime::now().duration_since(used).unwrap_or_default());
+    let expired = |reason: String| SessionExpired {
+        session_id: session_id.to_string(),
+        reason,
+        idle_secs: idle.map(|idle| idle.as_secs()),
+    };
+
+    if config.require_rollout && rollout.is_none() {
+        let reason = match sessions_dir {
+            Some(dir) => format!("has no rollout file under {}", dir.display()),
+            None => "has no rollout file: the Codex home could not be located".to_string(),
+        };
+        return Err(expired(reason));
+    }
+    if let (Some(max_age), Some(idle)) = (config.max_age_secs, idle) {
+        if idle > Duration::from_secs(max_age) {
+            return Err(expired(format!(
+                "was last used {} seconds ago, more than session_expiry.max_age_secs ({})",
+                idle.as_secs(),
+                max_age
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checks_rollout_and_age() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let day = temp_dir.path().join("2025/01/02");
+        std::fs::create_dir_all(&day).unwrap();
+        let rollout = day.join("rollout-2025-01-02T10-00-00-old-session.jsonl");
+        std::fs::write(&rollout, "{}\n").unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(&rollout)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+
+        let dir = Some(temp_dir.path());
+        let off = SessionExpiryConfig::default();
+        assert!(check(&off, "missing", dir).is_ok());
+
+        let config = SessionExpiryConfig {
+            max_age_secs: Some(600),
+            require_rollout: true,
+        };
+        let err = check(&config, "old-session", dir).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("SessionExpired: session old-session was last used"));
+        assert!(err.idle_secs.unwrap() >= 3600);
+
+        // A resume through this server counts as use
+        touch("old-session");
+        assert!(check(&config, "old-session", dir).is_ok());
+
+        let err = check(&config, "missing", dir).unwrap_err();
+        assert!(err.reason.starts_with("has no rollout file"));
+        assert_eq!(err.idle_secs, None);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::codex;
+use crate::codex_home;
+use crate::history::{self, RunRecord};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// At most this many transcript messages are returned (the last ones are kept)
+const MAX_RETURNED_MESSAGES: usize = 50;
+
+/// Injected context the CLI adds as user messages; not part of the conversation
+const CONTEXT_PREFIXES: &[&str] = &["<environment_context>", "<user_instructions>"];
+
+/// One user or assistant message of a rollout
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TranscriptMessage {
+    pub role: String,
+    pub text: String,
+}
+
+/// A session read from a Codex CLI rollout file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Transcript {
+    #[serde(rename = "SESSION_ID")]
+    pub session_id: String,
+    /// Directory the session was started in, when the rollout records it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cli_version: Option<String>,
+    pub messages: Vec<TranscriptMessage>,
+}
+
+/// Result of `codex_import_session`
+#[derive(Debug, Serialize)]
+pub struct ImportedSession {
+    /// History entry holding the transcript, usable in `{{run:..}}` references
+    pub run_id: String,
+    pub file: PathBuf,
+    #[serde(flatten)]
+    pub transcript: Transcript,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub messages_truncated: bool,
+}
+
+/// Where the Codex CLI keeps rollout files: `$CODEX_HOME/sessions`
+pub fn sessions_dir() -> Option<PathBuf> {
+    codex_home::default_template().map(|home| home.join("sessions"))
+}
+
+/// Import a terminal session, found by `session_id` or given as a rollout
+/// `file` under the sessions directory. The session becomes the last one seen
+/// for its working directory, and its transcript is recorded in run history.
+pub fn import(session_id: Option<&str>, file: Option<&Path>) -> Result<ImportedSession, String> {
+    let sessions_dir =
+        sessions_dir().ok_or("cannot locate the Codex home: set CODEX_HOME or HOME")?;
+    let file = match (session_id, file) {
+        (_, Some(file)) => {
+            let file = std::fs::canonicalize(file)
+                .map_err(|e| format!("cannot read {}: {}", file.display(), e))?;
+            let root =
+                std::fs::canonicalize(&sessions_dir).unwrap_or_else(|_| sessions_dir.clone());
+            if !file.starts_with(&root) {
+                return Err(format!(
+                    "{} is not under the Codex sessions directory {}",
+                    file.display(),
+                    root.display()
+                ));
+            }
+            file
+        }
+        (Some(session_id), None) => find(&sessions_dir, session_id.trim()).ok_or_else(|| {
+            format!(
+                "no rollout file for session {} under {}",
+                session_id,
+                sessions_dir.display()
+            )
+        })?,
+        (None, None) => return Err("either SESSION_ID or file is required".to_string()),
+    };
+
+    let content = std::fs::read_to_string(&file)
+        .map_err(|e| format!("cannot read {}: {}", file.display(), e))?;
+    let mut transcript = parse(&content)?;
+    if let Some(expected) = session_id {
+        if transcript.session_id != expected.trim() {
+            return Err(format!(
+                "{} belongs to session {}, not {}",
+                file.display(),
+                transcript.session_id,
+                expected
+            ));
+        }
+    }
+
+    if let Some(working_dir) = &transcript.working_dir {
+        codex::record_session(working_dir, &transcript.session_id);
+    }
+    let run_id = Uuid::new_v4().to_string();
+    history::record(RunRecord {
+        run_id: run_id.clone(),
+        source: "import".to_string(),
+        working_dir: transcript.working_dir.clone().unwrap_or_default(),
+        started_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        duration_ms: 0,
+        success: true,
+        timed_out: false,
+        session_id: transcript.session_id.clone(),
+        error: None,
+        failure_fingerprint: None,
+        resource_usage: None,
+        task_id: None,
+        meta: None,
+        token_usage: None,
+        changed_files: Vec::new(),
+        diff_file: None,
+        empty_response_retries: 0,
+        task_kind: None,
+        rerun_of: None,
+        primer_messages: 0,
+        agent_messages: transcript
+            .messages
+            .iter()
+            .filter(|m| m.role == "assistant")
+            .map(|m| m.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        invocation: None,
+    });
+
+    let dropped = transcript
+        .messages
+        .len()
+        .saturating_sub(MAX_RETURNED_MESSAGES);
+    transcript.messages.drain(..dropped);
+    Ok(ImportedSession {
+        run_id,
+        file,
+        transcript,
+        messages_truncated: dropped > 0,
+    })
+}
+
+/// Rollout file of `session_id`; the CLI names them
+/// `YYYY/MM/DD/rollout-<timestamp>-<session id>.jsonl`
+pub(crate) fn find(dir: &Path, session_id: &str) -> Option<PathBuf> {
+    if session_id.is_empty() {
+        return None;
+    }
+    let suffix = format!("{}.jsonl", session_id);
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("rollout-") && name.ends_with(&suffix))
+            {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+/// Parse a rollout: a `session_meta` line followed by `response_item` lines.
+/// Older CLIs wrote the metadata and items without the `type`/`payload`
+/// envelope, which is accepted too.
+fn parse(content: &str) -> Result<Transcript, String> {
+    let mut transcript = Transcript::default();
+    for line in content.lines() {
+        let Ok(value) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        let payload = value.get("payload");
+        match value.get("type").and_then(Value::as_str) {
+            Some("session_meta") => read_meta(&mut transcript, payload.unwrap_or(&value)),
+            Some("response_item") => {
+                transcript.messages.extend(payload.and_then(read_message));
+            }
+            Some("message") => transcript.messages.extend(read_message(&value)),
+            Some(_) => {}
+            None if transcript.session_id.is_empty() => read_meta(&mut transcript, &value),
+            None => {}
+        }
+    }
+    if transcript.session_id.is_empty() {
+        return Err("not a Codex rollout file: no session id found".to_string());
+    }
+    Ok(transcript)
+}
+
+fn read_meta(transcript: &mut Transcript, meta: &Value) {
+    let field = |name: &str| meta.get(name).and_then(Value::as_str).map(str::to_string);
+    if let Some(id) = field("id") {
+        transcript.session_id = id;
+    }
+    transcript.working_dir = field("cwd").map(PathBuf::from);
+    transcript.cli_version = field("cli_version");
+}
+
+fn read_message(item: &Value) -> Option<TranscriptMessage> {
+    if item.get("type").and_then(Value::as_str) != Some("message") {
+        return None;
+    }
+    let role = item.get("role").and_then(Value::as_str)?;
+    if role != "user" && role != "assistant" {
+        return None;
+    }
+    let text = item
+        .get("content")?
+        .as_array()?
+        .iter()
+        .filter_map(|part| part.get("text").and_then(Value::as_str))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let trimmed = text.trim_start();
+    if text.is_empty() || CONTEXT_PREFIXES.iter().any(|p| trimmed.starts_with(p)) {
+        return None;
+    }
+    Some(TranscriptMessage {
+        role: role.to_string(),
+        text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rollout() {
+        let rollout = r#"{"timestamp":"2025-09-01T10:00:00Z","type":"session_meta","payload":{"id":"0199-abc","cwd":"/work/repo","cli_version":"0.36.0"}}
+{"type":"response_item","payload":{"type":"message","role":"user","content":[{"type":"input_text","text":"<environment_context>\n<cwd>/work/repo</cwd>\n</environment_context>"}]}}
+{"type":"response_item","payload":{"type":"message","role":"user","content":[{"type":"input_text","text":"fix the test"}]}}
+{"type":"response_item","payload":{"type":"reasoning","summary":[]}}
+{"type":"event_msg","payload":{"type":"agent_message","message":"done"}}
+{"type":"response_item","payload":{"type":"message","role":"assistant","content":[{"type":"output_text","text":"Fixed it."}]}}
+not json
+"#;
+        let transcript = parse(rollout).unwrap();
+        assert_eq!(transcript.session_id, "0199-abc");
+        assert_eq!(transcript.working_dir, Some(PathBuf::from("/work/repo")));
+        assert_eq!(transcript.cli_version.as_deref(), Some("0.36.0"));
+        assert_eq!(
+            transcript.messages,
+            vec![
+                TranscriptMessage {
+                    role: "user".to_string(),
+                    text: "fix the test".to_string(),
+                },
+                TranscriptMessage {
+                    role: "assistant".to_string(),
+                    text: "Fixed it.".to_string(),
+                },
+            ]
+        );
+
+        let legacy = r#"{"id":"old-session","timestamp":"2025-05-01T10:00:00Z"}
+{"type":"message","role":"assistant","content":[{"type":"output_text","text":"hi"}]}
+"#;
+        let transcript = parse(legacy).unwrap();
+        assert_eq!(transcript.session_id, "old-session");
+        assert_eq!(transcript.messages.len(), 1);
+
+        assert!(parse("{\"type\":\"response_item\"}\n").is_err());
+    }
+
+    #[test]
+    fn test_find_rollout_by_session_id() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let day = temp_dir.path().join("2025/09/01");
+        std::fs::create_dir_all(&day).unwrap();
+        let file = day.join("rollout-2025-09-01T10-00-00-0199-abc.jsonl");
+        std::fs::write(&file, "").unwrap();
+
+        assert_eq!(find(temp_dir.path(), "0199-abc"), Some(file));
+        assert_eq!(find(temp_dir.path(), "0199-xyz"), None);
+        assert_eq!(find(temp_dir.path(), ""), None);
+    }
+}
@@ -0,0 +1,405 @@
+use crate::codex::{self, SandboxMode};
+use crate::history;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Length of the sliding window for `rate_limit_per_minute`
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// One entry of `api_keys` in `codex-mcp.config.json`: a client allowed to
+/// call the server over HTTP, and the limits that apply to it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ApiKeyConfig {
+    /// Client name, used in errors and logs
+    pub name: String,
+    /// Environment variable holding the key, so the key itself stays out of
+    /// the config file
+    pub key_env: String,
+    /// Tool calls allowed per minute (unlimited when absent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_per_minute: Option<u32>,
+    /// Workspaces the client may run in, including their subdirectories
+    /// (any workspace when empty)
+    #[serde(default)]
+    pub allowed_workspaces: Vec<PathBuf>,
+    /// Most permissive sandbox the client may request
+    #[serde(default)]
+    pub max_sandbox: SandboxMode,
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denied {
+    UnknownKey,
+    RateLimited {
+        client: String,
+        retry_after_secs: u64,
+    },
+    Workspace {
+        client: String,
+        path: PathBuf,
+    },
+    Sandbox {
+        client: String,
+        requested: SandboxMode,
+        max: SandboxMode,
+    },
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Denied::UnknownKey => write!(f, "Unauthorized: unknown or missing API key"),
+            Denied::RateLimited {
+                client,
+                retry_after_secs,
+            } => write!(
+                f,
+                "RateLimited: {} exceeded its rate limit; retry in {}s",
+                client, retry_after_secs
+            ),
+            Denied::Workspace { client, path } => write!(
+                f,
+                "Forbidden: {} may not use workspace {}",
+                client,
+                path.display()
+            ),
+            Denied::Sandbox {
+                client,
+                requested,
+                max,
+            } => write!(
+                f,
+                "Forbidden: {} may not use sandbox {} (max {})",
+                client,
+                requested.as_str(),
+                max.as_str()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Denied {}
+
+/// Configured clients with their keys resolved, plus rate-limit state. A
+/// transport checks each request with `authorize_request` before dispatch:
+/// the key, then the rate limit, workspace, and sandbox of its tool calls.
+#[derive(Debug)]
+pub struct Tenants {
+    clients: Vec<(ApiKeyConfig, Option<String>)>,
+    calls: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Tenants {
+    /// Resolve each client's key from its `key_env`. Clients whose variable
+    /// is unset or empty can never authenticate.
+    pub fn new(clients: &[ApiKeyConfig]) -> Self {
+        let clients = clients
+            .iter()
+            .map(|client| {
+                let key = std::env::var(&client.key_env)
+                    .ok()
+                    .filter(|key| !key.trim().is_empty());
+                (client.clone(), key)
+            })
+            .collect();
+        Tenants {
+            clients,
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// The client owning `key`, counting the call against its rate limit
+    pub fn authenticate(&self, key: &str, now: Instant) -> Result<&ApiKeyConfig, Denied> {
+        let client = self.identify(key)?;
+        self.count_call(client, now)?;
+        Ok(client)
+    }
+
+    /// The client owning `key`
+    pub fn identify(&self, key: &str) -> Result<&ApiKeyConfig, Denied> {
+        self.clients
+            .iter()
+            .find(|(_, expected)| {
+                expected
+                    .as_deref()
+                    .is_some_and(|expected| constant_time_eq(expected, key))
+            })
+            .map(|(client, _)| client)
+            .ok_or(Denied::UnknownKey)
+    }
+
+    /// Count a tool call against `client`'s rate limit
+    pub fn count_call(&self, client: &ApiKeyConfig, now: Instant) -> Result<(), Denied> {
+        if let Some(limit) = client.rate_limit_per_minute {
+            let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+            let window = calls.entry(client.name.clone()).or_default();
+            while window
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW)
+            {
+                window.pop_front();
+            }
+            if window.len() >= limit as usize {
+                let retry_after = window
+                    .front()
+                    .map(|oldest| RATE_WINDOW.saturating_sub(now.duration_since(*oldest)))
+                    .unwrap_or(RATE_WINDOW);
+                return Err(Denied::RateLimited {
+                    client: client.name.clone(),
+                    retry_after_secs: retry_after.as_secs().max(1),
+                });
+            }
+            window.push_back(now);
+        }
+        Ok(())
+    }
+
+    /// Check one HTTP request carrying `body`, a JSON-RPC message or batch,
+    /// before it is dispatched: the bearer key on every request, then the
+    /// rate limit, workspace, and sandbox of each `tools/call` in it
+    pub fn authorize_request(
+        &self,
+        bearer: Option<&str>,
+        body: &[u8],
+        now: Instant,
+    ) -> Result<(), Denied> {
+        let client = self.identify(bearer.unwrap_or(""))?;
+        // Anything else is left for the transport to reject
+        let Ok(message) = serde_json::from_slice::<Value>(body) else {
+            return Ok(());
+        };
+        let messages = match message {
+            Value::Array(batch) => batch,
+            single => vec![single],
+        };
+        for message in &messages {
+            if message.get("method").and_then(Value::as_str) != Some("tools/call") {
+                continue;
+            }
+            self.count_call(client, now)?;
+            let params = message.get("params").unwrap_or(&Value::Null);
+            let (working_dir, sandbox) = requested_run(params);
+            Self::authorize_run(client, &working_dir, sandbox)?;
+        }
+        Ok(())
+    }
+
+    /// Check a run's working directory and sandbox against the client's limits
+    pub fn authorize_run(
+        client: &ApiKeyConfig,
+        working_dir: &Path,
+        sandbox: SandboxMode,
+    ) -> Result<(), Denied> {
+        if sandbox > client.max_sandbox {
+            return Err(Denied::Sandbox {
+                client: client.name.clone(),
+                requested: sandbox,
+                max: client.max_sandbox,
+            });
+        }
+        if client.allowed_workspaces.is_empty() {
+            return Ok(());
+        }
+        let working_dir = canonical(working_dir);
+        if client
+            .allowed_workspaces
+            .iter()
+            .any(|allowed| working_dir.starts_with(canonical(allowed)))
+        {
+            Ok(())
+        } else {
+            Err(Denied::Workspace {
+                client: client.name.clone(),
+                path: working_dir,
+            })
+        }
+    }
+}
+
+/// Working directory and sandbox of the tool call with `tools/call` params
+/// `params`: its `WORKING_DIR` argument, else the server's current directory,
+/// and the sandbox the tool would run Codex in. Tools that start no run count
+/// as read-only; a rerun uses the original run's.
+pub fn requested_run(params: &Value) -> (PathBuf, SandboxMode) {
+    let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+    let arguments = params.get("arguments").unwrap_or(&Value::Null);
+    let argument = |key: &str| arguments.get(key).and_then(Value::as_str);
+    let working_dir = argument("WORKING_DIR")
+        .map(PathBuf::from)
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+
+    let capped = |args: &[String]| {
+        let mode = SandboxMode::from_args(args);
+        codex::sandbox_ceiling().map_or(mode, |ceiling| mode.min(ceiling))
+    };
+    let base = codex::default_additional_args();
+    match name {
+        "codex" if argument("MODE") == Some("plain") => (working_dir, SandboxMode::ReadOnly),
+        "codex" => {
+            let mut sandbox = capped(&base);
+            // The plan is carried out with write access
+            if arguments.get("EXPLORE_FIRST").and_then(Value::as_bool) == Some(true) {
+                sandbox = sandbox.max(capped(&codex::with_sandbox(
+                    &base,
+                    SandboxMode::WorkspaceWrite,
+                )));
+            }
+            (working_dir, sandbox)
+        }
+        "codex_rerun" => {
+            let original = argument("RUN_ID").and_then(|id| history::get(id.trim()));
+            match original {
+                Some(run) => {
+                    let args = run
+                        .invocation
+                        .map(|invocation| invocation.additional_args)
+                        .unwrap_or(base);
+                    (run.working_dir, capped(&args))
+                }
+                None => (working_dir, SandboxMode::ReadOnly),
+            }
+        }
+        "codex_consensus" | "codex_pipeline" | "codex_raw" => (working_dir, capped(&base)),
+        _ => match codex::tool_aliases()
+            .iter()
+            .find(|alias| alias.name == name)
+        {
+            Some(alias) => (working_dir, capped(&alias.additional_args(&base))),
+            None => (working_dir, SandboxMode::ReadOnly),
+        },
+    }
+}
+
+/// Resolve symlinks and `..` so workspace checks cannot be sidestepped
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(name: &str, key_env: &str) -> ApiKeyConfig {
+        ApiKeyConfig {
+            name: name.to_string(),
+            key_env: key_env.to_string(),
+            rate_limit_per_minute: None,
+            allowed_workspaces: Vec::new(),
+            max_sandbox: SandboxMode::WorkspaceWrite,
+        }
+    }
+
+    #[test]
+    fn test_authenticate_and_rate_limit() {
+        std::env::set_var("TENANTS_TEST_KEY_A", "key-a");
+        let mut limited = client("alice", "TENANTS_TEST_KEY_A");
+        limited.rate_limit_per_minute = Some(2);
+        let tenants = Tenants::new(&[limited, client("bob", "TENANTS_TEST_KEY_UNSET")]);
+
+        let start = Instant::now();
+        assert_eq!(tenants.authenticate("key-a", start).unwrap().name, "alice");
+        assert_eq!(
+            tenants.authenticate("wrong", start),
+            Err(Denied::UnknownKey)
+        );
+        // An unset key_env must not match an empty key
+        assert_eq!(tenants.authenticate("", start), Err(Denied::UnknownKey));
+
+        let later = start + Duration::from_secs(20);
+        assert!(tenants.authenticate("key-a", later).is_ok());
+        assert_eq!(
+            tenants.authenticate("key-a", later),
+            Err(Denied::RateLimited {
+                client: "alice".to_string(),
+                retry_after_secs: 40,
+            })
+        );
+        assert!(tenants.authenticate("key-a", start + RATE_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn test_authorize_request_checks_each_tool_call() {
+        std::env::set_var("TENANTS_TEST_KEY_B", "key-b");
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut carol = client("carol", "TENANTS_TEST_KEY_B");
+        carol.rate_limit_per_minute = Some(1);
+        carol.allowed_workspaces = vec![temp_dir.path().to_path_buf()];
+        let tenants = Tenants::new(&[carol]);
+        let now = Instant::now();
+
+        let list = br#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#;
+        assert_eq!(
+            tenants.authorize_request(None, list, now),
+            Err(Denied::UnknownKey)
+        );
+        // Requests other than tool calls do not count against the limit
+        assert!(tenants.authorize_request(Some("key-b"), list, now).is_ok());
+
+        let outside = serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": {"name": "codex_repo_map", "arguments": {"WORKING_DIR": "/"}}});
+        assert!(matches!(
+            tenants.authorize_request(Some("key-b"), outside.to_string().as_bytes(), now),
+            Err(Denied::Workspace { .. })
+        ));
+        // The refused call still counted, so this one is over the limit
+        let inside = serde_json::json!([{"jsonrpc": "2.0", "id": 3, "method": "tools/call",
+            "params": {"name": "codex", "arguments": {
+                "PROMPT": "x", "WORKING_DIR": temp_dir.path(), "MODE": "plain"}}}]);
+        assert!(matches!(
+            tenants.authorize_request(Some("key-b"), inside.to_string().as_bytes(), now),
+            Err(Denied::RateLimited { .. })
+        ));
+        let later = now + RATE_WINDOW;
+        assert!(tenants
+            .authorize_request(Some("key-b"), inside.to_string().as_bytes(), later)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_authorize_run_checks_workspace_and_sandbox() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let allowed = temp_dir.path().join("team");
+        std::fs::create_dir_all(allowed.join("repo")).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("other")).unwrap();
+
+        let mut alice = client("alice", "TENANTS_TEST_KEY_UNUSED");
+        alice.allowed_workspaces = vec![allowed.clone()];
+
+        assert!(
+            Tenants::authorize_run(&alice, &allowed.join("repo"), SandboxMode::ReadOnly).is_ok()
+        );
+        assert!(matches!(
+            Tenants::authorize_run(
+                &alice,
+                &allowed.join("repo/../../other"),
+                SandboxMode::ReadOnly
+            ),
+            Err(Denied::Workspace { .. })
+        ));
+        assert!(matches!(
+            Tenants::authorize_run(&alice, &allowed, SandboxMode::DangerFullAccess),
+            Err(Denied::Sandbox { .. })
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::rusage::ResourceUsage;
+use rmcp::schemars;
+use serde::Serialize;
+use std::process::ExitStatus;
+
+/// How a Codex process that died from a signal was stopped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct Termination {
+    /// e.g. `SIGKILL`, or `signal 42` for signals without a common name
+    pub signal: String,
+    pub signal_number: i32,
+    /// SIGKILL that codex-mcp did not send, which is almost always the
+    /// kernel's out-of-memory killer
+    pub likely_oom: bool,
+}
+
+impl Termination {
+    /// Termination of a process that exited with `status`, or None when it
+    /// exited normally. `killed_by_server` is set when codex-mcp killed the
+    /// process itself (cancellation, unparsable output).
+    pub fn from_status(status: &ExitStatus, killed_by_server: bool) -> Option<Self> {
+        let number = signal_number(status)?;
+        Some(Self {
+            signal: signal_name(number),
+            signal_number: number,
+            likely_oom: number == SIGKILL && !killed_by_server,
+        })
+    }
+
+    /// Error message for the run, with a hint when the process ran out of
+    /// memory
+    pub fn message(&self, usage: Option<&ResourceUsage>) -> String {
+        if !self.likely_oom {
+            return format!("codex was terminated by signal {}", self.signal);
+        }
+        let peak = usage
+            .filter(|usage| usage.max_rss_kb > 0)
+            .map(|usage| format!(" after reaching {} MiB", usage.max_rss_kb / 1024))
+            .unwrap_or_default();
+        format!(
+            "codex was killed by {}{}, most likely by the out-of-memory killer; lower the output limits or run fewer Codex tasks in parallel",
+            self.signal, peak
+        )
+    }
+}
+
+const SIGKILL: i32 = 9;
+
+#[cfg(unix)]
+fn signal_number(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn signal_number(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Name of the signals a Codex process is realistically stopped by. The
+/// numbers are the ones Linux and macOS agree on.
+fn signal_name(number: i32) -> String {
+    let name = match number {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        8 => "SIGFPE",
+        SIGKILL => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        _ => return format!("signal {}", number),
+    };
+    name.to_string()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn test_names_signal_and_flags_oom() {
+        assert_eq!(
+            Termination::from_status(&ExitStatus::from_raw(1 << 8), false),
+            None
+        );
+
+        let killed = Termination::from_status(&ExitStatus::from_raw(9), false).unwrap();
+        assert_eq!(killed.signal, "SIGKILL");
+        assert!(killed.likely_oom);
+        let usage = ResourceUsage {
+            max_rss_kb: 2048 * 1024,
+            ..Default::default()
+        };
+        let message = killed.message(Some(&usage));
+        assert!(message.contains("after reaching 2048 MiB"));
+        assert!(message.contains("out-of-memory"));
+
+        let cancelled = Termination::from_status(&ExitStatus::from_raw(9), true).unwrap();
+        assert!(!cancelled.likely_oom);
+        assert_eq!(
+            cancelled.message(None),
+            "codex was terminated by signal SIGKILL"
+        );
+
+        let segv = Termination::from_status(&ExitStatus::from_raw(11), false).unwrap();
+        assert_eq!(segv.signal, "SIGSEGV");
+        assert!(!segv.likely_oom);
+        assert_eq!(
+            Termination::from_status(&ExitStatus::from_raw(42), false)
+                .unwrap()
+                .signal,
+            "signal 42"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use crate::codex::{self, SandboxMode};
+use crate::identities;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+
+/// One entry of `tool_aliases` in `codex-mcp.config.json`: an extra tool that
+/// runs `codex` with a preset prompt, sandbox, model, and timeout
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ToolAlias {
+    /// Tool name, e.g. `fix_tests`
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Prompt sent to Codex; `{{input}}` is replaced with the caller's `INPUT`
+    pub prompt_template: String,
+    /// `read-only`, `workspace-write`, or `danger-full-access`; defaults to the
+    /// sandbox implied by the server's `additional_args`, which it may not exceed
+    #[serde(default)]
+    pub sandbox: Option<String>,
+    /// Passed to the CLI as `--model`
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Entry of `identities` the alias runs as, so one prompt can be
+    /// registered once per account
+    #[serde(default)]
+    pub identity: Option<String>,
+}
+
+/// Input parameters for alias tools
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct AliasArgs {
+    /// Text substituted for `{{input}}` in the tool's prompt template
+    #[serde(rename = "INPUT", default)]
+    pub input: String,
+    /// Groups related runs for `codex_task_summary`
+    #[serde(rename = "TASK_ID", default)]
+    pub task_id: Option<String>,
+}
+
+impl ToolAlias {
+    pub fn description(&self) -> String {
+        self.description.clone().unwrap_or_else(|| {
+            format!(
+                "Run Codex with the preset prompt: {}",
+                self.prompt_template.replace("{{input}}", "<INPUT>")
+            )
+        })
+    }
+
+    pub fn prompt(&self, input: &str) -> String {
+        self.prompt_template.replace("{{input}}", input.trim())
+    }
+
+    /// `base` with the alias's sandbox and model applied
+    pub fn additional_args(&self, base: &[String]) -> Vec<String> {
+        let mut args = match self.sandbox.as_deref().and_then(SandboxMode::parse) {
+            Some(mode) => codex::with_sandbox(base, mode),
+            None => base.to_vec(),
+        };
+        if let Some(model) = &self.model {
+            args.push("--model".to_string());
+            args.push(model.clone());
+        }
+        args
+    }
+}
+
+/// Check an alias before it is registered: the name must be a valid tool name
+/// not already taken, and the sandbox may not exceed `ceiling`
+pub fn validate(alias: &ToolAlias, taken: &[String], ceiling: SandboxMode) -> Result<(), String> {
+    let valid_name = !alias.name.is_empty()
+        && alias.name.len() <= 64
+        && alias
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid_name {
+        return Err("name must be 1-64 letters, digits, '_' or '-'".to_string());
+    }
+    if taken.contains(&alias.name) {
+        return Err("a tool with this name already exists".to_string());
+    }
+    if alias.prompt_template.trim().is_empty() {
+        return Err("prompt_template must not be empty".to_string());
+    }
+    if let Some(sandbox) = &alias.sandbox {
+        let mode =
+            SandboxMode::parse(sandbox).ok_or_else(|| format!("invalid sandbox: {}", sandbox))?;
+        if mode > ceiling {
+            return Err(format!(
+                "sandbox {} exceeds the server's {}",
+                sandbox,
+                ceiling.as_str()
+            ));
+        }
+    }
+    if alias.model.as_deref().is_some_and(|m| m.trim().is_empty()) {
+        return Err("model must not be empty".to_string());
+    }
+    if let Some(identity) = &alias.identity {
+        identities::find(codex::identities(), identity)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alias(name: &str, sandbox: Option<&str>) -> ToolAlias {
+        ToolAlias {
+            name: name.to_string(),
+            description: None,
+            prompt_template: "Fix the failing tests in {{input}}.".to_string(),
+            sandbox: sandbox.map(str::to_string),
+            model: Some("o3".to_string()),
+            timeout_secs: None,
+            identity: None,
+        }
+    }
+
+    #[test]
+    fn test_prompt_and_args() {
+        let fix_tests = alias("fix_tests", Some("read-only"));
+        assert_eq!(
+            fix_tests.prompt(" crates/core "),
+            "Fix the failing tests in crates/core."
+        );
+        assert_eq!(
+            fix_tests.description(),
+            "Run Codex with the preset prompt: Fix the failing tests in <INPUT>."
+        );
+        let base = vec!["--sandbox".to_string(), "workspace-write".to_string()];
+        assert_eq!(
+            fix_tests.additional_args(&base),
+            vec!["--sandbox", "read-only", "--model", "o3"]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let taken = vec!["codex".to_string()];
+        let ceiling = SandboxMode::WorkspaceWrite;
+        assert!(validate(&alias("fix_tests", None), &taken, ceiling).is_ok());
+        assert!(validate(&alias("codex", None), &taken, ceiling).is_err());
+        assert!(validate(&alias("fix tests", None), &taken, ceiling).is_err());
+        assert!(validate(&alias("x", Some("bogus")), &taken, ceiling).is_err());
+        let err = validate(&alias("x", Some("danger-full-access")), &taken, ceiling).unwrap_err();
+        assert!(err.contains("exceeds the server's workspace-write"));
+    }
+}
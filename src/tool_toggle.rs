@@ -0,0 +1,172 @@
+use crate::codex;
+use rmcp::model::Tool;
+use rmcp::service::Peer;
+use rmcp::RoleServer;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+/// How often the config file is checked for a changed `disabled_tools` list
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Tools currently hidden from clients, starting from `disabled_tools` in the
+/// config and replaced whenever the file changes
+fn disabled() -> &'static RwLock<BTreeSet<String>> {
+    static DISABLED: OnceLock<RwLock<BTreeSet<String>>> = OnceLock::new();
+    DISABLED.get_or_init(|| RwLock::new(normalize(codex::disabled_tools())))
+}
+
+/// Sessions told when the tool list changes
+fn peers() -> &'static Mutex<Vec<Peer<RoleServer>>> {
+    static PEERS: OnceLock<Mutex<Vec<Peer<RoleServer>>>> = OnceLock::new();
+    PEERS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+fn normalize(names: &[String]) -> BTreeSet<String> {
+    names
+        .iter()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Whether `name` is turned off by `disabled_tools`
+pub fn is_disabled(name: &str) -> bool {
+    disabled()
+        .read()
+        .map(|disabled| disabled.contains(name))
+        .unwrap_or(false)
+}
+
+/// `tools` without the disabled ones, for `tools/list`
+pub fn enabled(tools: Vec<Tool>) -> Vec<Tool> {
+    tools
+        .into_iter()
+        .filter(|tool| !is_disabled(&tool.name))
+        .collect()
+}
+
+/// Disabled names that match none of `known`, so typos get a warning
+pub fn unknown<'a>(known: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let known: BTreeSet<&str> = known.into_iter().collect();
+    disabled()
+        .read()
+        .map(|disabled| {
+            disabled
+                .iter()
+                .filter(|name| !known.contains(name.as_str()))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Remember a session so it gets `notifications/tools/list_changed`
+pub fn register(peer: Peer<RoleServer>) {
+    if let Ok(mut peers) = peers().lock() {
+        peers.push(peer);
+    }
+}
+
+/// `disabled_tools` from the raw config file. `None` when the file does not
+/// parse, so a half-saved edit keeps the current list.
+pub fn parse(raw: &str) -> Option<BTreeSet<String>> {
+    let value: serde_json::Value = serde_json::from_str(raw).ok()?;
+    let names = match value.get("disabled_tools") {
+        None | Some(serde_json::Value::Null) => Vec::new(),
+        Some(names) => serde_json::from_value::<Vec<String>>(names.clone()).ok()?,
+    };
+    Some(normalize(&names))
+}
+
+/// Watch the config file and apply changes to `disabled_tools` without a
+/// restart, telling connected clients to list tools again. Other settings
+/// still need a restart. `None` without a config path.
+pub fn start(config_path: Option<&'static Path>) -> Option<JoinHandle<()>> {
+    let path = config_path?;
+    Some(tokio::spawn(async move {
+        let mut modified = mtime(path).await;
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let current = mtime(path).await;
+            if current == modified {
+                continue;
+            }
+            modified = current;
+            // A removed file means every tool is enabled again
+            let raw = tokio::fs::read_to_string(path).await.unwrap_or_default();
+            let raw = if raw.trim().is_empty() { "{}" } else { &raw };
+            let Some(names) = parse(raw) else {
+                eprintln!(
+                    "Warning: {} does not parse; keeping the current disabled_tools",
+                    path.display()
+                );
+                continue;
+            };
+            if replace(names) {
+                notify_all().await;
+            }
+        }
+    }))
+}
+
+async fn mtime(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+/// Swap in a new disabled set; whether it differs from the old one
+fn replace(names: BTreeSet<String>) -> bool {
+    let Ok(mut disabled) = disabled().write() else {
+        return false;
+    };
+    if *disabled == names {
+        return false;
+    }
+    eprintln!(
+        "Config reloaded: disabled tools are now [{}]",
+        names.iter().cloned().collect::<Vec<_>>().join(", ")
+    );
+    *disabled = names;
+    true
+}
+
+/// Send `notifications/tools/list_changed` to every session, forgetting the
+/// ones that have closed
+async fn notify_all() {
+    let sessions = peers()
+        .lock()
+        .map(|mut peers| std::mem::take(&mut *peers))
+        .unwrap_or_default();
+    let mut open = Vec::with_capacity(sessions.len());
+    for peer in sessions {
+        if peer.notify_tool_list_changed().await.is_ok() {
+            open.push(peer);
+        }
+    }
+    if let Ok(mut peers) = peers().lock() {
+        peers.extend(open);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reads_disabled_tools() {
+        let names = parse(
+            r#"{"timeout_secs": 60, "disabled_tools": [" codex_raw ", "", "codex_pipeline"]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            names.into_iter().collect::<Vec<_>>(),
+            ["codex_pipeline", "codex_raw"]
+        );
+        assert_eq!(parse(r#"{"timeout_secs": 60}"#), Some(BTreeSet::new()));
+        assert_eq!(parse(r#"{"disabled_tools": "codex_raw"}"#), None);
+        assert_eq!(parse(r#"{"disabled_tools": ["#), None);
+    }
+}
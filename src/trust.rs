@@ -0,0 +1,136 @@
+use crate::codex::SandboxMode;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// `workspace_trust` section of `codex-mcp.config.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TrustConfig {
+    /// Require confirmation the first time a working directory is used
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directories trusted without asking, including their subdirectories
+    #[serde(default)]
+    pub allowlist: Vec<TrustedWorkspace>,
+    /// File remembering directories clients confirmed (defaults to
+    /// `~/.codex-mcp/trusted-workspaces.json`)
+    #[serde(default)]
+    pub store: Option<PathBuf>,
+}
+
+/// A directory codex-mcp may operate in
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TrustedWorkspace {
+    pub path: PathBuf,
+    /// Sandbox runs in this directory use unless a preset or step picks one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxMode>,
+}
+
+/// Serializes read-modify-write cycles of the store
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+impl TrustConfig {
+    pub fn store_path(&self) -> PathBuf {
+        self.store.clone().unwrap_or_else(|| {
+            std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(".codex-mcp")
+                .join("trusted-workspaces.json")
+        })
+    }
+
+    /// The entry trusting `dir`: an allowlisted parent, or a directory
+    /// confirmed earlier
+    pub fn lookup(&self, dir: &Path) -> Option<TrustedWorkspace> {
+        let allowed = self.allowlist.iter().find(|entry| {
+            let path = entry
+                .path
+                .canonicalize()
+                .unwrap_or_else(|_| entry.path.clone());
+            dir.starts_with(path)
+        });
+        if let Some(entry) = allowed {
+            return Some(entry.clone());
+        }
+        let _guard = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        read_store(&self.store_path())
+            .into_iter()
+            .find(|entry| entry.path == dir)
+    }
+
+    /// Remember that a client trusted `entry.path`
+    pub fn remember(&self, entry: TrustedWorkspace) -> std::io::Result<()> {
+        let path = self.store_path();
+        let _guard = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries = read_store(&path);
+        entries.retain(|existing| existing.path != entry.path);
+        entries.push(entry);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Written whole and renamed, so a crash cannot leave half a store
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&entries)?)?;
+        std::fs::rename(&tmp, &path)
+    }
+}
+
+/// Entries of the store; a missing or unreadable store trusts nothing
+fn read_store(path: &Path) -> Vec<TrustedWorkspace> {
+    std::fs::read(path)
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowlist_and_remembered_directories() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let allowed = root.join("allowed");
+        let other = root.join("other");
+        std::fs::create_dir_all(allowed.join("nested")).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+
+        let config = TrustConfig {
+            enabled: true,
+            allowlist: vec![TrustedWorkspace {
+                path: allowed.clone(),
+                sandbox: Some(SandboxMode::WorkspaceWrite),
+            }],
+            store: Some(root.join("store/trusted.json")),
+        };
+        assert_eq!(
+            config.lookup(&allowed.join("nested")).unwrap().sandbox,
+            Some(SandboxMode::WorkspaceWrite)
+        );
+        assert_eq!(config.lookup(&other), None);
+
+        config
+            .remember(TrustedWorkspace {
+                path: other.clone(),
+                sandbox: None,
+            })
+            .unwrap();
+        config
+            .remember(TrustedWorkspace {
+                path: other.clone(),
+                sandbox: Some(SandboxMode::ReadOnly),
+            })
+            .unwrap();
+        assert_eq!(
+            config.lookup(&other).unwrap().sandbox,
+            Some(SandboxMode::ReadOnly)
+        );
+        assert_eq!(read_store(&config.store_path()).len(), 1);
+        // Confirming a directory does not trust its subdirectories
+        assert_eq!(config.lookup(&other.join("sub")), None);
+    }
+}
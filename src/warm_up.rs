@@ -0,0 +1,174 @@
+use crate::codex;
+use crate::raw_command;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tokio::task::JoinHandle;
+
+/// Outcome of the startup warm-up, reported by `codex_status`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WarmUpReport {
+    /// False while the warm-up is still running
+    pub finished: bool,
+    pub ok: bool,
+    /// Where `CODEX_BIN` (or `codex`) resolved to on `PATH`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary: Option<PathBuf>,
+    /// First line of `codex --version`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cli_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logged_in: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    pub duration_ms: u64,
+}
+
+fn report() -> &'static Mutex<Option<WarmUpReport>> {
+    static REPORT: OnceLock<Mutex<Option<WarmUpReport>>> = OnceLock::new();
+    REPORT.get_or_init(|| Mutex::new(None))
+}
+
+/// The warm-up result, or `None` if no warm-up was started
+pub fn status() -> Option<WarmUpReport> {
+    report().lock().ok()?.clone()
+}
+
+/// Codex CLI version found by the warm-up
+pub fn cli_version() -> Option<String> {
+    status()?.cli_version
+}
+
+/// Check the Codex CLI in the background if `warm_up` is enabled, so a
+/// missing binary or expired login shows up in the server log and in
+/// `codex_status` rather than in the first real request
+pub fn start() -> Option<JoinHandle<()>> {
+    if !codex::warm_up_enabled() {
+        return None;
+    }
+    if let Ok(mut report) = report().lock() {
+        *report = Some(WarmUpReport::default());
+    }
+    Some(tokio::spawn(async {
+        let result = run(&codex::codex_bin()).await;
+        if result.ok {
+            eprintln!(
+                "Warm-up: {} at {}",
+                result.cli_version.as_deref().unwrap_or("codex"),
+                result
+                    .binary
+                    .as_deref()
+                    .map_or_else(|| "unknown path".to_string(), |p| p.display().to_string())
+            );
+        } else {
+            eprintln!("Warning: warm-up failed: {}", result.errors.join("; "));
+        }
+        if let Ok(mut report) = report().lock() {
+            *report = Some(result);
+        }
+    }))
+}
+
+async fn run(codex_bin: &str) -> WarmUpReport {
+    let started = std::time::Instant::now();
+    let mut result = WarmUpReport {
+        finished: true,
+        binary: resolve_binary(codex_bin),
+        ..Default::default()
+    };
+    if result.binary.is_none() {
+        result
+            .errors
+            .push(format!("{} was not found on PATH", codex_bin));
+    }
+
+    match raw_command::run(codex_bin, &["--version"]).await {
+        Ok(output) if output.exit_code == Some(0) => {
+            result.cli_version = output.stdout.lines().next().map(|l| l.trim().to_string());
+        }
+        Ok(output) => result.errors.push(format!(
+            "`{} --version` failed: {}",
+            codex_bin,
+            output.stderr.trim()
+        )),
+        Err(e) => result.errors.push(e),
+    }
+
+    // Without a working binary, the login check would only repeat the error
+    if result.cli_version.is_some() {
+        match raw_command::run(codex_bin, &["login", "status"]).await {
+            Ok(output) => {
+                let logged_in = output.exit_code == Some(0);
+                result.logged_in = Some(logged_in);
+                if !logged_in {
+                    result.errors.push(format!(
+                        "not logged in: {}",
+                        first_line(&output.stderr)
+                            .or_else(|| first_line(&output.stdout))
+                            .unwrap_or("")
+                    ));
+                }
+            }
+            Err(e) => result.errors.push(e),
+        }
+    }
+
+    result.ok = result.errors.is_empty();
+    result.duration_ms = started.elapsed().as_millis() as u64;
+    result
+}
+
+fn first_line(text: &str) -> Option<&str> {
+    text.lines().map(str::trim).find(|line| !line.is_empty())
+}
+
+/// Full path of `bin`: as given if it contains a path separator, otherwise
+/// the first match on `PATH`
+pub(crate) fn resolve_binary(bin: &str) -> Option<PathBuf> {
+    let path = Path::new(bin);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let suffixes: &[&str] = if cfg!(windows) {
+        &["", ".exe", ".cmd"]
+    } else {
+        &[""]
+    };
+    let search_path = std::env::var_os("PATH")?;
+    std::env::split_paths(&search_path)
+        .flat_map(|dir| {
+            suffixes
+                .iter()
+                .map(move |suffix| dir.join(format!("{}{}", bin, suffix)))
+        })
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_binary() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let bin = temp_dir.path().join("fake-codex");
+        std::fs::write(&bin, "").unwrap();
+
+        assert_eq!(resolve_binary(bin.to_str().unwrap()), Some(bin.clone()));
+        assert_eq!(
+            resolve_binary(temp_dir.path().join("missing").to_str().unwrap()),
+            None
+        );
+        assert_eq!(resolve_binary("codex-mcp-test-no-such-binary"), None);
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_missing_binary() {
+        let result = run("codex-mcp-test-no-such-binary").await;
+        assert!(result.finished);
+        assert!(!result.ok);
+        assert!(result.binary.is_none());
+        assert!(result.cli_version.is_none());
+        assert!(result.errors[0].contains("not found on PATH"));
+    }
+}
@@ -0,0 +1,383 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// How long to sleep between lock attempts when queueing
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Locks older than this are treated as abandoned even if their PID is alive,
+/// since no run can legitimately outlive the maximum timeout (guards against PID reuse)
+const STALE_LOCK_AGE_SECS: u64 = crate::codex::MAX_TIMEOUT_SECS + 60;
+
+/// Policy for concurrent write-mode runs on the same workspace, configured via
+/// `workspace_lock_policy` in `codex-mcp.config.json`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockPolicy {
+    /// Wait for the current holder to finish, bounded by the run timeout
+    #[default]
+    Queue,
+    /// Return `WorkspaceBusy` immediately if another run holds the lock
+    FailFast,
+    /// Do not lock at all
+    Proceed,
+}
+
+/// Contents of a workspace lock file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub pid: u32,
+    pub run_id: String,
+    pub working_dir: PathBuf,
+    /// Seconds since the Unix epoch when the lock was taken
+    pub created_at: u64,
+}
+
+/// Error returned when another live run holds the workspace lock
+#[derive(Debug, Clone)]
+pub struct WorkspaceBusy {
+    pub working_dir: PathBuf,
+    pub holder: LockInfo,
+}
+
+impl fmt::Display for WorkspaceBusy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "WorkspaceBusy: {} is locked by run {} (pid {})",
+            self.working_dir.display(),
+            self.holder.run_id,
+            self.holder.pid
+        )
+    }
+}
+
+impl std::error::Error for WorkspaceBusy {}
+
+/// Held workspace lock; the lock file is removed on drop
+#[derive(Debug)]
+pub struct WorkspaceLock {
+    path: PathBuf,
+    run_id: String,
+}
+
+impl WorkspaceLock {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for WorkspaceLock {
+    fn drop(&mut self) {
+        // Only remove the file if it is still ours (a stale-lock takeover may have replaced it)
+        if let Some(info) = read_lock_info(&self.path) {
+            if info.run_id == self.run_id {
+                let _ = std::fs::remove_file(&self.path);
+            }
+        }
+    }
+}
+
+enum Attempt {
+    Acquired(WorkspaceLock),
+    Held(LockInfo),
+}
+
+/// Directory holding lock files for all workspaces. Locks live outside the
+/// workspace so they never show up in `git status`.
+pub fn lock_dir() -> PathBuf {
+    std::env::temp_dir().join("codex-mcp-locks")
+}
+
+/// Lock file path for a working directory
+pub fn lock_path(working_dir: &Path) -> PathBuf {
+    // FNV-1a keeps the file name stable across builds, unlike DefaultHasher
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in working_dir.as_os_str().as_encoded_bytes() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    lock_dir().join(format!("{:016x}.lock", hash))
+}
+
+/// Read the current lock holder for a working directory, ignoring stale locks
+pub fn current_holder(working_dir: &Path) -> Option<LockInfo> {
+    read_lock_info(&lock_path(working_dir)).filter(|info| !is_stale(info))
+}
+
+/// Acquire the workspace lock according to `policy`.
+///
+/// Returns `Ok(None)` when the policy is `Proceed`. With `Queue`, waits up to
+/// `max_wait` before giving up with `WorkspaceBusy`.
+pub async fn acquire(
+    working_dir: &Path,
+    run_id: &str,
+    policy: LockPolicy,
+    max_wait: Duration,
+) -> anyhow::Result<Option<WorkspaceLock>> {
+    if policy == LockPolicy::Proceed {
+        return Ok(None);
+    }
+
+    let path = lock_path(working_dir);
+    let info = LockInfo {
+        pid: std::process::id(),
+        run_id: run_id.to_string(),
+        working_dir: working_dir.to_path_buf(),
+        created_at: now_secs(),
+    };
+    let deadline = Instant::now() + max_wait;
+
+    loop {
+        match try_acquire(&path, &info)? {
+            Attempt::Acquired(lock) => return Ok(Some(lock)),
+            Attempt::Held(holder) => {
+                if policy == LockPolicy::FailFast || Instant::now() >= deadline {
+                    return Err(WorkspaceBusy {
+                        working_dir: working_dir.to_path_buf(),
+                        holder,
+                    }
+                    .into());
+                }
+                tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+fn try_acquire(path: &Path, info: &LockInfo) -> std::io::Result<Attempt> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Write the full lock body to a private temp file, then hard-link it into
+    // place so other processes never observe a half-written lock.
+    let tmp_path = path.with_extension(format!("{}.tmp", info.run_id));
+    let body = serde_json::to_vec(info).map_err(std::io::Error::other)?;
+    std::fs::write(&tmp_path, body)?;
+
+    let result = loop {
+        match std::fs::hard_link(&tmp_path, path) {
+            Ok(()) => {
+                break Ok(Attempt::Acquired(WorkspaceLock {
+                    path: path.to_path_buf(),
+                    run_id: info.run_id.clone(),
+                }))
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => match read_lock_info(path) {
+                Some(holder) if !is_stale(&holder) => break Ok(Attempt::Held(holder)),
+                // Stale or corrupt lock: remove it and retry
+                _ => match remove_if_stale(path) {
+                    Ok(Some(holder)) => break Ok(Attempt::Held(holder)),
+                    Ok(None) => continue,
+                    Err(e) => break Err(e),
+                },
+            },
+            Err(e) => break Err(e),
+        }
+    };
+
+    let _ = std::fs::remove_file(&tmp_path);
+    result
+}
+
+/// Remove the lock file at `path` if it is stale or corrupt. The file is
+/// first renamed to a name of its own and checked again there, so a fresh
+/// lock written after the caller found the old one stale is put back instead
+/// of deleted. Returns the holder of such a fresh lock.
+pub fn remove_if_stale(path: &Path) -> std::io::Result<Option<LockInfo>> {
+    let aside = path.with_extension(format!("{}.stale", Uuid::new_v4()));
+    match std::fs::rename(path, &aside) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let holder = read_lock_info(&aside).filter(|info| !is_stale(info));
+    let restored = match holder {
+        // If another run took the free path meanwhile, its lock stands
+        Some(_) => match std::fs::hard_link(&aside, path) {
+            Err(e) if e.kind() != ErrorKind::AlreadyExists => Err(e),
+            _ => Ok(()),
+        },
+        None => Ok(()),
+    };
+    let _ = std::fs::remove_file(&aside);
+    restored.map(|()| holder)
+}
+
+/// True if the lock file at `path` is unreadable or its holder is gone
+pub fn is_stale_lock_file(path: &Path) -> bool {
+    read_lock_info(path).is_none_or(|info| is_stale(&info))
+}
+
+fn read_lock_info(path: &Path) -> Option<LockInfo> {
+    let raw = std::fs::read(path).ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+fn is_stale(info: &LockInfo) -> bool {
+    now_secs().saturating_sub(info.created_at) > STALE_LOCK_AGE_SECS || !process_alive(info.pid)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // Signal 0 only performs permission checks; EPERM still means the process exists
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    // Without a cheap liveness probe, rely on the age check alone
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_path_is_stable_per_directory() {
+        let a = lock_path(Path::new("/tmp/project-a"));
+        let b = lock_path(Path::new("/tmp/project-b"));
+        assert_eq!(a, lock_path(Path::new("/tmp/project-a")));
+        assert_ne!(a, b);
+        assert!(a.starts_with(lock_dir()));
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_reports_workspace_busy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let first = acquire(
+            temp_dir.path(),
+            "run-1",
+            LockPolicy::FailFast,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+        assert!(first.is_some());
+
+        let err = acquire(
+            temp_dir.path(),
+            "run-2",
+            LockPolicy::FailFast,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap_err();
+        let busy = err.downcast_ref::<WorkspaceBusy>().unwrap();
+        assert_eq!(busy.holder.run_id, "run-1");
+        assert_eq!(busy.holder.pid, std::process::id());
+
+        drop(first);
+        let second = acquire(
+            temp_dir.path(),
+            "run-2",
+            LockPolicy::FailFast,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+        assert!(second.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_queue_waits_for_release() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let first = acquire(temp_dir.path(), "run-1", LockPolicy::Queue, Duration::ZERO)
+            .await
+            .unwrap();
+
+        let releaser = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            drop(first);
+        });
+
+        let second = acquire(
+            temp_dir.path(),
+            "run-2",
+            LockPolicy::Queue,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        assert!(second.is_some());
+        releaser.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stale_lock_is_taken_over() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = lock_path(temp_dir.path());
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let stale = LockInfo {
+            pid: std::process::id(),
+            run_id: "dead-run".to_string(),
+            working_dir: temp_dir.path().to_path_buf(),
+            created_at: 0,
+        };
+        std::fs::write(&path, serde_json::to_vec(&stale).unwrap()).unwrap();
+
+        let lock = acquire(
+            temp_dir.path(),
+            "run-1",
+            LockPolicy::FailFast,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(read_lock_info(lock.path()).unwrap().run_id, "run-1");
+
+        // A lock found stale but replaced by a live one before removal survives
+        assert_eq!(
+            remove_if_stale(lock.path()).unwrap().unwrap().run_id,
+            "run-1"
+        );
+        assert_eq!(read_lock_info(lock.path()).unwrap().run_id, "run-1");
+        let leftovers = std::fs::read_dir(lock_dir())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                let name = name.to_string_lossy();
+                name.starts_with(&*path.file_stem().unwrap().to_string_lossy())
+                    && name.ends_with(".stale")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_proceed_policy_skips_locking() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let lock = acquire(
+            temp_dir.path(),
+            "run-1",
+            LockPolicy::Proceed,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+        assert!(lock.is_none());
+        assert!(current_holder(temp_dir.path()).is_none());
+    }
+}
@@ -0,0 +1,331 @@
+use crate::git_info::{self, GitInfo};
+use crate::glob;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// What to do with changes outside `ALLOWED_PATHS`, configured via
+/// `out_of_scope_changes` in `codex-mcp.config.json`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScopePolicy {
+    /// Keep the changes and report them as a warning
+    #[default]
+    Warn,
+    /// Restore out-of-scope files that were clean before the run
+    Revert,
+    /// Mark the run as failed
+    Fail,
+}
+
+/// Working tree state of the files git reports as changed, keyed by path
+/// relative to the working directory. The value is a content hash, or `None`
+/// for deleted files.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    repo_root: PathBuf,
+    prefix: String,
+    dirty: HashMap<String, Option<u64>>,
+}
+
+/// Outcome of checking a run's changes against `ALLOWED_PATHS`
+#[derive(Debug, Default)]
+pub struct ScopeReport {
+    pub out_of_scope: Vec<String>,
+    pub reverted: Vec<String>,
+    /// Out-of-scope files that could not be reverted (already dirty before the
+    /// run, or the restore failed)
+    pub not_reverted: Vec<String>,
+}
+
+impl ScopeReport {
+    pub fn is_clean(&self) -> bool {
+        self.out_of_scope.is_empty()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut message = format!(
+            "Changes outside ALLOWED_PATHS: {}",
+            self.out_of_scope.join(", ")
+        );
+        if !self.reverted.is_empty() {
+            message.push_str(&format!("; reverted: {}", self.reverted.join(", ")));
+        }
+        if !self.not_reverted.is_empty() {
+            message.push_str(&format!(
+                "; could not revert: {}",
+                self.not_reverted.join(", ")
+            ));
+        }
+        message
+    }
+}
+
+/// Record the current state of changed files under `working_dir`
+pub async fn snapshot(working_dir: &Path) -> Result<Snapshot, String> {
+    let GitInfo {
+        repo_root, prefix, ..
+    } = git_info::get(working_dir).await?;
+    let status = git_raw(
+        working_dir,
+        &[
+            "status",
+            "--porcelain=v1",
+            "-z",
+            "--untracked-files=all",
+            "--",
+            ".",
+        ],
+    )
+    .await?;
+
+    let mut dirty = HashMap::new();
+    for path in parse_porcelain(&status) {
+        let Some(relative) = path.strip_prefix(prefix.as_str()) else {
+            continue;
+        };
+        let hash = hash_file(&repo_root.join(&path));
+        dirty.insert(relative.to_string(), hash);
+    }
+
+    Ok(Snapshot {
+        repo_root,
+        prefix,
+        dirty,
+    })
+}
+
+/// Compare the working tree with `before`, and apply `policy` to any change
+/// that does not match `allowed`
+pub async fn enforce(
+    working_dir: &Path,
+    before: &Snapshot,
+    allowed: &[String],
+    policy: ScopePolicy,
+) -> Result<ScopeReport, String> {
+    let after = snapshot(working_dir).await?;
+
+    let mut report = ScopeReport::default();
+    for path in changed_paths(before, &after) {
+        if !allowed.iter().any(|pattern| is_allowed(pattern, &path)) {
+            report.out_of_scope.push(path);
+        }
+    }
+
+    if policy == ScopePolicy::Revert {
+        for path in &report.out_of_scope {
+            // Only files that were clean before the run can be restored without
+            // losing the user's own uncommitted work
+            if before.dirty.contains_key(path) {
+                report.not_reverted.push(path.clone());
+                continue;
+            }
+            match revert(&after, path).await {
+                Ok(()) => report.reverted.push(path.clone()),
+                Err(_) => report.not_reverted.push(path.clone()),
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Paths (relative to the working directory) whose state differs between
+/// two snapshots, sorted
+pub fn changed_paths(before: &Snapshot, after: &Snapshot) -> Vec<String> {
+    let candidates: BTreeSet<&String> = before.dirty.keys().chain(after.dirty.keys()).collect();
+    candidates
+        .into_iter()
+        .filter(
+            |path| match (before.dirty.get(*path), after.dirty.get(*path)) {
+                (Some(old), Some(new)) => old != new,
+                (None, Some(_)) => true,
+                // Dirty before, clean now: the run restored or committed it
+                (Some(_), None) => true,
+                (None, None) => false,
+            },
+        )
+        .cloned()
+        .collect()
+}
+
+/// Restore `path` to HEAD, or remove it if git does not track it
+async fn revert(snapshot: &Snapshot, path: &str) -> Result<(), String> {
+    let repo_path = format!("{}{}", snapshot.prefix, path);
+    let tracked = git(
+        &snapshot.repo_root,
+        &["ls-files", "--error-unmatch", "--", &repo_path],
+    )
+    .await
+    .is_ok();
+
+    if tracked {
+        git(
+            &snapshot.repo_root,
+            &[
+                "restore",
+                "--source=HEAD",
+                "--staged",
+                "--worktree",
+                "--",
+                &repo_path,
+            ],
+        )
+        .await
+        .map(|_| ())
+    } else {
+        std::fs::remove_file(snapshot.repo_root.join(&repo_path)).map_err(|e| e.to_string())
+    }
+}
+
+/// True if `path` (relative, `/`-separated) matches `pattern`. Supports `*`,
+/// `?`, and `**`; a pattern without wildcards also matches everything below it.
+pub fn is_allowed(pattern: &str, path: &str) -> bool {
+    let pattern = pattern
+        .trim()
+        .trim_start_matches("./")
+        .trim_end_matches('/');
+    if pattern.is_empty() {
+        return false;
+    }
+    if !pattern.contains(['*', '?']) {
+        return path == pattern || path.starts_with(&format!("{}/", pattern));
+    }
+
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    glob::match_segments(&pattern, &path)
+}
+
+/// Paths from `git status --porcelain=v1 -z`; renames contribute both names
+fn parse_porcelain(output: &[u8]) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut fields = output.split(|b| *b == 0).filter(|f| !f.is_empty());
+    while let Some(entry) = fields.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        let status = &entry[..2];
+        paths.push(String::from_utf8_lossy(&entry[3..]).into_owned());
+        if status.contains(&b'R') || status.contains(&b'C') {
+            if let Some(original) = fields.next() {
+                paths.push(String::from_utf8_lossy(original).into_owned());
+            }
+        }
+    }
+    paths
+}
+
+fn hash_file(path: &Path) -> Option<u64> {
+    let bytes = std::fs::read(path).ok()?;
+    // FNV-1a, as for workspace lock names
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    Some(hash)
+}
+
+async fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = git_raw(dir, args).await?;
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+async fn git_raw(dir: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_allowed_globs() {
+        assert!(is_allowed("src", "src/lib.rs"));
+        assert!(is_allowed("src/", "src/a/b.rs"));
+        assert!(!is_allowed("src", "srcs/lib.rs"));
+        assert!(is_allowed("src/*.rs", "src/lib.rs"));
+        assert!(!is_allowed("src/*.rs", "src/a/lib.rs"));
+        assert!(is_allowed("src/**/*.rs", "src/lib.rs"));
+        assert!(is_allowed("src/**/*.rs", "src/a/b/lib.rs"));
+        assert!(is_allowed("**/Cargo.toml", "Cargo.toml"));
+        assert!(is_allowed("./docs/?.md", "docs/a.md"));
+        assert!(!is_allowed("docs/?.md", "docs/ab.md"));
+    }
+
+    #[test]
+    fn test_parse_porcelain_handles_renames() {
+        let output = b" M src/lib.rs\0?? new.txt\0R  to.rs\0from.rs\0";
+        assert_eq!(
+            parse_porcelain(output),
+            vec!["src/lib.rs", "new.txt", "to.rs", "from.rs"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enforce_reverts_out_of_scope_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        let init = |args: &[&str]| {
+            std::process::Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .output()
+                .map(|o| o.status.success())
+                .unwrap_or(false)
+        };
+        if !init(&["init", "-q"]) {
+            return; // git not available
+        }
+        std::fs::create_dir(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "a").unwrap();
+        std::fs::write(dir.join("README.md"), "readme").unwrap();
+        init(&["add", "."]);
+        init(&[
+            "-c",
+            "user.name=t",
+            "-c",
+            "user.email=t@t",
+            "commit",
+            "-qm",
+            "init",
+        ]);
+
+        let before = snapshot(dir).await.unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "b").unwrap();
+        std::fs::write(dir.join("README.md"), "changed").unwrap();
+        std::fs::write(dir.join("stray.txt"), "x").unwrap();
+
+        let report = enforce(dir, &before, &["src".to_string()], ScopePolicy::Revert)
+            .await
+            .unwrap();
+        assert_eq!(report.out_of_scope, vec!["README.md", "stray.txt"]);
+        assert_eq!(report.reverted, vec!["README.md", "stray.txt"]);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("README.md")).unwrap(),
+            "readme"
+        );
+        assert!(!dir.join("stray.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("src/lib.rs")).unwrap(),
+            "b"
+        );
+    }
+}
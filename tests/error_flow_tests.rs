@@ -16,6 +16,7 @@ fn test_agent_messages_size_limit() {
         all_messages_truncated: false,
         error: None,
         warnings: None,
+        ..Default::default()
     };
 
     // The agent_messages should be truncatable in practice
@@ -34,6 +35,7 @@ fn test_agent_messages_truncation_flag() {
         all_messages_truncated: false,
         error: None,
         warnings: None,
+        ..Default::default()
     };
 
     assert!(result.agent_messages_truncated);
@@ -52,6 +54,7 @@ fn test_all_messages_limit() {
         all_messages_truncated: false,
         error: None,
         warnings: None,
+        ..Default::default()
     };
 
     // Simulate adding messages up to limit
@@ -82,6 +85,7 @@ fn test_error_and_warning_handling() {
         all_messages_truncated: false,
         error: Some("Test error message".to_string()),
         warnings: Some("Test warning message".to_string()),
+        ..Default::default()
     };
 
     assert!(!result.success);
@@ -102,6 +106,7 @@ fn test_path_handling_with_non_utf8() {
         additional_args: Vec::new(),
         image_paths: Vec::new(),
         timeout_secs: None,
+        ..Default::default()
     };
 
     // Should be able to create options without panicking
@@ -181,6 +186,7 @@ echo '{"thread_id":"test-session","item":{"type":"agent_message","text":"ok"}}'
         additional_args: additional.clone(),
         image_paths: Vec::new(),
         timeout_secs: Some(10),
+        ..Default::default()
     };
 
     let result = codex::run(opts).await.expect("run should return Ok");
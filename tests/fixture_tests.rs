@@ -0,0 +1,164 @@
+//! Replays the recorded Codex streams in `tests/fixtures/streams` through the
+//! parser and the full `run` pipeline. Each `<name>.jsonl` is a stream as
+//! Codex printed it, and `<name>.expected.json` the exit status to replay it
+//! with and the result it must produce, so covering a new CLI output format
+//! is a matter of adding the two files.
+
+use codex_mcp_rs::client_sampling::FailureKind;
+use codex_mcp_rs::codex::{CodexResult, Options};
+use codex_mcp_rs::parser::{self, Line};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// `<name>.expected.json`; fields left out are not checked
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Expected {
+    /// Exit status of the replayed process
+    #[serde(default)]
+    exit_code: i32,
+    /// Lines that are not JSON, e.g. one cut off mid-event
+    #[serde(default)]
+    invalid_lines: usize,
+    success: bool,
+    session_id: Option<String>,
+    agent_messages: Option<String>,
+    error_contains: Option<String>,
+    fatal_error: Option<FailureKind>,
+    token_usage: Option<Value>,
+    files_read: Option<Vec<PathBuf>>,
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+/// Recorded streams with their expectations, by name
+fn fixtures() -> Vec<(String, PathBuf, Expected)> {
+    let mut fixtures: Vec<_> = std::fs::read_dir(fixtures_dir().join("streams"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .map(|stream| {
+            let name = stream.file_stem().unwrap().to_string_lossy().into_owned();
+            let expected_path = stream.with_file_name(format!("{}.expected.json", name));
+            let expected = std::fs::read_to_string(&expected_path)
+                .unwrap_or_else(|e| panic!("{}: {}", expected_path.display(), e));
+            let expected = serde_json::from_str(&expected)
+                .unwrap_or_else(|e| panic!("{}: {}", expected_path.display(), e));
+            (name, stream, expected)
+        })
+        .collect();
+    fixtures.sort_by(|a, b| a.0.cmp(&b.0));
+    assert!(!fixtures.is_empty());
+    fixtures
+}
+
+/// Parse `stream` line by line, as `run` does, checking what the parser
+/// alone can tell
+fn check_parser(name: &str, stream: &[u8], expected: &Expected) {
+    let mut invalid_lines = 0;
+    let mut session_id = None;
+    let mut failed = false;
+    for line in stream.split_inclusive(|&byte| byte == b'\n') {
+        match parser::parse_line(line) {
+            Line::Blank => {}
+            Line::Event(event) => {
+                let fields = parser::extract(&event);
+                session_id = fields.thread_id.map(str::to_string).or(session_id);
+                failed |= fields.failed;
+            }
+            Line::Invalid { .. } => invalid_lines += 1,
+        }
+    }
+    assert_eq!(
+        invalid_lines, expected.invalid_lines,
+        "{}: invalid lines",
+        name
+    );
+    if let Some(expected_id) = &expected.session_id {
+        assert_eq!(
+            session_id.as_deref().unwrap_or(""),
+            expected_id,
+            "{}: parsed thread_id",
+            name
+        );
+    }
+    if expected.success {
+        assert!(!failed, "{}: parsed a failure event", name);
+    }
+}
+
+fn check_result(name: &str, result: &CodexResult, expected: &Expected) {
+    assert_eq!(
+        result.success, expected.success,
+        "{}: success (error: {:?})",
+        name, result.error
+    );
+    if expected.success {
+        assert_eq!(result.error, None, "{}: error", name);
+    }
+    if let Some(session_id) = &expected.session_id {
+        assert_eq!(&result.session_id, session_id, "{}: session_id", name);
+    }
+    if let Some(agent_messages) = &expected.agent_messages {
+        assert_eq!(
+            &result.agent_messages, agent_messages,
+            "{}: agent_messages",
+            name
+        );
+    }
+    if let Some(needle) = &expected.error_contains {
+        let error = result.error.as_deref().unwrap_or("");
+        assert!(
+            error.contains(needle.as_str()),
+            "{}: error {:?}",
+            name,
+            error
+        );
+    }
+    if expected.fatal_error.is_some() {
+        assert_eq!(
+            result.fatal_error, expected.fatal_error,
+            "{}: fatal_error",
+            name
+        );
+    }
+    if let Some(token_usage) = &expected.token_usage {
+        assert_eq!(
+            &serde_json::to_value(result.token_usage).unwrap(),
+            token_usage,
+            "{}: token_usage",
+            name
+        );
+    }
+    if let Some(files_read) = &expected.files_read {
+        assert_eq!(&result.files_read, files_read, "{}: files_read", name);
+    }
+}
+
+#[tokio::test]
+#[cfg(unix)] // The replay binary is a shell script
+async fn test_recorded_streams_replay_to_expected_results() {
+    // The only test in this file, so nothing else reads the environment
+    // while it is changed
+    std::env::set_var("CODEX_BIN", fixtures_dir().join("replay-codex.sh"));
+    for (name, stream, expected) in fixtures() {
+        check_parser(&name, &std::fs::read(&stream).unwrap(), &expected);
+
+        std::env::set_var("CODEX_REPLAY_FIXTURE", &stream);
+        std::env::set_var("CODEX_REPLAY_EXIT_CODE", expected.exit_code.to_string());
+        let temp_dir = tempfile::tempdir().unwrap();
+        let opts = Options {
+            prompt: format!("replay {}", name),
+            working_dir: temp_dir.path().to_path_buf(),
+            timeout_secs: Some(10),
+            ..Default::default()
+        };
+        let result = codex_mcp_rs::codex::run(opts)
+            .await
+            .unwrap_or_else(|e| panic!("{}: {}", name, e));
+        check_result(&name, &result, &expected);
+    }
+}
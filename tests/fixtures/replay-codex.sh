@@ -0,0 +1,8 @@
+#!/bin/sh
+# Stands in for the Codex CLI: prints the recorded stream named by
+# CODEX_REPLAY_FIXTURE and exits with CODEX_REPLAY_EXIT_CODE
+case "$2" in
+--help | --version) exit 0 ;;
+esac
+cat "$CODEX_REPLAY_FIXTURE"
+exit "${CODEX_REPLAY_EXIT_CODE:-0}"
@@ -52,6 +52,7 @@ echo '{"thread_id":"test-session","item":{"type":"agent_message","text":"ok"}}'
         additional_args: Vec::new(),
         image_paths: vec![image1.clone(), image2.clone()],
         timeout_secs: Some(10),
+        ..Default::default()
     };
 
     let result = codex::run(opts).await.expect("run should return Ok");
@@ -46,6 +46,7 @@ fn test_options_validation() {
         additional_args: Vec::new(),
         image_paths: Vec::new(),
         timeout_secs: None,
+        ..Default::default()
     };
 
     assert!(!opts.prompt.is_empty());
@@ -68,6 +69,7 @@ fn test_session_id_format() {
         additional_args: Vec::new(),
         image_paths: Vec::new(),
         timeout_secs: None,
+        ..Default::default()
     };
 
     assert!(opts.session_id.is_some());
@@ -93,6 +95,7 @@ fn test_working_directory_paths() {
             additional_args: Vec::new(),
             image_paths: Vec::new(),
             timeout_secs: None,
+            ..Default::default()
         };
 
         assert_eq!(opts.working_dir, PathBuf::from(path));
@@ -147,6 +150,7 @@ exit 0
         additional_args: Vec::new(),
         image_paths: vec![],
         timeout_secs: Some(5), // Short timeout for test
+        ..Default::default()
     };
 
     // Run codex (will use our fake binary)
@@ -211,6 +215,7 @@ exit 0
         additional_args: Vec::new(),
         image_paths: vec![],
         timeout_secs: Some(5),
+        ..Default::default()
     };
 
     let result = codex_mcp_rs::codex::run(opts).await;
@@ -225,3 +230,452 @@ exit 0
     // Note: No warnings expected for 50KB file since it's under the 1MB limit
     // The real truncation logic is tested in unit tests with controlled input
 }
+
+#[tokio::test]
+#[cfg(unix)] // Shell scripts don't work on Windows
+async fn test_log_to_file_tees_raw_stream() {
+    use codex_mcp_rs::run_log::LogTarget;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    let script_content = r#"#!/bin/bash
+echo '{"type":"thread.started","thread_id":"test-session-log"}'
+echo '{"type":"item.completed","item":{"type":"agent_message","text":"OK"}}'
+echo '{"type":"turn.completed"}'
+exit 0
+"#;
+    tokio::fs::write(&fake_codex_script, script_content)
+        .await
+        .unwrap();
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    let _guard = EnvVarGuard::new("CODEX_BIN", fake_codex_script.to_str().unwrap());
+
+    let opts = Options {
+        prompt: "log me".to_string(),
+        working_dir: temp_dir.path().to_path_buf(),
+        timeout_secs: Some(5),
+        log_to_file: Some(LogTarget::Path(PathBuf::from("logs/run.jsonl"))),
+        ..Default::default()
+    };
+
+    let result = codex_mcp_rs::codex::run(opts).await.unwrap();
+
+    let log_path = temp_dir.path().join("logs").join("run.jsonl");
+    assert_eq!(result.log_file.as_deref(), Some(log_path.as_path()));
+    let logged = std::fs::read_to_string(&log_path).unwrap();
+    assert_eq!(logged.lines().count(), 3);
+    assert!(logged.contains("test-session-log"));
+    assert!(logged.ends_with("{\"type\":\"turn.completed\"}\n"));
+}
+
+#[tokio::test]
+#[cfg(unix)] // Shell scripts don't work on Windows
+async fn test_spawn_run_handle_abort_and_result() {
+    use codex_mcp_rs::run_handle::{spawn_run, RunStatus};
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    let script_content = r#"#!/bin/bash
+case "$*" in
+  *slow*) sleep 30 ;;
+esac
+echo '{"type":"thread.started","thread_id":"test-session-handle"}'
+echo '{"type":"item.completed","item":{"type":"agent_message","text":"done"}}'
+echo '{"type":"turn.completed"}'
+exit 0
+"#;
+    tokio::fs::write(&fake_codex_script, script_content)
+        .await
+        .unwrap();
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    let _guard = EnvVarGuard::new("CODEX_BIN", fake_codex_script.to_str().unwrap());
+    let opts = |prompt: &str| Options {
+        prompt: prompt.to_string(),
+        working_dir: temp_dir.path().to_path_buf(),
+        timeout_secs: Some(60),
+        ..Default::default()
+    };
+
+    let handle = spawn_run(opts("fast"));
+    let run_id = handle.run_id().to_string();
+    let result = handle.await_result().await.unwrap();
+    assert_eq!(result.run_id, run_id);
+    assert_eq!(result.agent_messages, "done");
+
+    let handle = spawn_run(opts("slow"));
+    assert_eq!(handle.status(), RunStatus::Running);
+    handle.abort();
+    assert_eq!(handle.status(), RunStatus::Aborted);
+    let err = handle.await_result().await.unwrap_err();
+    assert!(err.to_string().contains("aborted"));
+}
+
+#[tokio::test]
+#[cfg(unix)] // Shell scripts don't work on Windows
+async fn test_spawn_run_cancel_stops_flooding_output() {
+    use codex_mcp_rs::run_handle::spawn_run;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    let script_content = r#"#!/bin/bash
+echo '{"type":"thread.started","thread_id":"test-session-flood"}'
+yes '{"type":"turn.started"}'
+"#;
+    tokio::fs::write(&fake_codex_script, script_content)
+        .await
+        .unwrap();
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    let _guard = EnvVarGuard::new("CODEX_BIN", fake_codex_script.to_str().unwrap());
+    let handle = spawn_run(Options {
+        prompt: "flood".to_string(),
+        working_dir: temp_dir.path().to_path_buf(),
+        timeout_secs: Some(60),
+        ..Default::default()
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    handle.cancel();
+
+    let result = tokio::time::timeout(std::time::Duration::from_secs(10), handle.await_result())
+        .await
+        .expect("cancelled run should finish promptly")
+        .unwrap();
+    assert!(!result.success);
+    assert!(result.error.unwrap().contains("cancelled"));
+}
+
+#[tokio::test]
+#[cfg(unix)] // Shell scripts don't work on Windows
+async fn test_run_plain_returns_stdout_text() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    let script_content = r#"#!/bin/bash
+case "$*" in
+  *--json*) echo "unexpected --json" >&2; exit 1 ;;
+esac
+echo "progress noise" >&2
+echo "The answer is 42."
+exit 0
+"#;
+    tokio::fs::write(&fake_codex_script, script_content)
+        .await
+        .unwrap();
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    let _guard = EnvVarGuard::new("CODEX_BIN", fake_codex_script.to_str().unwrap());
+
+    let opts = Options {
+        prompt: "what is the answer?".to_string(),
+        working_dir: temp_dir.path().to_path_buf(),
+        timeout_secs: Some(5),
+        ..Default::default()
+    };
+
+    let result = codex_mcp_rs::codex::run_plain(opts).await.unwrap();
+    assert!(result.success, "{:?}", result.error);
+    assert_eq!(result.agent_messages, "The answer is 42.");
+    assert!(result.session_id.is_empty());
+}
+
+#[tokio::test]
+#[cfg(unix)] // Shell scripts don't work on Windows
+async fn test_run_plain_cuts_overlong_lines() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    // A 3 MiB line, past the per-line limit, then a normal one
+    let script_content = r#"#!/bin/bash
+head -c 3145728 /dev/zero | tr '\0' 'x'
+echo
+echo "after"
+exit 0
+"#;
+    tokio::fs::write(&fake_codex_script, script_content)
+        .await
+        .unwrap();
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    let _guard = EnvVarGuard::new("CODEX_BIN", fake_codex_script.to_str().unwrap());
+
+    let opts = Options {
+        prompt: "print a lot".to_string(),
+        working_dir: temp_dir.path().to_path_buf(),
+        timeout_secs: Some(10),
+        ..Default::default()
+    };
+
+    let result = codex_mcp_rs::codex::run_plain(opts).await.unwrap();
+    assert!(result.success, "{:?}", result.error);
+    assert!(result.agent_messages_truncated);
+    assert_eq!(result.agent_messages.len(), 1024 * 1024);
+    assert!(!result.agent_messages.contains("after"));
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")] // Exit is detected early only on Linux
+async fn test_fast_failure_returns_without_waiting_for_pipes() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    // The background sleep inherits stdout and stderr, so neither reaches EOF
+    // until it exits
+    let script_content = r#"#!/bin/bash
+sleep 30 &
+echo "error: unknown model 'gpt-0'" >&2
+exit 1
+"#;
+    tokio::fs::write(&fake_codex_script, script_content)
+        .await
+        .unwrap();
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    let _guard = EnvVarGuard::new("CODEX_BIN", fake_codex_script.to_str().unwrap());
+
+    let started = std::time::Instant::now();
+    let result = codex_mcp_rs::codex::run(Options {
+        prompt: "fail fast".to_string(),
+        working_dir: temp_dir.path().to_path_buf(),
+        timeout_secs: Some(60),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(5),
+        "took {:?}",
+        started.elapsed()
+    );
+    assert!(!result.success);
+    let error = result.error.unwrap();
+    assert!(error.contains("exit code: Some(1)"), "{}", error);
+    assert!(error.contains("unknown model 'gpt-0'"), "{}", error);
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn test_fatal_error_event_stops_run_immediately() {
+    use codex_mcp_rs::client_sampling::FailureKind;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    // Reports an auth failure, then hangs as if winding down
+    let script_content = r#"#!/bin/bash
+echo '{"type":"thread.started","thread_id":"t-1"}'
+echo '{"type":"error","message":"unexpected status 401 Unauthorized: Missing bearer"}'
+exec sleep 30
+"#;
+    tokio::fs::write(&fake_codex_script, script_content)
+        .await
+        .unwrap();
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    let _guard = EnvVarGuard::new("CODEX_BIN", fake_codex_script.to_str().unwrap());
+
+    let started = std::time::Instant::now();
+    let result = codex_mcp_rs::codex::run(Options {
+        prompt: "needs auth".to_string(),
+        working_dir: temp_dir.path().to_path_buf(),
+        timeout_secs: Some(60),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(10),
+        "took {:?}",
+        started.elapsed()
+    );
+    assert!(!result.success);
+    assert_eq!(result.fatal_error, Some(FailureKind::Auth));
+    let error = result.error.unwrap();
+    assert!(error.contains("401 Unauthorized"), "{}", error);
+    // Stopped by the server, so not mistaken for an out-of-memory kill
+    assert!(result.termination.is_none_or(|t| !t.likely_oom));
+}
+
+/// Whether `pid` is a zombie child of this process
+#[cfg(target_os = "linux")]
+fn is_zombie_child(pid: &str) -> bool {
+    let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+        return false;
+    };
+    // `pid (comm) state ppid ...`; comm may contain spaces
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .map_or("", |(_, rest)| rest)
+        .split_whitespace()
+        .collect();
+    fields.first() == Some(&"Z") && fields.get(1) == Some(&std::process::id().to_string().as_str())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+#[cfg(target_os = "linux")]
+async fn test_many_short_runs_leave_no_zombies() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    let pids_file = temp_dir.path().join("pids");
+    // "slow" runs exit right around their 1 second timeout, so the timeout
+    // kill and the natural exit race
+    let script_content = format!(
+        r#"#!/bin/bash
+echo $$ >> '{}'
+echo '{{"type":"thread.started","thread_id":"t-1"}}'
+case "$*" in *slow*) sleep 1 ;; esac
+echo '{{"type":"item.completed","item":{{"id":"i0","type":"agent_message","text":"done"}}}}'
+"#,
+        pids_file.display()
+    );
+    tokio::fs::write(&fake_codex_script, script_content)
+        .await
+        .unwrap();
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    let _guard = EnvVarGuard::new("CODEX_BIN", fake_codex_script.to_str().unwrap());
+
+    const RUNS: usize = 200;
+    let mut runs = tokio::task::JoinSet::new();
+    for index in 0..RUNS {
+        let working_dir = temp_dir.path().to_path_buf();
+        runs.spawn(async move {
+            codex_mcp_rs::codex::run(Options {
+                prompt: if index % 2 == 0 { "fast" } else { "slow" }.to_string(),
+                working_dir,
+                timeout_secs: Some(1),
+                ..Default::default()
+            })
+            .await
+        });
+    }
+    let mut finished = 0;
+    while let Some(run) = runs.join_next().await {
+        let result = run.unwrap().unwrap();
+        assert!(
+            result.success || result.error.is_some(),
+            "{:?}",
+            result.error
+        );
+        finished += 1;
+    }
+    assert_eq!(finished, RUNS);
+
+    let pids = std::fs::read_to_string(&pids_file).unwrap();
+    // A run timed out under load may be killed before writing its pid
+    assert!(!pids.is_empty());
+    // Reaping after a timeout happens on the runtime; give it a moment
+    let mut zombies = Vec::new();
+    for _ in 0..50 {
+        zombies = pids.lines().filter(|pid| is_zombie_child(pid)).collect();
+        if zombies.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert!(zombies.is_empty(), "zombie processes left: {:?}", zombies);
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn test_rejected_model_falls_back_to_default() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    // Refuses gpt-9, answers with any other (or no) model
+    let script_content = r#"#!/bin/bash
+for arg in "$@"; do
+  if [ "$arg" = "gpt-9" ]; then
+    echo '{"type":"thread.started","thread_id":"t-1"}'
+    echo '{"type":"error","message":"unknown model: gpt-9"}'
+    exit 1
+  fi
+done
+echo '{"type":"thread.started","thread_id":"t-2"}'
+echo '{"type":"item.completed","item":{"id":"i0","type":"agent_message","text":"Answered"}}'
+"#;
+    tokio::fs::write(&fake_codex_script, script_content)
+        .await
+        .unwrap();
+
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    let _guard = EnvVarGuard::new("CODEX_BIN", fake_codex_script.to_str().unwrap());
+
+    let result = codex_mcp_rs::codex::run(Options {
+        prompt: "hello".to_string(),
+        working_dir: temp_dir.path().to_path_buf(),
+        additional_args: vec!["-m".to_string(), "gpt-9".to_string()],
+        timeout_secs: Some(60),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    assert!(result.success, "{:?}", result.error);
+    assert_eq!(result.session_id, "t-2");
+    assert_eq!(result.agent_messages, "Answered");
+    let fallback = result.model_fallback.unwrap();
+    assert_eq!(fallback.requested, "gpt-9");
+    assert_eq!(fallback.used, None);
+    assert!(
+        fallback.reason.contains("unknown model"),
+        "{}",
+        fallback.reason
+    );
+    assert!(result
+        .warnings
+        .unwrap()
+        .contains("Model gpt-9 was unavailable"));
+}
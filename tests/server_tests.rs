@@ -39,6 +39,118 @@ fn test_default_implementation() {
     assert!(std::mem::size_of_val(&server2) > 0);
 }
 
+/// Start the server binary in `dir` with `env`, send `tool_call` after the
+/// MCP handshake, and return the JSON-RPC response to it
+#[cfg(unix)]
+async fn call_tool(
+    dir: &std::path::Path,
+    env: &[(&str, &std::path::Path)],
+    tool_call: serde_json::Value,
+) -> serde_json::Value {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let mut server = tokio::process::Command::new(env!("CARGO_BIN_EXE_codex-mcp-rs"))
+        .current_dir(dir)
+        .envs(env.iter().copied())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut stdin = server.stdin.take().unwrap();
+    let messages = [
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": {"name": "server-tests", "version": "0"},
+            },
+        }),
+        serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": tool_call,
+        }),
+    ];
+    for message in messages {
+        stdin
+            .write_all(format!("{}\n", message).as_bytes())
+            .await
+            .unwrap();
+    }
+
+    let mut lines = BufReader::new(server.stdout.take().unwrap()).lines();
+    let read = async {
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+            if response["id"] == 2 {
+                return response;
+            }
+        }
+        panic!("server exited without answering the tool call");
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(30), read)
+        .await
+        .expect("tool call should be answered")
+}
+
+#[tokio::test]
+#[cfg(unix)] // Shell scripts don't work on Windows
+async fn test_codex_tool_runs_in_relative_working_dir() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let repo = temp_dir.path().join("repo");
+    std::fs::create_dir(&repo).unwrap();
+    let args_log = temp_dir.path().join("args.log");
+    let fake_codex_script = temp_dir.path().join("fake-codex.sh");
+    let script_content = r#"#!/bin/bash
+printf '%s\n' "$@" > "$ARGS_LOG"
+echo '{"type":"thread.started","thread_id":"test-session-working-dir"}'
+echo '{"type":"item.completed","item":{"type":"agent_message","text":"done"}}'
+exit 0
+"#;
+    std::fs::write(&fake_codex_script, script_content).unwrap();
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&fake_codex_script).unwrap().permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&fake_codex_script, perms).unwrap();
+    }
+
+    // No config file, so the defaults apply
+    let config_path = temp_dir.path().join("none.json");
+    let response = call_tool(
+        temp_dir.path(),
+        &[
+            ("CODEX_BIN", fake_codex_script.as_path()),
+            ("ARGS_LOG", args_log.as_path()),
+            ("HOME", temp_dir.path()),
+            ("CODEX_MCP_CONFIG_PATH", config_path.as_path()),
+        ],
+        serde_json::json!({
+            "name": "codex",
+            "arguments": {"PROMPT": "where am I?", "WORKING_DIR": "repo"},
+        }),
+    )
+    .await;
+    assert!(response.get("error").is_none(), "{}", response);
+    assert_ne!(response["result"]["isError"], true, "{}", response);
+
+    let args = std::fs::read_to_string(&args_log).unwrap();
+    let args: Vec<&str> = args.lines().collect();
+    let cd = args.iter().position(|arg| *arg == "--cd").unwrap();
+    assert_eq!(
+        args[cd + 1],
+        repo.canonicalize().unwrap().to_str().unwrap(),
+        "{:?}",
+        args
+    );
+}
+
 #[cfg(test)]
 mod validation_tests {
     use super::*;